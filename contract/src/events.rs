@@ -0,0 +1,461 @@
+//! Standard events of the lease lifecycle, as per NEP-297. e.g.
+//! `EVENT_JSON:{"standard":"niftyrent","version":"1.0.0","event":"lease_activated","data":[{...}]}`
+//! The events follow the same layout as the NEP-171 events of the LEASE tokens.
+use crate::{Currency, LeaseId};
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{env, serde_json, AccountId};
+
+pub const EVENT_STANDARD: &str = "niftyrent";
+pub const EVENT_VERSION: &str = "1.0.0";
+
+/// A lease has been created, waiting for the rent.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LeaseCreated<'a> {
+    pub lease_id: &'a LeaseId,
+    pub contract_addr: &'a AccountId,
+    pub token_id: &'a TokenId,
+    pub lender_id: &'a AccountId,
+    pub borrower_id: &'a AccountId,
+    pub currency: &'a Currency,
+    pub price: U128,
+    pub protocol_fee: U128,
+    pub start_ts_nano: U64,
+    pub end_ts_nano: U64,
+}
+
+impl LeaseCreated<'_> {
+    pub fn emit(self) {
+        new_event(EventKind::LeaseCreated(&[self])).emit()
+    }
+}
+
+/// The rent of a lease has been paid, and the lease has become active.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LeaseActivated<'a> {
+    pub lease_id: &'a LeaseId,
+    pub contract_addr: &'a AccountId,
+    pub token_id: &'a TokenId,
+    pub borrower_id: &'a AccountId,
+}
+
+impl LeaseActivated<'_> {
+    pub fn emit(self) {
+        new_event(EventKind::LeaseActivated(&[self])).emit()
+    }
+}
+
+/// An active lease has been claimed back or returned early, and its rent has been paid out.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LeaseSettled<'a> {
+    pub lease_id: &'a LeaseId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keeper_id: Option<&'a AccountId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keeper_bounty: Option<U128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub early_return_refund: Option<U128>,
+}
+
+impl LeaseSettled<'_> {
+    pub fn emit(self) {
+        new_event(EventKind::LeaseSettled(&[self])).emit()
+    }
+}
+
+/// A pending lease has been cancelled, without its rent being paid.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LeaseCancelled<'a> {
+    pub lease_id: &'a LeaseId,
+    pub contract_addr: &'a AccountId,
+    pub token_id: &'a TokenId,
+    pub lender_id: &'a AccountId,
+}
+
+impl LeaseCancelled<'_> {
+    pub fn emit(self) {
+        new_event(EventKind::LeaseCancelled(&[self])).emit()
+    }
+}
+
+/// The NFT has been transferred in, and stays in this contract until its last lease is over.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftCustodyStarted<'a> {
+    pub contract_addr: &'a AccountId,
+    pub token_id: &'a TokenId,
+    pub owner_id: &'a AccountId,
+    pub depositor_id: &'a AccountId,
+}
+
+impl NftCustodyStarted<'_> {
+    pub fn emit(self) {
+        new_event(EventKind::NftCustodyStarted(&[self])).emit()
+    }
+}
+
+/// The borrower has extended an active lease, paying the extra rent.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LeaseExtended<'a> {
+    pub lease_id: &'a LeaseId,
+    pub borrower_id: &'a AccountId,
+    pub extra_rent: U128,
+    pub old_end_ts_nano: U64,
+    pub new_end_ts_nano: U64,
+}
+
+impl LeaseExtended<'_> {
+    pub fn emit(self) {
+        new_event(EventKind::LeaseExtended(&[self])).emit()
+    }
+}
+
+/// The collateral of an active lease has been forfeited, so it goes to the lender at settlement.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollateralForfeited<'a> {
+    pub lease_id: &'a LeaseId,
+    pub collateral: U128,
+    pub forfeited_by: &'a AccountId,
+}
+
+impl CollateralForfeited<'_> {
+    pub fn emit(self) {
+        new_event(EventKind::CollateralForfeited(&[self])).emit()
+    }
+}
+
+/// The NFT failed to be transferred back to the lender, so the lease stays active.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ClaimBackFailed<'a> {
+    pub lease_id: &'a LeaseId,
+}
+
+impl ClaimBackFailed<'_> {
+    pub fn emit(self) {
+        new_event(EventKind::ClaimBackFailed(&[self])).emit()
+    }
+}
+
+/// The metadata of an FT failed to be fetched, so the FT hasn't been allowed.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtMetadataFetchFailed<'a> {
+    pub ft_contract_addr: &'a AccountId,
+}
+
+impl FtMetadataFetchFailed<'_> {
+    pub fn emit(self) {
+        new_event(EventKind::FtMetadataFetchFailed(&[self])).emit()
+    }
+}
+
+/// FT or NEAR has been transferred out of this contract, e.g. the rent to a payout receiver.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PayoutSent<'a> {
+    pub currency: &'a Currency,
+    pub receiver_id: &'a AccountId,
+    pub amount: U128,
+}
+
+impl PayoutSent<'_> {
+    pub fn emit(self) {
+        new_event(EventKind::PayoutSent(&[self])).emit()
+    }
+}
+
+/// An FT or NEAR transfer has failed. The amount can be withdrawn by the receiver later.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PayoutFailed<'a> {
+    pub currency: &'a Currency,
+    pub receiver_id: &'a AccountId,
+    pub amount: U128,
+}
+
+impl PayoutFailed<'_> {
+    pub fn emit(self) {
+        new_event(EventKind::PayoutFailed(&[self])).emit()
+    }
+}
+
+/// The borrower has called a method of the leased NFT contract through the proxy.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ProxyCall<'a> {
+    pub lease_id: &'a LeaseId,
+    pub method_name: &'a str,
+    pub success: bool,
+}
+
+impl ProxyCall<'_> {
+    pub fn emit(self) {
+        new_event(EventKind::ProxyCall(&[self])).emit()
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+enum EventKind<'a> {
+    LeaseCreated(&'a [LeaseCreated<'a>]),
+    LeaseActivated(&'a [LeaseActivated<'a>]),
+    LeaseSettled(&'a [LeaseSettled<'a>]),
+    LeaseCancelled(&'a [LeaseCancelled<'a>]),
+    NftCustodyStarted(&'a [NftCustodyStarted<'a>]),
+    LeaseExtended(&'a [LeaseExtended<'a>]),
+    CollateralForfeited(&'a [CollateralForfeited<'a>]),
+    ClaimBackFailed(&'a [ClaimBackFailed<'a>]),
+    FtMetadataFetchFailed(&'a [FtMetadataFetchFailed<'a>]),
+    PayoutSent(&'a [PayoutSent<'a>]),
+    PayoutFailed(&'a [PayoutFailed<'a>]),
+    ProxyCall(&'a [ProxyCall<'a>]),
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+struct Event<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event_kind: EventKind<'a>,
+}
+
+impl Event<'_> {
+    fn emit(self) {
+        env::log_str(&format!(
+            "EVENT_JSON:{}",
+            serde_json::to_string(&self).unwrap()
+        ));
+    }
+}
+
+fn new_event(event_kind: EventKind) -> Event {
+    Event {
+        standard: EVENT_STANDARD,
+        version: EVENT_VERSION,
+        event_kind,
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils;
+
+    fn alice() -> AccountId {
+        AccountId::new_unchecked("alice".to_string())
+    }
+
+    fn bob() -> AccountId {
+        AccountId::new_unchecked("bob".to_string())
+    }
+
+    fn nft() -> AccountId {
+        AccountId::new_unchecked("nft".to_string())
+    }
+
+    fn ft() -> AccountId {
+        AccountId::new_unchecked("ft".to_string())
+    }
+
+    #[test]
+    fn test_lease_created_log_succeeds() {
+        LeaseCreated {
+            lease_id: &"test_key".to_string(),
+            contract_addr: &nft(),
+            token_id: &"test_token".to_string(),
+            lender_id: &alice(),
+            borrower_id: &bob(),
+            currency: &Currency::Ft(ft()),
+            price: U128::from(100),
+            protocol_fee: U128::from(1),
+            start_ts_nano: U64::from(1),
+            end_ts_nano: U64::from(1000),
+        }
+        .emit();
+
+        let log_expected = r#"EVENT_JSON:{"standard":"niftyrent","version":"1.0.0","event":"lease_created","data":[{"lease_id":"test_key","contract_addr":"nft","token_id":"test_token","lender_id":"alice","borrower_id":"bob","currency":{"ft":"ft"},"price":"100","protocol_fee":"1","start_ts_nano":"1","end_ts_nano":"1000"}]}"#;
+        assert_eq!(test_utils::get_logs()[0], log_expected);
+    }
+
+    #[test]
+    fn test_lease_activated_log_succeeds() {
+        LeaseActivated {
+            lease_id: &"test_key".to_string(),
+            contract_addr: &nft(),
+            token_id: &"test_token".to_string(),
+            borrower_id: &bob(),
+        }
+        .emit();
+
+        let log_expected = r#"EVENT_JSON:{"standard":"niftyrent","version":"1.0.0","event":"lease_activated","data":[{"lease_id":"test_key","contract_addr":"nft","token_id":"test_token","borrower_id":"bob"}]}"#;
+        assert_eq!(test_utils::get_logs()[0], log_expected);
+    }
+
+    #[test]
+    fn test_lease_settled_log_succeeds() {
+        LeaseSettled {
+            lease_id: &"test_key".to_string(),
+            keeper_id: None,
+            keeper_bounty: None,
+            early_return_refund: None,
+        }
+        .emit();
+
+        let log_expected = r#"EVENT_JSON:{"standard":"niftyrent","version":"1.0.0","event":"lease_settled","data":[{"lease_id":"test_key"}]}"#;
+        assert_eq!(test_utils::get_logs()[0], log_expected);
+    }
+
+    #[test]
+    fn test_lease_settled_log_succeeds_by_keeper() {
+        LeaseSettled {
+            lease_id: &"test_key".to_string(),
+            keeper_id: Some(&bob()),
+            keeper_bounty: Some(U128::from(5)),
+            early_return_refund: None,
+        }
+        .emit();
+
+        let log_expected = r#"EVENT_JSON:{"standard":"niftyrent","version":"1.0.0","event":"lease_settled","data":[{"lease_id":"test_key","keeper_id":"bob","keeper_bounty":"5"}]}"#;
+        assert_eq!(test_utils::get_logs()[0], log_expected);
+    }
+
+    #[test]
+    fn test_lease_cancelled_log_succeeds() {
+        LeaseCancelled {
+            lease_id: &"test_key".to_string(),
+            contract_addr: &nft(),
+            token_id: &"test_token".to_string(),
+            lender_id: &alice(),
+        }
+        .emit();
+
+        let log_expected = r#"EVENT_JSON:{"standard":"niftyrent","version":"1.0.0","event":"lease_cancelled","data":[{"lease_id":"test_key","contract_addr":"nft","token_id":"test_token","lender_id":"alice"}]}"#;
+        assert_eq!(test_utils::get_logs()[0], log_expected);
+    }
+
+    #[test]
+    fn test_nft_custody_started_log_succeeds() {
+        NftCustodyStarted {
+            contract_addr: &nft(),
+            token_id: &"test_token".to_string(),
+            owner_id: &alice(),
+            depositor_id: &bob(),
+        }
+        .emit();
+
+        let log_expected = r#"EVENT_JSON:{"standard":"niftyrent","version":"1.0.0","event":"nft_custody_started","data":[{"contract_addr":"nft","token_id":"test_token","owner_id":"alice","depositor_id":"bob"}]}"#;
+        assert_eq!(test_utils::get_logs()[0], log_expected);
+    }
+
+    #[test]
+    fn test_lease_extended_log_succeeds() {
+        LeaseExtended {
+            lease_id: &"test_key".to_string(),
+            borrower_id: &bob(),
+            extra_rent: U128::from(10),
+            old_end_ts_nano: U64::from(1000),
+            new_end_ts_nano: U64::from(2000),
+        }
+        .emit();
+
+        let log_expected = r#"EVENT_JSON:{"standard":"niftyrent","version":"1.0.0","event":"lease_extended","data":[{"lease_id":"test_key","borrower_id":"bob","extra_rent":"10","old_end_ts_nano":"1000","new_end_ts_nano":"2000"}]}"#;
+        assert_eq!(test_utils::get_logs()[0], log_expected);
+    }
+
+    #[test]
+    fn test_collateral_forfeited_log_succeeds() {
+        CollateralForfeited {
+            lease_id: &"test_key".to_string(),
+            collateral: U128::from(50),
+            forfeited_by: &alice(),
+        }
+        .emit();
+
+        let log_expected = r#"EVENT_JSON:{"standard":"niftyrent","version":"1.0.0","event":"collateral_forfeited","data":[{"lease_id":"test_key","collateral":"50","forfeited_by":"alice"}]}"#;
+        assert_eq!(test_utils::get_logs()[0], log_expected);
+    }
+
+    #[test]
+    fn test_claim_back_failed_log_succeeds() {
+        ClaimBackFailed {
+            lease_id: &"test_key".to_string(),
+        }
+        .emit();
+
+        let log_expected = r#"EVENT_JSON:{"standard":"niftyrent","version":"1.0.0","event":"claim_back_failed","data":[{"lease_id":"test_key"}]}"#;
+        assert_eq!(test_utils::get_logs()[0], log_expected);
+    }
+
+    #[test]
+    fn test_ft_metadata_fetch_failed_log_succeeds() {
+        FtMetadataFetchFailed {
+            ft_contract_addr: &ft(),
+        }
+        .emit();
+
+        let log_expected = r#"EVENT_JSON:{"standard":"niftyrent","version":"1.0.0","event":"ft_metadata_fetch_failed","data":[{"ft_contract_addr":"ft"}]}"#;
+        assert_eq!(test_utils::get_logs()[0], log_expected);
+    }
+
+    #[test]
+    fn test_payout_sent_log_succeeds() {
+        PayoutSent {
+            currency: &Currency::Ft(ft()),
+            receiver_id: &alice(),
+            amount: U128::from(100),
+        }
+        .emit();
+
+        let log_expected = r#"EVENT_JSON:{"standard":"niftyrent","version":"1.0.0","event":"payout_sent","data":[{"currency":{"ft":"ft"},"receiver_id":"alice","amount":"100"}]}"#;
+        assert_eq!(test_utils::get_logs()[0], log_expected);
+    }
+
+    #[test]
+    fn test_payout_failed_log_succeeds() {
+        PayoutFailed {
+            currency: &Currency::Ft(ft()),
+            receiver_id: &alice(),
+            amount: U128::from(100),
+        }
+        .emit();
+
+        let log_expected = r#"EVENT_JSON:{"standard":"niftyrent","version":"1.0.0","event":"payout_failed","data":[{"currency":{"ft":"ft"},"receiver_id":"alice","amount":"100"}]}"#;
+        assert_eq!(test_utils::get_logs()[0], log_expected);
+    }
+
+    #[test]
+    fn test_proxy_call_log_succeeds() {
+        ProxyCall {
+            lease_id: &"test_key".to_string(),
+            method_name: "play",
+            success: false,
+        }
+        .emit();
+
+        let log_expected = r#"EVENT_JSON:{"standard":"niftyrent","version":"1.0.0","event":"proxy_call","data":[{"lease_id":"test_key","method_name":"play","success":false}]}"#;
+        assert_eq!(test_utils::get_logs()[0], log_expected);
+    }
+}
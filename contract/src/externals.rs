@@ -5,7 +5,24 @@ use crate::*;
 #[ext_contract(ext_self)]
 trait ExtSelf {
     fn activate_lease(&mut self, lease_id: LeaseId) -> PromiseOrValue<U128>;
-    fn resolve_claim_back(&mut self, lease_id: LeaseId) -> Promise;
+    fn resolve_claim_back(&mut self, lease_id: LeaseId, keeper_id: Option<AccountId>) -> bool;
+    fn resolve_claim_back_many(
+        &mut self,
+        pending_lease_ids: Vec<LeaseId>,
+        results: Vec<ClaimBackResult>,
+    ) -> Vec<ClaimBackResult>;
+    fn resolve_cancel_pending_lease(&mut self, lease_id: LeaseId) -> Promise;
+    fn resolve_proxy_func_calls(
+        &mut self,
+        lease_id: LeaseId,
+        method_name: String,
+        borrower_id: AccountId,
+        deposit: U128,
+    ) -> ProxyCallResult;
+    fn resolve_transfer_ft(&mut self, currency: Currency, receiver_id: AccountId, amount: U128);
+    fn resolve_withdraw_fees(&mut self, currency: Currency, amount: U128);
+    fn resolve_return_early(&mut self, lease_id: LeaseId, refund: U128) -> Promise;
+    fn resolve_add_allowed_ft_contract_addr(&mut self, ft_contract_addr: AccountId) -> bool;
 }
 
 /// NFT interface, for cross-contract calls
@@ -1,17 +1,26 @@
 use std::collections::HashMap;
 
+use near_contract_standards::fungible_token::metadata::{ext_ft_metadata, FungibleTokenMetadata};
 use near_contract_standards::non_fungible_token::events::NftBurn;
+use near_contract_standards::non_fungible_token::refund_approved_account_ids;
 use near_contract_standards::non_fungible_token::TokenId;
+use near_contract_standards::storage_management::StorageBalance;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet};
-use near_sdk::json_types::{Base64VecU8, U128};
+use near_sdk::json_types::{Base64VecU8, U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
-use near_sdk::{bs58, ext_contract, require, serde_json::json, CryptoHash, PromiseOrValue};
+use near_sdk::{
+    bs58, ext_contract, is_promise_success, require, CryptoHash, PromiseOrValue, PromiseResult,
+};
 use near_sdk::{env, near_bindgen, AccountId, BorshStorageKey, Gas, PanicOnDefault, Promise};
 
+mod events;
 mod externals;
+mod migration;
 mod nft;
+mod storage;
 mod utils;
+use crate::events::*;
 use crate::externals::*;
 
 // Copied from Paras market contract. Will need to be fine-tuned.
@@ -21,11 +30,37 @@ pub const XCC_GAS: Gas = Gas(5 * TGAS); // cross contract gas
 pub const GAS_FOR_NFT_TRANSFER: Gas = Gas(5 * TGAS);
 pub const BASE_GAS: Gas = Gas(5 * TGAS);
 pub const GAS_FOR_ROYALTIES: Gas = BASE_GAS;
-pub const GAS_FOR_RESOLVE_CLAIM_BACK: Gas = Gas(BASE_GAS.0 * 10u64);
+// The gas for settling a lease, on top of the transfers it makes
+pub const GAS_FOR_RESOLVE_CLAIM_BACK: Gas = Gas(BASE_GAS.0 * 4u64);
+pub const GAS_FOR_RESOLVE_CLAIM_BACK_MANY: Gas = BASE_GAS;
+pub const GAS_FOR_RESOLVE_TRANSFER_FT: Gas = BASE_GAS;
+pub const GAS_FOR_FT_TRANSFER: Gas = Gas(10 * TGAS);
+// each FT or NEAR transfer made when settling a lease is followed by its own callback
+pub const GAS_PER_SETTLEMENT_TRANSFER: Gas =
+    Gas(GAS_FOR_FT_TRANSFER.0 + GAS_FOR_RESOLVE_TRANSFER_FT.0);
+pub const GAS_FOR_PROXY_CALL: Gas = Gas(5 * TGAS); // used when the caller doesn't specify the gas
+pub const DEFAULT_MAX_PROXY_CALL_GAS: Gas = Gas(50 * TGAS);
+pub const GAS_FOR_RESOLVE_PROXY_CALL: Gas = BASE_GAS;
 // the tolerance of lease price minus the sum of payout
 // Set it to 1 to avoid linter error
 pub const PAYOUT_DIFF_TORLANCE_YACTO: u128 = 1;
-pub const MAX_LEN_PAYOUT: u32 = 50;
+// Keeps the payouts of a lease settlement within the gas limit of a transaction.
+// Any payout of length 10 or less must be accepted by the NFT contracts, see `Payout`.
+pub const MAX_LEN_PAYOUT: u32 = 10;
+// A lease pending on rent can be cancelled after this period, e.g. when the rent never arrived.
+pub const PENDING_LEASE_TIMEOUT_NANO: u64 = 24 * 60 * 60 * 1_000_000_000; // 1 day
+
+// After this period past the end of a lease, anyone can claim it back as a keeper, for a bounty from the rent.
+pub const DEFAULT_KEEPER_GRACE_PERIOD_NANO: u64 = 24 * 60 * 60 * 1_000_000_000; // 1 day
+pub const MAX_KEEPER_BOUNTY_BPS: u16 = 1_000; // 10%
+pub const MAX_PROTOCOL_FEE_BPS: u16 = 1_000; // 10%
+pub const MAX_LEASE_TOKEN_ROYALTY_BPS: u16 = 5_000; // 50%
+pub const BPS_DENOMINATOR: u128 = 10_000;
+
+// The version of the contract state layout. V1 and V2 are migrated by `migrate`
+pub const STATE_VERSION: u8 = 3;
+// The max number of leases rewritten in the latest format by one `migrate` call
+pub const DEFAULT_MIGRATION_PAGE_SIZE: u64 = 50;
 
 pub type LeaseId = String;
 pub type ListingId = String; // marketplace listing_id
@@ -49,6 +84,23 @@ pub enum LeaseState {
     PendingOnRent,
     Active,
 }
+/// Terms set by the lender, under which the borrower can extend an active lease.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RenewalTerms {
+    pub price_per_unit: U128, // Rent for extending the lease by one unit of time
+    pub unit_duration_nano: U64, // The length of one unit of time
+    pub max_end_ts_nano: U64, // The lease can not be extended beyond this timestamp
+}
+/// A method of the leased NFT contract, which the borrower can call through `proxy_func_calls`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AllowedMethod {
+    pub method_name: String,
+    pub max_deposit: Option<U128>, // No limit on the attached deposit, if not set
+    pub max_gas: Option<U64>,      // Capped only by the default proxy call gas, if not set
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct LeaseJson {
@@ -56,11 +108,87 @@ pub struct LeaseJson {
     nft_token_id: TokenId,
     lender_id: AccountId,
     borrower_id: AccountId,
-    ft_contract_addr: AccountId,
+    currency: Currency,
     price: U128,
     start_ts_nano: u64,
     end_ts_nano: u64,
     nft_payout: Payout,
+    refund_on_early_return: Option<bool>, // default: true
+    renewal_terms: Option<RenewalTerms>,  // default: the lease can not be extended
+    collateral: Option<U128>,             // default: no collateral
+    // default: no method can be called through the proxy
+    allowed_methods: Option<Vec<AllowedMethod>>,
+    // default: true
+    sublease_allowed: Option<bool>,
+}
+
+/// The optional terms of a lease, set by the lender on top of the rent.
+#[derive(Clone, Debug)]
+pub struct LeaseTerms {
+    pub refund_on_early_return: bool,
+    pub renewal_terms: Option<RenewalTerms>,
+    pub collateral: U128,
+    pub allowed_methods: Vec<AllowedMethod>,
+    pub sublease_allowed: bool,
+}
+
+impl Default for LeaseTerms {
+    // The terms of a lease created without any option
+    fn default() -> Self {
+        Self {
+            refund_on_early_return: true,
+            renewal_terms: None,
+            collateral: U128::from(0),
+            allowed_methods: Vec::new(),
+            sublease_allowed: true,
+        }
+    }
+}
+
+/// The rent of a lease: what the borrower pays, for which period, and to whom.
+#[derive(Clone, Debug)]
+pub struct RentTerms {
+    pub currency: Currency,
+    pub price: U128,
+    pub start_ts_nano: u64,
+    pub end_ts_nano: u64,
+    pub nft_payout: Payout,
+}
+
+/// The NFT held by this contract, between the leases of its calendar.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftCustody {
+    pub owner_id: AccountId, // The lender, who owned the NFT before transferring it in
+    // The account which transferred the NFT in for the owner, e.g. the marketplace.
+    // Only it can book more leases of the NFT, while the NFT stays in custody.
+    pub depositor_id: AccountId,
+}
+
+/// The metadata of an allowed FT, cached when it's added to the allowlist. It's used to display prices.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtMetadata {
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+/// The currency the rent and the collateral of a lease are paid in.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum Currency {
+    Near,          // Native NEAR, paid through `near_on_transfer`
+    Ft(AccountId), // The contract of a NEP-141 token, paid through `ft_on_transfer`
+}
+
+impl std::fmt::Display for Currency {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Currency::Near => write!(f, "NEAR"),
+            Currency::Ft(ft_contract_addr) => write!(f, "{}", ft_contract_addr),
+        }
+    }
 }
 
 /// Struct for keeping track of the lease conditions
@@ -71,23 +199,24 @@ pub struct LeaseCondition {
     pub token_id: TokenId,           // NFT token
     pub lender_id: AccountId,        // Owner of the NFT
     pub borrower_id: AccountId,      // Borrower of the NFT
-    pub ft_contract_addr: AccountId, // the account id for the ft contract
+    pub currency: Currency,          // The currency of the rent and the collateral
     pub start_ts_nano: u64, // The timestamp in nano to start the lease, i.e. the current user will be the borrower
     pub end_ts_nano: u64, // The timestamp in nano to end the lease, i.e. the lender can claim back the NFT
     pub price: U128,      // Proposed lease price
     pub payout: Option<Payout>, // Payout info (e.g. for Royalty split)
     pub state: LeaseState, // Current lease state
-}
-
-#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
-pub struct ContractV1 {
-    owner: AccountId,
-    lease_map: UnorderedMap<LeaseId, LeaseCondition>,
-    lease_ids_by_lender: LookupMap<AccountId, UnorderedSet<LeaseId>>,
-    lease_ids_by_borrower: LookupMap<AccountId, UnorderedSet<LeaseId>>,
-    lease_id_by_contract_addr_and_token_id: LookupMap<(AccountId, TokenId), LeaseId>,
-    active_lease_ids: UnorderedSet<LeaseId>, // This also records all existing LEASE token ids
-    active_lease_ids_by_lender: LookupMap<AccountId, UnorderedSet<LeaseId>>,
+    pub refund_on_early_return: bool, // Whether the borrower gets the unused rent back when returning early
+    pub renewal_terms: Option<RenewalTerms>, // Terms for the borrower to extend the lease, if allowed
+    pub collateral: U128, // Refundable deposit paid by the borrower on top of the price
+    pub collateral_forfeited: bool, // Whether the collateral goes to the lender at settlement
+    pub pending_deadline_ts_nano: u64, // The rent must arrive before this timestamp, otherwise the lease can be cancelled
+    pub allowed_methods: Vec<AllowedMethod>, // Methods of the NFT contract the borrower can call through the proxy
+    pub sublease_allowed: bool, // Whether the borrower can sub-lease the NFT during the lease
+    pub parent_lease_id: Option<LeaseId>, // The lease this sub-lease is nested in. None for a lease created from a listing
+    pub sublease_id: Option<LeaseId>,     // The current sub-lease of this lease, if any
+    pub protocol_fee_bps: u16, // The protocol fee on the rent, in basis points, as of the lease creation
+    pub storage_bytes: u64,    // The storage used by the lease records
+    pub storage_payer_id: AccountId, // The account that paid for the storage, refunded when the lease is removed
 }
 
 #[near_bindgen]
@@ -97,14 +226,67 @@ pub struct Contract {
     lease_map: UnorderedMap<LeaseId, LeaseCondition>,
     lease_ids_by_lender: LookupMap<AccountId, UnorderedSet<LeaseId>>,
     lease_ids_by_borrower: LookupMap<AccountId, UnorderedSet<LeaseId>>,
-    lease_id_by_contract_addr_and_token_id: LookupMap<(AccountId, TokenId), LeaseId>, // <(NFT_contract, token_id), lease_id>
+    // The calendar of each NFT, i.e. its non-overlapping leases sorted by start time.
+    // Sub-leases are reached from their parent leases instead.
+    lease_ids_by_contract_addr_and_token_id: LookupMap<(AccountId, TokenId), Vec<LeaseId>>, // <(NFT_contract, token_id), lease_ids>
+    // The NFTs staying in this contract between back-to-back leases.
+    nft_custodies: LookupMap<(AccountId, TokenId), NftCustody>,
 
     active_lease_ids: UnorderedSet<LeaseId>, // This also records all existing LEASE token ids
     active_lease_ids_by_lender: LookupMap<AccountId, UnorderedSet<LeaseId>>,
+    // The owners of the borrower tokens, which exist along with the LEASE tokens of the active leases.
+    active_lease_ids_by_borrower: LookupMap<AccountId, UnorderedSet<LeaseId>>,
+    // NEP-178 approvals of the LEASE tokens, by lease id. <lease_id, <approved_account_id, approval_id>>
+    approvals_by_lease_id: LookupMap<LeaseId, HashMap<AccountId, u64>>,
+    next_approval_id_by_lease_id: LookupMap<LeaseId, u64>,
 
     // Allowlist of the contract addresses of the FT for the rent payment currency.
     // It's ok to load all allowed FT addresses into memory at once, since it's won't be long.
     allowed_ft_contract_addrs: Vec<AccountId>,
+    // The metadata of each allowed FT, fetched from its contract when it's added.
+    allowed_ft_metadata: LookupMap<AccountId, FtMetadata>,
+    // Whether the rent can be paid in native NEAR, besides the allowed FTs.
+    near_allowed: bool,
+
+    // The account, besides the lender, allowed to forfeit the collateral of a lease.
+    collateral_arbitrator_id: Option<AccountId>,
+
+    // The max gas a borrower can attach to a call through `proxy_func_calls`.
+    max_proxy_call_gas: Gas,
+
+    // Keepers can claim back a lease once this period has passed since the lease ended.
+    keeper_grace_period_nano: u64,
+    // The share of the rent, in basis points, paid to the keeper claiming back a lease.
+    keeper_bounty_bps: u16,
+
+    // The protocol fee on the rent paid out to lenders, in basis points. It applies to new leases only.
+    protocol_fee_bps: u16,
+    // The account receiving the withdrawn protocol fees.
+    fee_receiver_id: AccountId,
+    // Protocol fees collected so far and not withdrawn yet, by currency.
+    accrued_fees: UnorderedMap<Currency, U128>,
+
+    // The royalty policy of the LEASE token sales, in basis points of the sale price, by receiver.
+    // Without it, the sales pass through the royalty split of the leased NFT.
+    lease_token_royalty_bps: Option<HashMap<AccountId, u16>>,
+
+    // Payments which failed to be transferred, e.g. when the receiver isn't registered on the FT contract.
+    // They can be withdrawn by the receivers. <(receiver, currency), amount>
+    unclaimed_balances: LookupMap<(AccountId, Currency), U128>,
+
+    // NEP-145 storage balances. Lenders pay for the storage of their leases out of them.
+    storage_balances: LookupMap<AccountId, StorageBalance>,
+    // The storage used by registering one account, which is the min storage balance.
+    account_storage_usage: u64,
+
+    // Incremented for every new lease, to derive unique lease ids.
+    lease_nonce: u64,
+
+    // The version of the contract state layout, to pick the migration on upgrades.
+    // It stays at the previous version, until all the leases are migrated.
+    state_version: u8,
+    // The number of leases already rewritten in the latest format, while migrating.
+    lease_migration_cursor: u64,
 }
 
 #[derive(BorshStorageKey, BorshSerialize)]
@@ -114,10 +296,41 @@ enum StorageKey {
     LeasesIdsByLenderInner { account_id_hash: CryptoHash },
     LeaseIdsByBorrower,
     LeaseIdsByBorrowerInner { account_id_hash: CryptoHash },
+    // The one-lease-per-NFT index of the previous versions, only cleared by the migration.
+    // It stays in place, so the borsh discriminants of the keys below don't shift.
     LeaseIdByContractAddrAndTokenId,
     ActiveLeaseIdsByOwner,
     ActiveLeaseIdsByOwnerInner { account_id_hash: CryptoHash },
     ActiveLeaseIds,
+    AccruedFees,
+    UnclaimedBalances,
+    StorageBalances,
+    LeaseIdsByContractAddrAndTokenId,
+    NftCustodies,
+    ApprovalsByLeaseId,
+    NextApprovalIdByLeaseId,
+    ActiveLeaseIdsByBorrower,
+    ActiveLeaseIdsByBorrowerInner { account_id_hash: CryptoHash },
+    AllowedFtMetadata,
+}
+
+/// Optional filters of the lease views. A lease is returned only if it matches all the given filters.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LeaseFilter {
+    pub state: Option<LeaseState>,
+    pub contract_addr: Option<AccountId>, // NFT contract
+    pub currency: Option<Currency>,
+    pub time_window: Option<LeaseTimeWindow>,
+}
+
+/// The period of a lease, relative to the current block time.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum LeaseTimeWindow {
+    Active,   // The lease has started and not ended yet
+    Upcoming, // The lease has not started yet
+    Expired,  // The lease has ended
 }
 
 #[derive(Serialize, Deserialize)]
@@ -125,6 +338,49 @@ enum StorageKey {
 pub struct RentAcceptanceJson {
     nft_contract_id: AccountId,
     nft_token_id: TokenId,
+    // The start of the lease in the calendar of the NFT. Optional, if the NFT has only one lease.
+    start_ts_nano: Option<u64>,
+}
+
+/// Message to be passed in by the borrower, to pay for extending an active lease.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LeaseExtensionJson {
+    lease_id: LeaseId,
+}
+
+/// Message to be passed in by the borrower of a sub-lease, to pay for its rent.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SubleaseRentJson {
+    sublease_id: LeaseId,
+}
+
+/// The outcome of claiming back one of the leases in `claim_back_many`.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ClaimBackResult {
+    pub lease_id: LeaseId,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// The outcome of a call made through `proxy_func_calls`, with the raw value returned by the NFT contract.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ProxyCallResult {
+    pub success: bool,
+    pub result: Option<Base64VecU8>,
+}
+
+/// All the messages accepted by `ft_on_transfer`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(untagged)]
+pub enum FtOnTransferJson {
+    RentAcceptance(RentAcceptanceJson),
+    LeaseExtension(LeaseExtensionJson),
+    SubleaseRent(SubleaseRentJson),
 }
 
 #[near_bindgen]
@@ -132,18 +388,40 @@ impl Contract {
     #[init]
     pub fn new(owner_id: AccountId) -> Self {
         assert!(!env::state_exists(), "Already initialized");
-        Self {
-            owner: owner_id,
+        let mut this = Self {
+            owner: owner_id.clone(),
             lease_map: UnorderedMap::new(StorageKey::LendingsKey),
             lease_ids_by_lender: LookupMap::new(StorageKey::LeaseIdsByLender),
             lease_ids_by_borrower: LookupMap::new(StorageKey::LeaseIdsByBorrower),
-            lease_id_by_contract_addr_and_token_id: LookupMap::new(
-                StorageKey::LeaseIdByContractAddrAndTokenId,
+            lease_ids_by_contract_addr_and_token_id: LookupMap::new(
+                StorageKey::LeaseIdsByContractAddrAndTokenId,
             ),
+            nft_custodies: LookupMap::new(StorageKey::NftCustodies),
             active_lease_ids_by_lender: LookupMap::new(StorageKey::ActiveLeaseIdsByOwner),
+            active_lease_ids_by_borrower: LookupMap::new(StorageKey::ActiveLeaseIdsByBorrower),
             active_lease_ids: UnorderedSet::new(StorageKey::ActiveLeaseIds),
+            approvals_by_lease_id: LookupMap::new(StorageKey::ApprovalsByLeaseId),
+            next_approval_id_by_lease_id: LookupMap::new(StorageKey::NextApprovalIdByLeaseId),
             allowed_ft_contract_addrs: Vec::new(),
-        }
+            allowed_ft_metadata: LookupMap::new(StorageKey::AllowedFtMetadata),
+            near_allowed: false,
+            collateral_arbitrator_id: None,
+            max_proxy_call_gas: DEFAULT_MAX_PROXY_CALL_GAS,
+            keeper_grace_period_nano: DEFAULT_KEEPER_GRACE_PERIOD_NANO,
+            keeper_bounty_bps: 0,
+            protocol_fee_bps: 0,
+            fee_receiver_id: owner_id,
+            accrued_fees: UnorderedMap::new(StorageKey::AccruedFees),
+            lease_token_royalty_bps: None,
+            unclaimed_balances: LookupMap::new(StorageKey::UnclaimedBalances),
+            storage_balances: LookupMap::new(StorageKey::StorageBalances),
+            account_storage_usage: 0,
+            lease_nonce: 0,
+            state_version: STATE_VERSION,
+            lease_migration_cursor: 0,
+        };
+        this.measure_account_storage_usage();
+        this
     }
 
     /// A temporary method to completely reset the contract state.
@@ -158,17 +436,26 @@ impl Contract {
         Self::new(owner_id)
     }
 
-    /// Note: This migration function will clear all existing leases.
+    /// Migrates the contract state of a previous version to the latest one.
+    /// All existing leases and their indices are kept, so it's safe to upgrade while NFTs are in custody.
+    /// The leases are rewritten `limit` at a time. Call it again until `get_state_version` returns `STATE_VERSION`.
     #[init(ignore_state)]
-    pub fn migrate() -> Self {
-        let prev: ContractV1 = env::state_read().expect("ERR_NOT_INITIALIZED");
+    pub fn migrate(limit: Option<u64>) -> Self {
+        let mut this = Self::internal_read_state_for_migration();
         assert_eq!(
             env::predecessor_account_id(),
-            prev.owner,
+            this.owner,
             "Only the owner can invoke the migration"
         );
 
-        Self::new(prev.owner)
+        match this.state_version {
+            STATE_VERSION => panic!("The contract state has already been migrated!"),
+            migration::STATE_VERSION_V2 => {
+                this.internal_migrate_leases_from_v2(limit.unwrap_or(DEFAULT_MIGRATION_PAGE_SIZE))
+            }
+            _ => panic!("Unknown contract state version!"),
+        }
+        this
     }
 
     fn activate_lease(&mut self, lease_id: LeaseId) {
@@ -179,50 +466,205 @@ impl Contract {
         };
         self.lease_map.insert(&lease_id, &new_lease_condition);
 
-        env::log_str(
-            &json!({
-                "type": "[INFO] NiFTyRent Rental: A lease has been activated",
-                "params": {
-                    "lease_id": lease_id.clone(),
-                    "lease_state": new_lease_condition.state,
-                    "nft_contract": new_lease_condition.contract_addr.clone(),
-                    "nft_token_id": new_lease_condition.token_id.clone(),
-                }
-            })
-            .to_string(),
-        );
+        LeaseActivated {
+            lease_id: &lease_id,
+            contract_addr: &new_lease_condition.contract_addr,
+            token_id: &new_lease_condition.token_id,
+            borrower_id: &new_lease_condition.borrower_id,
+        }
+        .emit();
 
-        self.nft_mint(lease_id, new_lease_condition.lender_id.clone());
+        // The LEASE token stands for the NFT to be claimed back, which only applies to the outermost lease.
+        // The borrower token stands for the right to use the NFT during that lease.
+        if new_lease_condition.parent_lease_id.is_none() {
+            self.nft_mint(lease_id.clone(), new_lease_condition.lender_id.clone());
+            self.nft_mint_borrower_token(lease_id, new_lease_condition.borrower_id.clone());
+        }
     }
 
     #[payable]
     pub fn claim_back(&mut self, lease_id: LeaseId) {
+        self.internal_assert_migrated();
         // Function to allow a user to claim back the NFT and rent after a lease expired.
 
-        let lease_condition: LeaseCondition = self.lease_map.get(&lease_id).unwrap();
+        // 1. check the lease can be claimed back by the caller
+        let lease_condition = self
+            .internal_check_claim_back(&lease_id)
+            .unwrap_or_else(|err| panic!("{}", err));
 
-        // 1. check expire time
-        assert!(
-            lease_condition.end_ts_nano < env::block_timestamp(),
-            "Lease has not expired yet!"
+        let keeper_id = self.internal_keeper_id(&lease_condition);
+
+        // A sub-lease, or a lease followed by other leases of the NFT, is settled right away,
+        // as the NFT stays in this contract
+        if self.internal_keeps_nft_in_custody(&lease_id, &lease_condition) {
+            self.internal_settle_lease(&lease_id, keeper_id);
+            return;
+        }
+
+        // 2. transfer nft to owner, then pay the rent to lender and royalty to relevant parties.
+        // Finally remove the lease.
+        self.internal_claim_back_nft(lease_id, &lease_condition, keeper_id);
+    }
+
+    /// Claims back many expired leases at once. Each lease is checked and claimed back independently,
+    /// so that one bad lease doesn't revert the others. The outcome of each lease is returned.
+    /// Leases which don't fit into the attached gas are skipped and reported as failed.
+    #[payable]
+    pub fn claim_back_many(
+        &mut self,
+        lease_ids: Vec<LeaseId>,
+    ) -> PromiseOrValue<Vec<ClaimBackResult>> {
+        self.internal_assert_migrated();
+        let reserved_gas = env::used_gas() + GAS_FOR_RESOLVE_CLAIM_BACK_MANY + BASE_GAS;
+        require!(
+            env::prepaid_gas() >= reserved_gas,
+            "Not enough gas attached to claim back the leases!"
         );
-        // 2. check state == active
+        let mut remaining_gas = env::prepaid_gas() - reserved_gas;
+
+        let mut results: Vec<ClaimBackResult> = Vec::new();
+        let mut pending_lease_ids: Vec<LeaseId> = Vec::new();
+        let mut claim_back_promise: Option<Promise> = None;
+
+        for lease_id in lease_ids {
+            let lease_condition = match self.internal_check_claim_back(&lease_id) {
+                Ok(lease_condition) => lease_condition,
+                Err(err) => {
+                    results.push(ClaimBackResult {
+                        lease_id,
+                        success: false,
+                        error: Some(err),
+                    });
+                    continue;
+                }
+            };
+
+            let keeper_id = self.internal_keeper_id(&lease_condition);
+
+            // A sub-lease, or a lease followed by other leases of the NFT, is settled right away,
+            // as the NFT stays in this contract
+            if self.internal_keeps_nft_in_custody(&lease_id, &lease_condition) {
+                self.internal_settle_lease(&lease_id, keeper_id);
+                results.push(ClaimBackResult {
+                    lease_id,
+                    success: true,
+                    error: None,
+                });
+                continue;
+            }
+
+            let gas_for_lease =
+                GAS_FOR_NFT_TRANSFER + self.internal_gas_for_settlement(&lease_condition);
+            if remaining_gas < gas_for_lease {
+                results.push(ClaimBackResult {
+                    lease_id,
+                    success: false,
+                    error: Some("Not enough gas to claim back this lease!".to_string()),
+                });
+                continue;
+            }
+            remaining_gas -= gas_for_lease;
+
+            let promise =
+                self.internal_claim_back_nft(lease_id.clone(), &lease_condition, keeper_id);
+            claim_back_promise = match claim_back_promise {
+                None => Some(promise),
+                Some(claim_back_promise) => Some(claim_back_promise.and(promise)),
+            };
+            pending_lease_ids.push(lease_id);
+        }
+
+        match claim_back_promise {
+            None => PromiseOrValue::Value(results),
+            Some(claim_back_promise) => claim_back_promise
+                .then(
+                    ext_self::ext(env::current_account_id())
+                        .with_attached_deposit(0)
+                        .with_static_gas(GAS_FOR_RESOLVE_CLAIM_BACK_MANY)
+                        .resolve_claim_back_many(pending_lease_ids, results),
+                )
+                .into(),
+        }
+    }
+
+    /// Settles the lease, if the NFT has been transferred back to the lender.
+    /// The keeper, if any, is paid a bounty from the rent.
+    /// Returns whether the lease has been claimed back.
+    #[private]
+    pub fn resolve_claim_back(&mut self, lease_id: LeaseId, keeper_id: Option<AccountId>) -> bool {
+        // The lease should only be settled if the NFT is back to the lender
+        if !is_promise_success() {
+            ClaimBackFailed {
+                lease_id: &lease_id,
+            }
+            .emit();
+            return false;
+        }
+
+        self.internal_settle_lease(&lease_id, keeper_id);
+        true
+    }
+
+    /// Collects the outcome of each lease claimed back in `claim_back_many`.
+    #[private]
+    pub fn resolve_claim_back_many(
+        &mut self,
+        pending_lease_ids: Vec<LeaseId>,
+        results: Vec<ClaimBackResult>,
+    ) -> Vec<ClaimBackResult> {
+        let mut results = results;
+        for (index, lease_id) in pending_lease_ids.into_iter().enumerate() {
+            let success = match env::promise_result(index as u64) {
+                PromiseResult::Successful(value) => {
+                    near_sdk::serde_json::from_slice::<bool>(&value).unwrap_or(false)
+                }
+                _ => false,
+            };
+            results.push(ClaimBackResult {
+                lease_id,
+                success,
+                error: if success {
+                    None
+                } else {
+                    Some("NFT transfer failed!".to_string())
+                },
+            });
+        }
+        results
+    }
+
+    /// Cancels a lease whose rent has not arrived before its pending deadline, e.g. when the
+    /// marketplace failed to forward the rent. The NFT is returned to the lender.
+    /// Anyone can call it, so that no NFT gets stuck in this contract.
+    #[payable]
+    pub fn cancel_pending_lease(&mut self, lease_id: LeaseId) {
+        self.internal_assert_migrated();
+        let lease_condition: LeaseCondition = self.lease_map.get(&lease_id).unwrap();
+
+        // 1. check state == pending on rent
         assert_eq!(
             lease_condition.state,
-            LeaseState::Active,
-            "Queried Lease is not active!"
+            LeaseState::PendingOnRent,
+            "This lease is not pending on rent!"
         );
 
-        // 3. only the current lease lender or service contract owner can claim back from expried lease
+        // 2. check the pending deadline has passed
         assert!(
-            (lease_condition.lender_id == env::predecessor_account_id())
-                || (self.owner == env::predecessor_account_id()),
-            "Only original lender or service owner can claim back!"
+            lease_condition.pending_deadline_ts_nano < env::block_timestamp(),
+            "Pending lease has not timed out yet!"
         );
 
-        // 4. transfer nft to owner
+        // A sub-lease, or a lease among other leases of the NFT, is removed right away,
+        // as the NFT stays in this contract
+        if self.internal_keeps_nft_in_custody(&lease_id, &lease_condition) {
+            self.internal_cancel_lease(&lease_id);
+            return;
+        }
+
+        // 3. transfer nft back to the lender
+        self.internal_release_custody(&lease_condition);
         ext_nft::ext(lease_condition.contract_addr.clone())
-            .with_static_gas(Gas(5 * TGAS))
+            .with_static_gas(GAS_FOR_NFT_TRANSFER)
             .with_attached_deposit(1)
             .nft_transfer(
                 lease_condition.lender_id.clone(),
@@ -230,138 +672,738 @@ impl Contract {
                 None,
                 None,
             )
-            // 5. Pay the rent to lender and royalty to relevant parties. Finally remove the lease.
+            // 4. Remove the lease, once the NFT is back to the lender
             .then(
                 ext_self::ext(env::current_account_id())
                     .with_attached_deposit(0)
                     .with_static_gas(GAS_FOR_RESOLVE_CLAIM_BACK)
-                    .resolve_claim_back(lease_id),
+                    .resolve_cancel_pending_lease(lease_id),
             );
     }
 
     #[private]
-    pub fn resolve_claim_back(&mut self, lease_id: LeaseId) {
-        // TODO: avoid re-fetch lease condition
+    pub fn resolve_cancel_pending_lease(&mut self, lease_id: LeaseId) {
+        require!(
+            is_promise_success(),
+            "NFT transfer failed. Abort lease cancellation!"
+        );
+
+        self.internal_cancel_lease(&lease_id);
+    }
+
+    /// Allows the borrower to end an active lease before it expires.
+    /// The NFT is returned to the lender. The unused share of the rent, prorated by time, is refunded
+    /// to the borrower, unless the lender opted out of refunds for this lease.
+    /// The rest of the rent is paid out as usual.
+    #[payable]
+    pub fn return_early(&mut self, lease_id: LeaseId) {
+        self.internal_assert_migrated();
         let lease_condition: LeaseCondition = self.lease_map.get(&lease_id).unwrap();
 
-        match lease_condition.payout {
-            Some(payout) => {
-                for (receiver_id, amount) in payout.payout {
-                    self.internal_transfer_ft(
-                        lease_condition.ft_contract_addr.clone(),
-                        receiver_id,
-                        amount,
-                    );
-                }
-            }
-            None => {
-                self.internal_transfer_ft(
-                    lease_condition.ft_contract_addr.clone(),
-                    lease_condition.lender_id,
-                    U128::from(lease_condition.price),
-                );
-            }
+        // 1. check state == active
+        assert_eq!(
+            lease_condition.state,
+            LeaseState::Active,
+            "Queried Lease is not active!"
+        );
+
+        // 2. only the borrower can return the NFT before the lease ends
+        assert_eq!(
+            lease_condition.borrower_id,
+            env::predecessor_account_id(),
+            "Only the borrower can return the NFT early!"
+        );
+
+        // 3. check expire time. An expired lease should be claimed back instead.
+        assert!(
+            env::block_timestamp() < lease_condition.end_ts_nano,
+            "Lease has already expired!"
+        );
+
+        // 4. the sub-lease of the NFT should end first
+        assert!(
+            lease_condition.sublease_id.is_none(),
+            "The NFT is sub-leased!"
+        );
+
+        let refund = self.internal_early_return_refund(&lease_condition, env::block_timestamp());
+
+        // A sub-lease, or a lease followed by other leases of the NFT, is settled right away,
+        // as the NFT stays in this contract
+        if self.internal_keeps_nft_in_custody(&lease_id, &lease_condition) {
+            self.internal_settle_early_return(&lease_id, refund);
+            return;
         }
 
-        self.internal_remove_lease(&lease_id);
+        // 5. transfer nft to owner
+        self.internal_release_custody(&lease_condition);
+        ext_nft::ext(lease_condition.contract_addr.clone())
+            .with_static_gas(GAS_FOR_NFT_TRANSFER)
+            .with_attached_deposit(1)
+            .nft_transfer(
+                lease_condition.lender_id.clone(),
+                lease_condition.token_id.clone(),
+                None,
+                None,
+            )
+            // 6. Refund the borrower and pay the rest of the rent. Finally remove the lease.
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_attached_deposit(0)
+                    .with_static_gas(self.internal_gas_for_settlement(&lease_condition))
+                    .resolve_return_early(lease_id, U128::from(refund)),
+            );
     }
 
-    // private function to transfer FT to receiver_id
-    fn internal_transfer_ft(
-        &self,
-        ft_contract_addr: AccountId,
-        receiver_id: AccountId,
-        amount: U128,
-    ) -> Promise {
-        ext_ft_core::ext(ft_contract_addr)
-            .with_static_gas(Gas(10 * TGAS))
-            .with_attached_deposit(1)
-            .ft_transfer(receiver_id, amount, None)
-            .as_return()
+    #[private]
+    pub fn resolve_return_early(&mut self, lease_id: LeaseId, refund: U128) {
+        // The lease should only be settled if the NFT is back to the lender
+        require!(
+            is_promise_success(),
+            "NFT transfer failed. Abort early return!"
+        );
+
+        self.internal_settle_early_return(&lease_id, refund.0);
     }
 
-    pub fn leases_by_owner(&self, account_id: AccountId) -> Vec<(String, LeaseCondition)> {
-        let mut results: Vec<(String, LeaseCondition)> = vec![];
+    /// Allows the borrower of an active lease to re-rent the NFT to someone else, for a period inside
+    /// the lease, unless the lender forbids it. The rent of the sub-lease goes to the borrower.
+    /// A lease can only have one sub-lease at a time.
+    pub fn create_sublease(
+        &mut self,
+        lease_id: LeaseId,
+        borrower_id: AccountId,
+        start_ts_nano: U64,
+        end_ts_nano: U64,
+        price: U128,
+    ) -> LeaseId {
+        self.internal_assert_migrated();
+        let mut lease_condition: LeaseCondition = self
+            .lease_map
+            .get(&lease_id)
+            .expect("Input lease_id does not exist");
 
-        let lease_ids = self
-            .lease_ids_by_lender
-            .get(&account_id)
-            .unwrap_or(UnorderedSet::new(b"s"));
-        for id in lease_ids.iter() {
-            let lease_condition = self.lease_map.get(&id).unwrap();
-            results.push((id, lease_condition));
-        }
+        assert_eq!(
+            lease_condition.state,
+            LeaseState::Active,
+            "Queried Lease is not active!"
+        );
+        assert_eq!(
+            lease_condition.borrower_id,
+            env::predecessor_account_id(),
+            "Only the borrower can sub-lease the NFT!"
+        );
+        assert!(
+            lease_condition.sublease_allowed,
+            "Sub-leasing is not allowed for this lease!"
+        );
+        assert!(
+            lease_condition.sublease_id.is_none(),
+            "The NFT is sub-leased!"
+        );
+        self.internal_assert_currency_allowed(&lease_condition.currency);
+        assert!(
+            env::block_timestamp() < end_ts_nano.0,
+            "Sub-lease should end in the future!"
+        );
+        assert!(
+            lease_condition.start_ts_nano <= start_ts_nano.0
+                && start_ts_nano.0 < end_ts_nano.0
+                && end_ts_nano.0 <= lease_condition.end_ts_nano,
+            "Sub-lease should be inside the parent lease!"
+        );
 
-        return results;
-    }
+        // The sub-lease inherits the usage rights of the NFT from its parent lease
+        let sublease_condition = LeaseCondition {
+            contract_addr: lease_condition.contract_addr.clone(),
+            token_id: lease_condition.token_id.clone(),
+            lender_id: lease_condition.borrower_id.clone(),
+            borrower_id: borrower_id,
+            currency: lease_condition.currency.clone(),
+            start_ts_nano: start_ts_nano.0,
+            end_ts_nano: end_ts_nano.0,
+            price: price,
+            payout: Some(Payout {
+                payout: HashMap::from([(lease_condition.borrower_id.clone(), price)]),
+            }),
+            state: LeaseState::PendingOnRent,
+            refund_on_early_return: true,
+            renewal_terms: None,
+            collateral: U128::from(0),
+            collateral_forfeited: false,
+            pending_deadline_ts_nano: env::block_timestamp() + PENDING_LEASE_TIMEOUT_NANO,
+            allowed_methods: lease_condition.allowed_methods.clone(),
+            sublease_allowed: lease_condition.sublease_allowed,
+            parent_lease_id: Some(lease_id.clone()),
+            sublease_id: None,
+            protocol_fee_bps: self.protocol_fee_bps,
+            storage_bytes: 0,
+            storage_payer_id: lease_condition.borrower_id.clone(),
+        };
 
-    pub fn leases_by_borrower(&self, account_id: AccountId) -> Vec<(String, LeaseCondition)> {
-        let mut results: Vec<(String, LeaseCondition)> = vec![];
+        // The borrower, as the lender of the sub-lease, pays for its storage
+        let sublease_id =
+            self.internal_new_lease_id(&lease_condition.contract_addr, &lease_condition.token_id);
+        self.internal_insert_lease_paying_storage(&sublease_id, sublease_condition);
 
-        let lease_ids = self
-            .lease_ids_by_borrower
-            .get(&account_id)
-            .unwrap_or(UnorderedSet::new(b"s"));
-        for id in lease_ids.iter() {
-            let lease_condition = self.lease_map.get(&id).unwrap();
-            results.push((id, lease_condition))
-        }
-        return results;
+        lease_condition.sublease_id = Some(sublease_id.clone());
+        self.lease_map.insert(&lease_id, &lease_condition);
+
+        sublease_id
     }
 
-    pub fn lease_by_contract_and_token(
-        &self,
-        contract_id: AccountId,
-        token_id: TokenId,
-    ) -> Option<(String, LeaseCondition)> {
-        let lease_id = self
-            .lease_id_by_contract_addr_and_token_id
-            .get(&(contract_id, token_id));
+    /// Books another lease of an NFT staying in this contract, e.g. for the week after its current lease.
+    /// It takes the same lease json as `nft_on_transfer`, and the lease waits for its rent in the same way.
+    /// Only the account which transferred the NFT in, e.g. the marketplace, can book for the NFT owner.
+    /// Returns false if the NFT is not in this contract, so that the caller can transfer it in instead.
+    pub fn book_lease_in_custody(&mut self, msg: String) -> bool {
+        self.internal_assert_migrated();
+        let lease_json: LeaseJson =
+            near_sdk::serde_json::from_str(&msg).expect("Invalid lease json!");
 
-        if lease_id.is_none() {
-            return None;
-        } else {
-            let lease_condition = self.lease_map.get(&lease_id.clone().unwrap()).unwrap();
-            return Some((lease_id.unwrap(), lease_condition));
-        }
+        let nft_custody = match self.nft_custodies.get(&(
+            lease_json.nft_contract_id.clone(),
+            lease_json.nft_token_id.clone(),
+        )) {
+            Some(nft_custody) => nft_custody,
+            None => return false,
+        };
+        assert_eq!(
+            nft_custody.depositor_id,
+            env::predecessor_account_id(),
+            "Only the depositor of the NFT can book its leases!"
+        );
+        assert_eq!(
+            nft_custody.owner_id, lease_json.lender_id,
+            "Only the owner of the NFT can lend it!"
+        );
+
+        self.internal_create_lease_from_json(lease_json);
+        true
     }
 
-    pub fn active_leases_by_lender(&self, account_id: AccountId) -> Vec<(String, LeaseCondition)> {
-        let mut results = vec![];
+    /// Flags an active lease, so that its collateral goes to the lender instead of the borrower at settlement.
+    /// E.g. when the borrower misused the NFT through `proxy_func_calls`.
+    /// Only the lender or the collateral arbitrator can forfeit the collateral.
+    pub fn forfeit_collateral(&mut self, lease_id: LeaseId) {
+        self.internal_assert_migrated();
+        let mut lease_condition: LeaseCondition = self.lease_map.get(&lease_id).unwrap();
 
-        let active_lease_ids = self
-            .active_lease_ids_by_lender
-            .get(&account_id)
-            .unwrap_or(UnorderedSet::new(b"s"));
-        for id in active_lease_ids.iter() {
-            let lease_condition = self.lease_map.get(&id).unwrap();
-            results.push((id, lease_condition));
+        assert_eq!(
+            lease_condition.state,
+            LeaseState::Active,
+            "Queried Lease is not active!"
+        );
+        assert!(
+            (lease_condition.lender_id == env::predecessor_account_id())
+                || (self.collateral_arbitrator_id.as_ref() == Some(&env::predecessor_account_id())),
+            "Only the lender or the arbitrator can forfeit the collateral!"
+        );
+        assert!(
+            lease_condition.collateral.0 > 0,
+            "This lease has no collateral!"
+        );
+
+        lease_condition.collateral_forfeited = true;
+        self.lease_map.insert(&lease_id, &lease_condition);
+
+        CollateralForfeited {
+            lease_id: &lease_id,
+            collateral: lease_condition.collateral,
+            forfeited_by: &env::predecessor_account_id(),
         }
-        return results;
+        .emit();
     }
 
-    #[private]
-    pub fn get_lease_by_contract_and_token(
-        &self,
-        contract_id: AccountId,
-        token_id: TokenId,
-    ) -> Option<LeaseCondition> {
-        let lease_id = self
-            .lease_id_by_contract_addr_and_token_id
-            .get(&(contract_id, token_id));
+    // Checks whether the lease has expired and can be claimed back by the caller.
+    fn internal_check_claim_back(&self, lease_id: &LeaseId) -> Result<LeaseCondition, String> {
+        let lease_condition = self
+            .lease_map
+            .get(lease_id)
+            .ok_or_else(|| "Input lease_id does not exist".to_string())?;
 
-        if lease_id.is_none() {
-            return None;
-        } else {
-            return Some(self.lease_map.get(&lease_id.unwrap()).unwrap());
+        // check expire time
+        if lease_condition.end_ts_nano >= env::block_timestamp() {
+            return Err("Lease has not expired yet!".to_string());
+        }
+        // check state == active
+        if lease_condition.state != LeaseState::Active {
+            return Err("Queried Lease is not active!".to_string());
+        }
+        // only the current lease lender or service contract owner can claim back from expried lease,
+        // until the grace period for keepers has passed
+        if lease_condition.lender_id != env::predecessor_account_id()
+            && self.owner != env::predecessor_account_id()
+            && lease_condition.end_ts_nano + self.keeper_grace_period_nano >= env::block_timestamp()
+        {
+            return Err("Only original lender or service owner can claim back!".to_string());
         }
+
+        Ok(lease_condition)
     }
 
-    pub fn get_borrower_by_contract_and_token(
+    // Whether the NFT stays in this contract once the lease is over,
+    // i.e. for the parent lease of a sub-lease, or for the other leases in the calendar of the NFT.
+    fn internal_keeps_nft_in_custody(
         &self,
-        contract_id: AccountId,
-        token_id: TokenId,
+        lease_id: &LeaseId,
+        lease_condition: &LeaseCondition,
+    ) -> bool {
+        if lease_condition.parent_lease_id.is_some() {
+            return true;
+        }
+        self.lease_ids_by_contract_addr_and_token_id
+            .get(&(
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone(),
+            ))
+            .is_some_and(|lease_ids| lease_ids.iter().any(|id| id != lease_id))
+    }
+
+    // Stops booking more leases of the NFT, as it's being transferred out of this contract.
+    fn internal_release_custody(&mut self, lease_condition: &LeaseCondition) {
+        self.nft_custodies.remove(&(
+            lease_condition.contract_addr.clone(),
+            lease_condition.token_id.clone(),
+        ));
+    }
+
+    // The caller claims back a lease as a keeper, if it's neither the lender nor the contract owner.
+    fn internal_keeper_id(&self, lease_condition: &LeaseCondition) -> Option<AccountId> {
+        let caller_id = env::predecessor_account_id();
+        if caller_id == lease_condition.lender_id || caller_id == self.owner {
+            None
+        } else {
+            Some(caller_id)
+        }
+    }
+
+    // Leases can only be created and activated in the allowed FTs, or in NEAR if allowed
+    fn internal_assert_currency_allowed(&self, currency: &Currency) {
+        match currency {
+            Currency::Near => require!(self.near_allowed, "NEAR is not allowed!"),
+            Currency::Ft(ft_contract_addr) => require!(
+                self.allowed_ft_contract_addrs.contains(ft_contract_addr),
+                "FT contract is not allowed!"
+            ),
+        }
+    }
+
+    // The metadata of an allowed currency, to display the amounts in it
+    fn internal_currency_metadata(&self, currency: &Currency) -> Option<FtMetadata> {
+        match currency {
+            Currency::Near => Some(FtMetadata {
+                symbol: "NEAR".to_string(),
+                decimals: 24,
+            }),
+            Currency::Ft(ft_contract_addr) => self.allowed_ft_metadata.get(ft_contract_addr),
+        }
+    }
+
+    fn internal_add_allowed_ft(&mut self, ft_contract_addr: &AccountId, ft_metadata: FtMetadata) {
+        if !self.allowed_ft_contract_addrs.contains(ft_contract_addr) {
+            self.allowed_ft_contract_addrs
+                .push(ft_contract_addr.clone());
+        }
+        self.allowed_ft_metadata
+            .insert(ft_contract_addr, &ft_metadata);
+    }
+
+    // The bounty for the keeper claiming back a lease with the given rent.
+    fn internal_keeper_bounty(&self, rent: u128) -> u128 {
+        utils::mul_div(rent, self.keeper_bounty_bps as u128, BPS_DENOMINATOR)
+    }
+
+    // Transfers the NFT of an expired lease back to its lender, then settles the lease.
+    fn internal_claim_back_nft(
+        &mut self,
+        lease_id: LeaseId,
+        lease_condition: &LeaseCondition,
+        keeper_id: Option<AccountId>,
+    ) -> Promise {
+        self.internal_release_custody(lease_condition);
+        ext_nft::ext(lease_condition.contract_addr.clone())
+            .with_static_gas(GAS_FOR_NFT_TRANSFER)
+            .with_attached_deposit(1)
+            .nft_transfer(
+                lease_condition.lender_id.clone(),
+                lease_condition.token_id.clone(),
+                None,
+                None,
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_attached_deposit(0)
+                    .with_static_gas(self.internal_gas_for_settlement(lease_condition))
+                    .resolve_claim_back(lease_id, keeper_id),
+            )
+    }
+
+    // The gas to settle a lease, and its sub-lease if any, in a callback.
+    // Each payout receiver, the lender, the keeper or the refunded borrower, and the collateral
+    // receiver get a transfer of their own.
+    fn internal_gas_for_settlement(&self, lease_condition: &LeaseCondition) -> Gas {
+        GAS_FOR_RESOLVE_CLAIM_BACK
+            + GAS_PER_SETTLEMENT_TRANSFER
+                * self.internal_count_settlement_transfers(lease_condition)
+    }
+
+    fn internal_count_settlement_transfers(&self, lease_condition: &LeaseCondition) -> u64 {
+        let payout_len = lease_condition
+            .payout
+            .as_ref()
+            .map_or(0, |payout| payout.payout.len() as u64);
+        let sublease_transfers = lease_condition
+            .sublease_id
+            .as_ref()
+            .and_then(|sublease_id| self.lease_map.get(sublease_id))
+            .map_or(0, |sublease_condition| {
+                self.internal_count_settlement_transfers(&sublease_condition)
+            });
+        payout_len + 3 + sublease_transfers
+    }
+
+    // Pays out the rent and the collateral of a finished lease, and its sub-lease if any.
+    // The keeper, if any, gets a bounty from the rent. Finally removes the lease.
+    fn internal_settle_lease(&mut self, lease_id: &LeaseId, keeper_id: Option<AccountId>) {
+        let lease_condition: LeaseCondition = self.lease_map.get(lease_id).unwrap();
+
+        if let Some(sublease_id) = &lease_condition.sublease_id {
+            // A pending sub-lease has nothing to pay out
+            let sublease_condition: LeaseCondition = self.lease_map.get(sublease_id).unwrap();
+            if sublease_condition.state == LeaseState::Active {
+                self.internal_settle_lease(sublease_id, None);
+            } else {
+                self.internal_remove_lease(sublease_id);
+            }
+        }
+
+        let mut rent = lease_condition.price.0;
+        let mut keeper_bounty = None;
+        if let Some(keeper_id) = &keeper_id {
+            let bounty = self.internal_keeper_bounty(rent);
+            if bounty > 0 {
+                self.internal_transfer_ft(
+                    lease_condition.currency.clone(),
+                    keeper_id.clone(),
+                    U128::from(bounty),
+                );
+                rent -= bounty;
+            }
+            keeper_bounty = Some(U128::from(bounty));
+        }
+
+        self.internal_pay_out_rent(&lease_condition, rent);
+        self.internal_settle_collateral(&lease_condition);
+
+        LeaseSettled {
+            lease_id,
+            keeper_id: keeper_id.as_ref(),
+            keeper_bounty,
+            early_return_refund: None,
+        }
+        .emit();
+        self.internal_remove_lease(lease_id);
+    }
+
+    // Removes a pending lease, whose rent has not been paid
+    fn internal_cancel_lease(&mut self, lease_id: &LeaseId) {
+        let lease_condition: LeaseCondition = self.lease_map.get(lease_id).unwrap();
+        LeaseCancelled {
+            lease_id,
+            contract_addr: &lease_condition.contract_addr,
+            token_id: &lease_condition.token_id,
+            lender_id: &lease_condition.lender_id,
+        }
+        .emit();
+
+        self.internal_remove_lease(lease_id);
+    }
+
+    // Refunds the borrower and pays out the rest of the rent of a lease returned early.
+    // Finally removes the lease.
+    fn internal_settle_early_return(&mut self, lease_id: &LeaseId, refund: u128) {
+        let lease_condition: LeaseCondition = self.lease_map.get(lease_id).unwrap();
+
+        if refund > 0 {
+            self.internal_transfer_ft(
+                lease_condition.currency.clone(),
+                lease_condition.borrower_id.clone(),
+                U128::from(refund),
+            );
+        }
+        self.internal_pay_out_rent(&lease_condition, lease_condition.price.0 - refund);
+        self.internal_settle_collateral(&lease_condition);
+
+        LeaseSettled {
+            lease_id,
+            keeper_id: None,
+            keeper_bounty: None,
+            early_return_refund: Some(U128::from(refund)),
+        }
+        .emit();
+        self.internal_remove_lease(lease_id);
+    }
+
+    // private function to return the collateral to the borrower, or to pay it to the lender if forfeited
+    fn internal_settle_collateral(&self, lease_condition: &LeaseCondition) {
+        if lease_condition.collateral.0 == 0 {
+            return;
+        }
+
+        let receiver_id = if lease_condition.collateral_forfeited {
+            lease_condition.lender_id.clone()
+        } else {
+            lease_condition.borrower_id.clone()
+        };
+        self.internal_transfer_ft(
+            lease_condition.currency.clone(),
+            receiver_id,
+            lease_condition.collateral,
+        );
+    }
+
+    // private function to calculate the rent refund, if the lease is returned at the given time.
+    // The refund is the unused share of the rent. Returning before the lease starts refunds all of it.
+    fn internal_early_return_refund(
+        &self,
+        lease_condition: &LeaseCondition,
+        returned_at_ts_nano: u64,
+    ) -> u128 {
+        if !lease_condition.refund_on_early_return
+            || returned_at_ts_nano >= lease_condition.end_ts_nano
+        {
+            return 0;
+        }
+        if returned_at_ts_nano <= lease_condition.start_ts_nano {
+            return lease_condition.price.0;
+        }
+
+        let unused_duration = lease_condition.end_ts_nano - returned_at_ts_nano;
+        let lease_duration = lease_condition.end_ts_nano - lease_condition.start_ts_nano;
+        utils::mul_div(
+            lease_condition.price.0,
+            unused_duration as u128,
+            lease_duration as u128,
+        )
+    }
+
+    // private function to pay the given amount of rent to all payout receivers
+    fn internal_pay_out_rent(&mut self, lease_condition: &LeaseCondition, amount: u128) {
+        // The protocol fee is kept in this contract until the owner withdraws it
+        let fee = self.internal_protocol_fee(lease_condition, amount);
+        if fee > 0 {
+            self.internal_accrue_fee(&lease_condition.currency, fee);
+        }
+
+        for (receiver_id, share) in self.internal_split_rent(lease_condition, amount - fee) {
+            if share > 0 {
+                self.internal_transfer_ft(
+                    lease_condition.currency.clone(),
+                    receiver_id,
+                    U128::from(share),
+                );
+            }
+        }
+    }
+
+    // The protocol fee taken from the given amount of rent of a lease
+    fn internal_protocol_fee(&self, lease_condition: &LeaseCondition, amount: u128) -> u128 {
+        utils::mul_div(
+            amount,
+            lease_condition.protocol_fee_bps as u128,
+            BPS_DENOMINATOR,
+        )
+    }
+
+    fn internal_accrue_fee(&mut self, currency: &Currency, fee: u128) {
+        let accrued_fee = self.accrued_fees.get(currency).unwrap_or(U128::from(0));
+        self.accrued_fees
+            .insert(currency, &U128::from(accrued_fee.0 + fee));
+    }
+
+    // private function to split the given amount of rent in proportion to the lease payout.
+    // Rounding leftovers go to the lender. Without a payout, all goes to the lender.
+    fn internal_split_rent(
+        &self,
+        lease_condition: &LeaseCondition,
+        amount: u128,
+    ) -> HashMap<AccountId, u128> {
+        let mut shares: HashMap<AccountId, u128> = HashMap::new();
+        let mut remaining = amount;
+
+        if let Some(payout) = &lease_condition.payout {
+            if lease_condition.price.0 > 0 {
+                for (receiver_id, payout_amount) in payout.payout.iter() {
+                    let share = utils::mul_div(payout_amount.0, amount, lease_condition.price.0);
+                    remaining -= share;
+                    *shares.entry(receiver_id.clone()).or_insert(0) += share;
+                }
+            }
+        }
+        *shares.entry(lease_condition.lender_id.clone()).or_insert(0) += remaining;
+
+        shares
+    }
+
+    // private function to transfer FT, or NEAR for a lease paid in NEAR, to receiver_id.
+    // A failed transfer is recorded as unclaimed, for the receiver to withdraw later.
+    fn internal_transfer_ft(
+        &self,
+        currency: Currency,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> Promise {
+        internal_send(&currency, &receiver_id, amount).then(
+            ext_self::ext(env::current_account_id())
+                .with_attached_deposit(0)
+                .with_static_gas(GAS_FOR_RESOLVE_TRANSFER_FT)
+                .resolve_transfer_ft(currency, receiver_id, amount),
+        )
+    }
+
+    // Adds to the unclaimed balance of the account in the given currency
+    fn internal_add_unclaimed(
+        &mut self,
+        account_id: &AccountId,
+        currency: &Currency,
+        amount: u128,
+    ) {
+        let key = (account_id.clone(), currency.clone());
+        let balance = self.unclaimed_balances.get(&key).unwrap_or(U128::from(0));
+        self.unclaimed_balances
+            .insert(&key, &U128::from(balance.0 + amount));
+    }
+
+    /// Returns all leases, paginated over the lease map.
+    pub fn leases(
+        &self,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<(String, LeaseCondition)> {
+        self.internal_assert_migrated();
+        self.internal_paginate_leases(
+            self.lease_map.keys_as_vector().iter(),
+            from_index,
+            limit,
+            None,
+        )
+    }
+
+    pub fn lease_by_id(&self, lease_id: LeaseId) -> Option<LeaseCondition> {
+        self.internal_assert_migrated();
+        self.lease_map.get(&lease_id)
+    }
+
+    /// Returns the leases lent by the account.
+    /// `from_index` and `limit` page through its leases matching the filter.
+    pub fn leases_by_owner(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+        filter: Option<LeaseFilter>,
+    ) -> Vec<(String, LeaseCondition)> {
+        self.internal_assert_migrated();
+        match self.lease_ids_by_lender.get(&account_id) {
+            Some(lease_ids) => {
+                self.internal_paginate_leases(lease_ids.iter(), from_index, limit, filter)
+            }
+            None => vec![],
+        }
+    }
+
+    /// Returns the leases borrowed by the account. Paginated and filtered as `leases_by_owner`.
+    pub fn leases_by_borrower(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+        filter: Option<LeaseFilter>,
+    ) -> Vec<(String, LeaseCondition)> {
+        self.internal_assert_migrated();
+        match self.lease_ids_by_borrower.get(&account_id) {
+            Some(lease_ids) => {
+                self.internal_paginate_leases(lease_ids.iter(), from_index, limit, filter)
+            }
+            None => vec![],
+        }
+    }
+
+    /// Returns the lease of the NFT covering the current time. Otherwise the next lease, or the last one.
+    pub fn lease_by_contract_and_token(
+        &self,
+        contract_id: AccountId,
+        token_id: TokenId,
+    ) -> Option<(String, LeaseCondition)> {
+        self.internal_assert_migrated();
+        let lease_id = self.internal_current_lease_id(&contract_id, &token_id);
+
+        if lease_id.is_none() {
+            return None;
+        } else {
+            let lease_condition = self.lease_map.get(&lease_id.clone().unwrap()).unwrap();
+            return Some((lease_id.unwrap(), lease_condition));
+        }
+    }
+
+    /// Returns the calendar of the NFT, i.e. all its leases sorted by start time.
+    pub fn leases_by_contract_and_token(
+        &self,
+        contract_id: AccountId,
+        token_id: TokenId,
+    ) -> Vec<(String, LeaseCondition)> {
+        self.internal_assert_migrated();
+        self.lease_ids_by_contract_addr_and_token_id
+            .get(&(contract_id, token_id))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|id| {
+                let lease_condition = self.lease_map.get(&id).unwrap();
+                (id, lease_condition)
+            })
+            .collect()
+    }
+
+    /// Returns the active leases lent by the account. Paginated and filtered as `leases_by_owner`.
+    pub fn active_leases_by_lender(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+        filter: Option<LeaseFilter>,
+    ) -> Vec<(String, LeaseCondition)> {
+        self.internal_assert_migrated();
+        match self.active_lease_ids_by_lender.get(&account_id) {
+            Some(lease_ids) => {
+                self.internal_paginate_leases(lease_ids.iter(), from_index, limit, filter)
+            }
+            None => vec![],
+        }
+    }
+
+    #[private]
+    pub fn get_lease_by_contract_and_token(
+        &self,
+        contract_id: AccountId,
+        token_id: TokenId,
+    ) -> Option<LeaseCondition> {
+        self.internal_assert_migrated();
+        let lease_id = self.internal_current_lease_id(&contract_id, &token_id);
+
+        if lease_id.is_none() {
+            return None;
+        } else {
+            return Some(self.lease_map.get(&lease_id.unwrap()).unwrap());
+        }
+    }
+
+    pub fn get_borrower_by_contract_and_token(
+        &self,
+        contract_id: AccountId,
+        token_id: TokenId,
     ) -> Option<AccountId> {
+        self.internal_assert_migrated();
         // return the current borrower of the NFTs
         // Only active lease has valid borrower
 
@@ -385,6 +1427,7 @@ impl Contract {
         contract_id: AccountId,
         token_id: TokenId,
     ) -> Option<AccountId> {
+        self.internal_assert_migrated();
         // return the current user of the NFTs
         // The current user of an active lease is the borrower, otherwise it is the lender
 
@@ -396,1398 +1439,4961 @@ impl Contract {
         );
 
         let lease_condition = lease_condition_option.unwrap();
-        if lease_condition.state == LeaseState::Active
+        return Some(self.internal_current_user(&lease_condition));
+    }
+
+    // The lease of the NFT covering the current time. Otherwise the next lease, or the last one.
+    fn internal_current_lease_id(
+        &self,
+        contract_id: &AccountId,
+        token_id: &TokenId,
+    ) -> Option<LeaseId> {
+        let lease_ids = self
+            .lease_ids_by_contract_addr_and_token_id
+            .get(&(contract_id.clone(), token_id.clone()))?;
+        let now = env::block_timestamp();
+
+        let mut last_lease_id = None;
+        for lease_id in lease_ids {
+            let lease_condition = self.lease_map.get(&lease_id).unwrap();
+            if now <= lease_condition.end_ts_nano {
+                return Some(lease_id);
+            }
+            last_lease_id = Some(lease_id);
+        }
+        last_lease_id
+    }
+
+    // The current user of an active lease is its borrower, or the current user of its sub-lease if any.
+    // Otherwise it is the lender.
+    fn internal_current_user(&self, lease_condition: &LeaseCondition) -> AccountId {
+        if !(lease_condition.state == LeaseState::Active
             && lease_condition.start_ts_nano < env::block_timestamp()
-            && lease_condition.end_ts_nano > env::block_timestamp()
+            && lease_condition.end_ts_nano > env::block_timestamp())
         {
-            return Some(lease_condition.borrower_id);
-        } else {
-            return Some(lease_condition.lender_id);
+            return lease_condition.lender_id.clone();
+        }
+
+        match lease_condition
+            .sublease_id
+            .as_ref()
+            .and_then(|sublease_id| self.lease_map.get(sublease_id))
+        {
+            Some(sublease_condition) => self.internal_current_user(&sublease_condition),
+            None => lease_condition.borrower_id.clone(),
         }
     }
 
-    pub fn set_allowed_ft_contract_addrs(&mut self, addrs: Vec<AccountId>) {
+    /// Adds an FT to the allowlist of rent currencies, once its metadata has been fetched from its contract.
+    pub fn add_allowed_ft_contract_addr(&mut self, ft_contract_addr: AccountId) -> Promise {
+        self.internal_assert_migrated();
         assert_eq!(
             env::predecessor_account_id(),
             self.owner,
-            "Only the owner can set allowed FT contracts"
+            "Only the owner can add allowed FT contracts"
         );
 
-        self.allowed_ft_contract_addrs = addrs
+        ext_ft_metadata::ext(ft_contract_addr.clone())
+            .with_static_gas(XCC_GAS)
+            .ft_metadata()
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_attached_deposit(0)
+                    .with_static_gas(BASE_GAS)
+                    .resolve_add_allowed_ft_contract_addr(ft_contract_addr),
+            )
     }
 
-    pub fn get_allowed_ft_contract_addrs(&self) -> Vec<AccountId> {
-        self.allowed_ft_contract_addrs.clone()
-    }
+    /// Adds the FT to the allowlist, if its metadata has been fetched. Returns whether it's been added.
+    #[private]
+    pub fn resolve_add_allowed_ft_contract_addr(&mut self, ft_contract_addr: AccountId) -> bool {
+        let ft_metadata = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                near_sdk::serde_json::from_slice::<FungibleTokenMetadata>(&value).ok()
+            }
+            _ => None,
+        };
 
-    pub fn proxy_func_calls(&self, contract_id: AccountId, method_name: String, args: String) {
-        // proxy function to open accessible functions calls in a NFT contract during lease
-        let promise = Promise::new(contract_id.clone());
+        match ft_metadata {
+            Some(ft_metadata) => {
+                self.internal_add_allowed_ft(
+                    &ft_contract_addr,
+                    FtMetadata {
+                        symbol: ft_metadata.symbol,
+                        decimals: ft_metadata.decimals,
+                    },
+                );
+                true
+            }
+            None => {
+                FtMetadataFetchFailed {
+                    ft_contract_addr: &ft_contract_addr,
+                }
+                .emit();
+                false
+            }
+        }
+    }
 
-        // TODO: allow the lend to define white list of method names.
-        // unreachable methods in leased NFT contract
-        assert_ne!(
-            "nft_transfer", &method_name,
-            "Calling method is not accessiable!"
-        );
-        assert_ne!(
-            "nft_approve", &method_name,
-            "Calling method is not accessiable!"
+    pub fn remove_allowed_ft_contract_addr(&mut self, ft_contract_addr: AccountId) {
+        self.internal_assert_migrated();
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only the owner can remove allowed FT contracts"
         );
 
-        promise.function_call(
-            method_name.clone(),
-            args.into(),
-            env::attached_deposit(),
-            Gas(5 * TGAS),
-        );
+        let index = self
+            .allowed_ft_contract_addrs
+            .iter()
+            .position(|addr| *addr == ft_contract_addr)
+            .expect("FT contract is not allowed!");
+        self.allowed_ft_contract_addrs.remove(index);
+        self.allowed_ft_metadata.remove(&ft_contract_addr);
     }
 
-    // internal function to create a lease based on given info
-    fn create_lease_with_payout(
-        &mut self,
-        nft_contract_id: AccountId,
-        nft_token_id: TokenId,
-        owner_id: AccountId,
-        borrower_id: AccountId,
-        ft_contract_addr: AccountId,
-        start_ts_nano: u64,
-        end_ts_nano: u64,
-        price: U128,
-        nft_payout: Payout,
-    ) {
-        // build lease condition from the parsed json
-        let lease_condition: LeaseCondition = LeaseCondition {
-            contract_addr: nft_contract_id,
-            token_id: nft_token_id,
-            lender_id: owner_id.clone(),
-            borrower_id: borrower_id,
-            ft_contract_addr: ft_contract_addr,
-            price: price,
-            start_ts_nano: start_ts_nano,
-            end_ts_nano: end_ts_nano,
-            payout: Some(nft_payout),
-            state: LeaseState::PendingOnRent,
-        };
-
-        let seed = near_sdk::env::random_seed();
-        let lease_id = bs58::encode(seed)
-            .with_alphabet(bs58::Alphabet::BITCOIN)
-            .into_string();
+    /// Allows or disallows the rent of new leases to be paid in native NEAR.
+    pub fn set_near_allowed(&mut self, near_allowed: bool) {
+        self.internal_assert_migrated();
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only the owner can allow NEAR"
+        );
 
-        self.internal_insert_lease(&lease_id, &lease_condition);
+        self.near_allowed = near_allowed
     }
 
-    // helper method to remove records of a lease
-    fn internal_remove_lease(&mut self, lease_id: &LeaseId) {
-        // check if a lease condition exist
-        let lease_condition = self
-            .lease_map
-            .get(&lease_id)
-            .expect("Input lease_id does not exist");
+    pub fn is_near_allowed(&self) -> bool {
+        self.near_allowed
+    }
 
-        // remove lease map record
-        self.lease_map.remove(&lease_id);
+    pub fn get_state_version(&self) -> u8 {
+        self.state_version
+    }
 
-        // remove from index by_lender
-        let mut lease_set = self
-            .lease_ids_by_lender
-            .get(&lease_condition.lender_id)
-            .unwrap();
-        lease_set.remove(&lease_id);
+    pub fn get_allowed_ft_contract_addrs(&self) -> Vec<AccountId> {
+        self.allowed_ft_contract_addrs.clone()
+    }
 
-        if lease_set.is_empty() {
-            self.lease_ids_by_lender.remove(&lease_condition.lender_id);
-        } else {
-            self.lease_ids_by_lender
-                .insert(&lease_condition.lender_id, &lease_set);
-        }
+    pub fn get_allowed_ft_metadata(&self, ft_contract_addr: AccountId) -> Option<FtMetadata> {
+        self.allowed_ft_metadata.get(&ft_contract_addr)
+    }
 
-        // remove from index by_borrower
-        let mut lease_set = self
-            .lease_ids_by_borrower
-            .get(&lease_condition.borrower_id)
-            .unwrap();
-        lease_set.remove(&lease_id);
+    pub fn set_collateral_arbitrator(&mut self, arbitrator_id: Option<AccountId>) {
+        self.internal_assert_migrated();
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only the owner can set the collateral arbitrator"
+        );
 
-        if lease_set.is_empty() {
-            self.lease_ids_by_borrower
-                .remove(&lease_condition.borrower_id);
-        } else {
-            self.lease_ids_by_borrower
-                .insert(&lease_condition.borrower_id, &lease_set);
-        }
+        self.collateral_arbitrator_id = arbitrator_id
+    }
 
-        // remove from index by_contract_addr_and_token_id
-        self.lease_id_by_contract_addr_and_token_id
-            .remove(&(lease_condition.contract_addr, lease_condition.token_id));
+    pub fn get_collateral_arbitrator(&self) -> Option<AccountId> {
+        self.collateral_arbitrator_id.clone()
+    }
 
-        // Clean up NFT related fields
-        // update active leases set
-        self.active_lease_ids.remove(&lease_id);
+    pub fn set_max_proxy_call_gas(&mut self, gas: U64) {
+        self.internal_assert_migrated();
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only the owner can set the max proxy call gas"
+        );
 
-        // update active_lease_ids_by_lender
-        let mut active_lease_id_set = self
-            .active_lease_ids_by_lender
-            .get(&lease_condition.lender_id);
+        self.max_proxy_call_gas = Gas(gas.0)
+    }
 
-        if let Some(active_lease_id_set) = active_lease_id_set.as_mut() {
-            active_lease_id_set.remove(&lease_id);
+    pub fn get_max_proxy_call_gas(&self) -> U64 {
+        U64::from(self.max_proxy_call_gas.0)
+    }
 
-            if active_lease_id_set.is_empty() {
-                self.active_lease_ids_by_lender
-                    .remove(&lease_condition.lender_id);
-            } else {
-                self.active_lease_ids_by_lender
-                    .insert(&lease_condition.lender_id, &active_lease_id_set);
-            }
-        }
+    pub fn set_keeper_grace_period(&mut self, grace_period_nano: U64) {
+        self.internal_assert_migrated();
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only the owner can set the keeper grace period"
+        );
 
-        // Event Log to capture token burning as per the Events standard
-        let token_id = self.lease_id_to_lease_token_id(&lease_id);
-        NftBurn {
-            owner_id: &lease_condition.lender_id,
-            token_ids: &[&token_id],
-            authorized_id: None,
-            memo: None,
-        }
-        .emit()
+        self.keeper_grace_period_nano = grace_period_nano.0
     }
 
-    // helper method to insert a new lease and update all indices
-    fn internal_insert_lease(&mut self, lease_id: &LeaseId, lease_condition: &LeaseCondition) {
-        // insert into lease map
-        self.lease_map.insert(&lease_id, &lease_condition);
+    pub fn get_keeper_grace_period(&self) -> U64 {
+        U64::from(self.keeper_grace_period_nano)
+    }
 
-        //update index for leases by lender. If there are none, create a new empty set
-        let mut lease_ids_set = self
-            .lease_ids_by_lender
-            .get(&lease_condition.lender_id)
-            .unwrap_or_else(|| {
-                UnorderedSet::new(
-                    StorageKey::LeasesIdsByLenderInner {
-                        // get a new unique prefix for the collection by hashing owner
-                        account_id_hash: utils::hash_account_id(&lease_condition.lender_id),
-                    }
-                    .try_to_vec()
-                    .unwrap(),
-                )
-            });
-        lease_ids_set.insert(&lease_id);
-        self.lease_ids_by_lender
-            .insert(&lease_condition.lender_id, &lease_ids_set);
+    pub fn set_keeper_bounty_bps(&mut self, bounty_bps: u16) {
+        self.internal_assert_migrated();
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only the owner can set the keeper bounty"
+        );
+        assert!(
+            bounty_bps <= MAX_KEEPER_BOUNTY_BPS,
+            "Keeper bounty is too large!"
+        );
 
-        // update index for leases by borrower. If there are none, create a new empty set
-        let mut lease_ids_set = self
-            .lease_ids_by_borrower
-            .get(&lease_condition.borrower_id)
-            .unwrap_or_else(|| {
-                UnorderedSet::new(
-                    StorageKey::LeaseIdsByBorrowerInner {
-                        // get a new unique prefix for the collection by hashing owner
-                        account_id_hash: utils::hash_account_id(&lease_condition.borrower_id),
-                    }
-                    .try_to_vec()
-                    .unwrap(),
-                )
-            });
-        lease_ids_set.insert(&lease_id);
-        self.lease_ids_by_borrower
-            .insert(&lease_condition.borrower_id, &lease_ids_set);
+        self.keeper_bounty_bps = bounty_bps
+    }
 
-        // update index for lease_id_by_contract_addr_and_token_id
-        self.lease_id_by_contract_addr_and_token_id.insert(
-            &(
-                lease_condition.contract_addr.clone(),
-                lease_condition.token_id.clone(),
-            ),
-            &lease_id,
-        );
-
-        // log lease insertion
-        env::log_str(
-            &json!({
-                "type": "[INFO] NiFTyRent Rental: A new lease has been inserted.",
-                "params": {
-                    "lease_id": lease_id.clone(),
-                    "nft_contract_id": lease_condition.contract_addr.clone(),
-                    "nft_token_id": lease_condition.token_id.clone(),
-                    "lender": lease_condition.lender_id.clone(),
-                    "borrower": lease_condition.borrower_id.clone(),
-                    "lease_state": lease_condition.state,
-                }
-            })
-            .to_string(),
+    pub fn get_keeper_bounty_bps(&self) -> u16 {
+        self.keeper_bounty_bps
+    }
+
+    pub fn set_protocol_fee_bps(&mut self, fee_bps: u16) {
+        self.internal_assert_migrated();
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only the owner can set the protocol fee"
         );
+        assert!(
+            fee_bps <= MAX_PROTOCOL_FEE_BPS,
+            "Protocol fee is too large!"
+        );
+
+        self.protocol_fee_bps = fee_bps
     }
 
-    /// This function updates only the lender info in an active lease
-    /// All affecting indices will be updated
-    fn internal_update_active_lease_lender(
-        &mut self,
-        old_lender: &AccountId,
-        new_lender: &AccountId,
-        lease_id: &LeaseId,
-    ) {
-        // 1. Check if the active lease exist
+    pub fn get_protocol_fee_bps(&self) -> u16 {
+        self.protocol_fee_bps
+    }
+
+    pub fn set_fee_receiver(&mut self, fee_receiver_id: AccountId) {
+        self.internal_assert_migrated();
         assert_eq!(
-            self.active_lease_ids.contains(lease_id),
-            true,
-            "Only active lease can update lender!"
+            env::predecessor_account_id(),
+            self.owner,
+            "Only the owner can set the fee receiver"
         );
 
-        // 2. Ensure the given active lease belongs to the old owner
-        let mut active_lease_ids_set = self
-            .active_lease_ids_by_lender
-            .get(old_lender)
-            .expect("Active Lease is not owned by the old lender!");
+        self.fee_receiver_id = fee_receiver_id
+    }
 
-        // 3. Remove the active lease from the old lender
-        // update index for active lease ids
-        active_lease_ids_set.remove(lease_id);
-        if active_lease_ids_set.is_empty() {
-            self.active_lease_ids_by_lender.remove(old_lender);
-        } else {
-            self.active_lease_ids_by_lender
-                .insert(old_lender, &active_lease_ids_set);
-        }
-        // Update the index for lease ids by lender for old lender
-        let mut lease_ids_set = self.lease_ids_by_lender.get(old_lender).unwrap();
-        lease_ids_set.remove(lease_id);
-        if lease_ids_set.is_empty() {
-            self.lease_ids_by_lender.remove(old_lender);
-        } else {
-            self.lease_ids_by_lender.insert(old_lender, &lease_ids_set);
+    pub fn get_fee_receiver(&self) -> AccountId {
+        self.fee_receiver_id.clone()
+    }
+
+    /// Sets the royalty policy of the LEASE token sales, replacing the royalty split of the leased NFTs.
+    /// The rest of the sale price goes to the seller, i.e. the current lender.
+    pub fn set_lease_token_royalty_bps(&mut self, royalty_bps: Option<HashMap<AccountId, u16>>) {
+        self.internal_assert_migrated();
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only the owner can set the LEASE token royalty"
+        );
+        if let Some(royalty_bps) = &royalty_bps {
+            assert!(
+                (royalty_bps.len() as u32) < MAX_LEN_PAYOUT,
+                "Too many LEASE token royalty receivers!"
+            );
+            assert!(
+                royalty_bps.values().map(|bps| *bps as u32).sum::<u32>()
+                    <= MAX_LEASE_TOKEN_ROYALTY_BPS as u32,
+                "LEASE token royalty is too large!"
+            );
         }
 
-        // 4. Add the active lease to the new lender
-        // update the index for active lease ids
-        let mut active_lease_ids_set = self
-            .active_lease_ids_by_lender
-            .get(new_lender)
-            .unwrap_or_else(|| {
-                // if the new lender doesn't have any active lease, create a new record
-                UnorderedSet::new(
-                    StorageKey::ActiveLeaseIdsByOwnerInner {
-                        account_id_hash: utils::hash_account_id(new_lender),
-                    }
-                    .try_to_vec()
-                    .unwrap(),
-                )
-            });
-        active_lease_ids_set.insert(lease_id);
-        self.active_lease_ids_by_lender
-            .insert(new_lender, &active_lease_ids_set);
-        // Udpate the index for lease ids by lender for new lender
-        let mut lease_ids_set = self.lease_ids_by_lender.get(new_lender).unwrap_or_else(|| {
-            // if the receiver doesn;t have any lease, create a new record
-            UnorderedSet::new(
-                StorageKey::LeasesIdsByLenderInner {
-                    account_id_hash: utils::hash_account_id(new_lender),
-                }
-                .try_to_vec()
-                .unwrap(),
-            )
-        });
-        lease_ids_set.insert(lease_id);
-        self.lease_ids_by_lender.insert(new_lender, &lease_ids_set);
+        self.lease_token_royalty_bps = royalty_bps
+    }
 
-        // 5. Update the lease map index accordingly
-        let mut lease_condition = self.lease_map.get(lease_id).unwrap();
-        lease_condition.lender_id = new_lender.clone();
-        self.lease_map.insert(&lease_id, &lease_condition); // insert data back to persis the value
+    pub fn get_lease_token_royalty_bps(&self) -> Option<HashMap<AccountId, u16>> {
+        self.lease_token_royalty_bps.clone()
     }
-}
 
-/**
- * Trait that will handle the receival of the leasing NFT.
- * When the Marketplace calls nft_transfer_call on NFT contract, the NFT contract
- * will invoke this function.
-*/
-trait NonFungibleTokenTransferReceiver {
-    fn nft_on_transfer(
-        &mut self,
-        sender_id: AccountId,
-        previous_owner_id: AccountId,
-        token_id: TokenId,
-        msg: String,
-    ) -> PromiseOrValue<bool>;
-}
+    pub fn get_accrued_fees(&self, currency: Currency) -> U128 {
+        self.accrued_fees.get(&currency).unwrap_or(U128::from(0))
+    }
 
-#[near_bindgen]
-impl NonFungibleTokenTransferReceiver for Contract {
-    /**
-     * 1. Check NFT transfer is successful
-     * 2. Create proxy payouts if not supported
-     * 3. Create a lease
-     */
-    fn nft_on_transfer(
-        &mut self,
-        sender_id: AccountId,
-        previous_owner_id: AccountId,
-        token_id: TokenId,
-        msg: String,
-    ) -> PromiseOrValue<bool> {
-        // Enforce cross contract call
-        let nft_contract_id = env::predecessor_account_id();
-        assert_ne!(
-            env::current_account_id(),
-            nft_contract_id,
-            "nft_on_transfer should only be called via XCC."
-        );
+    /// The full rent of the given lease in a human-readable form, e.g. "1.5 USDC".
+    /// Without the metadata of its FT, the raw amount is followed by the FT contract address instead.
+    pub fn get_readable_price_by_lease(&self, lease_id: LeaseId) -> String {
+        self.internal_assert_migrated();
+        let lease_condition: LeaseCondition = self
+            .lease_map
+            .get(&lease_id)
+            .expect("Input lease_id does not exist");
 
-        let lease_json: LeaseJson =
-            near_sdk::serde_json::from_str(&msg).expect("Invalid lease json!");
+        match self.internal_currency_metadata(&lease_condition.currency) {
+            Some(ft_metadata) => format!(
+                "{} {}",
+                utils::format_amount(lease_condition.price.0, ft_metadata.decimals),
+                ft_metadata.symbol
+            ),
+            None => format!("{} {}", lease_condition.price.0, lease_condition.currency),
+        }
+    }
 
-        // Enforce the leasing token is the same as the transferring token
-        assert_eq!(nft_contract_id, lease_json.nft_contract_id);
-        assert_eq!(token_id, lease_json.nft_token_id);
+    /// The protocol fee to be taken from the full rent of the given lease
+    pub fn get_protocol_fee_by_lease(&self, lease_id: LeaseId) -> U128 {
+        self.internal_assert_migrated();
+        let lease_condition: LeaseCondition = self
+            .lease_map
+            .get(&lease_id)
+            .expect("Input lease_id does not exist");
+
+        U128::from(self.internal_protocol_fee(&lease_condition, lease_condition.price.0))
+    }
+
+    /// Transfers all the accrued protocol fees in the given currency to the fee receiver.
+    #[payable]
+    pub fn withdraw_fees(&mut self, currency: Currency) -> Promise {
+        self.internal_assert_migrated();
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only the owner can withdraw the fees"
+        );
+
+        let amount = self
+            .accrued_fees
+            .remove(&currency)
+            .expect("No fees to withdraw!");
+
+        internal_send(&currency, &self.fee_receiver_id, amount).then(
+            ext_self::ext(env::current_account_id())
+                .with_attached_deposit(0)
+                .with_static_gas(BASE_GAS)
+                .resolve_withdraw_fees(currency, amount),
+        )
+    }
+
+    /// Records the amount as unclaimed by the receiver, if the FT transfer failed.
+    #[private]
+    pub fn resolve_transfer_ft(
+        &mut self,
+        currency: Currency,
+        receiver_id: AccountId,
+        amount: U128,
+    ) {
+        if is_promise_success() {
+            PayoutSent {
+                currency: &currency,
+                receiver_id: &receiver_id,
+                amount,
+            }
+            .emit();
+            return;
+        }
+
+        self.internal_add_unclaimed(&receiver_id, &currency, amount.0);
+
+        PayoutFailed {
+            currency: &currency,
+            receiver_id: &receiver_id,
+            amount,
+        }
+        .emit();
+    }
+
+    /// Withdraws the caller's payments in the given currency, which failed to be transferred before.
+    pub fn withdraw_unclaimed(&mut self, currency: Currency) -> Promise {
+        self.internal_assert_migrated();
+        let account_id = env::predecessor_account_id();
+        let amount = self
+            .unclaimed_balances
+            .remove(&(account_id.clone(), currency.clone()))
+            .expect("No unclaimed balance to withdraw!");
+
+        // If it fails again, the amount will be recorded as unclaimed again
+        self.internal_transfer_ft(currency, account_id, amount)
+    }
+
+    pub fn get_unclaimed_balance(&self, account_id: AccountId, currency: Currency) -> U128 {
+        self.unclaimed_balances
+            .get(&(account_id, currency))
+            .unwrap_or(U128::from(0))
+    }
+
+    /// Puts the fees back, if they failed to be transferred to the fee receiver.
+    #[private]
+    pub fn resolve_withdraw_fees(&mut self, currency: Currency, amount: U128) {
+        if !is_promise_success() {
+            self.internal_accrue_fee(&currency, amount.0);
+        }
+    }
+
+    /// Proxy function for the borrower to call methods of the leased NFT contract during the lease,
+    /// e.g. to use the NFT in a game. Only the methods allowed by the lender can be called.
+    /// The outcome of the proxied call, with its result if any, is returned to the caller.
+    /// `gas` is capped by both the lender's limit for the method and the contract wide limit.
+    #[payable]
+    pub fn proxy_func_calls(
+        &mut self,
+        lease_id: LeaseId,
+        method_name: String,
+        args: String,
+        gas: Option<U64>,
+    ) -> Promise {
+        self.internal_assert_migrated();
+        let lease_condition: LeaseCondition = self
+            .lease_map
+            .get(&lease_id)
+            .expect("Input lease_id does not exist");
+
+        // 1. only the borrower of an ongoing lease can make proxy calls
+        assert_eq!(
+            lease_condition.state,
+            LeaseState::Active,
+            "Queried Lease is not active!"
+        );
+        assert_eq!(
+            lease_condition.borrower_id,
+            env::predecessor_account_id(),
+            "Only the borrower can make proxy calls!"
+        );
+        assert!(
+            lease_condition.start_ts_nano < env::block_timestamp()
+                && env::block_timestamp() < lease_condition.end_ts_nano,
+            "Proxy calls are only allowed during the lease!"
+        );
+        assert_eq!(
+            self.internal_current_user(&lease_condition),
+            lease_condition.borrower_id,
+            "The NFT is sub-leased to another user!"
+        );
+
+        // 2. methods moving the leased NFT are never reachable, even if allowed by mistake
+        assert_ne!(
+            "nft_transfer", &method_name,
+            "Calling method is not accessiable!"
+        );
+        assert_ne!(
+            "nft_approve", &method_name,
+            "Calling method is not accessiable!"
+        );
+
+        // 3. check the method against the allowlist of the lender
+        let allowed_method = lease_condition
+            .allowed_methods
+            .iter()
+            .find(|allowed_method| allowed_method.method_name == method_name)
+            .expect("Calling method is not accessiable!");
+        if let Some(max_deposit) = allowed_method.max_deposit {
+            assert!(
+                env::attached_deposit() <= max_deposit.0,
+                "Attached deposit exceeds the allowed maximum!"
+            );
+        }
+        let max_gas = match allowed_method.max_gas {
+            Some(method_max_gas) => std::cmp::min(self.max_proxy_call_gas, Gas(method_max_gas.0)),
+            None => self.max_proxy_call_gas,
+        };
+        let gas = match gas {
+            Some(gas) => {
+                assert!(
+                    Gas(gas.0) <= max_gas,
+                    "Requested gas exceeds the allowed maximum!"
+                );
+                Gas(gas.0)
+            }
+            None => std::cmp::min(GAS_FOR_PROXY_CALL, max_gas),
+        };
+
+        // 4. make the call and pass its result back to the caller
+        Promise::new(lease_condition.contract_addr)
+            .function_call(
+                method_name.clone(),
+                args.into(),
+                env::attached_deposit(),
+                gas,
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_attached_deposit(0)
+                    .with_static_gas(GAS_FOR_RESOLVE_PROXY_CALL)
+                    .resolve_proxy_func_calls(
+                        lease_id,
+                        method_name,
+                        lease_condition.borrower_id,
+                        U128::from(env::attached_deposit()),
+                    ),
+            )
+    }
+
+    /// Records the outcome of a proxied call for auditing, and returns it to the caller.
+    /// A failed proxied call is returned as such, rather than failing this callback.
+    /// Its deposit is refunded to this contract then, so it's passed on to the borrower.
+    #[private]
+    pub fn resolve_proxy_func_calls(
+        &mut self,
+        lease_id: LeaseId,
+        method_name: String,
+        borrower_id: AccountId,
+        deposit: U128,
+    ) -> ProxyCallResult {
+        let result = match env::promise_result(0) {
+            PromiseResult::Successful(value) => Some(Base64VecU8::from(value)),
+            _ => None,
+        };
+        if result.is_none() && deposit.0 > 0 {
+            Promise::new(borrower_id).transfer(deposit.0);
+        }
+
+        ProxyCall {
+            lease_id: &lease_id,
+            method_name: &method_name,
+            success: result.is_some(),
+        }
+        .emit();
+
+        ProxyCallResult {
+            success: result.is_some(),
+            result,
+        }
+    }
+
+    // internal function to activate the lease the rent is paid for
+    fn internal_accept_rent(
+        &mut self,
+        currency: Currency,
+        amount: U128,
+        rent_acceptance_json: RentAcceptanceJson,
+    ) {
+        // Find the targeting lease in the calendar of the NFT
+        let lease_ids = self
+            .lease_ids_by_contract_addr_and_token_id
+            .get(&(
+                rent_acceptance_json.nft_contract_id,
+                rent_acceptance_json.nft_token_id,
+            ))
+            .unwrap_or_default();
+        let lease_id = match rent_acceptance_json.start_ts_nano {
+            Some(start_ts_nano) => lease_ids
+                .into_iter()
+                .find(|id| self.lease_map.get(id).unwrap().start_ts_nano == start_ts_nano),
+            None => {
+                require!(
+                    lease_ids.len() <= 1,
+                    "The start of the lease is required, as the NFT has many leases!"
+                );
+                lease_ids.into_iter().next()
+            }
+        }
+        .expect("The targeting lease does not exist!");
+        let lease_condition = self.lease_map.get(&lease_id).unwrap();
+
+        // Enforce the currency matches, and is still allowed
+        assert_eq!(
+            currency, lease_condition.currency,
+            "Wrong FT contract address!"
+        );
+        self.internal_assert_currency_allowed(&currency);
+
+        // Enforce the rent amount matches. The collateral is paid together with the rent.
+        assert_eq!(
+            amount.0,
+            lease_condition.price.0 + lease_condition.collateral.0,
+            "Transferred amount doesn't match the asked rent!"
+        );
+
+        // Update the lease state accordingly
+        assert_eq!(
+            lease_condition.state,
+            LeaseState::PendingOnRent,
+            "This lease is not pending on rent!"
+        );
+
+        // Late rent is rejected, as the lease may be cancelled at any time after the deadline
+        assert!(
+            env::block_timestamp() <= lease_condition.pending_deadline_ts_nano,
+            "This lease has timed out waiting for the rent!"
+        );
+
+        self.activate_lease(lease_id);
+    }
+
+    // internal function to activate the sub-lease the rent is paid for
+    fn internal_accept_sublease_rent(
+        &mut self,
+        currency: Currency,
+        sender_id: AccountId,
+        amount: U128,
+        sublease_rent_json: SubleaseRentJson,
+    ) {
+        let sublease_id = sublease_rent_json.sublease_id;
+        let sublease_condition = self
+            .lease_map
+            .get(&sublease_id)
+            .expect("The targeting lease does not exist!");
+
+        assert!(
+            sublease_condition.parent_lease_id.is_some(),
+            "The targeting lease is not a sub-lease!"
+        );
+        assert_eq!(
+            sender_id, sublease_condition.borrower_id,
+            "Only the borrower can pay for the sub-lease!"
+        );
+        assert_eq!(
+            currency, sublease_condition.currency,
+            "Wrong FT contract address!"
+        );
+        self.internal_assert_currency_allowed(&currency);
+        assert_eq!(
+            amount.0, sublease_condition.price.0,
+            "Transferred amount doesn't match the asked rent!"
+        );
+        assert_eq!(
+            sublease_condition.state,
+            LeaseState::PendingOnRent,
+            "This lease is not pending on rent!"
+        );
+        assert!(
+            env::block_timestamp() <= sublease_condition.pending_deadline_ts_nano,
+            "This lease has timed out waiting for the rent!"
+        );
+
+        self.activate_lease(sublease_id);
+    }
+
+    // internal function to extend an active lease, with the rent paid by its borrower
+    fn internal_extend_lease(
+        &mut self,
+        currency: Currency,
+        sender_id: AccountId,
+        amount: U128,
+        lease_extension_json: LeaseExtensionJson,
+    ) {
+        let lease_id = lease_extension_json.lease_id;
+        let mut lease_condition = self
+            .lease_map
+            .get(&lease_id)
+            .expect("The targeting lease does not exist!");
+
+        assert_eq!(
+            lease_condition.state,
+            LeaseState::Active,
+            "Queried Lease is not active!"
+        );
+        assert_eq!(
+            sender_id, lease_condition.borrower_id,
+            "Only the borrower can extend the lease!"
+        );
+        assert!(
+            env::block_timestamp() < lease_condition.end_ts_nano,
+            "Lease has already expired!"
+        );
+        assert_eq!(
+            currency, lease_condition.currency,
+            "Wrong FT contract address!"
+        );
+        // The currency may have been removed from the allowlist since the lease was created
+        self.internal_assert_currency_allowed(&currency);
+
+        let renewal_terms = lease_condition
+            .renewal_terms
+            .clone()
+            .expect("This lease can not be extended!");
+        require!(
+            renewal_terms.price_per_unit.0 > 0,
+            "The extension price should be positive!"
+        );
+
+        // The rent should pay for a whole number of time units
+        let units = amount.0 / renewal_terms.price_per_unit.0;
+        require!(
+            units > 0 && units * renewal_terms.price_per_unit.0 == amount.0,
+            "Transferred amount doesn't match the extension price!"
+        );
+        let new_end_ts_nano = u64::try_from(units)
+            .ok()
+            .and_then(|units| units.checked_mul(renewal_terms.unit_duration_nano.0))
+            .and_then(|extension| lease_condition.end_ts_nano.checked_add(extension))
+            .expect("Lease extension is too long!");
+        require!(
+            new_end_ts_nano <= renewal_terms.max_end_ts_nano.0,
+            "Lease can not be extended beyond the max end time!"
+        );
+        require!(
+            !self.internal_overlaps_calendar(&lease_id, &lease_condition, new_end_ts_nano),
+            "Lease can not be extended into the next lease of the NFT!"
+        );
+
+        // Add the extra rent to the payout split, before updating the total price
+        let extra_shares = self.internal_split_rent(&lease_condition, amount.0);
+        let mut payout = lease_condition.payout.clone().unwrap_or(Payout {
+            payout: HashMap::new(),
+        });
+        for (receiver_id, share) in extra_shares {
+            let payout_amount = payout.payout.entry(receiver_id).or_insert(U128::from(0));
+            payout_amount.0 += share;
+        }
+
+        let old_end_ts_nano = lease_condition.end_ts_nano;
+        lease_condition.payout = Some(payout);
+        lease_condition.price = U128::from(lease_condition.price.0 + amount.0);
+        lease_condition.end_ts_nano = new_end_ts_nano;
+        self.lease_map.insert(&lease_id, &lease_condition);
+
+        LeaseExtended {
+            lease_id: &lease_id,
+            borrower_id: &lease_condition.borrower_id,
+            extra_rent: amount,
+            old_end_ts_nano: U64::from(old_end_ts_nano),
+            new_end_ts_nano: U64::from(new_end_ts_nano),
+        }
+        .emit();
+    }
+
+    // internal function to create a lease from the lease json
+    fn internal_create_lease_from_json(&mut self, lease_json: LeaseJson) {
+        self.internal_assert_currency_allowed(&lease_json.currency);
+        require!(
+            lease_json.nft_payout.payout.len() as u32 <= MAX_LEN_PAYOUT,
+            "Too many payout receivers!"
+        );
+        // The rent is split by the payout, so it can't pay out more than the price
+        require!(
+            lease_json
+                .nft_payout
+                .payout
+                .values()
+                .map(|amount| amount.0)
+                .sum::<u128>()
+                <= lease_json.price.0,
+            "The payout exceeds the price of the lease!"
+        );
+        self.create_lease_with_payout(
+            lease_json.nft_contract_id,
+            lease_json.nft_token_id,
+            lease_json.lender_id, // use lender here, as the token owner has been updated to Rental contract
+            lease_json.borrower_id,
+            RentTerms {
+                currency: lease_json.currency,
+                price: lease_json.price,
+                start_ts_nano: lease_json.start_ts_nano,
+                end_ts_nano: lease_json.end_ts_nano,
+                nft_payout: lease_json.nft_payout,
+            },
+            LeaseTerms {
+                refund_on_early_return: lease_json.refund_on_early_return.unwrap_or(true),
+                renewal_terms: lease_json.renewal_terms,
+                collateral: lease_json.collateral.unwrap_or(U128::from(0)),
+                allowed_methods: lease_json.allowed_methods.unwrap_or_default(),
+                sublease_allowed: lease_json.sublease_allowed.unwrap_or(true),
+            },
+        );
+    }
+
+    // internal function to create a lease based on given info
+    fn create_lease_with_payout(
+        &mut self,
+        nft_contract_id: AccountId,
+        nft_token_id: TokenId,
+        owner_id: AccountId,
+        borrower_id: AccountId,
+        rent_terms: RentTerms,
+        lease_terms: LeaseTerms,
+    ) {
+        // build lease condition from the parsed json
+        let lease_condition: LeaseCondition = LeaseCondition {
+            contract_addr: nft_contract_id,
+            token_id: nft_token_id,
+            lender_id: owner_id.clone(),
+            borrower_id: borrower_id,
+            currency: rent_terms.currency,
+            price: rent_terms.price,
+            start_ts_nano: rent_terms.start_ts_nano,
+            end_ts_nano: rent_terms.end_ts_nano,
+            payout: Some(rent_terms.nft_payout),
+            state: LeaseState::PendingOnRent,
+            refund_on_early_return: lease_terms.refund_on_early_return,
+            renewal_terms: lease_terms.renewal_terms,
+            collateral: lease_terms.collateral,
+            collateral_forfeited: false,
+            pending_deadline_ts_nano: env::block_timestamp() + PENDING_LEASE_TIMEOUT_NANO,
+            allowed_methods: lease_terms.allowed_methods,
+            sublease_allowed: lease_terms.sublease_allowed,
+            parent_lease_id: None,
+            sublease_id: None,
+            protocol_fee_bps: self.protocol_fee_bps,
+            storage_bytes: 0,
+            storage_payer_id: owner_id,
+        };
+
+        let lease_id =
+            self.internal_new_lease_id(&lease_condition.contract_addr, &lease_condition.token_id);
+        self.internal_insert_lease_paying_storage(&lease_id, lease_condition);
+    }
+
+    // helper method to insert a new lease, charging its lender for the storage used
+    fn internal_insert_lease_paying_storage(
+        &mut self,
+        lease_id: &LeaseId,
+        mut lease_condition: LeaseCondition,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+        self.internal_insert_lease(lease_id, &lease_condition);
+
+        // Updating the fixed-size field doesn't change the storage usage
+        lease_condition.storage_bytes = env::storage_usage() - initial_storage_usage;
+        self.lease_map.insert(lease_id, &lease_condition);
+
+        self.internal_charge_storage(
+            &lease_condition.storage_payer_id,
+            lease_condition.storage_bytes,
+        );
+    }
+
+    // helper method to generate the id of a new lease, by hashing the leased NFT with a nonce.
+    // The nonce increases for every lease, so the ids don't collide even for leases created in the same block.
+    fn internal_new_lease_id(
+        &mut self,
+        nft_contract_id: &AccountId,
+        nft_token_id: &TokenId,
+    ) -> LeaseId {
+        let nonce = self.lease_nonce;
+        self.lease_nonce += 1;
+
+        let seed = env::sha256(&(nft_contract_id, nft_token_id, nonce).try_to_vec().unwrap());
+        bs58::encode(seed)
+            .with_alphabet(bs58::Alphabet::BITCOIN)
+            .into_string()
+    }
+
+    // helper method to remove records of a lease
+    fn internal_remove_lease(&mut self, lease_id: &LeaseId) {
+        // check if a lease condition exist
+        let lease_condition = self
+            .lease_map
+            .get(&lease_id)
+            .expect("Input lease_id does not exist");
+        let initial_storage_usage = env::storage_usage();
+
+        // remove lease map record
+        self.lease_map.remove(&lease_id);
+
+        // remove from index by_lender
+        let mut lease_set = self
+            .lease_ids_by_lender
+            .get(&lease_condition.lender_id)
+            .unwrap();
+        lease_set.remove(&lease_id);
+
+        if lease_set.is_empty() {
+            self.lease_ids_by_lender.remove(&lease_condition.lender_id);
+        } else {
+            self.lease_ids_by_lender
+                .insert(&lease_condition.lender_id, &lease_set);
+        }
+
+        // remove from index by_borrower
+        let mut lease_set = self
+            .lease_ids_by_borrower
+            .get(&lease_condition.borrower_id)
+            .unwrap();
+        lease_set.remove(&lease_id);
+
+        if lease_set.is_empty() {
+            self.lease_ids_by_borrower
+                .remove(&lease_condition.borrower_id);
+        } else {
+            self.lease_ids_by_borrower
+                .insert(&lease_condition.borrower_id, &lease_set);
+        }
+
+        // remove from the calendar of the NFT, which only records the outermost leases.
+        // A sub-lease is unlinked from its parent lease instead.
+        match &lease_condition.parent_lease_id {
+            None => {
+                let nft = (
+                    lease_condition.contract_addr.clone(),
+                    lease_condition.token_id.clone(),
+                );
+                let mut lease_ids = self
+                    .lease_ids_by_contract_addr_and_token_id
+                    .get(&nft)
+                    .unwrap_or_default();
+                lease_ids.retain(|id| id != lease_id);
+
+                // The NFT leaves this contract with its last lease
+                if lease_ids.is_empty() {
+                    self.lease_ids_by_contract_addr_and_token_id.remove(&nft);
+                    self.nft_custodies.remove(&nft);
+                } else {
+                    self.lease_ids_by_contract_addr_and_token_id
+                        .insert(&nft, &lease_ids);
+                }
+            }
+            Some(parent_lease_id) => {
+                if let Some(mut parent_lease_condition) = self.lease_map.get(parent_lease_id) {
+                    parent_lease_condition.sublease_id = None;
+                    self.lease_map
+                        .insert(parent_lease_id, &parent_lease_condition);
+                }
+            }
+        }
+
+        // Clean up NFT related fields
+        // update active leases set. A pending lease has never had its LEASE token minted.
+        let was_active = self.active_lease_ids.remove(&lease_id);
+
+        // update active_lease_ids_by_lender
+        let mut active_lease_id_set = self
+            .active_lease_ids_by_lender
+            .get(&lease_condition.lender_id);
+
+        if let Some(active_lease_id_set) = active_lease_id_set.as_mut() {
+            active_lease_id_set.remove(&lease_id);
+
+            if active_lease_id_set.is_empty() {
+                self.active_lease_ids_by_lender
+                    .remove(&lease_condition.lender_id);
+            } else {
+                self.active_lease_ids_by_lender
+                    .insert(&lease_condition.lender_id, &active_lease_id_set);
+            }
+        }
+
+        // Event Log to capture token burning as per the Events standard
+        if was_active {
+            let token_id = self.lease_id_to_lease_token_id(&lease_id);
+            NftBurn {
+                owner_id: &lease_condition.lender_id,
+                token_ids: &[&token_id],
+                authorized_id: None,
+                memo: None,
+            }
+            .emit();
+
+            self.nft_burn_borrower_token(lease_id, &lease_condition.borrower_id);
+        }
+
+        // Refund the payer for the freed storage, up to what was paid for the lease.
+        // The lender may have changed since, through a transfer of the LEASE token.
+        let freed_bytes = initial_storage_usage.saturating_sub(env::storage_usage());
+        self.internal_refund_storage(
+            &lease_condition.storage_payer_id,
+            std::cmp::min(freed_bytes, lease_condition.storage_bytes),
+        );
+
+        // Clear the approvals of the burned LEASE token, refunding their storage to the lender
+        let approved_account_ids = self.internal_clear_approvals(lease_id);
+        if !approved_account_ids.is_empty() {
+            refund_approved_account_ids(lease_condition.lender_id.clone(), &approved_account_ids);
+        }
+        self.next_approval_id_by_lease_id.remove(lease_id);
+    }
+
+    // helper method to check whether the lease, ending at the given time, overlaps with
+    // the other leases in the calendar of the NFT
+    fn internal_overlaps_calendar(
+        &self,
+        lease_id: &LeaseId,
+        lease_condition: &LeaseCondition,
+        end_ts_nano: u64,
+    ) -> bool {
+        self.lease_ids_by_contract_addr_and_token_id
+            .get(&(
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone(),
+            ))
+            .unwrap_or_default()
+            .iter()
+            .filter(|id| *id != lease_id)
+            .any(|id| {
+                let other = self.lease_map.get(id).unwrap();
+                lease_condition.start_ts_nano <= other.end_ts_nano
+                    && other.start_ts_nano <= end_ts_nano
+            })
+    }
+
+    // helper method to insert a new lease and update all indices
+    fn internal_insert_lease(&mut self, lease_id: &LeaseId, lease_condition: &LeaseCondition) {
+        // insert into lease map
+        assert!(
+            self.lease_map.insert(&lease_id, &lease_condition).is_none(),
+            "The lease id already exists!"
+        );
+
+        //update index for leases by lender. If there are none, create a new empty set
+        let mut lease_ids_set = self
+            .lease_ids_by_lender
+            .get(&lease_condition.lender_id)
+            .unwrap_or_else(|| {
+                UnorderedSet::new(
+                    StorageKey::LeasesIdsByLenderInner {
+                        // get a new unique prefix for the collection by hashing owner
+                        account_id_hash: utils::hash_account_id(&lease_condition.lender_id),
+                    }
+                    .try_to_vec()
+                    .unwrap(),
+                )
+            });
+        lease_ids_set.insert(&lease_id);
+        self.lease_ids_by_lender
+            .insert(&lease_condition.lender_id, &lease_ids_set);
+
+        // update index for leases by borrower. If there are none, create a new empty set
+        let mut lease_ids_set = self
+            .lease_ids_by_borrower
+            .get(&lease_condition.borrower_id)
+            .unwrap_or_else(|| {
+                UnorderedSet::new(
+                    StorageKey::LeaseIdsByBorrowerInner {
+                        // get a new unique prefix for the collection by hashing owner
+                        account_id_hash: utils::hash_account_id(&lease_condition.borrower_id),
+                    }
+                    .try_to_vec()
+                    .unwrap(),
+                )
+            });
+        lease_ids_set.insert(&lease_id);
+        self.lease_ids_by_borrower
+            .insert(&lease_condition.borrower_id, &lease_ids_set);
+
+        // update the calendar of the NFT, keeping it sorted by start time. A sub-lease is reached from its parent lease.
+        if lease_condition.parent_lease_id.is_none() {
+            require!(
+                !self.internal_overlaps_calendar(
+                    lease_id,
+                    lease_condition,
+                    lease_condition.end_ts_nano
+                ),
+                "The lease overlaps with another lease of the NFT!"
+            );
+
+            let nft = (
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone(),
+            );
+            let mut lease_ids = self
+                .lease_ids_by_contract_addr_and_token_id
+                .get(&nft)
+                .unwrap_or_default();
+            let index = lease_ids
+                .iter()
+                .position(|id| {
+                    self.lease_map.get(id).unwrap().start_ts_nano > lease_condition.start_ts_nano
+                })
+                .unwrap_or(lease_ids.len());
+            lease_ids.insert(index, lease_id.clone());
+            self.lease_ids_by_contract_addr_and_token_id
+                .insert(&nft, &lease_ids);
+        }
+
+        // log lease insertion, with the fee for frontends to display
+        let protocol_fee = self.internal_protocol_fee(lease_condition, lease_condition.price.0);
+        LeaseCreated {
+            lease_id,
+            contract_addr: &lease_condition.contract_addr,
+            token_id: &lease_condition.token_id,
+            lender_id: &lease_condition.lender_id,
+            borrower_id: &lease_condition.borrower_id,
+            currency: &lease_condition.currency,
+            price: lease_condition.price,
+            protocol_fee: U128::from(protocol_fee),
+            start_ts_nano: U64::from(lease_condition.start_ts_nano),
+            end_ts_nano: U64::from(lease_condition.end_ts_nano),
+        }
+        .emit();
+    }
+
+    /// This function updates only the lender info in an active lease
+    /// All affecting indices will be updated
+    fn internal_update_active_lease_lender(
+        &mut self,
+        old_lender: &AccountId,
+        new_lender: &AccountId,
+        lease_id: &LeaseId,
+    ) {
+        // 1. Check if the active lease exist
+        assert_eq!(
+            self.active_lease_ids.contains(lease_id),
+            true,
+            "Only active lease can update lender!"
+        );
+
+        // 2. Ensure the given active lease belongs to the old owner
+        let mut active_lease_ids_set = self
+            .active_lease_ids_by_lender
+            .get(old_lender)
+            .expect("Active Lease is not owned by the old lender!");
+
+        // 3. Remove the active lease from the old lender
+        // update index for active lease ids
+        active_lease_ids_set.remove(lease_id);
+        if active_lease_ids_set.is_empty() {
+            self.active_lease_ids_by_lender.remove(old_lender);
+        } else {
+            self.active_lease_ids_by_lender
+                .insert(old_lender, &active_lease_ids_set);
+        }
+        // Update the index for lease ids by lender for old lender
+        let mut lease_ids_set = self.lease_ids_by_lender.get(old_lender).unwrap();
+        lease_ids_set.remove(lease_id);
+        if lease_ids_set.is_empty() {
+            self.lease_ids_by_lender.remove(old_lender);
+        } else {
+            self.lease_ids_by_lender.insert(old_lender, &lease_ids_set);
+        }
+
+        // 4. Add the active lease to the new lender
+        // update the index for active lease ids
+        let mut active_lease_ids_set = self
+            .active_lease_ids_by_lender
+            .get(new_lender)
+            .unwrap_or_else(|| {
+                // if the new lender doesn't have any active lease, create a new record
+                UnorderedSet::new(
+                    StorageKey::ActiveLeaseIdsByOwnerInner {
+                        account_id_hash: utils::hash_account_id(new_lender),
+                    }
+                    .try_to_vec()
+                    .unwrap(),
+                )
+            });
+        active_lease_ids_set.insert(lease_id);
+        self.active_lease_ids_by_lender
+            .insert(new_lender, &active_lease_ids_set);
+        // Udpate the index for lease ids by lender for new lender
+        let mut lease_ids_set = self.lease_ids_by_lender.get(new_lender).unwrap_or_else(|| {
+            // if the receiver doesn;t have any lease, create a new record
+            UnorderedSet::new(
+                StorageKey::LeasesIdsByLenderInner {
+                    account_id_hash: utils::hash_account_id(new_lender),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        lease_ids_set.insert(lease_id);
+        self.lease_ids_by_lender.insert(new_lender, &lease_ids_set);
+
+        // 5. Update the lease map index accordingly
+        let mut lease_condition = self.lease_map.get(lease_id).unwrap();
+        lease_condition.lender_id = new_lender.clone();
+        // The lender's share of the rent follows the new lender, while royalty receivers keep theirs.
+        // A lender also receiving royalties of the NFT can't be told apart, so both shares move.
+        if let Some(payout) = lease_condition.payout.as_mut() {
+            if let Some(lender_share) = payout.payout.remove(old_lender) {
+                let new_lender_share = payout
+                    .payout
+                    .entry(new_lender.clone())
+                    .or_insert(U128::from(0));
+                new_lender_share.0 += lender_share.0;
+            }
+        }
+        self.lease_map.insert(&lease_id, &lease_condition); // insert data back to persis the value
+
+        // 6. The new lender owns the NFT in custody, so only they can book its next leases
+        let nft = (lease_condition.contract_addr, lease_condition.token_id);
+        if let Some(mut nft_custody) = self.nft_custodies.get(&nft) {
+            nft_custody.owner_id = new_lender.clone();
+            self.nft_custodies.insert(&nft, &nft_custody);
+        }
+    }
+}
+
+/**
+ * Trait that will handle the receival of the leasing NFT.
+ * When the Marketplace calls nft_transfer_call on NFT contract, the NFT contract
+ * will invoke this function.
+*/
+pub trait NonFungibleTokenTransferReceiver {
+    fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: TokenId,
+        msg: String,
+    ) -> PromiseOrValue<bool>;
+}
+
+// Helpers of the lease views, taking arguments not supported by near_bindgen
+impl Contract {
+    // Keeps the leases matching the filter, then skips `from_index` of them and returns at most `limit`.
+    // Without a `limit`, a page of 10 leases is returned, so that the view doesn't run out of gas.
+    fn internal_paginate_leases(
+        &self,
+        lease_ids: impl Iterator<Item = LeaseId>,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+        filter: Option<LeaseFilter>,
+    ) -> Vec<(String, LeaseCondition)> {
+        let start_index = from_index.map_or(0, |from_index| from_index.0 as usize);
+        let limit = limit.map_or(10, |limit| limit as usize);
+        require!(limit != 0, "Cannot provide limit of 0!");
+
+        lease_ids
+            .map(|id| {
+                let lease_condition = self.lease_map.get(&id).unwrap();
+                (id, lease_condition)
+            })
+            .filter(|(_, lease_condition)| match &filter {
+                Some(filter) => self.internal_matches_filter(lease_condition, filter),
+                None => true,
+            })
+            .skip(start_index)
+            .take(limit)
+            .collect()
+    }
+
+    fn internal_matches_filter(
+        &self,
+        lease_condition: &LeaseCondition,
+        filter: &LeaseFilter,
+    ) -> bool {
+        if let Some(state) = &filter.state {
+            if lease_condition.state != *state {
+                return false;
+            }
+        }
+        if let Some(contract_addr) = &filter.contract_addr {
+            if lease_condition.contract_addr != *contract_addr {
+                return false;
+            }
+        }
+        if let Some(currency) = &filter.currency {
+            if lease_condition.currency != *currency {
+                return false;
+            }
+        }
+        if let Some(time_window) = &filter.time_window {
+            let now = env::block_timestamp();
+            let lease_time_window = if now < lease_condition.start_ts_nano {
+                LeaseTimeWindow::Upcoming
+            } else if now > lease_condition.end_ts_nano {
+                LeaseTimeWindow::Expired
+            } else {
+                LeaseTimeWindow::Active
+            };
+            if lease_time_window != *time_window {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenTransferReceiver for Contract {
+    /**
+     * 1. Check NFT transfer is successful
+     * 2. Create proxy payouts if not supported
+     * 3. Create a lease
+     */
+    fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: TokenId,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        self.internal_assert_migrated();
+        // Enforce cross contract call
+        let nft_contract_id = env::predecessor_account_id();
+        assert_ne!(
+            env::current_account_id(),
+            nft_contract_id,
+            "nft_on_transfer should only be called via XCC."
+        );
+
+        let lease_json: LeaseJson =
+            near_sdk::serde_json::from_str(&msg).expect("Invalid lease json!");
+
+        // Enforce the leasing token is the same as the transferring token
+        assert_eq!(nft_contract_id, lease_json.nft_contract_id);
+        assert_eq!(token_id, lease_json.nft_token_id);
+
+        // The NFT stays in this contract until its last lease is over.
+        // The sender can book more leases of the NFT meanwhile.
+        NftCustodyStarted {
+            contract_addr: &nft_contract_id,
+            token_id: &token_id,
+            owner_id: &lease_json.lender_id,
+            depositor_id: &sender_id,
+        }
+        .emit();
+        self.nft_custodies.insert(
+            &(nft_contract_id, token_id),
+            &NftCustody {
+                owner_id: lease_json.lender_id.clone(),
+                depositor_id: sender_id,
+            },
+        );
+
+        // Create a lease using recieved lease info
+        self.internal_create_lease_from_json(lease_json);
+
+        // at this stage, lease creation has succedded
+        // return false to indicate that don't revert the nft transfer
+        return PromiseOrValue::Value(false);
+    }
+}
+
+/*
+    The trait for receiving rent transfer from marketplace.
+    Depending on the FT contract implementation, it may need the users to register to deposit.
+    So far we do not check if all partis have registered thier account on the FT contract,
+        - Lender: he should make sure he has registered otherwise he will not receive the payment
+        - Borrower: he cannot accept the lease if he does not register
+        - Royalty payments: if any accounts in the royalty didn't register, they will not receive the payout.
+    Any payment failed to be transferred is recorded as unclaimed, and can be withdrawn by the receiver
+    using `withdraw_unclaimed`, e.g. after registering on the FT contract.
+*/
+#[ext_contract(ext_ft_receiver)]
+pub trait FungibleTokenReceiver {
+    fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> U128;
+}
+
+/**
+ * This method receives borrower's rent transferred by markeplace. It also trigers a lease activation
+ * 1. Marketplace(Sender) calls `ft_transfer_call` on FT contract.
+ * 2. FT contract transfers `amount` tokens from marketplace to core rental contract (reciever).
+ * 3. FT contract calls `ft_on_transfer` on core rental contract.
+ * 4. Rental contract updates lease state accordingly. Rent condition checks have been performed on marketplace side.
+ * 5. Rental contract returns Promise accordingly.
+ *
+ * The borrower of an active lease can also call `ft_transfer_call` directly, with a `LeaseExtensionJson` msg,
+ * to pay for extending the lease under the renewal terms set by the lender.
+ * Similarly, the borrower of a sub-lease pays for its rent with a `SubleaseRentJson` msg.
+ */
+#[near_bindgen]
+impl FungibleTokenReceiver for Contract {
+    #[payable]
+    fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> U128 {
+        self.internal_assert_migrated();
+        // update the lease state to from PendingOnRent to active
+
+        // Enforce cross contract call
+        let ft_contract_id = env::predecessor_account_id();
+        assert_ne!(
+            env::current_account_id(),
+            ft_contract_id,
+            "ft_on_transfer should only be called via XCC."
+        );
+
+        // Extract recived message
+        let ft_on_transfer_json: FtOnTransferJson =
+            near_sdk::serde_json::from_str(&msg).expect("Not valid listing id data!");
+
+        let currency = Currency::Ft(ft_contract_id);
+        match ft_on_transfer_json {
+            FtOnTransferJson::RentAcceptance(rent_acceptance_json) => {
+                self.internal_accept_rent(currency, amount, rent_acceptance_json)
+            }
+            FtOnTransferJson::LeaseExtension(lease_extension_json) => {
+                self.internal_extend_lease(currency, sender_id, amount, lease_extension_json)
+            }
+            FtOnTransferJson::SubleaseRent(sublease_rent_json) => {
+                self.internal_accept_sublease_rent(currency, sender_id, amount, sublease_rent_json)
+            }
+        }
+
+        // Specify the unused amount as required by NEP-141
+        let unused_ammount: U128 = U128::from(0);
+        return unused_ammount;
+    }
+}
+
+/**
+ * This method receives rent paid in native NEAR, for the leases recording `Currency::Near` as their currency.
+ * It takes the same messages as `ft_on_transfer`, with the attached deposit as the transferred amount.
+ * e.g. the marketplace pays the rent of a lease this way, when the borrower accepts a listing priced in NEAR.
+ */
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn near_on_transfer(&mut self, msg: String) {
+        self.internal_assert_migrated();
+        let sender_id = env::predecessor_account_id();
+        let amount = U128::from(env::attached_deposit());
+
+        let ft_on_transfer_json: FtOnTransferJson =
+            near_sdk::serde_json::from_str(&msg).expect("Not valid listing id data!");
+
+        let currency = Currency::Near;
+        match ft_on_transfer_json {
+            FtOnTransferJson::RentAcceptance(rent_acceptance_json) => {
+                self.internal_accept_rent(currency, amount, rent_acceptance_json)
+            }
+            FtOnTransferJson::LeaseExtension(lease_extension_json) => {
+                self.internal_extend_lease(currency, sender_id, amount, lease_extension_json)
+            }
+            FtOnTransferJson::SubleaseRent(sublease_rent_json) => {
+                self.internal_accept_sublease_rent(currency, sender_id, amount, sublease_rent_json)
+            }
+        }
+    }
+}
+
+// Transfers the amount to the receiver in the given currency
+fn internal_send(currency: &Currency, receiver_id: &AccountId, amount: U128) -> Promise {
+    match currency {
+        Currency::Near => Promise::new(receiver_id.clone()).transfer(amount.0),
+        Currency::Ft(ft_contract_addr) => ext_ft_core::ext(ft_contract_addr.clone())
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .with_attached_deposit(1)
+            .ft_transfer(receiver_id.clone(), amount, None),
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    /*
+    Unit test cases and helper functions
+
+    Test naming format for better readability:
+    - test_{function_name} _{succeeds_or_fails} _{condition}
+    - When more than one test cases are needed for one function,
+    follow the code order of testing failing conditions first and success condition last
+    */
+    use super::*;
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::serde_json::json;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+    #[test]
+    fn test_new() {
+        let contract = Contract::new(accounts(1).into());
+        assert_eq!(accounts(1), contract.owner);
+        assert!(UnorderedMap::is_empty(&contract.lease_map));
+    }
+
+    // TODO(syu): borrower check is done on marketside. Maybe update to check target lease exist, or remove this
+    fn test_lending_accept_wrong_borrower() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        let lease_id = "test_key".to_string();
+
+        contract.lease_map.insert(&lease_id, &lease_condition);
+        let wrong_borrower: AccountId = accounts(4).into();
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&lease_condition))
+            .build());
+
+        contract.ft_on_transfer(
+            wrong_borrower.clone(),
+            U128::from(lease_condition.price),
+            json!({ "lease_id": lease_id }).to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Wrong FT contract address!")]
+    fn test_lending_accept_fail_wrong_ft_addr() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        let lease_id = "test_lease_id".to_string();
+        let wrong_ft_addr = accounts(0);
+        contract.lease_map.insert(&lease_id, &lease_condition);
+        // needed for finding the target lease_condition at ft_on_transfer
+        contract.lease_ids_by_contract_addr_and_token_id.insert(
+            &(
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone(),
+            ),
+            &vec![lease_id.clone()],
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(wrong_ft_addr.into())
+            .build());
+
+        let msg_rent_transfer_json = json!({
+            "nft_contract_id": lease_condition.contract_addr.clone().to_string(),
+            "nft_token_id": lease_condition.token_id.clone().to_string(),
+        })
+        .to_string();
+
+        contract.ft_on_transfer(
+            lease_condition.borrower_id.clone(),
+            U128::from(lease_condition.price),
+            msg_rent_transfer_json,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "FT contract is not allowed!")]
+    fn test_lending_accept_fail_ft_not_allowed() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        let lease_id = "test_lease_id".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&lease_condition))
+            .build());
+
+        let msg_rent_transfer_json = json!({
+            "nft_contract_id": lease_condition.contract_addr.clone().to_string(),
+            "nft_token_id": lease_condition.token_id.clone().to_string(),
+        })
+        .to_string();
+
+        contract.ft_on_transfer(
+            lease_condition.borrower_id.clone(),
+            U128::from(lease_condition.price),
+            msg_rent_transfer_json,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Transferred amount doesn't match the asked rent!")]
+    fn test_lending_accept_fail_wrong_rent() {
+        let mut contract = Contract::new(accounts(1).into());
+        allow_ft(&mut contract, &accounts(5));
+        let lease_condition = create_lease_condition_default();
+        let lease_id = "test_lease_id".to_string();
+        contract.lease_map.insert(&lease_id, &lease_condition);
+        // needed for finding the target lease_condition at ft_on_transfer
+        contract.lease_ids_by_contract_addr_and_token_id.insert(
+            &(
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone(),
+            ),
+            &vec![lease_id.clone()],
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&lease_condition))
+            .build());
+
+        let msg_rent_transfer_json = json!({
+            "nft_contract_id": lease_condition.contract_addr.clone().to_string(),
+            "nft_token_id": lease_condition.token_id.clone().to_string(),
+        })
+        .to_string();
+
+        contract.ft_on_transfer(
+            lease_condition.borrower_id.clone(),
+            U128::from(lease_condition.price.0 - 1),
+            msg_rent_transfer_json,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "This lease is not pending on rent!")]
+    fn test_lending_accept_fail_wrong_lease_state() {
+        let mut contract = Contract::new(accounts(1).into());
+        allow_ft(&mut contract, &accounts(5));
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        let lease_id = "test_lease_id".to_string();
+        contract.lease_map.insert(&lease_id, &lease_condition);
+        // needed for finding the target lease_condition at ft_on_transfer
+        contract.lease_ids_by_contract_addr_and_token_id.insert(
+            &(
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone(),
+            ),
+            &vec![lease_id.clone()],
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&lease_condition))
+            .build());
+
+        let msg_rent_transfer_json = json!({
+            "nft_contract_id": lease_condition.contract_addr.clone().to_string(),
+            "nft_token_id": lease_condition.token_id.clone().to_string(),
+        })
+        .to_string();
+
+        contract.ft_on_transfer(
+            lease_condition.borrower_id.clone(),
+            U128::from(lease_condition.price),
+            msg_rent_transfer_json,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "This lease has timed out waiting for the rent!")]
+    fn test_lending_accept_fail_timed_out() {
+        let mut contract = Contract::new(accounts(1).into());
+        allow_ft(&mut contract, &accounts(5));
+        let lease_condition = create_lease_condition_default();
+        let lease_id = "test_lease_id".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&lease_condition))
+            .block_timestamp(lease_condition.pending_deadline_ts_nano + 1)
+            .build());
+
+        let msg_rent_transfer_json = json!({
+            "nft_contract_id": lease_condition.contract_addr.clone().to_string(),
+            "nft_token_id": lease_condition.token_id.clone().to_string(),
+        })
+        .to_string();
+
+        contract.ft_on_transfer(
+            lease_condition.borrower_id.clone(),
+            U128::from(lease_condition.price),
+            msg_rent_transfer_json,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The start of the lease is required, as the NFT has many leases!")]
+    fn test_lending_accept_fail_start_required_for_many_leases() {
+        let mut contract = Contract::new(accounts(1).into());
+        let (_, next_key) = create_back_to_back_leases(&mut contract);
+        let next_lease_condition = contract.lease_map.get(&next_key).unwrap();
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&next_lease_condition))
+            .build());
+
+        contract.ft_on_transfer(
+            next_lease_condition.borrower_id.clone(),
+            next_lease_condition.price,
+            json!({
+                "nft_contract_id": next_lease_condition.contract_addr.to_string(),
+                "nft_token_id": next_lease_condition.token_id,
+            })
+            .to_string(),
+        );
+    }
+
+    #[test]
+    fn test_lending_accept_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        allow_ft(&mut contract, &accounts(5));
+        let lease_condition = create_lease_condition_default();
+        let lease_id = "test_lease_id".to_string();
+        contract.lease_map.insert(&lease_id, &lease_condition);
+        // needed for finding the target lease_condition at ft_on_transfer
+        contract.lease_ids_by_contract_addr_and_token_id.insert(
+            &(
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone(),
+            ),
+            &vec![lease_id.clone()],
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&lease_condition))
+            .build());
+
+        let msg_rent_transfer_json = json!({
+            "nft_contract_id": lease_condition.contract_addr.clone().to_string(),
+            "nft_token_id": lease_condition.token_id.clone().to_string(),
+        })
+        .to_string();
+
+        contract.ft_on_transfer(
+            lease_condition.borrower_id.clone(),
+            U128::from(lease_condition.price),
+            msg_rent_transfer_json,
+        );
+
+        // Nothing can be checked, except the fact the call doesn't panic.
+    }
+
+    #[test]
+    fn test_lending_accept_success_by_start() {
+        let mut contract = Contract::new(accounts(1).into());
+        allow_ft(&mut contract, &accounts(5));
+        let (_, next_key) = create_back_to_back_leases(&mut contract);
+        let next_lease_condition = contract.lease_map.get(&next_key).unwrap();
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&next_lease_condition))
+            .block_timestamp(next_lease_condition.start_ts_nano)
+            .build());
+
+        contract.ft_on_transfer(
+            next_lease_condition.borrower_id.clone(),
+            next_lease_condition.price,
+            json!({
+                "nft_contract_id": next_lease_condition.contract_addr.to_string(),
+                "nft_token_id": next_lease_condition.token_id,
+                "start_ts_nano": next_lease_condition.start_ts_nano,
+            })
+            .to_string(),
+        );
+
+        // Nothing can be checked, except the fact the call doesn't panic.
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the borrower can extend the lease!")]
+    fn test_lease_extension_fail_wrong_borrower() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_extendable();
+        let lease_id = "test_lease_id".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+        allow_ft(&mut contract, &ft_contract_addr(&lease_condition));
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&lease_condition))
+            .block_timestamp(500)
+            .build());
+
+        contract.ft_on_transfer(
+            accounts(0),
+            U128::from(10),
+            json!({ "lease_id": lease_id }).to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "This lease can not be extended!")]
+    fn test_lease_extension_fail_no_renewal_terms() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_extendable();
+        lease_condition.renewal_terms = None;
+        let lease_id = "test_lease_id".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+        allow_ft(&mut contract, &ft_contract_addr(&lease_condition));
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&lease_condition))
+            .block_timestamp(500)
+            .build());
+
+        contract.ft_on_transfer(
+            lease_condition.borrower_id.clone(),
+            U128::from(10),
+            json!({ "lease_id": lease_id }).to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "FT contract is not allowed!")]
+    fn test_lease_extension_fail_ft_not_allowed() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_extendable();
+        let lease_id = "test_lease_id".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&lease_condition))
+            .block_timestamp(500)
+            .build());
+
+        contract.ft_on_transfer(
+            lease_condition.borrower_id.clone(),
+            U128::from(10),
+            json!({ "lease_id": lease_id }).to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The extension price should be positive!")]
+    fn test_lease_extension_fail_zero_price_per_unit() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_extendable();
+        lease_condition.renewal_terms = Some(RenewalTerms {
+            price_per_unit: U128::from(0),
+            unit_duration_nano: U64::from(100),
+            max_end_ts_nano: U64::from(2000),
+        });
+        let lease_id = "test_lease_id".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+        allow_ft(&mut contract, &ft_contract_addr(&lease_condition));
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&lease_condition))
+            .block_timestamp(500)
+            .build());
+
+        contract.ft_on_transfer(
+            lease_condition.borrower_id.clone(),
+            U128::from(10),
+            json!({ "lease_id": lease_id }).to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Transferred amount doesn't match the extension price!")]
+    fn test_lease_extension_fail_partial_unit() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_extendable();
+        let lease_id = "test_lease_id".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+        allow_ft(&mut contract, &ft_contract_addr(&lease_condition));
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&lease_condition))
+            .block_timestamp(500)
+            .build());
+
+        contract.ft_on_transfer(
+            lease_condition.borrower_id.clone(),
+            U128::from(15),
+            json!({ "lease_id": lease_id }).to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Lease can not be extended beyond the max end time!")]
+    fn test_lease_extension_fail_beyond_max_end() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_extendable();
+        let lease_id = "test_lease_id".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+        allow_ft(&mut contract, &ft_contract_addr(&lease_condition));
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&lease_condition))
+            .block_timestamp(500)
+            .build());
+
+        // 11 units of 100 nanos, while at most 10 units are allowed
+        contract.ft_on_transfer(
+            lease_condition.borrower_id.clone(),
+            U128::from(110),
+            json!({ "lease_id": lease_id }).to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Lease can not be extended into the next lease of the NFT!")]
+    fn test_lease_extension_fail_into_next_lease() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_extendable();
+        let lease_id = "test_lease_id".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+        allow_ft(&mut contract, &ft_contract_addr(&lease_condition));
+
+        let mut next_lease_condition = create_lease_condition_default();
+        next_lease_condition.start_ts_nano = lease_condition.end_ts_nano + 100;
+        next_lease_condition.end_ts_nano = lease_condition.end_ts_nano + 500;
+        contract.internal_insert_lease(&"test_next_lease_id".to_string(), &next_lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&lease_condition))
+            .block_timestamp(500)
+            .build());
+
+        // 1 unit of 100 nanos, ending at the start of the next lease
+        contract.ft_on_transfer(
+            lease_condition.borrower_id.clone(),
+            U128::from(10),
+            json!({ "lease_id": lease_id }).to_string(),
+        );
+    }
+
+    #[test]
+    fn test_lease_extension_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_extendable();
+        lease_condition.price = U128::from(20);
+        lease_condition.payout = Some(Payout {
+            payout: HashMap::from([
+                (accounts(0), U128::from(4)),
+                (lease_condition.lender_id.clone(), U128::from(16)),
+            ]),
+        });
+        let lease_id = "test_lease_id".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+        allow_ft(&mut contract, &ft_contract_addr(&lease_condition));
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&lease_condition))
+            .block_timestamp(500)
+            .build());
+
+        // 3 units of 100 nanos
+        let unused_amount = contract.ft_on_transfer(
+            lease_condition.borrower_id.clone(),
+            U128::from(30),
+            json!({ "lease_id": lease_id }).to_string(),
+        );
+        assert_eq!(unused_amount.0, 0);
+
+        let lease_condition_result = contract.lease_map.get(&lease_id).unwrap();
+        assert_eq!(lease_condition_result.end_ts_nano, 1300);
+        assert_eq!(lease_condition_result.price.0, 50);
+        let payout = lease_condition_result.payout.unwrap().payout;
+        assert_eq!(payout[&accounts(0)].0, 10);
+        assert_eq!(payout[&lease_condition.lender_id].0, 40);
+    }
+
+    #[test]
+    #[should_panic(expected = "Transferred amount doesn't match the asked rent!")]
+    fn test_lending_accept_fail_missing_collateral() {
+        let mut contract = Contract::new(accounts(1).into());
+        allow_ft(&mut contract, &accounts(5));
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.collateral = U128::from(100);
+        let lease_id = "test_lease_id".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&lease_condition))
+            .build());
+
+        let msg_rent_transfer_json = json!({
+            "nft_contract_id": lease_condition.contract_addr.clone().to_string(),
+            "nft_token_id": lease_condition.token_id.clone().to_string(),
+        })
+        .to_string();
+
+        contract.ft_on_transfer(
+            lease_condition.borrower_id.clone(),
+            U128::from(lease_condition.price),
+            msg_rent_transfer_json,
+        );
+    }
+
+    #[test]
+    fn test_lending_accept_success_with_collateral() {
+        let mut contract = Contract::new(accounts(1).into());
+        allow_ft(&mut contract, &accounts(5));
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.collateral = U128::from(100);
+        let lease_id = "test_lease_id".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&lease_condition))
+            .build());
+
+        let msg_rent_transfer_json = json!({
+            "nft_contract_id": lease_condition.contract_addr.clone().to_string(),
+            "nft_token_id": lease_condition.token_id.clone().to_string(),
+        })
+        .to_string();
+
+        contract.ft_on_transfer(
+            lease_condition.borrower_id.clone(),
+            U128::from(lease_condition.price.0 + lease_condition.collateral.0),
+            msg_rent_transfer_json,
+        );
+
+        let lease_condition_result = contract.lease_map.get(&lease_id).unwrap();
+        assert_eq!(lease_condition_result.state, LeaseState::Active);
+    }
+
+    #[test]
+    #[should_panic(expected = "Wrong FT contract address!")]
+    fn test_ft_on_transfer_fails_lease_paid_in_near() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.currency = Currency::Near;
+        let lease_id = "test_lease_id".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+
+        // An FT contract can't pay for a lease in NEAR, whatever its account id
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id("near".parse().unwrap())
+            .build());
+
+        contract.ft_on_transfer(
+            lease_condition.borrower_id.clone(),
+            lease_condition.price,
+            json!({
+                "nft_contract_id": lease_condition.contract_addr.to_string(),
+                "nft_token_id": lease_condition.token_id,
+            })
+            .to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Wrong FT contract address!")]
+    fn test_near_on_transfer_fails_lease_paid_in_ft() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        let lease_id = "test_lease_id".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(lease_condition.price.0)
+            .build());
+
+        contract.near_on_transfer(
+            json!({
+                "nft_contract_id": lease_condition.contract_addr.to_string(),
+                "nft_token_id": lease_condition.token_id,
+            })
+            .to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "NEAR is not allowed!")]
+    fn test_near_on_transfer_fails_near_not_allowed() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.currency = Currency::Near;
+        let lease_id = "test_lease_id".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(lease_condition.price.0)
+            .build());
+
+        contract.near_on_transfer(
+            json!({
+                "nft_contract_id": lease_condition.contract_addr.to_string(),
+                "nft_token_id": lease_condition.token_id,
+            })
+            .to_string(),
+        );
+    }
+
+    #[test]
+    fn test_near_on_transfer_succeeds_rent_acceptance() {
+        let mut contract = Contract::new(accounts(1).into());
+        contract.near_allowed = true;
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.currency = Currency::Near;
+        let lease_id = "test_lease_id".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(lease_condition.price.0)
+            .build());
+
+        contract.near_on_transfer(
+            json!({
+                "nft_contract_id": lease_condition.contract_addr.to_string(),
+                "nft_token_id": lease_condition.token_id,
+            })
+            .to_string(),
+        );
+
+        let lease_condition_result = contract.lease_map.get(&lease_id).unwrap();
+        assert_eq!(lease_condition_result.state, LeaseState::Active);
+    }
+
+    #[test]
+    fn test_activate_lease_with_payout_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        let key = "test_key".to_string();
+        let payout = Payout {
+            payout: HashMap::from([
+                (accounts(2).into(), U128::from(1)),
+                (accounts(3).into(), U128::from(4)),
+            ]),
+        };
+
+        lease_condition.payout = Some(payout.clone());
+
+        contract.lease_map.insert(&key, &lease_condition);
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(lease_condition.borrower_id.clone())
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(Vec::new())],
+        );
+
+        contract.activate_lease(key.clone());
+
+        let lease_condition_result = contract.lease_map.get(&key).unwrap();
+        assert_eq!(lease_condition_result.payout, Some(payout));
+        assert_eq!(lease_condition_result.state, LeaseState::Active);
+    }
+
+    #[test]
+    fn test_activate_lease_without_payout_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        let key = "test_key".to_string();
+
+        contract.lease_map.insert(&key, &lease_condition);
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(lease_condition.borrower_id.clone())
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(Vec::new())],
+        );
+
+        contract.activate_lease(key.clone());
+
+        let lease_condition_result = contract.lease_map.get(&key).unwrap();
+        assert_eq!(lease_condition_result.payout, None);
+        assert_eq!(lease_condition_result.state, LeaseState::Active);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_activate_lease_promise_panic() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        let key = "test_key".to_string();
+        contract.lease_map.insert(&key, &lease_condition);
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(lease_condition.borrower_id.clone())
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Failed],
+        );
+
+        contract.activate_lease(key.clone());
+
+        let lease_condition_result = contract.lease_map.get(&key).unwrap();
+        assert_eq!(lease_condition_result.payout, None);
+        assert_eq!(lease_condition_result.state, LeaseState::PendingOnRent);
+    }
+
+    #[test]
+    #[should_panic(expected = "Lease has not expired yet!")]
+    fn test_claim_back_not_expired_yet() {
+        let mut contract = Contract::new(accounts(1).into());
+
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        lease_condition.end_ts_nano = 1000;
+
+        let key = "test_key".to_string();
+        contract.lease_map.insert(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.lender_id.clone())
+            .block_timestamp(lease_condition.end_ts_nano - 1)
+            .build());
+
+        contract.claim_back(key);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only original lender or service owner can claim back!")]
+    fn test_claim_back_wrong_lender() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        let key = "test_key".to_string();
+
+        contract.lease_map.insert(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(5).into()) // non-owner, non-lender
+            .block_timestamp(lease_condition.end_ts_nano + 1)
+            .build());
+
+        contract.claim_back(key);
+    }
+
+    #[test]
+    #[should_panic(expected = "Queried Lease is not active!")]
+    fn test_claim_back_inactive_lease() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::PendingOnRent;
+        let key = "test_key".to_string();
+
+        contract.lease_map.insert(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.lender_id.clone())
+            .block_timestamp(lease_condition.end_ts_nano + 1)
+            .build());
+
+        contract.claim_back(key);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_claim_back_non_exsisting_lease_id() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        let key = "test_key".to_string();
+        contract.lease_map.insert(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.lender_id.clone())
+            .block_timestamp(lease_condition.end_ts_nano + 1)
+            .build());
+
+        let non_existing_key = "dummy_key".to_string();
+        contract.claim_back(non_existing_key);
+    }
+
+    #[test]
+    fn test_claim_back_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        lease_condition.price = U128::from(20);
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.lender_id.clone())
+            .block_timestamp(lease_condition.end_ts_nano + 1)
+            .build());
+
+        contract.claim_back(key);
+
+        // Nothing can be checked, except the fact the call doesn't panic.
+    }
+
+    #[test]
+    fn test_claim_back_success_scales_gas_with_payout() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        lease_condition.payout = Some(Payout {
+            payout: HashMap::from([
+                (lease_condition.lender_id.clone(), U128::from(10)),
+                (accounts(3), U128::from(5)),
+                (accounts(4), U128::from(5)),
+            ]),
+        });
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.lender_id.clone())
+            .block_timestamp(lease_condition.end_ts_nano + 1)
+            .build());
+
+        contract.claim_back(key);
+
+        // 3 payout receivers, the lender's leftovers, the keeper bounty and the collateral
+        let expected_gas = GAS_FOR_RESOLVE_CLAIM_BACK + GAS_PER_SETTLEMENT_TRANSFER * 6;
+        let receipts = near_sdk::test_utils::get_created_receipts();
+        assert!(receipts
+            .iter()
+            .any(|receipt| receipt.actions.iter().any(|action| matches!(
+                action,
+                near_sdk::mock::VmAction::FunctionCall { function_name, gas, .. }
+                    if function_name == "resolve_claim_back" && *gas == expected_gas
+            ))));
+    }
+
+    #[test]
+    fn test_claim_back_success_next_lease_booked() {
+        let mut contract = Contract::new(accounts(1).into());
+        let (key, next_key) = create_back_to_back_leases(&mut contract);
+        let lease_condition = contract.lease_map.get(&key).unwrap();
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.lender_id.clone())
+            .block_timestamp(lease_condition.end_ts_nano + 1)
+            .build());
+
+        contract.claim_back(key.clone());
+
+        // The lease is settled right away, with the NFT kept in custody for the next lease
+        assert!(contract.lease_map.get(&key).is_none());
+        assert_eq!(
+            contract.lease_ids_by_contract_addr_and_token_id.get(&(
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone()
+            )),
+            Some(vec![next_key])
+        );
+        assert!(contract
+            .nft_custodies
+            .contains_key(&(lease_condition.contract_addr, lease_condition.token_id)));
+    }
+
+    #[test]
+    #[should_panic(expected = "This lease is not pending on rent!")]
+    fn test_cancel_pending_lease_fails_active_lease() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(5).into())
+            .block_timestamp(lease_condition.pending_deadline_ts_nano + 1)
+            .build());
+
+        contract.cancel_pending_lease(key);
+    }
+
+    #[test]
+    #[should_panic(expected = "Pending lease has not timed out yet!")]
+    fn test_cancel_pending_lease_fails_not_timed_out() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.lender_id.clone())
+            .block_timestamp(lease_condition.pending_deadline_ts_nano)
+            .build());
+
+        contract.cancel_pending_lease(key);
+    }
+
+    #[test]
+    fn test_cancel_pending_lease_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        // anyone can cancel a timed out pending lease
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(5).into())
+            .block_timestamp(lease_condition.pending_deadline_ts_nano + 1)
+            .build());
+
+        contract.cancel_pending_lease(key);
+
+        // Nothing can be checked, except the fact the call doesn't panic.
+    }
+
+    #[test]
+    #[should_panic(expected = "NFT transfer failed. Abort lease cancellation!")]
+    fn test_resolve_cancel_pending_lease_fails_nft_transfer_failed() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Failed],
+        );
+
+        contract.resolve_cancel_pending_lease(key);
+    }
+
+    #[test]
+    fn test_resolve_cancel_pending_lease_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(Vec::new())],
+        );
+
+        contract.resolve_cancel_pending_lease(key.clone());
+
+        assert!(contract.lease_map.get(&key).is_none());
+        assert!(contract
+            .lease_ids_by_contract_addr_and_token_id
+            .get(&(lease_condition.contract_addr, lease_condition.token_id))
+            .is_none());
+        assert!(contract
+            .leases_by_owner(lease_condition.lender_id, None, None, None)
+            .is_empty());
+        assert!(contract
+            .leases_by_borrower(lease_condition.borrower_id, None, None, None)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_claim_back_success_by_keeper_after_grace_period() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(5).into()) // non-owner, non-lender
+            .block_timestamp(lease_condition.end_ts_nano + DEFAULT_KEEPER_GRACE_PERIOD_NANO + 1)
+            .build());
+
+        contract.claim_back(key);
+
+        // Nothing can be checked, except the fact the call doesn't panic.
+    }
+
+    #[test]
+    fn test_resolve_claim_back_success_by_keeper() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(Vec::new())],
+        );
+
+        assert!(contract.resolve_claim_back(key.clone(), Some(accounts(5))));
+
+        assert!(contract.lease_map.get(&key).is_none());
+        let logs = near_sdk::test_utils::get_logs();
+        assert!(logs[0].contains("\"event\":\"lease_settled\""));
+        assert!(logs[0].contains("\"keeper_id\":\"fargo\""));
+    }
+
+    #[test]
+    fn test_internal_keeper_bounty_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        assert_eq!(contract.internal_keeper_bounty(1_000), 0);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.set_keeper_bounty_bps(250);
+
+        assert_eq!(contract.internal_keeper_bounty(1_000), 25);
+        assert_eq!(contract.internal_keeper_bounty(u128::MAX), u128::MAX / 40);
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough gas attached to claim back the leases!")]
+    fn test_claim_back_many_fails_not_enough_gas() {
+        let mut contract = Contract::new(accounts(1).into());
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(2))
+            .prepaid_gas(Gas(TGAS))
+            .build());
+
+        contract.claim_back_many(vec!["test_key".to_string()]);
+    }
+
+    #[test]
+    fn test_claim_back_many_success_all_leases_invalid() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.lender_id.clone())
+            .block_timestamp(lease_condition.end_ts_nano - 1)
+            .build());
+
+        let results = contract.claim_back_many(vec![key.clone(), "dummy_key".to_string()]);
+
+        match results {
+            PromiseOrValue::Value(results) => assert_eq!(
+                results,
+                vec![
+                    ClaimBackResult {
+                        lease_id: key,
+                        success: false,
+                        error: Some("Lease has not expired yet!".to_string()),
+                    },
+                    ClaimBackResult {
+                        lease_id: "dummy_key".to_string(),
+                        success: false,
+                        error: Some("Input lease_id does not exist".to_string()),
+                    },
+                ]
+            ),
+            PromiseOrValue::Promise(_) => panic!("No lease should be claimed back!"),
+        }
+    }
+
+    #[test]
+    fn test_claim_back_many_success_capped_by_gas() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.lender_id.clone())
+            .block_timestamp(lease_condition.end_ts_nano + 1)
+            .prepaid_gas(Gas(30 * TGAS))
+            .build());
+
+        let results = contract.claim_back_many(vec![key.clone()]);
+
+        match results {
+            PromiseOrValue::Value(results) => assert_eq!(
+                results,
+                vec![ClaimBackResult {
+                    lease_id: key.clone(),
+                    success: false,
+                    error: Some("Not enough gas to claim back this lease!".to_string()),
+                }]
+            ),
+            PromiseOrValue::Promise(_) => panic!("No lease should be claimed back!"),
+        }
+        assert!(contract.lease_map.get(&key).is_some());
+    }
+
+    #[test]
+    fn test_claim_back_many_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.lender_id.clone())
+            .block_timestamp(lease_condition.end_ts_nano + 1)
+            .build());
+
+        let results = contract.claim_back_many(vec![key, "dummy_key".to_string()]);
+
+        assert!(matches!(results, PromiseOrValue::Promise(_)));
+    }
+
+    #[test]
+    fn test_resolve_claim_back_success_nft_transfer_failed() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Failed],
+        );
+
+        assert!(!contract.resolve_claim_back(key.clone(), None));
+        // The lease is kept, so that it can be claimed back again
+        assert!(contract.lease_map.get(&key).is_some());
+    }
+
+    #[test]
+    fn test_resolve_claim_back_success_native_near_payout() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        lease_condition.currency = Currency::Near;
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(Vec::new())],
+        );
+
+        assert!(contract.resolve_claim_back(key.clone(), None));
+        assert!(contract.lease_map.get(&key).is_none());
+        // The rent is paid to the lender in NEAR
+        let receipts = near_sdk::test_utils::get_created_receipts();
+        assert!(receipts
+            .iter()
+            .any(|receipt| receipt.receiver_id == lease_condition.lender_id
+                && receipt.actions
+                    == vec![near_sdk::mock::VmAction::Transfer {
+                        deposit: lease_condition.price.0
+                    }]));
+    }
+
+    #[test]
+    fn test_resolve_claim_back_many_success() {
+        let mut contract = Contract::new(accounts(1).into());
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![
+                PromiseResult::Successful(b"true".to_vec()),
+                PromiseResult::Successful(b"false".to_vec()),
+                PromiseResult::Failed,
+            ],
+        );
+
+        let invalid_lease_result = ClaimBackResult {
+            lease_id: "invalid_key".to_string(),
+            success: false,
+            error: Some("Lease has not expired yet!".to_string()),
+        };
+        let results = contract.resolve_claim_back_many(
+            vec![
+                "key_1".to_string(),
+                "key_2".to_string(),
+                "key_3".to_string(),
+            ],
+            vec![invalid_lease_result],
+        );
+
+        let outcomes: Vec<(String, bool)> = results
+            .into_iter()
+            .map(|result| (result.lease_id, result.success))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                ("invalid_key".to_string(), false),
+                ("key_1".to_string(), true),
+                ("key_2".to_string(), false),
+                ("key_3".to_string(), false),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the borrower can return the NFT early!")]
+    fn test_return_early_fails_wrong_borrower() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.lender_id.clone())
+            .block_timestamp(lease_condition.end_ts_nano - 1)
+            .build());
+
+        contract.return_early(key);
+    }
+
+    #[test]
+    #[should_panic(expected = "Queried Lease is not active!")]
+    fn test_return_early_fails_inactive_lease() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.borrower_id.clone())
+            .block_timestamp(lease_condition.end_ts_nano - 1)
+            .build());
+
+        contract.return_early(key);
+    }
+
+    #[test]
+    #[should_panic(expected = "Lease has already expired!")]
+    fn test_return_early_fails_expired_lease() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.borrower_id.clone())
+            .block_timestamp(lease_condition.end_ts_nano + 1)
+            .build());
+
+        contract.return_early(key);
+    }
+
+    #[test]
+    fn test_return_early_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.borrower_id.clone())
+            .block_timestamp(lease_condition.end_ts_nano - 1)
+            .build());
+
+        contract.return_early(key);
+
+        // Nothing can be checked, except the fact the call doesn't panic.
+    }
+
+    #[test]
+    #[should_panic(expected = "NFT transfer failed. Abort early return!")]
+    fn test_resolve_return_early_fails_nft_transfer_failed() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Failed],
+        );
+
+        contract.resolve_return_early(key, U128::from(1));
+    }
+
+    #[test]
+    fn test_resolve_return_early_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(Vec::new())],
+        );
+
+        contract.resolve_return_early(key.clone(), U128::from(1));
+
+        assert!(contract.lease_map.get(&key).is_none());
+    }
+
+    #[test]
+    fn test_internal_early_return_refund_success_prorated_by_time() {
+        let contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.start_ts_nano = 1000;
+        lease_condition.end_ts_nano = 2000;
+        lease_condition.price = U128::from(100);
+
+        assert_eq!(
+            contract.internal_early_return_refund(&lease_condition, 1250),
+            75
+        );
+        assert_eq!(
+            contract.internal_early_return_refund(&lease_condition, 1999),
+            0
+        );
+        // returned before the lease starts
+        assert_eq!(
+            contract.internal_early_return_refund(&lease_condition, 500),
+            100
+        );
+    }
+
+    #[test]
+    fn test_internal_early_return_refund_success_large_price() {
+        let contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        // 30 days lease, priced at 1M NEAR
+        lease_condition.start_ts_nano = 1_680_000_000_000_000_000;
+        lease_condition.end_ts_nano = lease_condition.start_ts_nano + 30 * 86_400_000_000_000;
+        lease_condition.price = U128::from(1_000_000 * 10u128.pow(24));
+
+        let refund = contract.internal_early_return_refund(
+            &lease_condition,
+            lease_condition.start_ts_nano + 15 * 86_400_000_000_000,
+        );
+        assert_eq!(refund, lease_condition.price.0 / 2);
+    }
+
+    #[test]
+    fn test_internal_early_return_refund_success_lender_opted_out() {
+        let contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.start_ts_nano = 1000;
+        lease_condition.end_ts_nano = 2000;
+        lease_condition.refund_on_early_return = false;
+
+        assert_eq!(
+            contract.internal_early_return_refund(&lease_condition, 1250),
+            0
+        );
+    }
+
+    #[test]
+    fn test_internal_split_rent_success_with_payout() {
+        let contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.price = U128::from(100);
+        lease_condition.payout = Some(Payout {
+            payout: HashMap::from([
+                (accounts(0), U128::from(5)),
+                (lease_condition.lender_id.clone(), U128::from(95)),
+            ]),
+        });
+
+        let shares = contract.internal_split_rent(&lease_condition, 100);
+        assert_eq!(shares[&accounts(0)], 5);
+        assert_eq!(shares[&lease_condition.lender_id], 95);
+
+        // Rounding leftovers go to the lender
+        let shares = contract.internal_split_rent(&lease_condition, 30);
+        assert_eq!(shares[&accounts(0)], 1);
+        assert_eq!(shares[&lease_condition.lender_id], 29);
+    }
+
+    #[test]
+    fn test_internal_split_rent_success_without_payout() {
+        let contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+
+        let shares = contract.internal_split_rent(&lease_condition, 3);
+        assert_eq!(shares.len(), 1);
+        assert_eq!(shares[&lease_condition.lender_id], 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the depositor of the NFT can book its leases!")]
+    fn test_book_lease_in_custody_fails_wrong_depositor() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        contract.nft_custodies.insert(
+            &(
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone(),
+            ),
+            &NftCustody {
+                owner_id: lease_condition.lender_id.clone(),
+                depositor_id: accounts(0),
+            },
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(lease_condition.lender_id.clone())
+            .build());
+        contract.book_lease_in_custody(create_lease_json_msg(&lease_condition));
+    }
+
+    #[test]
+    #[should_panic(expected = "FT contract is not allowed!")]
+    fn test_book_lease_in_custody_fails_ft_not_allowed() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        contract.nft_custodies.insert(
+            &(
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone(),
+            ),
+            &NftCustody {
+                owner_id: lease_condition.lender_id.clone(),
+                depositor_id: accounts(0),
+            },
+        );
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.book_lease_in_custody(create_lease_json_msg(&lease_condition));
+    }
+
+    #[test]
+    #[should_panic(expected = "Too many payout receivers!")]
+    fn test_book_lease_in_custody_fails_too_many_payout_receivers() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        allow_ft(&mut contract, &ft_contract_addr(&lease_condition));
+        contract.nft_custodies.insert(
+            &(
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone(),
+            ),
+            &NftCustody {
+                owner_id: lease_condition.lender_id.clone(),
+                depositor_id: accounts(0),
+            },
+        );
+
+        let mut lease_json: near_sdk::serde_json::Value =
+            near_sdk::serde_json::from_str(&create_lease_json_msg(&lease_condition)).unwrap();
+        lease_json["nft_payout"] = json!(Payout {
+            payout: (0..=MAX_LEN_PAYOUT)
+                .map(|i| (
+                    create_a_dummy_account_id(&format!("receiver_{}", i)),
+                    U128::from(1)
+                ))
+                .collect(),
+        });
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.book_lease_in_custody(lease_json.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "The payout exceeds the price of the lease!")]
+    fn test_book_lease_in_custody_fails_payout_exceeds_price() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        allow_ft(&mut contract, &ft_contract_addr(&lease_condition));
+        contract.nft_custodies.insert(
+            &(
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone(),
+            ),
+            &NftCustody {
+                owner_id: lease_condition.lender_id.clone(),
+                depositor_id: accounts(0),
+            },
+        );
+
+        let mut lease_json: near_sdk::serde_json::Value =
+            near_sdk::serde_json::from_str(&create_lease_json_msg(&lease_condition)).unwrap();
+        lease_json["nft_payout"] = json!(Payout {
+            payout: HashMap::from([
+                (lease_condition.lender_id.clone(), lease_condition.price),
+                (accounts(4), U128::from(1)),
+            ]),
+        });
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.book_lease_in_custody(lease_json.to_string());
+    }
+
+    #[test]
+    fn test_book_lease_in_custody_success_nft_not_in_custody() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .build());
+        assert!(!contract.book_lease_in_custody(create_lease_json_msg(&lease_condition)));
+        assert!(contract.lease_map.is_empty());
+    }
+
+    #[test]
+    fn test_book_lease_in_custody_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        allow_ft(&mut contract, &accounts(5));
+        let (_, next_key) = create_back_to_back_leases(&mut contract);
+        let next_lease_condition = contract.lease_map.get(&next_key).unwrap();
+        register_storage(&mut contract, &next_lease_condition.lender_id);
+
+        let mut booked_lease_condition = create_lease_condition_default();
+        booked_lease_condition.start_ts_nano = next_lease_condition.end_ts_nano + 1;
+        booked_lease_condition.end_ts_nano = next_lease_condition.end_ts_nano + 1000;
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .build());
+        assert!(contract.book_lease_in_custody(create_lease_json_msg(&booked_lease_condition)));
+
+        let leases = contract.leases_by_contract_and_token(
+            booked_lease_condition.contract_addr,
+            booked_lease_condition.token_id,
+        );
+        assert_eq!(leases.len(), 3);
+        assert_eq!(
+            leases[2].1.start_ts_nano,
+            booked_lease_condition.start_ts_nano
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the lender or the arbitrator can forfeit the collateral!")]
+    fn test_forfeit_collateral_fails_wrong_caller() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        lease_condition.collateral = U128::from(100);
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.borrower_id.clone())
+            .build());
+
+        contract.forfeit_collateral(key);
+    }
+
+    #[test]
+    #[should_panic(expected = "This lease has no collateral!")]
+    fn test_forfeit_collateral_fails_no_collateral() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.lender_id.clone())
+            .build());
+
+        contract.forfeit_collateral(key);
+    }
+
+    #[test]
+    fn test_forfeit_collateral_success_by_lender() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        lease_condition.collateral = U128::from(100);
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.lender_id.clone())
+            .build());
+
+        contract.forfeit_collateral(key.clone());
+
+        assert!(contract.lease_map.get(&key).unwrap().collateral_forfeited);
+    }
+
+    #[test]
+    fn test_forfeit_collateral_success_by_arbitrator() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        lease_condition.collateral = U128::from(100);
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.set_collateral_arbitrator(Some(accounts(0)));
+        assert_eq!(contract.get_collateral_arbitrator(), Some(accounts(0)));
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.forfeit_collateral(key.clone());
+
+        assert!(contract.lease_map.get(&key).unwrap().collateral_forfeited);
+    }
+
+    #[test]
+    fn test_get_borrower_by_contract_and_token_success_no_matching_borrower() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+
+        let expected_contract_address: AccountId = accounts(4).into();
+        let expected_token_id = "test_token".to_string();
+        let expected_borrower_id: AccountId = accounts(3).into();
+
+        lease_condition.state = LeaseState::Active;
+        lease_condition.contract_addr = expected_contract_address.clone();
+        lease_condition.token_id = expected_token_id.clone();
+        lease_condition.borrower_id = expected_borrower_id.clone();
+
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        let test_contract_id: AccountId = accounts(5).into();
+        let test_token_id = "dummy_token".to_string();
+
+        let result_borrower = contract.get_borrower_by_contract_and_token(
+            test_contract_id.clone(),
+            expected_token_id.clone(),
+        );
+        assert!(result_borrower.is_none());
+
+        let result_borrower = contract.get_borrower_by_contract_and_token(
+            expected_contract_address.clone(),
+            test_token_id.clone(),
+        );
+        assert!(result_borrower.is_none());
+    }
+
+    #[test]
+    fn test_get_borrower_by_contract_and_token_success_lease_is_inactive() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+
+        let expected_contract_address: AccountId = accounts(4).into();
+        let expected_token_id = "test_token".to_string();
+        let expected_borrower_id: AccountId = accounts(3).into();
+
+        lease_condition.state = LeaseState::PendingOnRent;
+        lease_condition.contract_addr = expected_contract_address.clone();
+        lease_condition.token_id = expected_token_id.clone();
+        lease_condition.borrower_id = expected_borrower_id.clone();
+
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        let result_borrower = contract
+            .get_borrower_by_contract_and_token(expected_contract_address, expected_token_id);
+        assert!(result_borrower.is_none());
+    }
+
+    #[test]
+    fn test_get_current_user_by_contract_and_token_success_found_matching_borrower() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+
+        let expected_contract_address: AccountId = accounts(4).into();
+        let expected_token_id = "test_token".to_string();
+        let expected_borrower_id: AccountId = accounts(3).into();
+
+        lease_condition.state = LeaseState::Active;
+        lease_condition.contract_addr = expected_contract_address.clone();
+        lease_condition.token_id = expected_token_id.clone();
+        lease_condition.borrower_id = expected_borrower_id.clone();
+
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .block_timestamp(10)
+            .build());
+
+        let result_owner = contract
+            .get_current_user_by_contract_and_token(expected_contract_address, expected_token_id)
+            .unwrap();
+        assert!(result_owner == expected_borrower_id);
+    }
+
+    #[test]
+    fn test_get_current_user_by_contract_and_token_success_lease_is_inactive() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+
+        let expected_contract_address: AccountId = accounts(4).into();
+        let expected_token_id = "test_token".to_string();
+        let expected_lender_id: AccountId = accounts(2).into();
+        let expected_borrower_id: AccountId = accounts(3).into();
+
+        lease_condition.state = LeaseState::PendingOnRent;
+        lease_condition.contract_addr = expected_contract_address.clone();
+        lease_condition.token_id = expected_token_id.clone();
+        lease_condition.lender_id = expected_lender_id.clone();
+        lease_condition.borrower_id = expected_borrower_id.clone();
+
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        let result_owner = contract
+            .get_current_user_by_contract_and_token(expected_contract_address, expected_token_id)
+            .unwrap();
+        assert!(result_owner == expected_lender_id);
+    }
+
+    #[test]
+    fn test_get_current_user_by_contract_and_token_success_after_lease_expires() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+
+        let expected_contract_address: AccountId = accounts(4).into();
+        let expected_token_id = "test_token".to_string();
+        let expected_borrower_id: AccountId = accounts(3).into();
+        let expected_lender_id: AccountId = accounts(2).into();
+
+        lease_condition.state = LeaseState::Active;
+        lease_condition.contract_addr = expected_contract_address.clone();
+        lease_condition.token_id = expected_token_id.clone();
+        lease_condition.lender_id = expected_lender_id.clone();
+        lease_condition.borrower_id = expected_borrower_id.clone();
+
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .block_timestamp(1000)
+            .build());
+
+        let result_owner = contract
+            .get_current_user_by_contract_and_token(expected_contract_address, expected_token_id)
+            .unwrap();
+        assert!(result_owner == expected_lender_id);
+    }
+
+    #[test]
+    fn test_get_current_user_by_contract_and_token_success_lease_not_start() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+
+        let expected_contract_address: AccountId = accounts(4).into();
+        let expected_token_id = "test_token".to_string();
+        let expected_lender_id: AccountId = accounts(2).into();
+        let expected_borrower_id: AccountId = accounts(3).into();
+
+        lease_condition.state = LeaseState::PendingOnRent;
+        lease_condition.contract_addr = expected_contract_address.clone();
+        lease_condition.token_id = expected_token_id.clone();
+        lease_condition.lender_id = expected_lender_id.clone();
+        lease_condition.borrower_id = expected_borrower_id.clone();
+        // 2333/01/01 00:00
+        lease_condition.start_ts_nano = 11455171200000000000;
+
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        let result_owner = contract
+            .get_current_user_by_contract_and_token(expected_contract_address, expected_token_id)
+            .unwrap();
+        assert!(result_owner == expected_lender_id);
+    }
+
+    #[test]
+    fn test_get_current_user_by_contract_and_token_success_next_lease() {
+        let mut contract = Contract::new(accounts(1).into());
+        let (_, next_key) = create_back_to_back_leases(&mut contract);
+        let mut next_lease_condition = contract.lease_map.get(&next_key).unwrap();
+        next_lease_condition.state = LeaseState::Active;
+        contract.lease_map.insert(&next_key, &next_lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .block_timestamp(next_lease_condition.start_ts_nano + 1)
+            .build());
+
+        let result_user = contract
+            .get_current_user_by_contract_and_token(
+                next_lease_condition.contract_addr,
+                next_lease_condition.token_id,
+            )
+            .unwrap();
+        assert_eq!(result_user, next_lease_condition.borrower_id);
+    }
+
+    #[test]
+    fn test_get_borrower_by_contract_and_token_success_found_matching_borrower() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+
+        let expected_contract_address: AccountId = accounts(4).into();
+        let expected_token_id = "test_token".to_string();
+        let expected_borrower_id: AccountId = accounts(3).into();
+
+        lease_condition.state = LeaseState::Active;
+        lease_condition.contract_addr = expected_contract_address.clone();
+        lease_condition.token_id = expected_token_id.clone();
+        lease_condition.borrower_id = expected_borrower_id.clone();
+
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        let result_borrower = contract
+            .get_borrower_by_contract_and_token(expected_contract_address, expected_token_id)
+            .unwrap();
+        assert!(result_borrower == expected_borrower_id);
+    }
+
+    /// Creat two leases using the same lender
+    /// Before the leases got actived, active_leases_by_lender() should return 0 leases
+    /// After both leases got actived, active_leases_by_lender() should return 2 leases
+    #[test]
+    fn test_active_leases_by_lender_succeeds() {
+        let mut contract = Contract::new(accounts(0).into());
+        let expected_lender_id: AccountId = accounts(2).into();
+
+        let mut lease_condition_1 = create_lease_condition_default();
+        lease_condition_1.token_id = "test_token_1".to_string();
+        lease_condition_1.lender_id = expected_lender_id.clone();
+        let key_1 = "test_key_1".to_string();
+        contract.internal_insert_lease(&key_1, &lease_condition_1);
+
+        let mut lease_condition_2 = create_lease_condition_default();
+        lease_condition_2.token_id = "test_token_2".to_string();
+        lease_condition_2.lender_id = expected_lender_id.clone();
+        let key_2 = "test_key_2".to_string();
+        contract.internal_insert_lease(&key_2, &lease_condition_2);
+
+        // check before the leases got activated
+        let active_leases =
+            contract.active_leases_by_lender(expected_lender_id.clone(), None, None, None);
+        assert_eq!(active_leases.len(), 0);
+
+        // activate the 1st lease
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(Vec::new())],
+        );
+        contract.activate_lease(key_1.clone());
+
+        // activate the 2nd lease
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(Vec::new())],
+        );
+        contract.activate_lease(key_2.clone());
+
+        // test after the leases got activated
+        let active_leases =
+            contract.active_leases_by_lender(expected_lender_id.clone(), None, None, None);
+        assert_eq!(active_leases.len(), 2);
+    }
+
+    #[test]
+    fn test_leases_by_borrower_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        let expected_borrower_id: AccountId = accounts(3).into();
+
+        let mut lease_condition_1 = create_lease_condition_default();
+        lease_condition_1.state = LeaseState::Active;
+        lease_condition_1.token_id = "test_token_1".to_string();
+        lease_condition_1.borrower_id = expected_borrower_id.clone();
+
+        let key_1 = "test_key_1".to_string();
+        contract.internal_insert_lease(&key_1, &lease_condition_1);
+
+        let mut lease_condition_2 = create_lease_condition_default();
+        lease_condition_2.state = LeaseState::Active;
+        lease_condition_2.token_id = "test_token_2".to_string();
+        lease_condition_2.borrower_id = expected_borrower_id.clone();
+
+        let key_2 = "test_key_2".to_string();
+        contract.internal_insert_lease(&key_2, &lease_condition_2);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition_1.lender_id.clone())
+            .block_timestamp(lease_condition_1.end_ts_nano + 1)
+            .build());
+
+        let result = contract.leases_by_borrower(expected_borrower_id.clone(), None, None, None);
+        assert_eq!(result.len(), 2);
+    }
+
+    #[test]
+    fn test_leases_by_owner_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        let expected_owner_id: AccountId = accounts(2).into();
+
+        let mut lease_condition_1 = create_lease_condition_default();
+        lease_condition_1.state = LeaseState::Active;
+        lease_condition_1.token_id = "test_token_1".to_string();
+        lease_condition_1.lender_id = expected_owner_id.clone();
+
+        let key_1 = "test_key_1".to_string();
+        contract.internal_insert_lease(&key_1, &lease_condition_1);
+
+        let mut lease_condition_2 = create_lease_condition_default();
+        lease_condition_2.state = LeaseState::Active;
+        lease_condition_2.token_id = "test_token_2".to_string();
+        lease_condition_2.lender_id = expected_owner_id.clone();
+
+        let key_2 = "test_key_2".to_string();
+        contract.internal_insert_lease(&key_2, &lease_condition_2);
+
+        let mut builder = VMContextBuilder::new();
+        testing_env!(builder
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition_1.lender_id.clone())
+            .block_timestamp(lease_condition_1.end_ts_nano + 1)
+            .build());
+
+        let result = contract.leases_by_owner(expected_owner_id.clone(), None, None, None);
+        assert_eq!(result.len(), 2);
+    }
+
+    #[test]
+    fn test_leases_by_owner_success_filtered() {
+        let mut contract = Contract::new(accounts(1).into());
+        let expected_owner_id: AccountId = accounts(2).into();
+
+        // An active lease from 1 to 1000, and an upcoming lease from 2000 to 3000 in another FT
+        let mut lease_condition_1 = create_lease_condition_default();
+        lease_condition_1.state = LeaseState::Active;
+        lease_condition_1.token_id = "test_token_1".to_string();
+        contract.internal_insert_lease(&"test_key_1".to_string(), &lease_condition_1);
+
+        let mut lease_condition_2 = create_lease_condition_default();
+        lease_condition_2.token_id = "test_token_2".to_string();
+        lease_condition_2.currency = Currency::Ft(accounts(0));
+        lease_condition_2.start_ts_nano = 2000;
+        lease_condition_2.end_ts_nano = 3000;
+        contract.internal_insert_lease(&"test_key_2".to_string(), &lease_condition_2);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .block_timestamp(500)
+            .build());
+
+        let result = contract.leases_by_owner(
+            expected_owner_id.clone(),
+            None,
+            None,
+            Some(LeaseFilter {
+                state: Some(LeaseState::PendingOnRent),
+                contract_addr: None,
+                currency: None,
+                time_window: None,
+            }),
+        );
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].0, "test_key_2".to_string());
+
+        let result = contract.leases_by_owner(
+            expected_owner_id.clone(),
+            None,
+            None,
+            Some(LeaseFilter {
+                state: None,
+                contract_addr: Some(lease_condition_1.contract_addr.clone()),
+                currency: Some(lease_condition_1.currency.clone()),
+                time_window: None,
+            }),
+        );
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].0, "test_key_1".to_string());
+
+        let result = contract.leases_by_owner(
+            expected_owner_id.clone(),
+            None,
+            None,
+            Some(LeaseFilter {
+                state: None,
+                contract_addr: None,
+                currency: None,
+                time_window: Some(LeaseTimeWindow::Upcoming),
+            }),
+        );
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].0, "test_key_2".to_string());
+
+        // Both leases have expired
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .block_timestamp(3001)
+            .build());
+        let result = contract.leases_by_owner(
+            expected_owner_id,
+            None,
+            None,
+            Some(LeaseFilter {
+                state: None,
+                contract_addr: None,
+                currency: None,
+                time_window: Some(LeaseTimeWindow::Expired),
+            }),
+        );
+        assert_eq!(result.len(), 2);
+    }
+
+    #[test]
+    fn test_leases_by_owner_success_filtered_and_paginated() {
+        let mut contract = Contract::new(accounts(1).into());
+        let expected_owner_id: AccountId = accounts(2).into();
+
+        // Only the even leases are active
+        for i in 0..6 {
+            let mut lease_condition = create_lease_condition_default();
+            lease_condition.token_id = format!("test_token_{}", i);
+            if i % 2 == 0 {
+                lease_condition.state = LeaseState::Active;
+            }
+            contract.internal_insert_lease(&format!("test_key_{}", i), &lease_condition);
+        }
+        let filter = || LeaseFilter {
+            state: Some(LeaseState::Active),
+            contract_addr: None,
+            currency: None,
+            time_window: None,
+        };
+
+        // The pages are made of the matching leases only
+        let result =
+            contract.leases_by_owner(expected_owner_id.clone(), None, Some(2), Some(filter()));
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].0, "test_key_0".to_string());
+        assert_eq!(result[1].0, "test_key_2".to_string());
+
+        let result = contract.leases_by_owner(
+            expected_owner_id,
+            Some(U128::from(2)),
+            Some(2),
+            Some(filter()),
+        );
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].0, "test_key_4".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot provide limit of 0!")]
+    fn test_leases_fails_zero_limit() {
+        let contract = Contract::new(accounts(1).into());
+        contract.leases(None, Some(0));
+    }
+
+    #[test]
+    fn test_leases_success_paginated() {
+        let mut contract = Contract::new(accounts(1).into());
+        for i in 0..5 {
+            let mut lease_condition = create_lease_condition_default();
+            lease_condition.token_id = format!("test_token_{}", i);
+            contract.internal_insert_lease(&format!("test_key_{}", i), &lease_condition);
+        }
+
+        let result = contract.leases(Some(U128::from(1)), Some(2));
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].0, "test_key_1".to_string());
+        assert_eq!(result[1].0, "test_key_2".to_string());
+
+        let result = contract.leases(Some(U128::from(4)), Some(2));
+        assert_eq!(result.len(), 1);
+        assert!(contract.leases(Some(U128::from(5)), None).is_empty());
+        assert_eq!(contract.leases(None, None).len(), 5);
+    }
+
+    #[test]
+    fn test_leases_success_default_limit() {
+        let mut contract = Contract::new(accounts(1).into());
+        for i in 0..12 {
+            let mut lease_condition = create_lease_condition_default();
+            lease_condition.token_id = format!("test_token_{}", i);
+            contract.internal_insert_lease(&format!("test_key_{}", i), &lease_condition);
+        }
+
+        // Without a limit, a bounded page is returned
+        assert_eq!(contract.leases(None, None).len(), 10);
+        assert_eq!(contract.leases(Some(U128::from(10)), None).len(), 2);
+    }
+
+    #[test]
+    fn test_lease_by_id_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        contract.internal_insert_lease(&"test_key".to_string(), &lease_condition);
+
+        assert_eq!(
+            contract
+                .lease_by_id("test_key".to_string())
+                .unwrap()
+                .token_id,
+            lease_condition.token_id
+        );
+        assert!(contract.lease_by_id("test_key_2".to_string()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "The lease id already exists!")]
+    fn test_internal_insert_lease_fails_duplicate_lease_id() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        contract.internal_insert_lease(&key, &create_lease_condition_default());
+    }
+
+    #[test]
+    #[should_panic(expected = "The lease overlaps with another lease of the NFT!")]
+    fn test_internal_insert_lease_fails_overlapping_lease() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        contract.internal_insert_lease(&"test_key".to_string(), &lease_condition);
+
+        let mut next_lease_condition = create_lease_condition_default();
+        next_lease_condition.start_ts_nano = lease_condition.end_ts_nano;
+        next_lease_condition.end_ts_nano = lease_condition.end_ts_nano + 1000;
+        contract.internal_insert_lease(&"test_next_key".to_string(), &next_lease_condition);
+    }
+
+    #[test]
+    fn test_internal_insert_lease_success_sorted_by_start() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        let mut next_lease_condition = create_lease_condition_default();
+        next_lease_condition.start_ts_nano = lease_condition.end_ts_nano + 1;
+        next_lease_condition.end_ts_nano = lease_condition.end_ts_nano + 1000;
+
+        // The later lease is booked first
+        contract.internal_insert_lease(&"test_next_key".to_string(), &next_lease_condition);
+        contract.internal_insert_lease(&"test_key".to_string(), &lease_condition);
+
+        let leases = contract.leases_by_contract_and_token(
+            lease_condition.contract_addr.clone(),
+            lease_condition.token_id.clone(),
+        );
+        assert_eq!(leases.len(), 2);
+        assert_eq!(leases[0].0, "test_key".to_string());
+        assert_eq!(leases[1].0, "test_next_key".to_string());
+    }
+
+    #[test]
+    fn test_internal_insert_lease_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        lease_condition.price = U128::from(20);
+        lease_condition.contract_addr = accounts(4).into();
+        lease_condition.token_id = "test_token".to_string();
+        let key = "test_key".to_string();
+
+        assert!(contract.lease_map.is_empty());
+        assert!(!contract
+            .lease_ids_by_borrower
+            .contains_key(&lease_condition.borrower_id));
+        assert!(!contract
+            .lease_ids_by_lender
+            .contains_key(&lease_condition.lender_id));
+        assert!(!contract
+            .lease_ids_by_contract_addr_and_token_id
+            .contains_key(&(
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone()
+            )));
+
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        assert!(contract.lease_map.len() == 1);
+        assert!(contract
+            .lease_ids_by_borrower
+            .contains_key(&lease_condition.borrower_id));
+        assert!(contract
+            .lease_ids_by_lender
+            .contains_key(&lease_condition.lender_id));
+        assert!(contract
+            .lease_ids_by_contract_addr_and_token_id
+            .contains_key(&(
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone()
+            )));
+    }
+
+    #[test]
+    fn test_internal_remove_lease_success_only_one_lease() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        lease_condition.price = U128::from(20);
+        lease_condition.contract_addr = accounts(4).into();
+        lease_condition.token_id = "test_token".to_string();
+        let key = "test_key".to_string();
+
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        assert!(contract.lease_map.len() == 1);
+        assert!(contract
+            .lease_ids_by_borrower
+            .contains_key(&lease_condition.borrower_id));
+        assert!(contract
+            .lease_ids_by_lender
+            .contains_key(&lease_condition.lender_id));
+        assert!(contract
+            .lease_ids_by_contract_addr_and_token_id
+            .contains_key(&(
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone()
+            )));
+
+        contract.internal_remove_lease(&key);
+
+        assert!(contract.lease_map.is_empty());
+        assert!(!contract
+            .lease_ids_by_borrower
+            .contains_key(&lease_condition.borrower_id));
+        assert!(!contract
+            .lease_ids_by_lender
+            .contains_key(&lease_condition.lender_id));
+        assert!(!contract
+            .lease_ids_by_contract_addr_and_token_id
+            .contains_key(&(
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone()
+            )));
+    }
+
+    #[test]
+    fn test_internal_remove_lease_success_different_owners() {
+        let mut contract = Contract::new(accounts(1).into());
+        let owner_1: AccountId = accounts(2).into();
+        let owner_2: AccountId = accounts(4).into();
+
+        let mut lease_condition_1 = create_lease_condition_default();
+        lease_condition_1.state = LeaseState::Active;
+        lease_condition_1.token_id = "test_token_1".to_string();
+        lease_condition_1.lender_id = owner_1.clone();
+
+        let key_1 = "test_key_1".to_string();
+        contract.internal_insert_lease(&key_1, &lease_condition_1);
+
+        let mut lease_condition_2 = create_lease_condition_default();
+        lease_condition_2.state = LeaseState::Active;
+        lease_condition_2.token_id = "test_token_2".to_string();
+        lease_condition_2.lender_id = owner_2.clone();
+
+        let key_2 = "test_key_2".to_string();
+        contract.internal_insert_lease(&key_2, &lease_condition_2);
+
+        assert!(contract.lease_map.len() == 2);
+        assert!(contract.lease_ids_by_lender.contains_key(&owner_1));
+        assert!(contract.lease_ids_by_lender.contains_key(&owner_2));
+
+        contract.internal_remove_lease(&key_1);
+
+        assert!(contract.lease_map.len() == 1);
+        assert!(!contract.lease_ids_by_lender.contains_key(&owner_1));
+        assert!(contract.lease_ids_by_lender.contains_key(&owner_2));
+    }
+
+    #[test]
+    fn test_internal_remove_lease_success_different_borrowers() {
+        let mut contract = Contract::new(accounts(1).into());
+        let borrower_1: AccountId = accounts(3).into();
+        let borrower_2: AccountId = accounts(4).into();
+
+        let mut lease_condition_1 = create_lease_condition_default();
+        lease_condition_1.state = LeaseState::Active;
+        lease_condition_1.token_id = "test_token_1".to_string();
+        lease_condition_1.borrower_id = borrower_1.clone();
+
+        let key_1 = "test_key_1".to_string();
+        contract.internal_insert_lease(&key_1, &lease_condition_1);
+
+        let mut lease_condition_2 = create_lease_condition_default();
+        lease_condition_2.state = LeaseState::Active;
+        lease_condition_2.token_id = "test_token_2".to_string();
+        lease_condition_2.borrower_id = borrower_2.clone();
+
+        let key_2 = "test_key_2".to_string();
+        contract.internal_insert_lease(&key_2, &lease_condition_2);
+
+        assert!(contract.lease_map.len() == 2);
+        assert!(contract.lease_ids_by_borrower.contains_key(&borrower_1));
+        assert!(contract.lease_ids_by_borrower.contains_key(&borrower_2));
+
+        contract.internal_remove_lease(&key_1);
+
+        assert!(contract.lease_map.len() == 1);
+        assert!(!contract.lease_ids_by_borrower.contains_key(&borrower_1));
+        assert!(contract.lease_ids_by_borrower.contains_key(&borrower_2));
+    }
+
+    /// 1. Initially, Alice owns an active lease
+    /// 2. Alice transfers the lease to Bob
+    /// 3. Check Success:
+    ///    - Lease record for Alice is emptied
+    ///    - Lease record for Bob is updated
+    #[test]
+    fn test_internal_update_active_lease_lender_succeeds() {
+        let mut contract = Contract::new(accounts(0).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.lender_id = accounts(0).into(); //Alice
+
+        let lease_key = "test_key".to_string();
+        contract.internal_insert_lease(&lease_key, &lease_condition);
+
+        // update active lease records for Alice
+        lease_condition.state = LeaseState::Active;
+        contract.nft_mint(lease_key.clone(), lease_condition.lender_id.clone());
+
+        contract.internal_update_active_lease_lender(
+            &lease_condition.lender_id, // Alice
+            &accounts(1).into(),        // Bob
+            &lease_key,
+        );
+
+        assert_eq!(1, contract.active_lease_ids.len());
+        assert!(!contract
+            .active_lease_ids_by_lender
+            .contains_key(&accounts(0).into()));
+        assert!(!contract
+            .lease_ids_by_lender
+            .contains_key(&accounts(0).into()));
+        assert!(contract
+            .active_lease_ids_by_lender
+            .contains_key(&accounts(1).into()));
+        assert!(contract
+            .lease_ids_by_lender
+            .contains_key(&accounts(1).into()));
+        assert_eq!(
+            contract.lease_map.get(&lease_key).unwrap().lender_id,
+            accounts(1).into()
+        );
+    }
+
+    /// 1. Initially, Alice owns an active lease
+    /// 2. Charlie tries to transfer Alice's lease to Bob
+    /// 3. Panic, due to unmatching lenders
+    #[test]
+    #[should_panic(expected = "Active Lease is not owned by the old lender!")]
+    fn test_internal_update_active_lease_lender_fails_unmatched_old_lender() {
+        let mut contract = Contract::new(accounts(0).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.lender_id = accounts(1).into(); //Alice
+
+        let lease_key = "test_key".to_string();
+        contract.internal_insert_lease(&lease_key, &lease_condition);
+
+        // update active lease records
+        lease_condition.state = LeaseState::Active;
+        contract.active_lease_ids.insert(&lease_key);
+        let mut active_lease_ids_set: UnorderedSet<String> = UnorderedSet::new(
+            StorageKey::ActiveLeaseIdsByOwnerInner {
+                account_id_hash: utils::hash_account_id(&lease_condition.lender_id),
+            }
+            .try_to_vec()
+            .unwrap(),
+        );
+        active_lease_ids_set.insert(&lease_key);
+
+        contract.internal_update_active_lease_lender(
+            &accounts(3).into(), // Charlie
+            &accounts(2).into(), // Bob
+            &lease_key,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only active lease can update lender!")]
+    fn test_internal_update_active_lease_lender_fails_not_an_active_lease() {
+        let mut contract = Contract::new(accounts(0).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::PendingOnRent;
+
+        let lease_key = "test_key".to_string();
+        contract.internal_insert_lease(&lease_key, &lease_condition);
+
+        contract.internal_update_active_lease_lender(
+            &lease_condition.lender_id, // Alice
+            &accounts(2).into(),        // Bob
+            &lease_key,
+        );
+    }
+
+    #[test]
+    fn test_internal_update_active_lease_lender_succeeds_moves_nft_custody() {
+        let mut contract = Contract::new(accounts(0).into());
+        let lease_condition = create_lease_condition_default();
+        let nft = (
+            lease_condition.contract_addr.clone(),
+            lease_condition.token_id.clone(),
+        );
+        contract.nft_custodies.insert(
+            &nft,
+            &NftCustody {
+                owner_id: lease_condition.lender_id.clone(),
+                depositor_id: accounts(0),
+            },
+        );
+
+        let lease_key = "test_key".to_string();
+        contract.internal_insert_lease(&lease_key, &lease_condition);
+        contract.nft_mint(lease_key.clone(), lease_condition.lender_id.clone());
+
+        contract.internal_update_active_lease_lender(
+            &lease_condition.lender_id,
+            &accounts(1).into(), // Bob
+            &lease_key,
+        );
+
+        // Only the new lender can book the next leases of the NFT
+        let nft_custody = contract.nft_custodies.get(&nft).unwrap();
+        assert_eq!(nft_custody.owner_id, accounts(1));
+        assert_eq!(nft_custody.depositor_id, accounts(0));
+    }
+
+    #[test]
+    fn test_internal_update_active_lease_lender_succeeds_moves_lender_payout() {
+        let mut contract = Contract::new(accounts(0).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.lender_id = accounts(0).into(); //Alice
+        lease_condition.payout = Some(Payout {
+            payout: HashMap::from([
+                (accounts(0).into(), U128::from(4)),
+                (accounts(5).into(), U128::from(1)), // royalty receiver
+            ]),
+        });
 
-        // log nft transfer
-        env::log_str(
-            &json!({
-                "type": "[DEBUG] NiFTyRent Rental: Creating a lease for the received NFT.",
-                "params": {
-                    "nft_contract_id": nft_contract_id.clone(),
-                    "nft_token_id": token_id.clone(),
-                    "nft_payout": lease_json.nft_payout.clone(),
-                }
-            })
-            .to_string(),
-        );
+        let lease_key = "test_key".to_string();
+        contract.internal_insert_lease(&lease_key, &lease_condition);
+        contract.nft_mint(lease_key.clone(), lease_condition.lender_id.clone());
 
-        // Create a lease using recieved lease info
-        self.create_lease_with_payout(
-            lease_json.nft_contract_id,
-            lease_json.nft_token_id,
-            lease_json.lender_id, // use lender here, as the token owner has been updated to Rental contract
-            lease_json.borrower_id,
-            lease_json.ft_contract_addr,
-            lease_json.start_ts_nano,
-            lease_json.end_ts_nano,
-            lease_json.price,
-            lease_json.nft_payout.clone(),
+        contract.internal_update_active_lease_lender(
+            &lease_condition.lender_id, // Alice
+            &accounts(1).into(),        // Bob
+            &lease_key,
         );
 
-        // at this stage, lease creation has succedded
-        // return false to indicate that don't revert the nft transfer
-        return PromiseOrValue::Value(false);
+        let payout = contract
+            .lease_map
+            .get(&lease_key)
+            .unwrap()
+            .payout
+            .unwrap()
+            .payout;
+        assert_eq!(payout.len(), 2);
+        assert!(!payout.contains_key(&accounts(0).into()));
+        assert_eq!(payout[&accounts(1).into()], U128::from(4));
+        assert_eq!(payout[&accounts(5).into()], U128::from(1));
     }
-}
 
-/*
-    The trait for receiving rent transfer from marketplace.
-    Depending on the FT contract implementation, it may need the users to register to deposit.
-    So far we do not check if all partis have registered thier account on the FT contract,
-        - Lender: he should make sure he has registered otherwise he will not receive the payment
-        - Borrower: he cannot accept the lease if he does not register
-        - Royalty payments: if any accounts in the royalty didn't register, they will not receive the payout. That
-                             part of payment will be kept in this smart contract
-*/
-#[ext_contract(ext_ft_receiver)]
-pub trait FungibleTokenReceiver {
-    fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> U128;
-}
+    #[test]
+    #[should_panic(expected = "Only the owner can add allowed FT contracts")]
+    fn test_add_allowed_ft_contract_addr_fail_when_called_by_nonowner() {
+        let mut contract = Contract::new(accounts(1).into());
+        assert!(contract.get_allowed_ft_contract_addrs().is_empty());
 
-/**
- * This method receives borrower's rent transferred by markeplace. It also trigers a lease activation
- * 1. Marketplace(Sender) calls `ft_transfer_call` on FT contract.
- * 2. FT contract transfers `amount` tokens from marketplace to core rental contract (reciever).
- * 3. FT contract calls `ft_on_transfer` on core rental contract.
- * 4. Rental contract updates lease state accordingly. Rent condition checks have been performed on marketplace side.
- * 5. Rental contract returns Promise accordingly.
- */
-#[near_bindgen]
-impl FungibleTokenReceiver for Contract {
-    #[payable]
-    fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> U128 {
-        // update the lease state to from PendingOnRent to active
+        contract.add_allowed_ft_contract_addr(accounts(2));
+    }
 
-        // Enforce cross contract call
-        let ft_contract_id = env::predecessor_account_id();
-        assert_ne!(
-            env::current_account_id(),
-            ft_contract_id,
-            "ft_on_transfer should only be called via XCC."
-        );
+    #[test]
+    #[should_panic(expected = "Only the owner can remove allowed FT contracts")]
+    fn test_remove_allowed_ft_contract_addr_fail_when_called_by_nonowner() {
+        let mut contract = Contract::new(accounts(1).into());
 
-        // Extract recived message
-        let rent_acceptance_json: RentAcceptanceJson =
-            near_sdk::serde_json::from_str(&msg).expect("Not valid listing id data!");
+        contract.remove_allowed_ft_contract_addr(accounts(2));
+    }
 
-        // Find the targeting lease
-        let lease_condition = self
-            .get_lease_by_contract_and_token(
-                rent_acceptance_json.nft_contract_id.clone(),
-                rent_acceptance_json.nft_token_id.clone(),
-            )
-            .expect("The targeting lease does not exist!");
+    #[test]
+    #[should_panic(expected = "Only the owner can set the collateral arbitrator")]
+    fn test_set_collateral_arbitrator_fail_when_called_by_nonowner() {
+        let mut contract = Contract::new(accounts(1).into());
+        assert!(contract.get_collateral_arbitrator().is_none());
 
-        // Enforce the ft contract matches
-        assert_eq!(
-            ft_contract_id, lease_condition.ft_contract_addr,
-            "Wrong FT contract address!"
-        );
+        contract.set_collateral_arbitrator(Some(accounts(2)));
+    }
 
-        // Enforce the rent amount matches
-        assert_eq!(
-            amount.0, lease_condition.price.0,
-            "Transferred amount doesn't match the asked rent!"
-        );
+    #[test]
+    #[should_panic(expected = "Only the owner can set the keeper bounty")]
+    fn test_set_keeper_bounty_bps_fail_when_called_by_nonowner() {
+        let mut contract = Contract::new(accounts(1).into());
 
-        // Update the lease state accordingly
-        assert_eq!(
-            lease_condition.state,
-            LeaseState::PendingOnRent,
-            "This lease is not pending on rent!"
-        );
+        contract.set_keeper_bounty_bps(100);
+    }
 
-        let lease_id = self
-            .lease_id_by_contract_addr_and_token_id
-            .get(&(
-                rent_acceptance_json.nft_contract_id,
-                rent_acceptance_json.nft_token_id,
-            ))
-            .expect("The targeting lease id does not exist!");
+    #[test]
+    #[should_panic(expected = "Keeper bounty is too large!")]
+    fn test_set_keeper_bounty_bps_fail_too_large() {
+        let mut contract = Contract::new(accounts(1).into());
 
-        self.activate_lease(lease_id);
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.set_keeper_bounty_bps(MAX_KEEPER_BOUNTY_BPS + 1);
+    }
 
-        // Specify the unused amount as required by NEP-141
-        let unused_ammount: U128 = U128::from(0);
-        return unused_ammount;
+    #[test]
+    #[should_panic(expected = "Only the owner can set the protocol fee")]
+    fn test_set_protocol_fee_bps_fail_when_called_by_nonowner() {
+        let mut contract = Contract::new(accounts(1).into());
+
+        contract.set_protocol_fee_bps(100);
     }
-}
 
-#[cfg(all(test, not(target_arch = "wasm32")))]
-mod tests {
-    /*
-    Unit test cases and helper functions
+    #[test]
+    #[should_panic(expected = "Protocol fee is too large!")]
+    fn test_set_protocol_fee_bps_fail_too_large() {
+        let mut contract = Contract::new(accounts(1).into());
 
-    Test naming format for better readability:
-    - test_{function_name} _{succeeds_or_fails} _{condition}
-    - When more than one test cases are needed for one function,
-    follow the code order of testing failing conditions first and success condition last
-    */
-    use super::*;
-    use near_sdk::serde_json::json;
-    use near_sdk::test_utils::{accounts, VMContextBuilder};
-    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.set_protocol_fee_bps(MAX_PROTOCOL_FEE_BPS + 1);
+    }
 
     #[test]
-    fn test_new() {
-        let contract = Contract::new(accounts(1).into());
-        assert_eq!(accounts(1), contract.owner);
-        assert!(UnorderedMap::is_empty(&contract.lease_map));
+    #[should_panic(expected = "Only the owner can set the LEASE token royalty")]
+    fn test_set_lease_token_royalty_bps_fail_when_called_by_nonowner() {
+        let mut contract = Contract::new(accounts(1).into());
+
+        contract.set_lease_token_royalty_bps(None);
     }
 
-    // TODO(syu): borrower check is done on marketside. Maybe update to check target lease exist, or remove this
-    fn test_lending_accept_wrong_borrower() {
+    #[test]
+    #[should_panic(expected = "LEASE token royalty is too large!")]
+    fn test_set_lease_token_royalty_bps_fail_too_large() {
         let mut contract = Contract::new(accounts(1).into());
-        let lease_condition = create_lease_condition_default();
-        let lease_id = "test_key".to_string();
 
-        contract.lease_map.insert(&lease_id, &lease_condition);
-        let wrong_borrower: AccountId = accounts(4).into();
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.set_lease_token_royalty_bps(Some(HashMap::from([
+            (accounts(2), MAX_LEASE_TOKEN_ROYALTY_BPS),
+            (accounts(3), 1),
+        ])));
+    }
+
+    #[test]
+    fn test_set_lease_token_royalty_bps_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        let royalty_bps = HashMap::from([(accounts(2), 500)]);
 
         testing_env!(VMContextBuilder::new()
-            .predecessor_account_id(lease_condition.ft_contract_addr.clone())
+            .predecessor_account_id(accounts(1))
             .build());
+        contract.set_lease_token_royalty_bps(Some(royalty_bps.clone()));
 
-        contract.ft_on_transfer(
-            wrong_borrower.clone(),
-            U128::from(lease_condition.price),
-            json!({ "lease_id": lease_id }).to_string(),
-        );
+        assert_eq!(contract.get_lease_token_royalty_bps(), Some(royalty_bps));
     }
 
     #[test]
-    #[should_panic(expected = "Wrong FT contract address!")]
-    fn test_lending_accept_fail_wrong_ft_addr() {
+    fn test_create_lease_with_payout_success_snapshots_protocol_fee() {
         let mut contract = Contract::new(accounts(1).into());
         let lease_condition = create_lease_condition_default();
-        let lease_id = "test_lease_id".to_string();
-        let wrong_ft_addr = accounts(0);
-        contract.lease_map.insert(&lease_id, &lease_condition);
-        // needed for finding the target lease_condition at ft_on_transfer
-        contract.lease_id_by_contract_addr_and_token_id.insert(
-            &(
-                lease_condition.contract_addr.clone(),
-                lease_condition.token_id.clone(),
-            ),
-            &lease_id,
-        );
+        register_storage(&mut contract, &lease_condition.lender_id);
 
         testing_env!(VMContextBuilder::new()
-            .predecessor_account_id(wrong_ft_addr.into())
+            .predecessor_account_id(accounts(1))
             .build());
+        contract.set_protocol_fee_bps(250);
 
-        let msg_rent_transfer_json = json!({
-            "nft_contract_id": lease_condition.contract_addr.clone().to_string(),
-            "nft_token_id": lease_condition.token_id.clone().to_string(),
-        })
-        .to_string();
-
-        contract.ft_on_transfer(
+        contract.create_lease_with_payout(
+            lease_condition.contract_addr.clone(),
+            lease_condition.token_id.clone(),
+            lease_condition.lender_id.clone(),
             lease_condition.borrower_id.clone(),
-            U128::from(lease_condition.price),
-            msg_rent_transfer_json,
+            RentTerms {
+                currency: lease_condition.currency.clone(),
+                price: U128::from(1000),
+                start_ts_nano: lease_condition.start_ts_nano,
+                end_ts_nano: lease_condition.end_ts_nano,
+                nft_payout: Payout {
+                    payout: HashMap::from([(lease_condition.lender_id.clone(), U128::from(1000))]),
+                },
+            },
+            LeaseTerms::default(),
+        );
+        // Changing the fee doesn't affect existing leases
+        contract.set_protocol_fee_bps(500);
+
+        let lease_id = contract
+            .lease_ids_by_contract_addr_and_token_id
+            .get(&(lease_condition.contract_addr, lease_condition.token_id))
+            .unwrap()[0]
+            .clone();
+        assert_eq!(
+            contract.lease_map.get(&lease_id).unwrap().protocol_fee_bps,
+            250
         );
+        assert_eq!(contract.get_protocol_fee_by_lease(lease_id), U128::from(25));
+
+        let logs = near_sdk::test_utils::get_logs();
+        assert!(logs[0].contains("\"protocol_fee\":\"25\""));
     }
 
     #[test]
-    #[should_panic(expected = "Transferred amount doesn't match the asked rent!")]
-    fn test_lending_accept_fail_wrong_rent() {
+    fn test_create_lease_with_payout_success_unique_lease_ids_in_same_block() {
         let mut contract = Contract::new(accounts(1).into());
         let lease_condition = create_lease_condition_default();
-        let lease_id = "test_lease_id".to_string();
-        contract.lease_map.insert(&lease_id, &lease_condition);
-        // needed for finding the target lease_condition at ft_on_transfer
-        contract.lease_id_by_contract_addr_and_token_id.insert(
-            &(
+        register_storage(&mut contract, &lease_condition.lender_id);
+
+        // Both leases are created with the same random seed
+        let token_ids = ["test_token_1".to_string(), "test_token_2".to_string()];
+        for token_id in token_ids.iter() {
+            contract.create_lease_with_payout(
                 lease_condition.contract_addr.clone(),
-                lease_condition.token_id.clone(),
-            ),
-            &lease_id,
+                token_id.clone(),
+                lease_condition.lender_id.clone(),
+                lease_condition.borrower_id.clone(),
+                RentTerms {
+                    currency: lease_condition.currency.clone(),
+                    price: lease_condition.price,
+                    start_ts_nano: lease_condition.start_ts_nano,
+                    end_ts_nano: lease_condition.end_ts_nano,
+                    nft_payout: Payout {
+                        payout: HashMap::from([(
+                            lease_condition.lender_id.clone(),
+                            lease_condition.price,
+                        )]),
+                    },
+                },
+                LeaseTerms::default(),
+            );
+        }
+
+        assert_eq!(contract.lease_map.len(), 2);
+        let lease_ids: Vec<LeaseId> = token_ids
+            .iter()
+            .map(|token_id| {
+                contract
+                    .lease_ids_by_contract_addr_and_token_id
+                    .get(&(lease_condition.contract_addr.clone(), token_id.clone()))
+                    .unwrap()[0]
+                    .clone()
+            })
+            .collect();
+        assert_ne!(lease_ids[0], lease_ids[1]);
+        assert_eq!(
+            contract.lease_map.get(&lease_ids[1]).unwrap().token_id,
+            token_ids[1]
         );
+    }
 
-        testing_env!(VMContextBuilder::new()
-            .predecessor_account_id(lease_condition.ft_contract_addr.clone())
-            .build());
+    #[test]
+    fn test_internal_pay_out_rent_success_accrues_protocol_fee() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.protocol_fee_bps = 250;
 
-        let msg_rent_transfer_json = json!({
-            "nft_contract_id": lease_condition.contract_addr.clone().to_string(),
-            "nft_token_id": lease_condition.token_id.clone().to_string(),
-        })
-        .to_string();
+        contract.internal_pay_out_rent(&lease_condition, 1000);
+        contract.internal_pay_out_rent(&lease_condition, 1000);
 
-        contract.ft_on_transfer(
-            lease_condition.borrower_id.clone(),
-            U128::from(lease_condition.price.0 - 1),
-            msg_rent_transfer_json,
+        assert_eq!(
+            contract.get_accrued_fees(lease_condition.currency),
+            U128::from(50)
         );
     }
 
     #[test]
-    #[should_panic(expected = "This lease is not pending on rent!")]
-    fn test_lending_accept_fail_wrong_lease_state() {
+    #[should_panic(expected = "Only the owner can withdraw the fees")]
+    fn test_withdraw_fees_fail_when_called_by_nonowner() {
         let mut contract = Contract::new(accounts(1).into());
-        let mut lease_condition = create_lease_condition_default();
-        lease_condition.state = LeaseState::Active;
-        let lease_id = "test_lease_id".to_string();
-        contract.lease_map.insert(&lease_id, &lease_condition);
-        // needed for finding the target lease_condition at ft_on_transfer
-        contract.lease_id_by_contract_addr_and_token_id.insert(
-            &(
-                lease_condition.contract_addr.clone(),
-                lease_condition.token_id.clone(),
-            ),
-            &lease_id,
-        );
+        contract.internal_accrue_fee(&Currency::Ft(accounts(5)), 10);
+
+        contract.withdraw_fees(Currency::Ft(accounts(5)));
+    }
+
+    #[test]
+    fn test_withdraw_fees_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        contract.internal_accrue_fee(&Currency::Ft(accounts(5)), 10);
 
         testing_env!(VMContextBuilder::new()
-            .predecessor_account_id(lease_condition.ft_contract_addr.clone())
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
             .build());
+        contract.withdraw_fees(Currency::Ft(accounts(5)));
 
-        let msg_rent_transfer_json = json!({
-            "nft_contract_id": lease_condition.contract_addr.clone().to_string(),
-            "nft_token_id": lease_condition.token_id.clone().to_string(),
-        })
-        .to_string();
-
-        contract.ft_on_transfer(
-            lease_condition.borrower_id.clone(),
-            U128::from(lease_condition.price),
-            msg_rent_transfer_json,
+        assert_eq!(
+            contract.get_accrued_fees(Currency::Ft(accounts(5))),
+            U128::from(0)
         );
     }
 
     #[test]
-    fn test_lending_accept_success() {
+    fn test_withdraw_fees_success_native_near() {
         let mut contract = Contract::new(accounts(1).into());
-        let lease_condition = create_lease_condition_default();
-        let lease_id = "test_lease_id".to_string();
-        contract.lease_map.insert(&lease_id, &lease_condition);
-        // needed for finding the target lease_condition at ft_on_transfer
-        contract.lease_id_by_contract_addr_and_token_id.insert(
-            &(
-                lease_condition.contract_addr.clone(),
-                lease_condition.token_id.clone(),
-            ),
-            &lease_id,
-        );
+        contract.internal_accrue_fee(&Currency::Near, 10);
 
         testing_env!(VMContextBuilder::new()
-            .predecessor_account_id(lease_condition.ft_contract_addr.clone())
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
             .build());
+        contract.withdraw_fees(Currency::Near);
 
-        let msg_rent_transfer_json = json!({
-            "nft_contract_id": lease_condition.contract_addr.clone().to_string(),
-            "nft_token_id": lease_condition.token_id.clone().to_string(),
-        })
-        .to_string();
-
-        contract.ft_on_transfer(
-            lease_condition.borrower_id.clone(),
-            U128::from(lease_condition.price),
-            msg_rent_transfer_json,
+        assert_eq!(contract.get_accrued_fees(Currency::Near), U128::from(0));
+        // The fees are transferred in NEAR, instead of calling an FT contract
+        let receipt = &near_sdk::test_utils::get_created_receipts()[0];
+        assert_eq!(receipt.receiver_id, contract.fee_receiver_id);
+        assert_eq!(
+            receipt.actions,
+            vec![near_sdk::mock::VmAction::Transfer { deposit: 10 }]
         );
-
-        // Nothing can be checked, except the fact the call doesn't panic.
     }
 
     #[test]
-    fn test_activate_lease_with_payout_success() {
+    fn test_resolve_withdraw_fees_success_transfer_failed() {
         let mut contract = Contract::new(accounts(1).into());
-        let mut lease_condition = create_lease_condition_default();
-        let key = "test_key".to_string();
-        let payout = Payout {
-            payout: HashMap::from([
-                (accounts(2).into(), U128::from(1)),
-                (accounts(3).into(), U128::from(4)),
-            ]),
-        };
-
-        lease_condition.payout = Some(payout.clone());
 
-        contract.lease_map.insert(&key, &lease_condition);
         testing_env!(
             VMContextBuilder::new()
                 .current_account_id(accounts(0))
-                .predecessor_account_id(lease_condition.borrower_id.clone())
+                .predecessor_account_id(accounts(0))
                 .build(),
             VMConfig::test(),
             RuntimeFeesConfig::test(),
             HashMap::default(),
-            vec![PromiseResult::Successful(Vec::new())],
+            vec![PromiseResult::Failed],
         );
+        contract.resolve_withdraw_fees(Currency::Ft(accounts(5)), U128::from(10));
 
-        contract.activate_lease(key.clone());
-
-        let lease_condition_result = contract.lease_map.get(&key).unwrap();
-        assert_eq!(lease_condition_result.payout, Some(payout));
-        assert_eq!(lease_condition_result.state, LeaseState::Active);
+        // The fees are put back for a later withdrawal
+        assert_eq!(
+            contract.get_accrued_fees(Currency::Ft(accounts(5))),
+            U128::from(10)
+        );
     }
 
     #[test]
-    fn test_activate_lease_without_payout_success() {
+    fn test_resolve_transfer_ft_success_transfer_failed() {
         let mut contract = Contract::new(accounts(1).into());
-        let lease_condition = create_lease_condition_default();
-        let key = "test_key".to_string();
 
-        contract.lease_map.insert(&key, &lease_condition);
         testing_env!(
             VMContextBuilder::new()
                 .current_account_id(accounts(0))
-                .predecessor_account_id(lease_condition.borrower_id.clone())
+                .predecessor_account_id(accounts(0))
                 .build(),
             VMConfig::test(),
             RuntimeFeesConfig::test(),
             HashMap::default(),
-            vec![PromiseResult::Successful(Vec::new())],
+            vec![PromiseResult::Failed],
         );
+        contract.resolve_transfer_ft(Currency::Ft(accounts(5)), accounts(2), U128::from(10));
+        contract.resolve_transfer_ft(Currency::Ft(accounts(5)), accounts(2), U128::from(5));
 
-        contract.activate_lease(key.clone());
-
-        let lease_condition_result = contract.lease_map.get(&key).unwrap();
-        assert_eq!(lease_condition_result.payout, None);
-        assert_eq!(lease_condition_result.state, LeaseState::Active);
+        assert_eq!(
+            contract.get_unclaimed_balance(accounts(2), Currency::Ft(accounts(5))),
+            U128::from(15)
+        );
     }
 
     #[test]
-    #[should_panic]
-    fn test_activate_lease_promise_panic() {
+    fn test_resolve_transfer_ft_success_transfer_succeeded() {
         let mut contract = Contract::new(accounts(1).into());
-        let lease_condition = create_lease_condition_default();
-        let key = "test_key".to_string();
-        contract.lease_map.insert(&key, &lease_condition);
 
         testing_env!(
             VMContextBuilder::new()
                 .current_account_id(accounts(0))
-                .predecessor_account_id(lease_condition.borrower_id.clone())
+                .predecessor_account_id(accounts(0))
                 .build(),
             VMConfig::test(),
             RuntimeFeesConfig::test(),
             HashMap::default(),
-            vec![PromiseResult::Failed],
+            vec![PromiseResult::Successful(Vec::new())],
         );
+        contract.resolve_transfer_ft(Currency::Ft(accounts(5)), accounts(2), U128::from(10));
 
-        contract.activate_lease(key.clone());
-
-        let lease_condition_result = contract.lease_map.get(&key).unwrap();
-        assert_eq!(lease_condition_result.payout, None);
-        assert_eq!(lease_condition_result.state, LeaseState::PendingOnRent);
+        assert_eq!(
+            contract.get_unclaimed_balance(accounts(2), Currency::Ft(accounts(5))),
+            U128::from(0)
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Lease has not expired yet!")]
-    fn test_claim_back_not_expired_yet() {
+    #[should_panic(expected = "No unclaimed balance to withdraw!")]
+    fn test_withdraw_unclaimed_fail_no_balance() {
         let mut contract = Contract::new(accounts(1).into());
 
-        let mut lease_condition = create_lease_condition_default();
-        lease_condition.state = LeaseState::Active;
-        lease_condition.end_ts_nano = 1000;
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.withdraw_unclaimed(Currency::Ft(accounts(5)));
+    }
 
-        let key = "test_key".to_string();
-        contract.lease_map.insert(&key, &lease_condition);
+    #[test]
+    fn test_withdraw_unclaimed_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        contract.internal_add_unclaimed(&accounts(2), &Currency::Ft(accounts(5)), 10);
 
         testing_env!(VMContextBuilder::new()
             .current_account_id(accounts(0))
-            .predecessor_account_id(lease_condition.lender_id.clone())
-            .block_timestamp(lease_condition.end_ts_nano - 1)
+            .predecessor_account_id(accounts(2))
             .build());
+        contract.withdraw_unclaimed(Currency::Ft(accounts(5)));
 
-        contract.claim_back(key);
+        assert_eq!(
+            contract.get_unclaimed_balance(accounts(2), Currency::Ft(accounts(5))),
+            U128::from(0)
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Only original lender or service owner can claim back!")]
-    fn test_claim_back_wrong_lender() {
+    #[should_panic(expected = "Only the owner can allow NEAR")]
+    fn test_set_near_allowed_fail_when_called_by_nonowner() {
         let mut contract = Contract::new(accounts(1).into());
-        let mut lease_condition = create_lease_condition_default();
-        lease_condition.state = LeaseState::Active;
-        let key = "test_key".to_string();
 
-        contract.lease_map.insert(&key, &lease_condition);
+        contract.set_near_allowed(true);
+    }
+
+    #[test]
+    fn test_set_near_allowed_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        assert!(!contract.is_near_allowed());
 
         testing_env!(VMContextBuilder::new()
-            .current_account_id(accounts(0))
-            .predecessor_account_id(accounts(5).into()) // non-owner, non-lender
-            .block_timestamp(lease_condition.end_ts_nano + 1)
+            .predecessor_account_id(accounts(1))
             .build());
+        contract.set_near_allowed(true);
 
-        contract.claim_back(key);
+        // NEAR is allowed apart from the FTs, so no FT contract is allowed along with it
+        assert!(contract.is_near_allowed());
+        assert!(contract.get_allowed_ft_contract_addrs().is_empty());
     }
 
     #[test]
-    #[should_panic(expected = "Queried Lease is not active!")]
-    fn test_claim_back_inactive_lease() {
+    fn test_resolve_add_allowed_ft_contract_addr_success_metadata_fetched() {
         let mut contract = Contract::new(accounts(1).into());
-        let mut lease_condition = create_lease_condition_default();
-        lease_condition.state = LeaseState::PendingOnRent;
-        let key = "test_key".to_string();
+        let ft_metadata = FungibleTokenMetadata {
+            spec: "ft-1.0.0".to_string(),
+            name: "USD Coin".to_string(),
+            symbol: "USDC".to_string(),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals: 6,
+        };
 
-        contract.lease_map.insert(&key, &lease_condition);
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(
+                near_sdk::serde_json::to_vec(&ft_metadata).unwrap()
+            )],
+        );
 
-        testing_env!(VMContextBuilder::new()
-            .current_account_id(accounts(0))
-            .predecessor_account_id(lease_condition.lender_id.clone())
-            .block_timestamp(lease_condition.end_ts_nano + 1)
-            .build());
+        assert!(contract.resolve_add_allowed_ft_contract_addr(accounts(5)));
+        assert_eq!(contract.get_allowed_ft_contract_addrs(), vec![accounts(5)]);
+        assert_eq!(
+            contract.get_allowed_ft_metadata(accounts(5)),
+            Some(FtMetadata {
+                symbol: "USDC".to_string(),
+                decimals: 6,
+            })
+        );
+    }
 
-        contract.claim_back(key);
+    #[test]
+    fn test_resolve_add_allowed_ft_contract_addr_success_metadata_not_fetched() {
+        let mut contract = Contract::new(accounts(1).into());
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Failed],
+        );
+
+        // An account which isn't an FT contract is not allowed
+        assert!(!contract.resolve_add_allowed_ft_contract_addr(accounts(5)));
+        assert!(contract.get_allowed_ft_contract_addrs().is_empty());
+        assert!(contract.get_allowed_ft_metadata(accounts(5)).is_none());
     }
 
     #[test]
-    #[should_panic]
-    fn test_claim_back_non_exsisting_lease_id() {
+    #[should_panic(expected = "FT contract is not allowed!")]
+    fn test_remove_allowed_ft_contract_addr_fail_not_allowed() {
         let mut contract = Contract::new(accounts(1).into());
-        let mut lease_condition = create_lease_condition_default();
-        lease_condition.state = LeaseState::Active;
-        let key = "test_key".to_string();
-        contract.lease_map.insert(&key, &lease_condition);
 
         testing_env!(VMContextBuilder::new()
-            .current_account_id(accounts(0))
-            .predecessor_account_id(lease_condition.lender_id.clone())
-            .block_timestamp(lease_condition.end_ts_nano + 1)
+            .predecessor_account_id(accounts(1))
             .build());
-
-        let non_existing_key = "dummy_key".to_string();
-        contract.claim_back(non_existing_key);
+        contract.remove_allowed_ft_contract_addr(accounts(5));
     }
 
     #[test]
-    fn test_claim_back_success() {
+    fn test_remove_allowed_ft_contract_addr_success() {
         let mut contract = Contract::new(accounts(1).into());
-        let mut lease_condition = create_lease_condition_default();
-        lease_condition.state = LeaseState::Active;
-        lease_condition.price = U128::from(20);
-        let key = "test_key".to_string();
-        contract.internal_insert_lease(&key, &lease_condition);
+        allow_ft(&mut contract, &accounts(4));
+        allow_ft(&mut contract, &accounts(5));
 
         testing_env!(VMContextBuilder::new()
-            .current_account_id(accounts(0))
-            .predecessor_account_id(lease_condition.lender_id.clone())
-            .block_timestamp(lease_condition.end_ts_nano + 1)
+            .predecessor_account_id(accounts(1))
             .build());
+        contract.remove_allowed_ft_contract_addr(accounts(4));
 
-        contract.claim_back(key);
-
-        // Nothing can be checked, except the fact the call doesn't panic.
+        assert_eq!(contract.get_allowed_ft_contract_addrs(), vec![accounts(5)]);
+        assert!(contract.get_allowed_ft_metadata(accounts(4)).is_none());
     }
 
     #[test]
-    fn test_get_borrower_by_contract_and_token_success_no_matching_borrower() {
+    fn test_get_readable_price_by_lease_success() {
         let mut contract = Contract::new(accounts(1).into());
         let mut lease_condition = create_lease_condition_default();
+        lease_condition.price = U128::from(1_500_000);
+        let lease_id = "test_lease_id".to_string();
+        contract.lease_map.insert(&lease_id, &lease_condition);
 
-        let expected_contract_address: AccountId = accounts(4).into();
-        let expected_token_id = "test_token".to_string();
-        let expected_borrower_id: AccountId = accounts(3).into();
-
-        lease_condition.state = LeaseState::Active;
-        lease_condition.contract_addr = expected_contract_address.clone();
-        lease_condition.token_id = expected_token_id.clone();
-        lease_condition.borrower_id = expected_borrower_id.clone();
-
-        let key = "test_key".to_string();
-        contract.internal_insert_lease(&key, &lease_condition);
-
-        let test_contract_id: AccountId = accounts(5).into();
-        let test_token_id = "dummy_token".to_string();
-
-        let result_borrower = contract.get_borrower_by_contract_and_token(
-            test_contract_id.clone(),
-            expected_token_id.clone(),
+        // Without the FT metadata, the raw amount is shown
+        assert_eq!(
+            contract.get_readable_price_by_lease(lease_id.clone()),
+            "1500000 fargo".to_string()
         );
-        assert!(result_borrower.is_none());
 
-        let result_borrower = contract.get_borrower_by_contract_and_token(
-            expected_contract_address.clone(),
-            test_token_id.clone(),
+        allow_ft(&mut contract, &accounts(5));
+        assert_eq!(
+            contract.get_readable_price_by_lease(lease_id),
+            "1.5 USDC".to_string()
         );
-        assert!(result_borrower.is_none());
     }
 
     #[test]
-    fn test_get_borrower_by_contract_and_token_success_lease_is_inactive() {
+    fn test_get_readable_price_by_lease_success_native_near() {
         let mut contract = Contract::new(accounts(1).into());
         let mut lease_condition = create_lease_condition_default();
+        lease_condition.currency = Currency::Near;
+        lease_condition.price = U128::from(2_050_000_000_000_000_000_000_000);
+        let lease_id = "test_lease_id".to_string();
+        contract.lease_map.insert(&lease_id, &lease_condition);
 
-        let expected_contract_address: AccountId = accounts(4).into();
-        let expected_token_id = "test_token".to_string();
-        let expected_borrower_id: AccountId = accounts(3).into();
-
-        lease_condition.state = LeaseState::PendingOnRent;
-        lease_condition.contract_addr = expected_contract_address.clone();
-        lease_condition.token_id = expected_token_id.clone();
-        lease_condition.borrower_id = expected_borrower_id.clone();
-
-        let key = "test_key".to_string();
-        contract.internal_insert_lease(&key, &lease_condition);
-
-        let result_borrower = contract
-            .get_borrower_by_contract_and_token(expected_contract_address, expected_token_id);
-        assert!(result_borrower.is_none());
+        assert_eq!(
+            contract.get_readable_price_by_lease(lease_id),
+            "2.05 NEAR".to_string()
+        );
     }
 
     #[test]
-    fn test_get_current_user_by_contract_and_token_success_found_matching_borrower() {
+    #[should_panic(expected = "Only the borrower can sub-lease the NFT!")]
+    fn test_create_sublease_fails_wrong_borrower() {
         let mut contract = Contract::new(accounts(1).into());
         let mut lease_condition = create_lease_condition_default();
-
-        let expected_contract_address: AccountId = accounts(4).into();
-        let expected_token_id = "test_token".to_string();
-        let expected_borrower_id: AccountId = accounts(3).into();
-
         lease_condition.state = LeaseState::Active;
-        lease_condition.contract_addr = expected_contract_address.clone();
-        lease_condition.token_id = expected_token_id.clone();
-        lease_condition.borrower_id = expected_borrower_id.clone();
-
         let key = "test_key".to_string();
         contract.internal_insert_lease(&key, &lease_condition);
 
         testing_env!(VMContextBuilder::new()
-            .current_account_id(accounts(0))
-            .block_timestamp(10)
+            .predecessor_account_id(lease_condition.lender_id.clone())
             .build());
 
-        let result_owner = contract
-            .get_current_user_by_contract_and_token(expected_contract_address, expected_token_id)
-            .unwrap();
-        assert!(result_owner == expected_borrower_id);
-    }
-
-    #[test]
-    fn test_get_current_user_by_contract_and_token_success_lease_is_inactive() {
-        let mut contract = Contract::new(accounts(1).into());
-        let mut lease_condition = create_lease_condition_default();
-
-        let expected_contract_address: AccountId = accounts(4).into();
-        let expected_token_id = "test_token".to_string();
-        let expected_lender_id: AccountId = accounts(2).into();
-        let expected_borrower_id: AccountId = accounts(3).into();
-
-        lease_condition.state = LeaseState::PendingOnRent;
-        lease_condition.contract_addr = expected_contract_address.clone();
-        lease_condition.token_id = expected_token_id.clone();
-        lease_condition.lender_id = expected_lender_id.clone();
-        lease_condition.borrower_id = expected_borrower_id.clone();
-
-        let key = "test_key".to_string();
-        contract.internal_insert_lease(&key, &lease_condition);
-
-        let result_owner = contract
-            .get_current_user_by_contract_and_token(expected_contract_address, expected_token_id)
-            .unwrap();
-        assert!(result_owner == expected_lender_id);
+        contract.create_sublease(
+            key,
+            accounts(0),
+            U64::from(100),
+            U64::from(200),
+            U128::from(2),
+        );
     }
 
     #[test]
-    fn test_get_current_user_by_contract_and_token_success_after_lease_expires() {
+    #[should_panic(expected = "Sub-leasing is not allowed for this lease!")]
+    fn test_create_sublease_fails_forbidden_by_lender() {
         let mut contract = Contract::new(accounts(1).into());
-        let mut lease_condition = create_lease_condition_default();
-
-        let expected_contract_address: AccountId = accounts(4).into();
-        let expected_token_id = "test_token".to_string();
-        let expected_borrower_id: AccountId = accounts(3).into();
-        let expected_lender_id: AccountId = accounts(2).into();
-
-        lease_condition.state = LeaseState::Active;
-        lease_condition.contract_addr = expected_contract_address.clone();
-        lease_condition.token_id = expected_token_id.clone();
-        lease_condition.lender_id = expected_lender_id.clone();
-        lease_condition.borrower_id = expected_borrower_id.clone();
-
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        lease_condition.sublease_allowed = false;
         let key = "test_key".to_string();
         contract.internal_insert_lease(&key, &lease_condition);
 
         testing_env!(VMContextBuilder::new()
-            .current_account_id(accounts(0))
-            .block_timestamp(1000)
+            .predecessor_account_id(lease_condition.borrower_id.clone())
             .build());
 
-        let result_owner = contract
-            .get_current_user_by_contract_and_token(expected_contract_address, expected_token_id)
-            .unwrap();
-        assert!(result_owner == expected_lender_id);
+        contract.create_sublease(
+            key,
+            accounts(0),
+            U64::from(100),
+            U64::from(200),
+            U128::from(2),
+        );
     }
 
     #[test]
-    fn test_get_current_user_by_contract_and_token_success_lease_not_start() {
+    #[should_panic(expected = "Sub-lease should be inside the parent lease!")]
+    fn test_create_sublease_fails_outside_parent_lease() {
         let mut contract = Contract::new(accounts(1).into());
+        allow_ft(&mut contract, &accounts(5));
         let mut lease_condition = create_lease_condition_default();
-
-        let expected_contract_address: AccountId = accounts(4).into();
-        let expected_token_id = "test_token".to_string();
-        let expected_lender_id: AccountId = accounts(2).into();
-        let expected_borrower_id: AccountId = accounts(3).into();
-
-        lease_condition.state = LeaseState::PendingOnRent;
-        lease_condition.contract_addr = expected_contract_address.clone();
-        lease_condition.token_id = expected_token_id.clone();
-        lease_condition.lender_id = expected_lender_id.clone();
-        lease_condition.borrower_id = expected_borrower_id.clone();
-        // 2333/01/01 00:00
-        lease_condition.start_ts_nano = 11455171200000000000;
-
+        lease_condition.state = LeaseState::Active;
         let key = "test_key".to_string();
         contract.internal_insert_lease(&key, &lease_condition);
 
-        let result_owner = contract
-            .get_current_user_by_contract_and_token(expected_contract_address, expected_token_id)
-            .unwrap();
-        assert!(result_owner == expected_lender_id);
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(lease_condition.borrower_id.clone())
+            .build());
+
+        contract.create_sublease(
+            key,
+            accounts(0),
+            U64::from(100),
+            U64::from(lease_condition.end_ts_nano + 1),
+            U128::from(2),
+        );
     }
 
     #[test]
-    fn test_get_borrower_by_contract_and_token_success_found_matching_borrower() {
+    #[should_panic(expected = "The NFT is sub-leased!")]
+    fn test_create_sublease_fails_already_subleased() {
         let mut contract = Contract::new(accounts(1).into());
-        let mut lease_condition = create_lease_condition_default();
+        let (key, _) = create_lease_with_active_sublease(&mut contract);
+        let lease_condition = contract.lease_map.get(&key).unwrap();
 
-        let expected_contract_address: AccountId = accounts(4).into();
-        let expected_token_id = "test_token".to_string();
-        let expected_borrower_id: AccountId = accounts(3).into();
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(lease_condition.borrower_id.clone())
+            .build());
 
-        lease_condition.state = LeaseState::Active;
-        lease_condition.contract_addr = expected_contract_address.clone();
-        lease_condition.token_id = expected_token_id.clone();
-        lease_condition.borrower_id = expected_borrower_id.clone();
+        contract.create_sublease(
+            key,
+            accounts(0),
+            U64::from(300),
+            U64::from(400),
+            U128::from(2),
+        );
+    }
 
+    #[test]
+    fn test_create_sublease_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        allow_ft(&mut contract, &accounts(5));
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
         let key = "test_key".to_string();
         contract.internal_insert_lease(&key, &lease_condition);
+        register_storage(&mut contract, &lease_condition.borrower_id);
 
-        let result_borrower = contract
-            .get_borrower_by_contract_and_token(expected_contract_address, expected_token_id)
-            .unwrap();
-        assert!(result_borrower == expected_borrower_id);
-    }
-
-    /// Creat two leases using the same lender
-    /// Before the leases got actived, active_leases_by_lender() should return 0 leases
-    /// After both leases got actived, active_leases_by_lender() should return 2 leases
-    #[test]
-    fn test_active_leases_by_lender_succeeds() {
-        let mut contract = Contract::new(accounts(0).into());
-        let expected_lender_id: AccountId = accounts(2).into();
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(lease_condition.borrower_id.clone())
+            .build());
 
-        let mut lease_condition_1 = create_lease_condition_default();
-        lease_condition_1.token_id = "test_token_1".to_string();
-        lease_condition_1.lender_id = expected_lender_id.clone();
-        let key_1 = "test_key_1".to_string();
-        contract.internal_insert_lease(&key_1, &lease_condition_1);
+        let sublease_id = contract.create_sublease(
+            key.clone(),
+            accounts(0),
+            U64::from(100),
+            U64::from(200),
+            U128::from(2),
+        );
 
-        let mut lease_condition_2 = create_lease_condition_default();
-        lease_condition_2.token_id = "test_token_2".to_string();
-        lease_condition_2.lender_id = expected_lender_id.clone();
-        let key_2 = "test_key_2".to_string();
-        contract.internal_insert_lease(&key_2, &lease_condition_2);
+        let sublease_condition = contract.lease_map.get(&sublease_id).unwrap();
+        assert_eq!(sublease_condition.lender_id, lease_condition.borrower_id);
+        assert_eq!(sublease_condition.borrower_id, accounts(0));
+        assert_eq!(sublease_condition.state, LeaseState::PendingOnRent);
+        assert_eq!(sublease_condition.parent_lease_id, Some(key.clone()));
+        assert_eq!(
+            contract.lease_map.get(&key).unwrap().sublease_id,
+            Some(sublease_id)
+        );
+        // The NFT is still indexed by the outermost lease
+        assert_eq!(
+            contract
+                .lease_ids_by_contract_addr_and_token_id
+                .get(&(lease_condition.contract_addr, lease_condition.token_id)),
+            Some(vec![key])
+        );
+    }
 
-        // check before the leases got activated
-        let active_leases = contract.active_leases_by_lender(expected_lender_id.clone());
-        assert_eq!(active_leases.len(), 0);
+    #[test]
+    fn test_sublease_rent_accept_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        allow_ft(&mut contract, &accounts(5));
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+        register_storage(&mut contract, &lease_condition.borrower_id);
 
-        // activate the 1st lease
-        testing_env!(
-            VMContextBuilder::new()
-                .current_account_id(accounts(0))
-                .build(),
-            VMConfig::test(),
-            RuntimeFeesConfig::test(),
-            HashMap::default(),
-            vec![PromiseResult::Successful(Vec::new())],
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(lease_condition.borrower_id.clone())
+            .build());
+        let sublease_id = contract.create_sublease(
+            key,
+            accounts(0),
+            U64::from(100),
+            U64::from(200),
+            U128::from(2),
         );
-        contract.activate_lease(key_1.clone());
 
-        // activate the 2nd lease
-        testing_env!(
-            VMContextBuilder::new()
-                .current_account_id(accounts(0))
-                .build(),
-            VMConfig::test(),
-            RuntimeFeesConfig::test(),
-            HashMap::default(),
-            vec![PromiseResult::Successful(Vec::new())],
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(ft_contract_addr(&lease_condition))
+            .build());
+        contract.ft_on_transfer(
+            accounts(0),
+            U128::from(2),
+            json!({ "sublease_id": sublease_id }).to_string(),
         );
-        contract.activate_lease(key_2.clone());
 
-        // test after the leases got activated
-        let active_leases = contract.active_leases_by_lender(expected_lender_id.clone());
-        assert_eq!(active_leases.len(), 2);
+        let sublease_condition = contract.lease_map.get(&sublease_id).unwrap();
+        assert_eq!(sublease_condition.state, LeaseState::Active);
+        // No LEASE token is minted for a sub-lease
+        assert!(!contract.active_lease_ids.contains(&sublease_id));
     }
 
     #[test]
-    fn test_leases_by_borrower_success() {
+    fn test_get_current_user_by_contract_and_token_success_sublease_borrower() {
         let mut contract = Contract::new(accounts(1).into());
-        let expected_borrower_id: AccountId = accounts(3).into();
-
-        let mut lease_condition_1 = create_lease_condition_default();
-        lease_condition_1.state = LeaseState::Active;
-        lease_condition_1.token_id = "test_token_1".to_string();
-        lease_condition_1.borrower_id = expected_borrower_id.clone();
+        let (key, sublease_id) = create_lease_with_active_sublease(&mut contract);
+        let lease_condition = contract.lease_map.get(&key).unwrap();
+        let sublease_condition = contract.lease_map.get(&sublease_id).unwrap();
 
-        let key_1 = "test_key_1".to_string();
-        contract.internal_insert_lease(&key_1, &lease_condition_1);
+        // inside the sub-lease, the sub-lease borrower is the current user
+        testing_env!(VMContextBuilder::new()
+            .block_timestamp(sublease_condition.start_ts_nano + 1)
+            .build());
+        assert_eq!(
+            contract.get_current_user_by_contract_and_token(
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone(),
+            ),
+            Some(sublease_condition.borrower_id)
+        );
 
-        let mut lease_condition_2 = create_lease_condition_default();
-        lease_condition_2.state = LeaseState::Active;
-        lease_condition_2.token_id = "test_token_2".to_string();
-        lease_condition_2.borrower_id = expected_borrower_id.clone();
+        // outside the sub-lease, the borrower of the parent lease is the current user
+        testing_env!(VMContextBuilder::new()
+            .block_timestamp(sublease_condition.end_ts_nano + 1)
+            .build());
+        assert_eq!(
+            contract.get_current_user_by_contract_and_token(
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone(),
+            ),
+            Some(lease_condition.borrower_id)
+        );
+    }
 
-        let key_2 = "test_key_2".to_string();
-        contract.internal_insert_lease(&key_2, &lease_condition_2);
+    #[test]
+    fn test_claim_back_success_sublease() {
+        let mut contract = Contract::new(accounts(1).into());
+        let (key, sublease_id) = create_lease_with_active_sublease(&mut contract);
+        let sublease_condition = contract.lease_map.get(&sublease_id).unwrap();
 
         testing_env!(VMContextBuilder::new()
             .current_account_id(accounts(0))
-            .predecessor_account_id(lease_condition_1.lender_id.clone())
-            .block_timestamp(lease_condition_1.end_ts_nano + 1)
+            .predecessor_account_id(sublease_condition.lender_id.clone())
+            .block_timestamp(sublease_condition.end_ts_nano + 1)
             .build());
 
-        let result = contract.leases_by_borrower(expected_borrower_id.clone());
-        assert_eq!(result.len(), 2);
+        contract.claim_back(sublease_id.clone());
+
+        // The sub-lease is settled right away, with the parent lease left intact
+        assert!(contract.lease_map.get(&sublease_id).is_none());
+        assert!(contract.lease_map.get(&key).unwrap().sublease_id.is_none());
     }
 
     #[test]
-    fn test_leases_by_owner_success() {
+    fn test_resolve_claim_back_success_with_sublease() {
         let mut contract = Contract::new(accounts(1).into());
-        let expected_owner_id: AccountId = accounts(2).into();
+        let (key, sublease_id) = create_lease_with_active_sublease(&mut contract);
 
-        let mut lease_condition_1 = create_lease_condition_default();
-        lease_condition_1.state = LeaseState::Active;
-        lease_condition_1.token_id = "test_token_1".to_string();
-        lease_condition_1.lender_id = expected_owner_id.clone();
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(Vec::new())],
+        );
 
-        let key_1 = "test_key_1".to_string();
-        contract.internal_insert_lease(&key_1, &lease_condition_1);
+        assert!(contract.resolve_claim_back(key.clone(), None));
 
-        let mut lease_condition_2 = create_lease_condition_default();
-        lease_condition_2.state = LeaseState::Active;
-        lease_condition_2.token_id = "test_token_2".to_string();
-        lease_condition_2.lender_id = expected_owner_id.clone();
+        assert!(contract.lease_map.get(&key).is_none());
+        assert!(contract.lease_map.get(&sublease_id).is_none());
+    }
 
-        let key_2 = "test_key_2".to_string();
-        contract.internal_insert_lease(&key_2, &lease_condition_2);
+    #[test]
+    #[should_panic(expected = "The NFT is sub-leased!")]
+    fn test_return_early_fails_subleased() {
+        let mut contract = Contract::new(accounts(1).into());
+        let (key, _) = create_lease_with_active_sublease(&mut contract);
+        let lease_condition = contract.lease_map.get(&key).unwrap();
 
-        let mut builder = VMContextBuilder::new();
-        testing_env!(builder
+        testing_env!(VMContextBuilder::new()
             .current_account_id(accounts(0))
-            .predecessor_account_id(lease_condition_1.lender_id.clone())
-            .block_timestamp(lease_condition_1.end_ts_nano + 1)
+            .predecessor_account_id(lease_condition.borrower_id.clone())
+            .block_timestamp(lease_condition.end_ts_nano - 1)
             .build());
 
-        let result = contract.leases_by_owner(expected_owner_id.clone());
-        assert_eq!(result.len(), 2);
+        contract.return_early(key);
     }
 
     #[test]
-    fn test_internal_insert_lease_success() {
+    #[should_panic(expected = "The NFT is sub-leased to another user!")]
+    fn test_proxy_func_calls_fails_subleased() {
         let mut contract = Contract::new(accounts(1).into());
-        let mut lease_condition = create_lease_condition_default();
-        lease_condition.state = LeaseState::Active;
-        lease_condition.price = U128::from(20);
-        lease_condition.contract_addr = accounts(4).into();
-        lease_condition.token_id = "test_token".to_string();
-        let key = "test_key".to_string();
+        let (key, sublease_id) = create_lease_with_active_sublease(&mut contract);
+        let lease_condition = contract.lease_map.get(&key).unwrap();
+        let sublease_condition = contract.lease_map.get(&sublease_id).unwrap();
 
-        assert!(contract.lease_map.is_empty());
-        assert!(!contract
-            .lease_ids_by_borrower
-            .contains_key(&lease_condition.borrower_id));
-        assert!(!contract
-            .lease_ids_by_lender
-            .contains_key(&lease_condition.lender_id));
-        assert!(!contract
-            .lease_id_by_contract_addr_and_token_id
-            .contains_key(&(
-                lease_condition.contract_addr.clone(),
-                lease_condition.token_id.clone()
-            )));
-
-        contract.internal_insert_lease(&key, &lease_condition);
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(lease_condition.borrower_id.clone())
+            .block_timestamp(sublease_condition.start_ts_nano + 1)
+            .build());
 
-        assert!(contract.lease_map.len() == 1);
-        assert!(contract
-            .lease_ids_by_borrower
-            .contains_key(&lease_condition.borrower_id));
-        assert!(contract
-            .lease_ids_by_lender
-            .contains_key(&lease_condition.lender_id));
-        assert!(contract
-            .lease_id_by_contract_addr_and_token_id
-            .contains_key(&(
-                lease_condition.contract_addr.clone(),
-                lease_condition.token_id.clone()
-            )));
+        contract.proxy_func_calls(key, "play".to_string(), "".to_string(), None);
     }
 
     #[test]
-    fn test_internal_remove_lease_success_only_one_lease() {
+    #[should_panic(expected = "Only the borrower can make proxy calls!")]
+    fn test_proxy_func_calls_fails_wrong_borrower() {
         let mut contract = Contract::new(accounts(1).into());
-        let mut lease_condition = create_lease_condition_default();
-        lease_condition.state = LeaseState::Active;
-        lease_condition.price = U128::from(20);
-        lease_condition.contract_addr = accounts(4).into();
-        lease_condition.token_id = "test_token".to_string();
+        let lease_condition = create_lease_condition_with_allowed_methods();
         let key = "test_key".to_string();
-
         contract.internal_insert_lease(&key, &lease_condition);
 
-        assert!(contract.lease_map.len() == 1);
-        assert!(contract
-            .lease_ids_by_borrower
-            .contains_key(&lease_condition.borrower_id));
-        assert!(contract
-            .lease_ids_by_lender
-            .contains_key(&lease_condition.lender_id));
-        assert!(contract
-            .lease_id_by_contract_addr_and_token_id
-            .contains_key(&(
-                lease_condition.contract_addr.clone(),
-                lease_condition.token_id.clone()
-            )));
-
-        contract.internal_remove_lease(&key);
-
-        assert!(contract.lease_map.is_empty());
-        assert!(!contract
-            .lease_ids_by_borrower
-            .contains_key(&lease_condition.borrower_id));
-        assert!(!contract
-            .lease_ids_by_lender
-            .contains_key(&lease_condition.lender_id));
-        assert!(!contract
-            .lease_id_by_contract_addr_and_token_id
-            .contains_key(&(
-                lease_condition.contract_addr.clone(),
-                lease_condition.token_id.clone()
-            )));
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(lease_condition.lender_id.clone())
+            .block_timestamp(lease_condition.start_ts_nano + 1)
+            .build());
+
+        contract.proxy_func_calls(key, "play".to_string(), "".to_string(), None);
     }
 
     #[test]
-    fn test_internal_remove_lease_success_different_owners() {
+    #[should_panic(expected = "Proxy calls are only allowed during the lease!")]
+    fn test_proxy_func_calls_fails_lease_expired() {
         let mut contract = Contract::new(accounts(1).into());
-        let owner_1: AccountId = accounts(2).into();
-        let owner_2: AccountId = accounts(4).into();
+        let lease_condition = create_lease_condition_with_allowed_methods();
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
 
-        let mut lease_condition_1 = create_lease_condition_default();
-        lease_condition_1.state = LeaseState::Active;
-        lease_condition_1.token_id = "test_token_1".to_string();
-        lease_condition_1.lender_id = owner_1.clone();
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(lease_condition.borrower_id.clone())
+            .block_timestamp(lease_condition.end_ts_nano + 1)
+            .build());
 
-        let key_1 = "test_key_1".to_string();
-        contract.internal_insert_lease(&key_1, &lease_condition_1);
+        contract.proxy_func_calls(key, "play".to_string(), "".to_string(), None);
+    }
 
-        let mut lease_condition_2 = create_lease_condition_default();
-        lease_condition_2.state = LeaseState::Active;
-        lease_condition_2.token_id = "test_token_2".to_string();
-        lease_condition_2.lender_id = owner_2.clone();
+    #[test]
+    #[should_panic(expected = "Calling method is not accessiable!")]
+    fn test_proxy_func_calls_fails_nft_transfer_always_blocked() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_with_allowed_methods();
+        lease_condition.allowed_methods.push(AllowedMethod {
+            method_name: "nft_transfer".to_string(),
+            max_deposit: None,
+            max_gas: None,
+        });
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
 
-        let key_2 = "test_key_2".to_string();
-        contract.internal_insert_lease(&key_2, &lease_condition_2);
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(lease_condition.borrower_id.clone())
+            .block_timestamp(lease_condition.start_ts_nano + 1)
+            .build());
 
-        assert!(contract.lease_map.len() == 2);
-        assert!(contract.lease_ids_by_lender.contains_key(&owner_1));
-        assert!(contract.lease_ids_by_lender.contains_key(&owner_2));
+        contract.proxy_func_calls(key, "nft_transfer".to_string(), "".to_string(), None);
+    }
 
-        contract.internal_remove_lease(&key_1);
+    #[test]
+    #[should_panic(expected = "Calling method is not accessiable!")]
+    fn test_proxy_func_calls_fails_method_not_allowed() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_with_allowed_methods();
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
 
-        assert!(contract.lease_map.len() == 1);
-        assert!(!contract.lease_ids_by_lender.contains_key(&owner_1));
-        assert!(contract.lease_ids_by_lender.contains_key(&owner_2));
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(lease_condition.borrower_id.clone())
+            .block_timestamp(lease_condition.start_ts_nano + 1)
+            .build());
+
+        contract.proxy_func_calls(key, "set_admin".to_string(), "".to_string(), None);
     }
 
     #[test]
-    fn test_internal_remove_lease_success_different_borrowers() {
+    #[should_panic(expected = "Attached deposit exceeds the allowed maximum!")]
+    fn test_proxy_func_calls_fails_deposit_too_large() {
         let mut contract = Contract::new(accounts(1).into());
-        let borrower_1: AccountId = accounts(3).into();
-        let borrower_2: AccountId = accounts(4).into();
+        let lease_condition = create_lease_condition_with_allowed_methods();
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
 
-        let mut lease_condition_1 = create_lease_condition_default();
-        lease_condition_1.state = LeaseState::Active;
-        lease_condition_1.token_id = "test_token_1".to_string();
-        lease_condition_1.borrower_id = borrower_1.clone();
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(lease_condition.borrower_id.clone())
+            .block_timestamp(lease_condition.start_ts_nano + 1)
+            .attached_deposit(11)
+            .build());
 
-        let key_1 = "test_key_1".to_string();
-        contract.internal_insert_lease(&key_1, &lease_condition_1);
+        contract.proxy_func_calls(key, "play".to_string(), "".to_string(), None);
+    }
 
-        let mut lease_condition_2 = create_lease_condition_default();
-        lease_condition_2.state = LeaseState::Active;
-        lease_condition_2.token_id = "test_token_2".to_string();
-        lease_condition_2.borrower_id = borrower_2.clone();
+    #[test]
+    #[should_panic(expected = "Requested gas exceeds the allowed maximum!")]
+    fn test_proxy_func_calls_fails_gas_above_contract_max() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_with_allowed_methods();
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
 
-        let key_2 = "test_key_2".to_string();
-        contract.internal_insert_lease(&key_2, &lease_condition_2);
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(lease_condition.borrower_id.clone())
+            .block_timestamp(lease_condition.start_ts_nano + 1)
+            .build());
 
-        assert!(contract.lease_map.len() == 2);
-        assert!(contract.lease_ids_by_borrower.contains_key(&borrower_1));
-        assert!(contract.lease_ids_by_borrower.contains_key(&borrower_2));
+        contract.proxy_func_calls(
+            key,
+            "play".to_string(),
+            "".to_string(),
+            Some(U64::from(DEFAULT_MAX_PROXY_CALL_GAS.0 + 1)),
+        );
+    }
 
-        contract.internal_remove_lease(&key_1);
+    #[test]
+    #[should_panic(expected = "Requested gas exceeds the allowed maximum!")]
+    fn test_proxy_func_calls_fails_gas_above_method_max() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_with_allowed_methods();
+        lease_condition.allowed_methods[0].max_gas = Some(U64::from(10 * TGAS));
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
 
-        assert!(contract.lease_map.len() == 1);
-        assert!(!contract.lease_ids_by_borrower.contains_key(&borrower_1));
-        assert!(contract.lease_ids_by_borrower.contains_key(&borrower_2));
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(lease_condition.borrower_id.clone())
+            .block_timestamp(lease_condition.start_ts_nano + 1)
+            .build());
+
+        contract.proxy_func_calls(
+            key,
+            "play".to_string(),
+            "".to_string(),
+            Some(U64::from(10 * TGAS + 1)),
+        );
     }
 
-    /// 1. Initially, Alice owns an active lease
-    /// 2. Alice transfers the lease to Bob
-    /// 3. Check Success:
-    ///    - Lease record for Alice is emptied
-    ///    - Lease record for Bob is updated
     #[test]
-    fn test_internal_update_active_lease_lender_succeeds() {
-        let mut contract = Contract::new(accounts(0).into());
-        let mut lease_condition = create_lease_condition_default();
-        lease_condition.lender_id = accounts(0).into(); //Alice
-
-        let lease_key = "test_key".to_string();
-        contract.internal_insert_lease(&lease_key, &lease_condition);
+    fn test_proxy_func_calls_success() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_with_allowed_methods();
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
 
-        // update active lease records for Alice
-        lease_condition.state = LeaseState::Active;
-        contract.nft_mint(lease_key.clone(), lease_condition.lender_id.clone());
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(lease_condition.borrower_id.clone())
+            .block_timestamp(lease_condition.start_ts_nano + 1)
+            .attached_deposit(10)
+            .build());
 
-        contract.internal_update_active_lease_lender(
-            &lease_condition.lender_id, // Alice
-            &accounts(1).into(),        // Bob
-            &lease_key,
-        );
+        contract.proxy_func_calls(key, "play".to_string(), "".to_string(), None);
 
-        assert_eq!(1, contract.active_lease_ids.len());
-        assert!(!contract
-            .active_lease_ids_by_lender
-            .contains_key(&accounts(0).into()));
-        assert!(!contract
-            .lease_ids_by_lender
-            .contains_key(&accounts(0).into()));
-        assert!(contract
-            .active_lease_ids_by_lender
-            .contains_key(&accounts(1).into()));
-        assert!(contract
-            .lease_ids_by_lender
-            .contains_key(&accounts(1).into()));
-        assert_eq!(
-            contract.lease_map.get(&lease_key).unwrap().lender_id,
-            accounts(1).into()
-        );
+        // Nothing can be checked, except the fact the call doesn't panic.
     }
 
-    /// 1. Initially, Alice owns an active lease
-    /// 2. Charlie tries to transfer Alice's lease to Bob
-    /// 3. Panic, due to unmatching lenders
     #[test]
-    #[should_panic(expected = "Active Lease is not owned by the old lender!")]
-    fn test_internal_update_active_lease_lender_fails_unmatched_old_lender() {
-        let mut contract = Contract::new(accounts(0).into());
-        let mut lease_condition = create_lease_condition_default();
-        lease_condition.lender_id = accounts(1).into(); //Alice
+    fn test_resolve_proxy_func_calls_succeeds_proxy_call_failed() {
+        let mut contract = Contract::new(accounts(1).into());
 
-        let lease_key = "test_key".to_string();
-        contract.internal_insert_lease(&lease_key, &lease_condition);
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Failed],
+        );
 
-        // update active lease records
-        lease_condition.state = LeaseState::Active;
-        contract.active_lease_ids.insert(&lease_key);
-        let mut active_lease_ids_set: UnorderedSet<String> = UnorderedSet::new(
-            StorageKey::ActiveLeaseIdsByOwnerInner {
-                account_id_hash: utils::hash_account_id(&lease_condition.lender_id),
-            }
-            .try_to_vec()
-            .unwrap(),
+        let result = contract.resolve_proxy_func_calls(
+            "test_key".to_string(),
+            "play".to_string(),
+            accounts(3),
+            U128::from(0),
         );
-        active_lease_ids_set.insert(&lease_key);
 
-        contract.internal_update_active_lease_lender(
-            &accounts(3).into(), // Charlie
-            &accounts(2).into(), // Bob
-            &lease_key,
+        // The failure is returned to the caller, without failing the callback
+        assert_eq!(
+            result,
+            ProxyCallResult {
+                success: false,
+                result: None,
+            }
         );
+        let logs = near_sdk::test_utils::get_logs();
+        assert!(logs[0].contains("\"event\":\"proxy_call\""));
+        assert!(logs[0].contains("\"success\":false"));
     }
 
     #[test]
-    #[should_panic(expected = "Only active lease can update lender!")]
-    fn test_internal_update_active_lease_lender_fails_not_an_active_lease() {
-        let mut contract = Contract::new(accounts(0).into());
-        let mut lease_condition = create_lease_condition_default();
-        lease_condition.state = LeaseState::PendingOnRent;
+    fn test_resolve_proxy_func_calls_succeeds_refunds_deposit_on_failure() {
+        let mut contract = Contract::new(accounts(1).into());
 
-        let lease_key = "test_key".to_string();
-        contract.internal_insert_lease(&lease_key, &lease_condition);
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Failed],
+        );
 
-        contract.internal_update_active_lease_lender(
-            &lease_condition.lender_id, // Alice
-            &accounts(2).into(),        // Bob
-            &lease_key,
+        contract.resolve_proxy_func_calls(
+            "test_key".to_string(),
+            "play".to_string(),
+            accounts(3),
+            U128::from(10),
         );
+
+        // The deposit of the failed call is passed on to the borrower
+        let receipts = near_sdk::test_utils::get_created_receipts();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].receiver_id, accounts(3));
+        match &receipts[0].actions[0] {
+            near_sdk::mock::VmAction::Transfer { deposit } => assert_eq!(*deposit, 10),
+            _ => panic!("Expected a transfer!"),
+        }
     }
 
     #[test]
-    #[should_panic(expected = "Only the owner can set allowed FT contracts")]
-    fn test_update_allowed_contract_addrs_fail_when_called_by_nonowner() {
+    fn test_resolve_proxy_func_calls_success() {
         let mut contract = Contract::new(accounts(1).into());
-        assert!(contract.get_allowed_ft_contract_addrs().is_empty());
 
-        contract.set_allowed_ft_contract_addrs(vec![accounts(2)]);
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(b"\"level up\"".to_vec())],
+        );
+
+        let result = contract.resolve_proxy_func_calls(
+            "test_key".to_string(),
+            "play".to_string(),
+            accounts(3),
+            U128::from(0),
+        );
+
+        assert_eq!(
+            result,
+            ProxyCallResult {
+                success: true,
+                result: Some(Base64VecU8::from(b"\"level up\"".to_vec())),
+            }
+        );
+        let logs = near_sdk::test_utils::get_logs();
+        assert!(logs[0].contains("\"event\":\"proxy_call\""));
+        assert!(logs[0].contains("\"success\":true"));
     }
 
     #[test]
-    fn test_update_allowed_contract_addrs_success() {
+    #[should_panic(expected = "Only the owner can set the max proxy call gas")]
+    fn test_set_max_proxy_call_gas_fail_when_called_by_nonowner() {
         let mut contract = Contract::new(accounts(1).into());
-        assert!(contract.get_allowed_ft_contract_addrs().is_empty());
 
-        testing_env!(VMContextBuilder::new()
-            .current_account_id(accounts(0))
-            .predecessor_account_id(accounts(1))
-            .build());
-        contract.set_allowed_ft_contract_addrs(vec![accounts(2), accounts(3)]);
-        assert_eq!(
-            contract.get_allowed_ft_contract_addrs(),
-            vec![accounts(2), accounts(3)]
-        );
-        contract.set_allowed_ft_contract_addrs(vec![accounts(4)]);
-        assert_eq!(contract.get_allowed_ft_contract_addrs(), vec![accounts(4)]);
+        contract.set_max_proxy_call_gas(U64::from(100 * TGAS));
     }
 
     // Helper function to return a lease condition using default seting
@@ -1806,7 +6412,7 @@ mod tests {
             token_id.clone(),
             lender.clone(),
             borrower.clone(),
-            ft_contract_addr.clone(),
+            Currency::Ft(ft_contract_addr.clone()),
             start_ts_nano.clone(),
             end_ts_nano.clone(),
             price,
@@ -1815,6 +6421,119 @@ mod tests {
         )
     }
 
+    // Helper function to allow the given FT for the rent payment, as a USDC-like token with 6 decimals
+    pub(crate) fn allow_ft(contract: &mut Contract, ft_contract_addr: &AccountId) {
+        contract.internal_add_allowed_ft(
+            ft_contract_addr,
+            FtMetadata {
+                symbol: "USDC".to_string(),
+                decimals: 6,
+            },
+        );
+    }
+
+    // Helper function to return the FT contract of a lease paid in FT, which calls `ft_on_transfer`
+    pub(crate) fn ft_contract_addr(lease_condition: &LeaseCondition) -> AccountId {
+        match &lease_condition.currency {
+            Currency::Ft(ft_contract_addr) => ft_contract_addr.clone(),
+            Currency::Near => panic!("The lease is paid in NEAR!"),
+        }
+    }
+
+    // Helper function to return an active lease condition, which can be extended
+    // by at most 10 units of 100 nanos, with a price of 10 per unit
+    fn create_lease_condition_extendable() -> LeaseCondition {
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        lease_condition.renewal_terms = Some(RenewalTerms {
+            price_per_unit: U128::from(10),
+            unit_duration_nano: U64::from(100),
+            max_end_ts_nano: U64::from(2000),
+        });
+        lease_condition
+    }
+
+    // Helper function to return an active lease condition, whose borrower can call
+    // the "play" method through the proxy, attaching at most 10 yocto
+    fn create_lease_condition_with_allowed_methods() -> LeaseCondition {
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        lease_condition.allowed_methods = vec![AllowedMethod {
+            method_name: "play".to_string(),
+            max_deposit: Some(U128::from(10)),
+            max_gas: None,
+        }];
+        lease_condition
+    }
+
+    // Helper function to insert an active lease, sub-leased from 100 to 200 nanos with a price of 2.
+    // Returns the ids of the lease and the sub-lease
+    fn create_lease_with_active_sublease(contract: &mut Contract) -> (LeaseId, LeaseId) {
+        let mut lease_condition = create_lease_condition_with_allowed_methods();
+        let key = "test_key".to_string();
+        let sublease_key = "test_sublease_key".to_string();
+        lease_condition.sublease_id = Some(sublease_key.clone());
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        let mut sublease_condition = create_lease_condition_with_allowed_methods();
+        sublease_condition.lender_id = lease_condition.borrower_id.clone();
+        sublease_condition.borrower_id = accounts(0);
+        sublease_condition.start_ts_nano = 100;
+        sublease_condition.end_ts_nano = 200;
+        sublease_condition.price = U128::from(2);
+        sublease_condition.parent_lease_id = Some(key.clone());
+        contract.internal_insert_lease(&sublease_key, &sublease_condition);
+
+        (key, sublease_key)
+    }
+
+    // Helper function to insert an active lease, followed by a pending lease from 1001 to 2000 nanos.
+    // The NFT is in custody, deposited by accounts(0) for the lender.
+    // Returns the ids of both leases
+    fn create_back_to_back_leases(contract: &mut Contract) -> (LeaseId, LeaseId) {
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        let key = "test_key".to_string();
+        contract.internal_insert_lease(&key, &lease_condition);
+
+        let mut next_lease_condition = create_lease_condition_default();
+        next_lease_condition.start_ts_nano = 1001;
+        next_lease_condition.end_ts_nano = 2000;
+        next_lease_condition.pending_deadline_ts_nano = 1001;
+        let next_key = "test_next_key".to_string();
+        contract.internal_insert_lease(&next_key, &next_lease_condition);
+
+        contract.nft_custodies.insert(
+            &(
+                lease_condition.contract_addr.clone(),
+                lease_condition.token_id.clone(),
+            ),
+            &NftCustody {
+                owner_id: lease_condition.lender_id.clone(),
+                depositor_id: accounts(0),
+            },
+        );
+        (key, next_key)
+    }
+
+    // Helper function to return the lease json message of the lease condition
+    pub(crate) fn create_lease_json_msg(lease_condition: &LeaseCondition) -> String {
+        json!({
+            "nft_contract_id": lease_condition.contract_addr,
+            "nft_token_id": lease_condition.token_id,
+            "lender_id": lease_condition.lender_id,
+            "borrower_id": lease_condition.borrower_id,
+            "currency": lease_condition.currency,
+            "price": lease_condition.price,
+            "start_ts_nano": lease_condition.start_ts_nano,
+            "end_ts_nano": lease_condition.end_ts_nano,
+            "nft_payout": Payout {
+                payout: HashMap::from([(lease_condition.lender_id.clone(), lease_condition.price)]),
+            },
+        })
+        .to_string()
+    }
+
     // helper method to generate a dummy AccountId using input name
     pub(crate) fn create_a_dummy_account_id(account_name: &str) -> AccountId {
         AccountId::new_unchecked(account_name.to_string())
@@ -1826,7 +6545,7 @@ mod tests {
         token_id: TokenId,
         lender_id: AccountId,
         borrower_id: AccountId,
-        ft_contract_addr: AccountId,
+        currency: Currency,
         start_ts_nano: u64,
         end_ts_nano: u64,
         price: U128,
@@ -1836,14 +6555,37 @@ mod tests {
         LeaseCondition {
             contract_addr,
             token_id,
-            lender_id,
+            lender_id: lender_id.clone(),
             borrower_id,
-            ft_contract_addr,
+            currency,
             start_ts_nano,
             end_ts_nano,
             price,
             payout,
             state,
+            refund_on_early_return: true,
+            renewal_terms: None,
+            collateral: U128::from(0),
+            collateral_forfeited: false,
+            pending_deadline_ts_nano: start_ts_nano,
+            allowed_methods: Vec::new(),
+            sublease_allowed: true,
+            parent_lease_id: None,
+            sublease_id: None,
+            protocol_fee_bps: 0,
+            storage_bytes: 0,
+            storage_payer_id: lender_id,
         }
     }
+
+    // Helper function to register the account for storage, with enough balance to pay for some leases
+    pub(crate) fn register_storage(contract: &mut Contract, account_id: &AccountId) {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(account_id.clone())
+            .attached_deposit(
+                contract.storage_balance_bounds().min.0 + 10_000 * env::storage_byte_cost()
+            )
+            .build());
+        contract.storage_deposit(None, None);
+    }
 }
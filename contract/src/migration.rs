@@ -0,0 +1,492 @@
+use crate::*;
+
+// The key of the contract state in the storage, as used by near_bindgen
+const STATE_KEY: &[u8] = b"STATE";
+// The state version while the lease records are still in the format of V1 and V2
+pub(crate) const STATE_VERSION_V2: u8 = 2;
+
+/// The lease record of the previous versions, before the lease options were added
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct LeaseConditionV1 {
+    pub contract_addr: AccountId,
+    pub token_id: TokenId,
+    pub lender_id: AccountId,
+    pub borrower_id: AccountId,
+    pub ft_contract_addr: AccountId,
+    pub start_ts_nano: u64,
+    pub end_ts_nano: u64,
+    pub price: U128,
+    pub payout: Option<Payout>,
+    pub state: LeaseState,
+}
+
+/// The first version of the contract state
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV1 {
+    pub owner: AccountId,
+    pub lease_map: UnorderedMap<LeaseId, LeaseConditionV1>,
+    pub lease_ids_by_lender: LookupMap<AccountId, UnorderedSet<LeaseId>>,
+    pub lease_ids_by_borrower: LookupMap<AccountId, UnorderedSet<LeaseId>>,
+    pub lease_id_by_contract_addr_and_token_id: LookupMap<(AccountId, TokenId), LeaseId>,
+    pub active_lease_ids: UnorderedSet<LeaseId>,
+    pub active_lease_ids_by_lender: LookupMap<AccountId, UnorderedSet<LeaseId>>,
+}
+
+/// The second version of the contract state, which added the allowlist of FT contracts
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV2 {
+    pub owner: AccountId,
+    pub lease_map: UnorderedMap<LeaseId, LeaseConditionV1>,
+    pub lease_ids_by_lender: LookupMap<AccountId, UnorderedSet<LeaseId>>,
+    pub lease_ids_by_borrower: LookupMap<AccountId, UnorderedSet<LeaseId>>,
+    pub lease_id_by_contract_addr_and_token_id: LookupMap<(AccountId, TokenId), LeaseId>,
+    pub active_lease_ids: UnorderedSet<LeaseId>,
+    pub active_lease_ids_by_lender: LookupMap<AccountId, UnorderedSet<LeaseId>>,
+    pub allowed_ft_contract_addrs: Vec<AccountId>,
+}
+
+impl From<ContractV1> for ContractV2 {
+    fn from(prev: ContractV1) -> Self {
+        Self {
+            owner: prev.owner,
+            lease_map: prev.lease_map,
+            lease_ids_by_lender: prev.lease_ids_by_lender,
+            lease_ids_by_borrower: prev.lease_ids_by_borrower,
+            lease_id_by_contract_addr_and_token_id: prev.lease_id_by_contract_addr_and_token_id,
+            active_lease_ids: prev.active_lease_ids,
+            active_lease_ids_by_lender: prev.active_lease_ids_by_lender,
+            allowed_ft_contract_addrs: Vec::new(),
+        }
+    }
+}
+
+impl From<LeaseConditionV1> for LeaseCondition {
+    // The lease options take the values matching the behaviour of the previous versions
+    fn from(prev: LeaseConditionV1) -> Self {
+        Self {
+            contract_addr: prev.contract_addr,
+            token_id: prev.token_id,
+            lender_id: prev.lender_id.clone(),
+            borrower_id: prev.borrower_id,
+            currency: Currency::Ft(prev.ft_contract_addr),
+            start_ts_nano: prev.start_ts_nano,
+            end_ts_nano: prev.end_ts_nano,
+            price: prev.price,
+            payout: prev.payout,
+            state: prev.state,
+            refund_on_early_return: true,
+            renewal_terms: None,
+            collateral: U128::from(0),
+            collateral_forfeited: false,
+            pending_deadline_ts_nano: env::block_timestamp() + PENDING_LEASE_TIMEOUT_NANO,
+            allowed_methods: Vec::new(),
+            // The lender has never agreed to sub-leasing
+            sublease_allowed: false,
+            parent_lease_id: None,
+            sublease_id: None,
+            // The protocol fee only applies to new leases
+            protocol_fee_bps: 0,
+            // The lender hasn't paid for the storage, so there is nothing to refund
+            storage_bytes: 0,
+            storage_payer_id: prev.lender_id,
+        }
+    }
+}
+
+impl Contract {
+    /// Reads the contract state to be migrated.
+    /// The previous versions have no `state_version`, so their layout is told by trying from the latest version.
+    /// Borsh requires all bytes to be consumed, so only the matching version can be read.
+    pub(crate) fn internal_read_state_for_migration() -> Self {
+        let state = env::storage_read(STATE_KEY).expect("ERR_NOT_INITIALIZED");
+
+        if let Ok(this) = Contract::try_from_slice(&state) {
+            return this;
+        }
+        if let Ok(prev) = ContractV2::try_from_slice(&state) {
+            return Self::internal_migrate_from_v2(prev);
+        }
+        ContractV1::try_from_slice(&state)
+            .map(|prev| Self::internal_migrate_from_v2(ContractV2::from(prev)))
+            .expect("Unknown contract state version!")
+    }
+
+    /// Builds the latest contract state out of a previous one, keeping all leases and their indices.
+    /// The lease records are left in the previous format, to be rewritten by `internal_migrate_leases_from_v2`.
+    fn internal_migrate_from_v2(prev: ContractV2) -> Self {
+        let mut this = Self {
+            owner: prev.owner.clone(),
+            // The map only holds its storage prefix and length, so it's read with the latest lease type.
+            lease_map: UnorderedMap::try_from_slice(&prev.lease_map.try_to_vec().unwrap()).unwrap(),
+            lease_ids_by_lender: prev.lease_ids_by_lender,
+            lease_ids_by_borrower: prev.lease_ids_by_borrower,
+            lease_ids_by_contract_addr_and_token_id: LookupMap::new(
+                StorageKey::LeaseIdsByContractAddrAndTokenId,
+            ),
+            nft_custodies: LookupMap::new(StorageKey::NftCustodies),
+            active_lease_ids: prev.active_lease_ids,
+            active_lease_ids_by_lender: prev.active_lease_ids_by_lender,
+            active_lease_ids_by_borrower: LookupMap::new(StorageKey::ActiveLeaseIdsByBorrower),
+            approvals_by_lease_id: LookupMap::new(StorageKey::ApprovalsByLeaseId),
+            next_approval_id_by_lease_id: LookupMap::new(StorageKey::NextApprovalIdByLeaseId),
+            allowed_ft_contract_addrs: prev.allowed_ft_contract_addrs,
+            // The metadata of the FTs allowed before can be fetched by adding them again
+            allowed_ft_metadata: LookupMap::new(StorageKey::AllowedFtMetadata),
+            near_allowed: false,
+            collateral_arbitrator_id: None,
+            max_proxy_call_gas: DEFAULT_MAX_PROXY_CALL_GAS,
+            keeper_grace_period_nano: DEFAULT_KEEPER_GRACE_PERIOD_NANO,
+            keeper_bounty_bps: 0,
+            protocol_fee_bps: 0,
+            fee_receiver_id: prev.owner,
+            accrued_fees: UnorderedMap::new(StorageKey::AccruedFees),
+            lease_token_royalty_bps: None,
+            unclaimed_balances: LookupMap::new(StorageKey::UnclaimedBalances),
+            storage_balances: LookupMap::new(StorageKey::StorageBalances),
+            account_storage_usage: 0,
+            lease_nonce: 0,
+            state_version: STATE_VERSION_V2,
+            lease_migration_cursor: 0,
+        };
+        this.measure_account_storage_usage();
+        this
+    }
+
+    /// Rewrites up to `limit` more lease records of the previous versions in the latest format, in place.
+    /// The single lease of each NFT becomes its calendar. No custody is recorded for these NFTs,
+    /// so more leases can only be booked once they are transferred in again.
+    /// Once all the leases are rewritten, the state is at the latest version.
+    pub(crate) fn internal_migrate_leases_from_v2(&mut self, limit: u64) {
+        let prev_lease_map: UnorderedMap<LeaseId, LeaseConditionV1> =
+            UnorderedMap::try_from_slice(&self.lease_map.try_to_vec().unwrap()).unwrap();
+        let mut prev_lease_id_by_contract_addr_and_token_id: LookupMap<
+            (AccountId, TokenId),
+            LeaseId,
+        > = LookupMap::new(StorageKey::LeaseIdByContractAddrAndTokenId);
+
+        let end = self
+            .lease_map
+            .len()
+            .min(self.lease_migration_cursor.saturating_add(limit));
+        for index in self.lease_migration_cursor..end {
+            let lease_id = self.lease_map.keys_as_vector().get(index).unwrap();
+            let prev_lease_condition = prev_lease_map.values_as_vector().get(index).unwrap();
+
+            let nft = (
+                prev_lease_condition.contract_addr.clone(),
+                prev_lease_condition.token_id.clone(),
+            );
+            prev_lease_id_by_contract_addr_and_token_id.remove(&nft);
+            self.lease_ids_by_contract_addr_and_token_id
+                .insert(&nft, &vec![lease_id.clone()]);
+
+            // The previous record can't be read as the latest type, so it's replaced as raw bytes
+            let lease_condition = LeaseCondition::from(prev_lease_condition);
+            self.lease_map.insert_raw(
+                &lease_id.try_to_vec().unwrap(),
+                &lease_condition.try_to_vec().unwrap(),
+            );
+
+            // The borrower tokens of the active leases are owned by their borrowers
+            if self.active_lease_ids.contains(&lease_id) {
+                self.internal_add_borrower_token_owner(&lease_condition.borrower_id, &lease_id);
+            }
+        }
+
+        self.lease_migration_cursor = end;
+        if end == self.lease_map.len() {
+            self.state_version = STATE_VERSION;
+        }
+    }
+
+    /// Panics until all the lease records are rewritten in the latest format.
+    /// Meanwhile, the records past the cursor can't be read as the latest type,
+    /// and inserting or removing a lease would move records across the cursor.
+    pub(crate) fn internal_assert_migrated(&self) {
+        require!(
+            self.state_version == STATE_VERSION,
+            "The contract state is being migrated!"
+        );
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    /*
+    Unit test cases and helper functions
+    Test naming format for better readability:
+    - test_{function_name} _{succeeds_or_fails} _{condition}
+    - When more than one test cases are needed for one function,
+    follow the code order of testing failing conditions first and success condition last
+    */
+
+    use super::*;
+    use crate::tests::*;
+
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    #[test]
+    #[should_panic(expected = "Only the owner can invoke the migration")]
+    fn test_migrate_fails_called_by_non_owner() {
+        env::state_write(&create_contract_v2_with_leases());
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(2))
+            .build());
+        Contract::migrate(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The contract state has already been migrated!")]
+    fn test_migrate_fails_already_migrated() {
+        env::state_write(&Contract::new(accounts(1)));
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(1))
+            .build());
+        Contract::migrate(None);
+    }
+
+    #[test]
+    fn test_migrate_succeeds_from_v1() {
+        let prev = create_contract_v2_with_leases();
+        env::state_write(&ContractV1 {
+            owner: prev.owner,
+            lease_map: prev.lease_map,
+            lease_ids_by_lender: prev.lease_ids_by_lender,
+            lease_ids_by_borrower: prev.lease_ids_by_borrower,
+            lease_id_by_contract_addr_and_token_id: prev.lease_id_by_contract_addr_and_token_id,
+            active_lease_ids: prev.active_lease_ids,
+            active_lease_ids_by_lender: prev.active_lease_ids_by_lender,
+        });
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(1))
+            .build());
+        let contract = Contract::migrate(None);
+
+        assert_eq!(contract.lease_map.len(), 2);
+        assert!(contract.allowed_ft_contract_addrs.is_empty());
+        assert_eq!(contract.state_version, STATE_VERSION);
+    }
+
+    #[test]
+    fn test_migrate_succeeds_keeps_leases_and_indices() {
+        env::state_write(&create_contract_v2_with_leases());
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(1))
+            .build());
+        let contract = Contract::migrate(None);
+
+        assert_eq!(contract.owner, accounts(1));
+        assert_eq!(contract.allowed_ft_contract_addrs, vec![accounts(5)]);
+        assert!(!contract.near_allowed);
+        assert_eq!(contract.state_version, STATE_VERSION);
+
+        assert_eq!(contract.lease_map.len(), 2);
+        let lease_condition = contract.lease_map.get(&"active_lease".to_string()).unwrap();
+        assert_eq!(lease_condition.state, LeaseState::Active);
+        assert_eq!(lease_condition.lender_id, accounts(2));
+        assert_eq!(lease_condition.borrower_id, accounts(3));
+        assert_eq!(lease_condition.price, U128::from(5));
+        assert_eq!(lease_condition.currency, Currency::Ft(accounts(5)));
+        assert!(lease_condition.refund_on_early_return);
+        assert!(!lease_condition.sublease_allowed);
+        assert_eq!(lease_condition.protocol_fee_bps, 0);
+        assert_eq!(lease_condition.storage_bytes, 0);
+        assert_eq!(lease_condition.storage_payer_id, accounts(2));
+
+        assert_eq!(
+            contract
+                .lease_ids_by_lender
+                .get(&accounts(2))
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(contract
+            .lease_ids_by_borrower
+            .get(&accounts(3))
+            .unwrap()
+            .contains(&"active_lease".to_string()));
+        assert_eq!(
+            contract
+                .lease_ids_by_contract_addr_and_token_id
+                .get(&(accounts(4), "test_token".to_string())),
+            Some(vec!["active_lease".to_string()])
+        );
+        assert!(contract
+            .active_lease_ids
+            .contains(&"active_lease".to_string()));
+        assert!(contract
+            .active_lease_ids_by_lender
+            .get(&accounts(2))
+            .unwrap()
+            .contains(&"active_lease".to_string()));
+        assert!(contract
+            .active_lease_ids_by_borrower
+            .get(&accounts(3))
+            .unwrap()
+            .contains(&"active_lease".to_string()));
+    }
+
+    #[test]
+    fn test_migrate_succeeds_resumes_by_page() {
+        env::state_write(&create_contract_v2_with_leases());
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(1))
+            .build());
+        let contract = Contract::migrate(Some(1));
+
+        // Only the first lease is rewritten, so the leases are still pending migration
+        assert_eq!(contract.get_state_version(), STATE_VERSION_V2);
+        assert_eq!(contract.lease_migration_cursor, 1);
+        let lease_id = contract.lease_map.keys_as_vector().get(0).unwrap();
+        assert!(contract.lease_map.get(&lease_id).is_some());
+
+        env::state_write(&contract);
+        let contract = Contract::migrate(Some(1));
+
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        assert_eq!(contract.lease_migration_cursor, 2);
+        assert_eq!(contract.lease_map.to_vec().len(), 2);
+        assert!(contract
+            .active_lease_ids_by_borrower
+            .get(&accounts(3))
+            .unwrap()
+            .contains(&"active_lease".to_string()));
+    }
+
+    #[test]
+    fn test_migrate_succeeds_rejects_lease_changes_between_pages() {
+        env::state_write(&create_contract_v2_with_leases());
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(1))
+            .build());
+        let mut contract = Contract::migrate(Some(1));
+        assert_eq!(contract.get_state_version(), STATE_VERSION_V2);
+
+        // A new lease is booked, and the pending lease is cancelled, before the next page
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.token_id = "test_token_3".to_string();
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(lease_condition.contract_addr.clone())
+            .build());
+        let nft_on_transfer = catch_unwind(AssertUnwindSafe(|| {
+            contract.nft_on_transfer(
+                accounts(0),
+                lease_condition.lender_id.clone(),
+                lease_condition.token_id.clone(),
+                create_lease_json_msg(&lease_condition),
+            )
+        }));
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(u64::MAX)
+            .build());
+        let cancel_pending_lease = catch_unwind(AssertUnwindSafe(|| {
+            contract.cancel_pending_lease("pending_lease".to_string())
+        }));
+
+        for result in [nft_on_transfer.map(|_| ()), cancel_pending_lease] {
+            let error = result.unwrap_err();
+            assert_eq!(
+                error.downcast_ref::<String>().unwrap(),
+                "The contract state is being migrated!"
+            );
+        }
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(1))
+            .build());
+        env::state_write(&contract);
+        let contract = Contract::migrate(Some(1));
+
+        // Both leases are rewritten, and neither lease changed meanwhile
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        assert_eq!(contract.lease_map.len(), 2);
+        assert_eq!(
+            contract
+                .lease_map
+                .get(&"pending_lease".to_string())
+                .unwrap()
+                .state,
+            LeaseState::PendingOnRent
+        );
+        assert!(contract
+            .active_lease_ids
+            .contains(&"active_lease".to_string()));
+        assert!(contract
+            .lease_ids_by_contract_addr_and_token_id
+            .get(&(accounts(4), "test_token_3".to_string()))
+            .is_none());
+    }
+
+    // Helper function to build the V2 state, with an active lease and a pending lease by the same lender
+    fn create_contract_v2_with_leases() -> ContractV2 {
+        let mut prev = ContractV2 {
+            owner: accounts(1),
+            lease_map: UnorderedMap::new(StorageKey::LendingsKey),
+            lease_ids_by_lender: LookupMap::new(StorageKey::LeaseIdsByLender),
+            lease_ids_by_borrower: LookupMap::new(StorageKey::LeaseIdsByBorrower),
+            lease_id_by_contract_addr_and_token_id: LookupMap::new(
+                StorageKey::LeaseIdByContractAddrAndTokenId,
+            ),
+            active_lease_ids: UnorderedSet::new(StorageKey::ActiveLeaseIds),
+            active_lease_ids_by_lender: LookupMap::new(StorageKey::ActiveLeaseIdsByOwner),
+            allowed_ft_contract_addrs: vec![accounts(5)],
+        };
+
+        let mut lease_ids_by_lender = UnorderedSet::new(StorageKey::LeasesIdsByLenderInner {
+            account_id_hash: utils::hash_account_id(&accounts(2)),
+        });
+        let mut lease_ids_by_borrower = UnorderedSet::new(StorageKey::LeaseIdsByBorrowerInner {
+            account_id_hash: utils::hash_account_id(&accounts(3)),
+        });
+        for (lease_id, token_id, state) in [
+            ("active_lease", "test_token", LeaseState::Active),
+            ("pending_lease", "test_token_2", LeaseState::PendingOnRent),
+        ] {
+            let lease_condition = create_lease_condition_default();
+            prev.lease_map.insert(
+                &lease_id.to_string(),
+                &LeaseConditionV1 {
+                    contract_addr: lease_condition.contract_addr,
+                    token_id: token_id.to_string(),
+                    lender_id: lease_condition.lender_id,
+                    borrower_id: lease_condition.borrower_id,
+                    ft_contract_addr: accounts(5),
+                    start_ts_nano: lease_condition.start_ts_nano,
+                    end_ts_nano: lease_condition.end_ts_nano,
+                    price: lease_condition.price,
+                    payout: lease_condition.payout,
+                    state,
+                },
+            );
+            prev.lease_id_by_contract_addr_and_token_id
+                .insert(&(accounts(4), token_id.to_string()), &lease_id.to_string());
+            lease_ids_by_lender.insert(&lease_id.to_string());
+            lease_ids_by_borrower.insert(&lease_id.to_string());
+        }
+        prev.lease_ids_by_lender
+            .insert(&accounts(2), &lease_ids_by_lender);
+        prev.lease_ids_by_borrower
+            .insert(&accounts(3), &lease_ids_by_borrower);
+
+        let mut active_lease_ids_by_lender =
+            UnorderedSet::new(StorageKey::ActiveLeaseIdsByOwnerInner {
+                account_id_hash: utils::hash_account_id(&accounts(2)),
+            });
+        active_lease_ids_by_lender.insert(&"active_lease".to_string());
+        prev.active_lease_ids.insert(&"active_lease".to_string());
+        prev.active_lease_ids_by_lender
+            .insert(&accounts(2), &active_lease_ids_by_lender);
+
+        prev
+    }
+}
@@ -0,0 +1,366 @@
+use crate::*;
+
+use near_contract_standards::non_fungible_token::approval::ext_nft_approval_receiver;
+pub use near_contract_standards::non_fungible_token::approval::NonFungibleTokenApproval;
+use near_contract_standards::non_fungible_token::{
+    bytes_for_approved_account_id, refund_approved_account_ids, refund_approved_account_ids_iter,
+    refund_deposit,
+};
+
+use near_sdk::assert_one_yocto;
+
+const GAS_FOR_NFT_APPROVE: Gas = Gas(10_000_000_000_000);
+
+/// Approval management of the LEASE tokens, as per NEP-178.
+/// The approvals are stored per lease, and cleared when the LEASE token is transferred or burned.
+#[near_bindgen]
+impl NonFungibleTokenApproval for Contract {
+    /// Approves an account to transfer the LEASE token on behalf of its owner, i.e. the lender.
+    /// The owner pays for the storage of a new approval with the attached deposit. The excess is refunded.
+    /// Unlike leases, approvals are not charged to the storage balance: as per NEP-178, their storage
+    /// is refunded with a transfer to the owner whenever they are cleared, and the current owner of a
+    /// LEASE token, e.g. after a transfer, doesn't have to be registered for storage.
+    #[payable]
+    fn nft_approve(
+        &mut self,
+        token_id: TokenId,
+        account_id: AccountId,
+        msg: Option<String>,
+    ) -> Option<Promise> {
+        self.internal_assert_migrated();
+        assert!(
+            env::attached_deposit() >= 1,
+            "Requires attached deposit of at least 1 yoctoNEAR!"
+        );
+        let (lease_id, owner_id) = self.internal_lease_token_owner(&token_id);
+        assert_eq!(
+            env::predecessor_account_id(),
+            owner_id,
+            "Only current lender can approve!"
+        );
+
+        let mut approved_account_ids = self
+            .approvals_by_lease_id
+            .get(&lease_id)
+            .unwrap_or_default();
+        let approval_id = self
+            .next_approval_id_by_lease_id
+            .get(&lease_id)
+            .unwrap_or(1u64);
+        let old_approval_id = approved_account_ids.insert(account_id.clone(), approval_id);
+
+        self.approvals_by_lease_id
+            .insert(&lease_id, &approved_account_ids);
+        self.next_approval_id_by_lease_id
+            .insert(&lease_id, &(approval_id + 1));
+
+        // Re-approving an account takes no extra storage
+        let storage_used = if old_approval_id.is_none() {
+            bytes_for_approved_account_id(&account_id)
+        } else {
+            0
+        };
+        refund_deposit(storage_used);
+
+        msg.map(|msg| {
+            ext_nft_approval_receiver::ext(account_id)
+                .with_static_gas(env::prepaid_gas() - GAS_FOR_NFT_APPROVE)
+                .nft_on_approve(token_id, owner_id, approval_id, msg)
+        })
+    }
+
+    #[payable]
+    fn nft_revoke(&mut self, token_id: TokenId, account_id: AccountId) {
+        self.internal_assert_migrated();
+        assert_one_yocto();
+        let (lease_id, owner_id) = self.internal_lease_token_owner(&token_id);
+        assert_eq!(
+            env::predecessor_account_id(),
+            owner_id,
+            "Only current lender can revoke!"
+        );
+
+        if let Some(mut approved_account_ids) = self.approvals_by_lease_id.get(&lease_id) {
+            if approved_account_ids.remove(&account_id).is_some() {
+                refund_approved_account_ids_iter(owner_id, std::iter::once(&account_id));
+                if approved_account_ids.is_empty() {
+                    self.approvals_by_lease_id.remove(&lease_id);
+                } else {
+                    self.approvals_by_lease_id
+                        .insert(&lease_id, &approved_account_ids);
+                }
+            }
+        }
+    }
+
+    #[payable]
+    fn nft_revoke_all(&mut self, token_id: TokenId) {
+        self.internal_assert_migrated();
+        assert_one_yocto();
+        let (lease_id, owner_id) = self.internal_lease_token_owner(&token_id);
+        assert_eq!(
+            env::predecessor_account_id(),
+            owner_id,
+            "Only current lender can revoke!"
+        );
+
+        if let Some(approved_account_ids) = self.approvals_by_lease_id.remove(&lease_id) {
+            refund_approved_account_ids(owner_id, &approved_account_ids);
+        }
+    }
+
+    fn nft_is_approved(
+        &self,
+        token_id: TokenId,
+        approved_account_id: AccountId,
+        approval_id: Option<u64>,
+    ) -> bool {
+        self.internal_assert_migrated();
+        let (lease_id, _) = self.internal_lease_token_owner(&token_id);
+
+        let actual_approval_id =
+            self.approvals_by_lease_id
+                .get(&lease_id)
+                .and_then(|approved_account_ids| {
+                    approved_account_ids.get(&approved_account_id).copied()
+                });
+        actual_approval_id.is_some() && (approval_id.is_none() || approval_id == actual_approval_id)
+    }
+}
+
+impl Contract {
+    // Returns the lease id and the owner of an existing LEASE token
+    pub(crate) fn internal_lease_token_owner(&self, token_id: &TokenId) -> (LeaseId, AccountId) {
+        let lease_id = self.lease_token_id_to_lease_id(token_id);
+        assert!(
+            self.active_lease_ids.contains(&lease_id),
+            "No matching lease for the given LEASE token id!"
+        );
+        let lender_id = self.lease_map.get(&lease_id).unwrap().lender_id;
+        (lease_id, lender_id)
+    }
+
+    // Removes all the approvals of a LEASE token, e.g. once it's transferred or burned.
+    // Returns the removed approvals, so that the caller can refund or restore them.
+    pub(crate) fn internal_clear_approvals(
+        &mut self,
+        lease_id: &LeaseId,
+    ) -> HashMap<AccountId, u64> {
+        self.approvals_by_lease_id
+            .remove(lease_id)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    /*
+    Unit test cases and helper functions
+
+    Test naming format for better readability:
+    - test_{function_name} _{succeeds_or_fails} _{condition}
+    - When more than one test cases are needed for one function,
+    follow the code order of testing failing conditions first and success condition last
+    */
+
+    use crate::tests::*;
+    use crate::{Contract, LeaseId, LeaseState};
+
+    use near_contract_standards::non_fungible_token::approval::NonFungibleTokenApproval;
+    use near_contract_standards::non_fungible_token::core::NonFungibleTokenCore;
+    use near_contract_standards::non_fungible_token::TokenId;
+
+    use near_sdk::test_utils::{self, accounts, VMContextBuilder};
+    use near_sdk::{testing_env, AccountId};
+
+    const APPROVAL_DEPOSIT: u128 = 10_000_000_000_000_000_000_000;
+
+    // Helper function to mint the LEASE token of an active lease of the default lender, i.e. charlie
+    fn create_lease_token(contract: &mut Contract) -> (LeaseId, TokenId) {
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+
+        let lease_id = "test_key".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+        contract.nft_mint(lease_id.clone(), lease_condition.lender_id);
+
+        let token_id = contract.lease_id_to_lease_token_id(&lease_id);
+        (lease_id, token_id)
+    }
+
+    fn set_context(predecessor_account_id: AccountId, attached_deposit: u128) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(predecessor_account_id)
+            .attached_deposit(attached_deposit)
+            .build());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only current lender can approve!")]
+    fn test_nft_approve_fails_non_lender() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (_, token_id) = create_lease_token(&mut contract);
+
+        set_context(accounts(3), APPROVAL_DEPOSIT);
+        contract.nft_approve(token_id, accounts(4), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "No matching lease for the given LEASE token id!")]
+    fn test_nft_approve_fails_non_existing_token() {
+        let mut contract = Contract::new(accounts(0).into());
+
+        set_context(accounts(2), APPROVAL_DEPOSIT);
+        contract.nft_approve("dummy_lender".to_string(), accounts(4), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach")]
+    fn test_nft_approve_fails_insufficient_deposit() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (_, token_id) = create_lease_token(&mut contract);
+
+        set_context(accounts(2), 1);
+        contract.nft_approve(token_id, accounts(4), None);
+    }
+
+    #[test]
+    fn test_nft_approve_succeeds() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (_, token_id) = create_lease_token(&mut contract);
+
+        set_context(accounts(2), APPROVAL_DEPOSIT);
+        contract.nft_approve(token_id.clone(), accounts(4), None);
+        contract.nft_approve(token_id.clone(), accounts(5), None);
+
+        assert!(contract.nft_is_approved(token_id.clone(), accounts(4), Some(1)));
+        assert!(contract.nft_is_approved(token_id.clone(), accounts(5), Some(2)));
+        assert!(!contract.nft_is_approved(token_id.clone(), accounts(3), None));
+
+        let token = contract.nft_token(token_id).unwrap();
+        let approved_account_ids = token.approved_account_ids.unwrap();
+        assert_eq!(approved_account_ids.len(), 2);
+        assert_eq!(approved_account_ids.get(&accounts(4)), Some(&1));
+    }
+
+    #[test]
+    fn test_nft_approve_succeeds_reapproval_increments_approval_id() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (_, token_id) = create_lease_token(&mut contract);
+
+        set_context(accounts(2), APPROVAL_DEPOSIT);
+        contract.nft_approve(token_id.clone(), accounts(4), None);
+        contract.nft_approve(token_id.clone(), accounts(4), None);
+
+        assert!(!contract.nft_is_approved(token_id.clone(), accounts(4), Some(1)));
+        assert!(contract.nft_is_approved(token_id, accounts(4), Some(2)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only current lender can revoke!")]
+    fn test_nft_revoke_fails_non_lender() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (_, token_id) = create_lease_token(&mut contract);
+
+        set_context(accounts(3), 1);
+        contract.nft_revoke(token_id, accounts(4));
+    }
+
+    #[test]
+    fn test_nft_revoke_succeeds() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (lease_id, token_id) = create_lease_token(&mut contract);
+
+        set_context(accounts(2), APPROVAL_DEPOSIT);
+        contract.nft_approve(token_id.clone(), accounts(4), None);
+        contract.nft_approve(token_id.clone(), accounts(5), None);
+
+        set_context(accounts(2), 1);
+        contract.nft_revoke(token_id.clone(), accounts(4));
+        assert!(!contract.nft_is_approved(token_id.clone(), accounts(4), None));
+        assert!(contract.nft_is_approved(token_id.clone(), accounts(5), None));
+
+        contract.nft_revoke(token_id, accounts(5));
+        assert!(!contract.approvals_by_lease_id.contains_key(&lease_id));
+    }
+
+    #[test]
+    fn test_nft_revoke_all_succeeds() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (lease_id, token_id) = create_lease_token(&mut contract);
+
+        set_context(accounts(2), APPROVAL_DEPOSIT);
+        contract.nft_approve(token_id.clone(), accounts(4), None);
+        contract.nft_approve(token_id.clone(), accounts(5), None);
+
+        set_context(accounts(2), 1);
+        contract.nft_revoke_all(token_id.clone());
+        assert!(!contract.nft_is_approved(token_id.clone(), accounts(4), None));
+        assert!(!contract.nft_is_approved(token_id, accounts(5), None));
+        assert!(!contract.approvals_by_lease_id.contains_key(&lease_id));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only current lender or an approved account can transfer!")]
+    fn test_nft_transfer_fails_not_approved() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (_, token_id) = create_lease_token(&mut contract);
+
+        set_context(accounts(4), 1);
+        contract.nft_transfer(accounts(5), token_id, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The given approval_id does not match the approval of the sender!")]
+    fn test_nft_transfer_fails_wrong_approval_id() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (_, token_id) = create_lease_token(&mut contract);
+
+        set_context(accounts(2), APPROVAL_DEPOSIT);
+        contract.nft_approve(token_id.clone(), accounts(4), None);
+
+        set_context(accounts(4), 1);
+        contract.nft_transfer(accounts(5), token_id, Some(2), None);
+    }
+
+    #[test]
+    fn test_nft_transfer_succeeds_by_approved_account() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (lease_id, token_id) = create_lease_token(&mut contract);
+
+        set_context(accounts(2), APPROVAL_DEPOSIT);
+        contract.nft_approve(token_id.clone(), accounts(4), None);
+
+        set_context(accounts(4), 1);
+        contract.nft_transfer(accounts(5), token_id.clone(), Some(1), None);
+
+        assert_eq!(
+            contract.lease_map.get(&lease_id).unwrap().lender_id,
+            accounts(5)
+        );
+        // The approvals of the previous owner are cleared
+        assert!(!contract.nft_is_approved(token_id, accounts(4), None));
+
+        let transfer_log = &test_utils::get_logs()[0];
+        let transfer_log_expected = r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_transfer","data":[{"old_owner_id":"charlie","new_owner_id":"fargo","token_ids":["test_key_lender"],"authorized_id":"eugene"}]}"#;
+        assert_eq!(transfer_log, transfer_log_expected);
+    }
+
+    #[test]
+    fn test_internal_remove_lease_succeeds_clears_approvals() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (lease_id, token_id) = create_lease_token(&mut contract);
+
+        set_context(accounts(2), APPROVAL_DEPOSIT);
+        contract.nft_approve(token_id, accounts(4), None);
+        assert!(contract.approvals_by_lease_id.contains_key(&lease_id));
+
+        contract.internal_remove_lease(&lease_id);
+
+        assert!(!contract.approvals_by_lease_id.contains_key(&lease_id));
+        assert!(!contract
+            .next_approval_id_by_lease_id
+            .contains_key(&lease_id));
+    }
+}
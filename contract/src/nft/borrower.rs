@@ -0,0 +1,453 @@
+use crate::nft::metadata::{lease_terms_json, render_lease_svg, ts_nano_to_metadata_ts};
+use crate::*;
+use near_contract_standards::non_fungible_token::events::{NftMint, NftTransfer};
+use near_contract_standards::non_fungible_token::{metadata::TokenMetadata, Token};
+
+const BORROWER_TOKEN_SUFFIX: &str = "_borrower";
+
+/// The borrower tokens, i.e. `<lease_id>_borrower`, stand for the right to use the NFT during an active lease.
+/// They are minted and burned along with the LEASE tokens, and are transferred through the same NEP-171 methods.
+/// Transferring a borrower token hands the lease over to the receiver, as its new borrower.
+/// Their enumeration is kept apart from the LEASE tokens.
+#[near_bindgen]
+impl Contract {
+    pub fn nft_borrower_total_supply(&self) -> U128 {
+        U128(self.active_lease_ids.len() as u128)
+    }
+
+    pub fn nft_borrower_tokens(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<Token> {
+        self.internal_assert_migrated();
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        require!(
+            (self.active_lease_ids.len() as u128) >= start_index,
+            "Out of bounds, please use a smaller from_index."
+        );
+
+        self.active_lease_ids
+            .iter()
+            .skip(start_index as usize)
+            .take(limit.unwrap_or(10) as usize)
+            .map(|active_lease_id| {
+                self.internal_borrower_token(self.lease_id_to_borrower_token_id(&active_lease_id))
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    pub fn nft_borrower_supply_for_owner(&self, account_id: AccountId) -> U128 {
+        self.active_lease_ids_by_borrower
+            .get(&account_id)
+            .map(|active_lease_ids| U128(active_lease_ids.len() as u128))
+            .unwrap_or(U128(0))
+    }
+
+    pub fn nft_borrower_tokens_for_owner(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>, // default: "0"
+        limit: Option<u64>,       // default: 10
+    ) -> Vec<Token> {
+        self.internal_assert_migrated();
+        let active_lease_ids = match self.active_lease_ids_by_borrower.get(&account_id) {
+            Some(active_lease_ids) => active_lease_ids,
+            None => return vec![],
+        };
+
+        let start_index: u128 = from_index.map(From::from).unwrap_or_default();
+        require!(
+            (active_lease_ids.len() as u128) >= start_index,
+            "Out of bounds. Please use a smaller from_index."
+        );
+
+        active_lease_ids
+            .iter()
+            .skip(start_index as usize)
+            .take(limit.unwrap_or(10) as usize)
+            .map(|active_lease_id| {
+                self.internal_borrower_token(self.lease_id_to_borrower_token_id(&active_lease_id))
+                    .unwrap()
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Returns the borrower token info with a given token_id. Info are assembled on the fly
+    pub(crate) fn internal_borrower_token(&self, token_id: TokenId) -> Option<Token> {
+        let lease_id = self.borrower_token_id_to_lease_id(&token_id);
+        if !self.active_lease_ids.contains(&lease_id) {
+            return None;
+        }
+        let lease_condition = self.lease_map.get(&lease_id).unwrap();
+
+        let token_metadata = TokenMetadata {
+            title: Some(format!("NiFTyRent Lease Usage Token: {}", &lease_id)),
+            description: Some(format!(
+                "
+                This is a token representing the right to use the NFT under the NiFTyRent lease: {lease_id}\n
+                Leasing NFT's contract: {contract_id}\n
+                Leasing NFT's token id: {leased_token_id}\n",
+                lease_id = &lease_id,
+                contract_id = &lease_condition.contract_addr,
+                leased_token_id = &lease_condition.token_id,
+            )),
+            media: Some(render_lease_svg("USAGE", &lease_condition)),
+            media_hash: None,
+            copies: None,
+            issued_at: None,
+            expires_at: Some(ts_nano_to_metadata_ts(lease_condition.end_ts_nano)),
+            starts_at: Some(ts_nano_to_metadata_ts(lease_condition.start_ts_nano)),
+            updated_at: None,
+            extra: Some(lease_terms_json(&lease_condition)),
+            reference: None,
+            reference_hash: None,
+        };
+
+        Some(Token {
+            token_id,
+            owner_id: lease_condition.borrower_id,
+            metadata: Some(token_metadata),
+            approved_account_ids: None, // Approvals are supported by the LEASE tokens only
+        })
+    }
+
+    /// Transfers the borrower token by its owner, i.e. the borrower. The receiver becomes the borrower of the lease.
+    /// Returns the token info before the transfer.
+    pub(crate) fn internal_transfer_borrower_token(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        token_id: &TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+    ) -> Token {
+        let lease_id = self.borrower_token_id_to_lease_id(token_id);
+        assert!(
+            self.active_lease_ids.contains(&lease_id),
+            "No matching lease for the given borrower token id!"
+        );
+        let lease_condition = self.lease_map.get(&lease_id).unwrap();
+        let owner_id = lease_condition.borrower_id.clone();
+        assert!(
+            approval_id.is_none(),
+            "Approvals are not supported for borrower tokens!"
+        );
+        assert_eq!(&owner_id, sender_id, "Only current borrower can transfer!");
+        assert_ne!(
+            &owner_id, receiver_id,
+            "Current borrower can not be the receiver!"
+        );
+        // The sub-lease is paid to the current borrower, as its lender
+        assert!(
+            lease_condition.sublease_id.is_none(),
+            "Cannot transfer the borrower token of a sub-let lease!"
+        );
+
+        self.internal_update_active_lease_borrower(&owner_id, receiver_id, &lease_id);
+
+        NftTransfer {
+            old_owner_id: &owner_id,
+            new_owner_id: receiver_id,
+            token_ids: &[token_id],
+            authorized_id: None,
+            memo: memo.as_deref(),
+        }
+        .emit();
+
+        Token {
+            token_id: token_id.clone(),
+            owner_id,
+            metadata: None,
+            approved_account_ids: None,
+        }
+    }
+
+    /// Updates the borrower of an active lease, along with all affected indices.
+    pub(crate) fn internal_update_active_lease_borrower(
+        &mut self,
+        old_borrower: &AccountId,
+        new_borrower: &AccountId,
+        lease_id: &LeaseId,
+    ) {
+        self.internal_remove_borrower_token_owner(old_borrower, lease_id);
+        self.internal_add_borrower_token_owner(new_borrower, lease_id);
+
+        // Update the index for lease ids by borrower
+        let mut lease_ids_set = self.lease_ids_by_borrower.get(old_borrower).unwrap();
+        lease_ids_set.remove(lease_id);
+        if lease_ids_set.is_empty() {
+            self.lease_ids_by_borrower.remove(old_borrower);
+        } else {
+            self.lease_ids_by_borrower
+                .insert(old_borrower, &lease_ids_set);
+        }
+
+        let mut lease_ids_set = self
+            .lease_ids_by_borrower
+            .get(new_borrower)
+            .unwrap_or_else(|| {
+                UnorderedSet::new(
+                    StorageKey::LeaseIdsByBorrowerInner {
+                        account_id_hash: utils::hash_account_id(new_borrower),
+                    }
+                    .try_to_vec()
+                    .unwrap(),
+                )
+            });
+        lease_ids_set.insert(lease_id);
+        self.lease_ids_by_borrower
+            .insert(new_borrower, &lease_ids_set);
+
+        let mut lease_condition = self.lease_map.get(lease_id).unwrap();
+        lease_condition.borrower_id = new_borrower.clone();
+        self.lease_map.insert(lease_id, &lease_condition);
+    }
+
+    /// Mints the borrower token of a lease, once it becomes active along with its LEASE token.
+    pub(crate) fn nft_mint_borrower_token(&mut self, lease_id: LeaseId, receiver_id: AccountId) {
+        self.internal_add_borrower_token_owner(&receiver_id, &lease_id);
+
+        let token_id = self.lease_id_to_borrower_token_id(&lease_id);
+        NftMint {
+            owner_id: &receiver_id,
+            token_ids: &[&token_id],
+            memo: None,
+        }
+        .emit();
+    }
+
+    /// Burns the borrower token of a lease, once it's removed along with its LEASE token.
+    pub(crate) fn nft_burn_borrower_token(&mut self, lease_id: &LeaseId, owner_id: &AccountId) {
+        self.internal_remove_borrower_token_owner(owner_id, lease_id);
+
+        let token_id = self.lease_id_to_borrower_token_id(lease_id);
+        NftBurn {
+            owner_id,
+            token_ids: &[&token_id],
+            authorized_id: None,
+            memo: None,
+        }
+        .emit();
+    }
+
+    pub(crate) fn internal_add_borrower_token_owner(
+        &mut self,
+        owner_id: &AccountId,
+        lease_id: &LeaseId,
+    ) {
+        let mut active_lease_ids_set = self
+            .active_lease_ids_by_borrower
+            .get(owner_id)
+            .unwrap_or_else(|| {
+                UnorderedSet::new(
+                    StorageKey::ActiveLeaseIdsByBorrowerInner {
+                        account_id_hash: utils::hash_account_id(owner_id),
+                    }
+                    .try_to_vec()
+                    .unwrap(),
+                )
+            });
+        active_lease_ids_set.insert(lease_id);
+        self.active_lease_ids_by_borrower
+            .insert(owner_id, &active_lease_ids_set);
+    }
+
+    fn internal_remove_borrower_token_owner(&mut self, owner_id: &AccountId, lease_id: &LeaseId) {
+        if let Some(mut active_lease_ids_set) = self.active_lease_ids_by_borrower.get(owner_id) {
+            active_lease_ids_set.remove(lease_id);
+            if active_lease_ids_set.is_empty() {
+                self.active_lease_ids_by_borrower.remove(owner_id);
+            } else {
+                self.active_lease_ids_by_borrower
+                    .insert(owner_id, &active_lease_ids_set);
+            }
+        }
+    }
+
+    pub(crate) fn is_borrower_token_id(&self, token_id: &TokenId) -> bool {
+        token_id.ends_with(BORROWER_TOKEN_SUFFIX)
+    }
+
+    pub(crate) fn borrower_token_id_to_lease_id(&self, token_id: &TokenId) -> LeaseId {
+        token_id
+            .strip_suffix(BORROWER_TOKEN_SUFFIX)
+            .unwrap_or(token_id)
+            .to_string()
+    }
+
+    pub(crate) fn lease_id_to_borrower_token_id(&self, lease_id: &LeaseId) -> TokenId {
+        format!("{}{}", lease_id, BORROWER_TOKEN_SUFFIX)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    /*
+    Unit test cases and helper functions
+
+    Test naming format for better readability:
+    - test_{function_name} _{succeeds_or_fails} _{condition}
+    - When more than one test cases are needed for one function,
+    follow the code order of testing failing conditions first and success condition last
+    */
+
+    use crate::tests::*;
+    use crate::{Contract, LeaseId};
+
+    use near_contract_standards::non_fungible_token::core::NonFungibleTokenCore;
+    use near_contract_standards::non_fungible_token::TokenId;
+
+    use near_sdk::test_utils::{self, accounts, VMContextBuilder};
+    use near_sdk::{testing_env, AccountId};
+
+    // Helper function to activate a lease of the default borrower, i.e. danny, minting its tokens
+    fn create_borrower_token(contract: &mut Contract) -> (LeaseId, TokenId) {
+        let lease_condition = create_lease_condition_default();
+
+        let lease_id = "test_key".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+        contract.activate_lease(lease_id.clone());
+
+        let token_id = contract.lease_id_to_borrower_token_id(&lease_id);
+        (lease_id, token_id)
+    }
+
+    fn set_context(predecessor_account_id: AccountId) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(predecessor_account_id)
+            .attached_deposit(1)
+            .build());
+    }
+
+    #[test]
+    fn test_borrower_token_id_to_lease_id_succeeds() {
+        let contract = Contract::new(accounts(1));
+        let lease_id: LeaseId = "8Vin66zVuhiB6tb9Zn9P6vRJpjQMEUMum1EkKESxJnK".to_string();
+
+        let token_id = contract.lease_id_to_borrower_token_id(&lease_id);
+
+        assert_eq!(
+            token_id,
+            "8Vin66zVuhiB6tb9Zn9P6vRJpjQMEUMum1EkKESxJnK_borrower".to_string()
+        );
+        assert!(contract.is_borrower_token_id(&token_id));
+        assert_eq!(contract.borrower_token_id_to_lease_id(&token_id), lease_id);
+    }
+
+    #[test]
+    fn test_nft_mint_borrower_token_succeeds_on_activation() {
+        let mut contract = Contract::new(accounts(0));
+        let (_, token_id) = create_borrower_token(&mut contract);
+
+        let token = contract.nft_token(token_id.clone()).unwrap();
+        assert_eq!(token.owner_id, accounts(3));
+        assert_eq!(
+            token.metadata.unwrap().title,
+            Some("NiFTyRent Lease Usage Token: test_key".to_string())
+        );
+        assert_eq!(contract.nft_borrower_supply_for_owner(accounts(3)).0, 1);
+        assert_eq!(
+            contract.nft_borrower_tokens_for_owner(accounts(3), None, None)[0].token_id,
+            token_id
+        );
+
+        let mint_log = test_utils::get_logs()
+            .into_iter()
+            .find(|log| log.contains("test_key_borrower"))
+            .unwrap();
+        let mint_log_expected = r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_mint","data":[{"owner_id":"danny","token_ids":["test_key_borrower"]}]}"#;
+        assert_eq!(mint_log, mint_log_expected);
+    }
+
+    #[test]
+    fn test_nft_borrower_tokens_succeeds() {
+        let mut contract = Contract::new(accounts(0));
+        let (_, token_id) = create_borrower_token(&mut contract);
+
+        assert_eq!(contract.nft_borrower_total_supply().0, 1);
+        let tokens = contract.nft_borrower_tokens(None, None);
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_id, token_id);
+        assert!(contract
+            .nft_borrower_tokens_for_owner(accounts(2), None, None)
+            .is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only current borrower can transfer!")]
+    fn test_internal_transfer_borrower_token_fails_non_borrower() {
+        let mut contract = Contract::new(accounts(0));
+        let (_, token_id) = create_borrower_token(&mut contract);
+
+        set_context(accounts(2));
+        contract.nft_transfer(accounts(5), token_id, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Approvals are not supported for borrower tokens!")]
+    fn test_internal_transfer_borrower_token_fails_approval_id() {
+        let mut contract = Contract::new(accounts(0));
+        let (_, token_id) = create_borrower_token(&mut contract);
+
+        set_context(accounts(3));
+        contract.nft_transfer(accounts(5), token_id, Some(1), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot transfer the borrower token of a sub-let lease!")]
+    fn test_internal_transfer_borrower_token_fails_sub_let_lease() {
+        let mut contract = Contract::new(accounts(0));
+        let (lease_id, token_id) = create_borrower_token(&mut contract);
+        let mut lease_condition = contract.lease_map.get(&lease_id).unwrap();
+        lease_condition.sublease_id = Some("sublease_key".to_string());
+        contract.lease_map.insert(&lease_id, &lease_condition);
+
+        set_context(accounts(3));
+        contract.nft_transfer(accounts(5), token_id, None, None);
+    }
+
+    #[test]
+    fn test_internal_transfer_borrower_token_succeeds() {
+        let mut contract = Contract::new(accounts(0));
+        let (lease_id, token_id) = create_borrower_token(&mut contract);
+
+        set_context(accounts(3));
+        contract.nft_transfer(accounts(5), token_id.clone(), None, None);
+
+        // The receiver becomes the borrower of the lease
+        assert_eq!(
+            contract.lease_map.get(&lease_id).unwrap().borrower_id,
+            accounts(5)
+        );
+        assert!(!contract.lease_ids_by_borrower.contains_key(&accounts(3)));
+        assert!(contract
+            .lease_ids_by_borrower
+            .get(&accounts(5))
+            .unwrap()
+            .contains(&lease_id));
+        assert_eq!(contract.nft_borrower_supply_for_owner(accounts(3)).0, 0);
+        assert_eq!(contract.nft_token(token_id).unwrap().owner_id, accounts(5));
+
+        let transfer_log = &test_utils::get_logs()[0];
+        let transfer_log_expected = r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_transfer","data":[{"old_owner_id":"danny","new_owner_id":"fargo","token_ids":["test_key_borrower"]}]}"#;
+        assert_eq!(transfer_log, transfer_log_expected);
+    }
+
+    #[test]
+    fn test_nft_burn_borrower_token_succeeds_on_lease_removal() {
+        let mut contract = Contract::new(accounts(0));
+        let (lease_id, token_id) = create_borrower_token(&mut contract);
+
+        contract.internal_remove_lease(&lease_id);
+
+        assert!(contract.nft_token(token_id).is_none());
+        assert!(!contract
+            .active_lease_ids_by_borrower
+            .contains_key(&accounts(3)));
+        let burn_log = test_utils::get_logs()
+            .into_iter()
+            .find(|log| log.contains("nft_burn") && log.contains("test_key_borrower"));
+        assert!(burn_log.is_some());
+    }
+}
@@ -1,14 +1,16 @@
+use crate::nft::metadata::{lease_terms_json, render_lease_svg, ts_nano_to_metadata_ts};
 use crate::*;
 
 pub use near_contract_standards::non_fungible_token::core::NonFungibleTokenCore;
 use near_contract_standards::non_fungible_token::events::NftTransfer;
-use near_contract_standards::non_fungible_token::{metadata::TokenMetadata, Token};
+use near_contract_standards::non_fungible_token::{
+    metadata::TokenMetadata, refund_approved_account_ids, Token,
+};
 
 use near_sdk::{assert_one_yocto, PromiseOrValue, PromiseResult};
 
 const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(5_000_000_000_000);
 const GAS_FOR_NFT_ON_TRANSFER: Gas = Gas(25_000_000_000_000 + GAS_FOR_RESOLVE_TRANSFER.0);
-const DATA_IMAGE_SVG: &str = include_str!("data_image_svg.txt");
 
 #[ext_contract(ext_nft_receiver)]
 trait NonFungibleTokenReceiver {
@@ -44,14 +46,16 @@ impl NonFungibleTokenCore for Contract {
         &mut self,
         receiver_id: AccountId,
         token_id: TokenId,
-        // TODO(libo): remove this suppressor after we implemented approval.
-        #[allow(unused_variables)] approval_id: Option<u64>,
+        approval_id: Option<u64>,
         memo: Option<String>,
     ) {
+        self.internal_assert_migrated();
         // Security assurance, on full access
         assert_one_yocto();
         let sender_id = env::predecessor_account_id();
-        self.internal_transfer(&sender_id, &receiver_id, &token_id, memo);
+        let previous_token =
+            self.internal_transfer(&sender_id, &receiver_id, &token_id, approval_id, memo);
+        refund_cleared_approvals(previous_token.owner_id, previous_token.approved_account_ids);
     }
 
     #[payable]
@@ -59,15 +63,20 @@ impl NonFungibleTokenCore for Contract {
         &mut self,
         receiver_id: AccountId,
         token_id: TokenId,
-        // TODO(libo): remove this suppressor after we implemented approval.
-        #[allow(unused_variables)] approval_id: Option<u64>,
+        approval_id: Option<u64>,
         memo: Option<String>,
         msg: String,
     ) -> PromiseOrValue<bool> {
+        self.internal_assert_migrated();
         assert_one_yocto();
         let sender_id = env::predecessor_account_id();
-        let previous_token =
-            self.internal_transfer(&sender_id, &receiver_id, &token_id, memo.clone());
+        let previous_token = self.internal_transfer(
+            &sender_id,
+            &receiver_id,
+            &token_id,
+            approval_id,
+            memo.clone(),
+        );
 
         ext_nft_receiver::ext(receiver_id.clone())
             .with_static_gas(GAS_FOR_NFT_ON_TRANSFER)
@@ -84,7 +93,7 @@ impl NonFungibleTokenCore for Contract {
                         previous_token.owner_id,
                         receiver_id,
                         token_id,
-                        None,
+                        previous_token.approved_account_ids,
                         memo,
                     ),
             )
@@ -93,13 +102,18 @@ impl NonFungibleTokenCore for Contract {
 
     /// Returns the token info with a given token_id. Info are assembled on the fly
     fn nft_token(&self, token_id: TokenId) -> Option<Token> {
+        self.internal_assert_migrated();
+        if self.is_borrower_token_id(&token_id) {
+            return self.internal_borrower_token(token_id);
+        }
+
         let active_lease_id_for_token = self.lease_token_id_to_lease_id(&token_id);
 
         if self.active_lease_ids.contains(&active_lease_id_for_token) {
             // Get the lease condition to assemble token info and token metadata
             let lease_condition = self.lease_map.get(&active_lease_id_for_token).unwrap();
 
-            // Generate token metadata on the fly. Hard coded for now
+            // Generate token metadata on the fly, reflecting the lease terms
             let token_metadata = TokenMetadata{
                 title: Some(format!("NiFTyRent Lease Ownership Token: {}", &active_lease_id_for_token)), 
                 description: Some(
@@ -111,14 +125,14 @@ impl NonFungibleTokenCore for Contract {
                     contract_id=&lease_condition.contract_addr,
                     leased_token_id=&lease_condition.token_id,
                 )),
-                media: Some(DATA_IMAGE_SVG.to_string()),
+                media: Some(render_lease_svg("LEASE", &lease_condition)),
                 media_hash: None,
                 copies: None,
                 issued_at: None,
-                expires_at: None,
-                starts_at: None,
+                expires_at: Some(ts_nano_to_metadata_ts(lease_condition.end_ts_nano)),
+                starts_at: Some(ts_nano_to_metadata_ts(lease_condition.start_ts_nano)),
                 updated_at: None,
-                extra: None,
+                extra: Some(lease_terms_json(&lease_condition)),
                 reference: None,
                 reference_hash: None,
             };
@@ -128,7 +142,11 @@ impl NonFungibleTokenCore for Contract {
                 token_id,
                 owner_id: lease_condition.lender_id,
                 metadata: Some(token_metadata),
-                approved_account_ids: None, // TODO(syu): Add support for Approval
+                approved_account_ids: Some(
+                    self.approvals_by_lease_id
+                        .get(&active_lease_id_for_token)
+                        .unwrap_or_default(),
+                ),
             })
         } else {
             // If there wasn't any token_id in tokens_by_id, return None
@@ -137,18 +155,58 @@ impl NonFungibleTokenCore for Contract {
     }
 }
 
+/// Payout support of the LEASE tokens, as per NEP-199.
+/// See https://nomicon.io/Standards/Tokens/NonFungibleToken/Payout
+#[near_bindgen]
+impl Contract {
+    /// Returns the payout of selling the LEASE token at the given balance.
+    pub fn nft_payout(
+        &self,
+        token_id: TokenId,
+        balance: U128,
+        max_len_payout: Option<u32>,
+    ) -> Payout {
+        self.internal_assert_migrated();
+        self.internal_lease_token_payout(&token_id, balance.0, max_len_payout)
+    }
+
+    /// Transfers the LEASE token and returns the payout of the sale at the given balance.
+    #[payable]
+    pub fn nft_transfer_payout(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+        balance: U128,
+        max_len_payout: Option<u32>,
+    ) -> Payout {
+        self.internal_assert_migrated();
+        assert_one_yocto();
+        // The payout is taken before the transfer, so that the seller gets the rest of the balance
+        let payout = self.internal_lease_token_payout(&token_id, balance.0, max_len_payout);
+
+        let sender_id = env::predecessor_account_id();
+        let previous_token =
+            self.internal_transfer(&sender_id, &receiver_id, &token_id, approval_id, memo);
+        refund_cleared_approvals(previous_token.owner_id, previous_token.approved_account_ids);
+
+        payout
+    }
+}
+
 #[near_bindgen]
 impl NonFungibleTokenResolver for Contract {
     /// Resolves XCC result from receiver's nft_on_transfer
     /// Returns true if the token was successfully transferred to the receiver_id
+    #[private]
     fn nft_resolve_transfer(
         &mut self,
-        previouse_owner_id: AccountId,
+        owner_id: AccountId,
         receiver_id: AccountId,
         token_id: TokenId,
-        // TODO: remove this suppressor after implementing approval.
-        #[allow(unused_variables)] approved_account_ids: Option<HashMap<AccountId, u64>>, // logging trasnfer event - deault to None
-        memo: Option<String>, // memo for logging transfer event
+        approved_account_ids: Option<HashMap<AccountId, u64>>, // approvals cleared by the transfer
+        memo: Option<String>,                                  // memo for logging transfer event
     ) -> bool {
         // Check whether the token should be returned to previous owner
         let should_revert = match env::promise_result(0) {
@@ -165,27 +223,65 @@ impl NonFungibleTokenResolver for Contract {
 
         // If the XCC indicated no revert, return early
         if !should_revert {
+            refund_cleared_approvals(owner_id, approved_account_ids);
             return true;
         }
 
         // Otherwise, try to revert this transfer and return the token to the previous owner
-        if let Some(lease_condition) = self.lease_map.get(&token_id) {
+        if self.is_borrower_token_id(&token_id) {
+            // Borrower tokens keep no approvals to restore. Refund whatever the transfer cleared
+            refund_cleared_approvals(owner_id.clone(), approved_account_ids);
+
+            let lease_id = self.borrower_token_id_to_lease_id(&token_id);
             // Check that the receiver didn't transfer the token away or burned it
-            if lease_condition.lender_id != receiver_id {
-                // The token is no longer owned by the receiver. Can't return it
+            if !self.active_lease_ids.contains(&lease_id)
+                || self.lease_map.get(&lease_id).unwrap().borrower_id != receiver_id
+            {
                 return true;
             }
-        } else {
-            // No token_id record. The token doesn't exist any more, or got burned
+
+            self.internal_update_active_lease_borrower(&receiver_id, &owner_id, &lease_id);
+
+            NftTransfer {
+                old_owner_id: &receiver_id,
+                new_owner_id: &owner_id,
+                token_ids: &[&token_id],
+                authorized_id: None,
+                memo: memo.as_deref(),
+            }
+            .emit();
+
+            return false;
+        }
+
+        let lease_id = self.lease_token_id_to_lease_id(&token_id);
+        // Check that the receiver didn't transfer the token away or burned it
+        if !self.active_lease_ids.contains(&lease_id)
+            || self.lease_map.get(&lease_id).unwrap().lender_id != receiver_id
+        {
+            // The token is no longer owned by the receiver. Can't return it
+            refund_cleared_approvals(owner_id, approved_account_ids);
             return true;
         }
 
-        self.internal_update_active_lease_lender(&receiver_id, &previouse_owner_id, &token_id);
+        self.internal_update_active_lease_lender(&receiver_id, &owner_id, &lease_id);
+
+        // Restore the approvals of the previous owner, refunding the receiver for its own approvals
+        refund_cleared_approvals(
+            receiver_id.clone(),
+            Some(self.internal_clear_approvals(&lease_id)),
+        );
+        if let Some(approved_account_ids) = approved_account_ids {
+            if !approved_account_ids.is_empty() {
+                self.approvals_by_lease_id
+                    .insert(&lease_id, &approved_account_ids);
+            }
+        }
 
         // Log transfer event as per the Events standard
         NftTransfer {
             old_owner_id: &receiver_id,
-            new_owner_id: &previouse_owner_id,
+            new_owner_id: &owner_id,
             token_ids: &[&token_id],
             authorized_id: None,
             memo: memo.as_deref(),
@@ -196,6 +292,18 @@ impl NonFungibleTokenResolver for Contract {
     }
 }
 
+// Refunds the previous owner for the storage of the approvals cleared by a transfer
+fn refund_cleared_approvals(
+    previous_owner_id: AccountId,
+    approved_account_ids: Option<HashMap<AccountId, u64>>,
+) {
+    if let Some(approved_account_ids) = approved_account_ids {
+        if !approved_account_ids.is_empty() {
+            refund_approved_account_ids(previous_owner_id, &approved_account_ids);
+        }
+    }
+}
+
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
     /*
@@ -206,14 +314,17 @@ mod tests {
     follow the code order of testing failing conditions first and success condition last
     */
 
+    use super::NonFungibleTokenResolver;
     use crate::tests::*;
-    use crate::{Contract, LeaseState};
+    use crate::{Contract, LeaseId, LeaseState, Payout};
 
     use near_contract_standards::non_fungible_token::core::NonFungibleTokenCore;
     use near_contract_standards::non_fungible_token::TokenId;
 
+    use near_sdk::json_types::U128;
     use near_sdk::test_utils::{self, accounts, VMContextBuilder};
-    use near_sdk::testing_env;
+    use near_sdk::{testing_env, AccountId, PromiseResult, RuntimeFeesConfig, VMConfig};
+    use std::collections::HashMap;
 
     #[test]
     fn test_nft_token_succeeds_non_existing_token_id() {
@@ -269,6 +380,62 @@ mod tests {
             .is_some());
     }
 
+    #[test]
+    fn test_nft_token_succeeds_metadata_reflects_lease_terms() {
+        let mut contract = Contract::new(accounts(1).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        lease_condition.start_ts_nano = 1_000_000_000;
+        lease_condition.end_ts_nano = 2_000_000_000;
+
+        let lease_id = "test_lease_id".to_string();
+        contract.lease_map.insert(&lease_id, &lease_condition);
+        contract.active_lease_ids.insert(&lease_id);
+
+        let lease_nft_token_id = contract.lease_id_to_lease_token_id(&lease_id);
+        let metadata = contract
+            .nft_token(lease_nft_token_id)
+            .unwrap()
+            .metadata
+            .unwrap();
+
+        assert_eq!(metadata.starts_at, Some("1000".to_string()));
+        assert_eq!(metadata.expires_at, Some("2000".to_string()));
+        assert_eq!(
+            metadata.extra,
+            Some(crate::nft::metadata::lease_terms_json(&lease_condition))
+        );
+        assert!(metadata.media.unwrap().starts_with("data:image/svg+xml,"));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Can't transfer the LEASE token while other leases of the NFT are booked!"
+    )]
+    fn test_nft_transfer_fails_other_leases_of_nft_booked() {
+        let mut contract = Contract::new(accounts(0).into());
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+
+        let lease_key = "test_key".to_string();
+        contract.internal_insert_lease(&lease_key, &lease_condition);
+        contract.nft_mint(lease_key.clone(), lease_condition.lender_id.clone());
+
+        // The lender has booked the next lease of the NFT
+        let mut next_lease_condition = create_lease_condition_default();
+        next_lease_condition.start_ts_nano = lease_condition.end_ts_nano + 1;
+        next_lease_condition.end_ts_nano = lease_condition.end_ts_nano + 1000;
+        contract.internal_insert_lease(&"next_test_key".to_string(), &next_lease_condition);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.lender_id.clone())
+            .attached_deposit(1)
+            .build());
+        let token_id = contract.lease_id_to_lease_token_id(&lease_key);
+        contract.nft_transfer(accounts(5), token_id, None, None);
+    }
+
     #[test]
     fn test_event_transfer_log_for_nft_transfer_succeeds() {
         let mut contract = Contract::new(accounts(0).into());
@@ -300,4 +467,231 @@ mod tests {
         let transfer_log_expected = r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_transfer","data":[{"old_owner_id":"alice","new_owner_id":"bob","token_ids":["test_key_lender"]}]}"#;
         assert_eq!(transfer_log, transfer_log_expected);
     }
+
+    // Helper function to mint the LEASE token of an active lease, with a price of 100.
+    // The leased NFT pays 10 of the rent to its royalty receiver, i.e. alice.
+    fn create_lease_token_with_royalty(contract: &mut Contract) -> (LeaseId, TokenId) {
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+        lease_condition.price = U128::from(100);
+        lease_condition.payout = Some(Payout {
+            payout: HashMap::from([
+                (accounts(0), U128::from(10)),
+                (lease_condition.lender_id.clone(), U128::from(90)),
+            ]),
+        });
+
+        let lease_id = "test_key".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+        contract.nft_mint(lease_id.clone(), lease_condition.lender_id);
+
+        let token_id = contract.lease_id_to_lease_token_id(&lease_id);
+        (lease_id, token_id)
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot payout to that many receivers!")]
+    fn test_nft_payout_fails_too_many_receivers() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (_, token_id) = create_lease_token_with_royalty(&mut contract);
+
+        contract.nft_payout(token_id, U128::from(1000), Some(1));
+    }
+
+    #[test]
+    fn test_nft_payout_succeeds_royalty_split_of_nft() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (_, token_id) = create_lease_token_with_royalty(&mut contract);
+
+        let payout = contract.nft_payout(token_id, U128::from(1000), None).payout;
+
+        assert_eq!(payout.len(), 2);
+        assert_eq!(payout[&accounts(0)], U128::from(100));
+        assert_eq!(payout[&accounts(2)], U128::from(900));
+    }
+
+    #[test]
+    fn test_nft_payout_succeeds_owner_configured_policy() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (_, token_id) = create_lease_token_with_royalty(&mut contract);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.set_lease_token_royalty_bps(Some(HashMap::from([(accounts(1), 250)])));
+
+        let payout = contract.nft_payout(token_id, U128::from(1000), None).payout;
+
+        assert_eq!(payout.len(), 2);
+        assert_eq!(payout[&accounts(1)], U128::from(25));
+        assert_eq!(payout[&accounts(2)], U128::from(975));
+    }
+
+    #[test]
+    fn test_nft_transfer_payout_succeeds() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (lease_id, token_id) = create_lease_token_with_royalty(&mut contract);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        let payout = contract
+            .nft_transfer_payout(
+                accounts(5),
+                token_id,
+                None,
+                None,
+                U128::from(1000),
+                Some(10),
+            )
+            .payout;
+
+        // The seller gets the rest of the sale price
+        assert_eq!(payout[&accounts(0)], U128::from(100));
+        assert_eq!(payout[&accounts(2)], U128::from(900));
+        assert_eq!(
+            contract.lease_map.get(&lease_id).unwrap().lender_id,
+            accounts(5)
+        );
+    }
+
+    // Helper function to transfer the LEASE token of an active lease from its lender, i.e. charlie,
+    // to the receiver via nft_transfer_call, and to set up the XCC result for the resolver
+    fn transfer_call_lease_token(
+        contract: &mut Contract,
+        receiver_id: AccountId,
+        promise_result: PromiseResult,
+    ) -> (LeaseId, TokenId) {
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.state = LeaseState::Active;
+
+        let lease_id = "test_key".to_string();
+        contract.internal_insert_lease(&lease_id, &lease_condition);
+        contract.nft_mint(lease_id.clone(), lease_condition.lender_id.clone());
+        contract
+            .approvals_by_lease_id
+            .insert(&lease_id, &HashMap::from([(accounts(1), 0)]));
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(lease_condition.lender_id)
+            .attached_deposit(1)
+            .build());
+        let token_id = contract.lease_id_to_lease_token_id(&lease_id);
+        let _ =
+            contract.nft_transfer_call(receiver_id, token_id.clone(), None, None, "".to_string());
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![promise_result],
+        );
+        (lease_id, token_id)
+    }
+
+    #[test]
+    fn test_nft_resolve_transfer_succeeds_receiver_keeps_token() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (lease_id, token_id) = transfer_call_lease_token(
+            &mut contract,
+            accounts(5),
+            PromiseResult::Successful(b"false".to_vec()),
+        );
+
+        let transferred =
+            contract.nft_resolve_transfer(accounts(2), accounts(5), token_id, None, None);
+
+        assert!(transferred);
+        assert_eq!(
+            contract.lease_map.get(&lease_id).unwrap().lender_id,
+            accounts(5)
+        );
+        assert!(contract.approvals_by_lease_id.get(&lease_id).is_none());
+    }
+
+    #[test]
+    fn test_nft_resolve_transfer_succeeds_receiver_rejects_token() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (lease_id, token_id) = transfer_call_lease_token(
+            &mut contract,
+            accounts(5),
+            PromiseResult::Successful(b"true".to_vec()),
+        );
+
+        let transferred = contract.nft_resolve_transfer(
+            accounts(2),
+            accounts(5),
+            token_id.clone(),
+            Some(HashMap::from([(accounts(1), 0)])),
+            None,
+        );
+
+        assert!(!transferred);
+        assert_eq!(
+            contract.lease_map.get(&lease_id).unwrap().lender_id,
+            accounts(2)
+        );
+        assert_eq!(contract.nft_token(token_id).unwrap().owner_id, accounts(2));
+        assert!(contract
+            .active_lease_ids_by_lender
+            .get(&accounts(2))
+            .unwrap()
+            .contains(&lease_id));
+        assert!(contract
+            .active_lease_ids_by_lender
+            .get(&accounts(5))
+            .is_none());
+        assert_eq!(
+            contract.approvals_by_lease_id.get(&lease_id),
+            Some(HashMap::from([(accounts(1), 0)]))
+        );
+
+        let transfer_log = &test_utils::get_logs()[0];
+        let transfer_log_expected = r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_transfer","data":[{"old_owner_id":"fargo","new_owner_id":"charlie","token_ids":["test_key_lender"]}]}"#;
+        assert_eq!(transfer_log, transfer_log_expected);
+    }
+
+    #[test]
+    fn test_nft_resolve_transfer_succeeds_receiver_panics() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (lease_id, token_id) =
+            transfer_call_lease_token(&mut contract, accounts(5), PromiseResult::Failed);
+
+        let transferred =
+            contract.nft_resolve_transfer(accounts(2), accounts(5), token_id, None, None);
+
+        assert!(!transferred);
+        assert_eq!(
+            contract.lease_map.get(&lease_id).unwrap().lender_id,
+            accounts(2)
+        );
+    }
+
+    #[test]
+    fn test_nft_resolve_transfer_succeeds_receiver_transferred_token_away() {
+        let mut contract = Contract::new(accounts(0).into());
+        let (lease_id, token_id) = transfer_call_lease_token(
+            &mut contract,
+            accounts(5),
+            PromiseResult::Successful(b"true".to_vec()),
+        );
+        // The receiver passes the token on before the resolver runs
+        contract.internal_update_active_lease_lender(&accounts(5), &accounts(1), &lease_id);
+
+        let transferred =
+            contract.nft_resolve_transfer(accounts(2), accounts(5), token_id, None, None);
+
+        assert!(transferred);
+        assert_eq!(
+            contract.lease_map.get(&lease_id).unwrap().lender_id,
+            accounts(1)
+        );
+    }
 }
@@ -19,6 +19,7 @@ impl NonFungibleTokenEnumeration for Contract {
         from_index: Option<U128>, // default: "0"
         limit: Option<u64>,       // default: unlimited (could fail due to gas limit)
     ) -> Vec<Token> {
+        self.internal_assert_migrated();
         // Get starting index, default to 0
         let start_index: u128 = from_index.map(From::from).unwrap_or_default();
         require!(
@@ -58,6 +59,7 @@ impl NonFungibleTokenEnumeration for Contract {
         from_index: Option<U128>, // default: "0"
         limit: Option<u64>,       // 10
     ) -> Vec<Token> {
+        self.internal_assert_migrated();
         let active_lease_ids_per_owner_set = self.active_lease_ids_by_lender.get(&account_id);
 
         // If there is some set of active lease ids, process that ids set
@@ -6,13 +6,27 @@ use near_contract_standards::non_fungible_token::Token;
 
 // #[near_bindgen]
 impl Contract {
+    /// Transfers the LEASE token by its owner, i.e. the lender, or by an account approved by the owner.
+    /// Borrower tokens are transferred by their owner only, see `internal_transfer_borrower_token`.
+    /// Returns the token info before the transfer, including the approvals cleared by the transfer.
     pub(crate) fn internal_transfer(
         &mut self,
         sender_id: &AccountId,
         receiver_id: &AccountId,
         token_id: &TokenId,
+        approval_id: Option<u64>,
         memo: Option<String>,
     ) -> Token {
+        if self.is_borrower_token_id(token_id) {
+            return self.internal_transfer_borrower_token(
+                sender_id,
+                receiver_id,
+                token_id,
+                approval_id,
+                memo,
+            );
+        }
+
         // Check if the lease exist
         let lease_id = self.lease_token_id_to_lease_id(token_id);
         let lease_condition = self
@@ -20,34 +34,100 @@ impl Contract {
             .get(&lease_id)
             .expect("No matching lease for the given LEASE token id!");
         let owner_id = lease_condition.lender_id.clone();
-        assert_eq!(&owner_id, sender_id, "Only current lender can transfer!");
+
+        // Check that the sender is approved, with the given approval id if any
+        let authorized_id = if sender_id != &owner_id {
+            let actual_approval_id = self
+                .approvals_by_lease_id
+                .get(&lease_id)
+                .and_then(|approved_account_ids| approved_account_ids.get(sender_id).copied());
+            assert!(
+                actual_approval_id.is_some(),
+                "Only current lender or an approved account can transfer!"
+            );
+            assert!(
+                approval_id.is_none() || approval_id == actual_approval_id,
+                "The given approval_id does not match the approval of the sender!"
+            );
+            Some(sender_id)
+        } else {
+            None
+        };
         assert_ne!(
             &owner_id, receiver_id,
             "Current lender can not be the receiver!"
         );
+        // The NFT goes back to the lender of its last lease, so the booked leases of the NFT must keep the same lender
+        assert!(
+            !self.internal_keeps_nft_in_custody(&lease_id, &lease_condition),
+            "Can't transfer the LEASE token while other leases of the NFT are booked!"
+        );
+
+        // The approvals are granted by the previous owner, so they don't apply to the receiver
+        let approved_account_ids = self.internal_clear_approvals(&lease_id);
 
-        // Transfer lease from sender to receiver
-        self.internal_update_active_lease_lender(sender_id, receiver_id, &lease_id);
+        // Transfer lease from the owner to receiver
+        self.internal_update_active_lease_lender(&owner_id, receiver_id, &lease_id);
 
         // Log transfer event as per the Events standard
         NftTransfer {
-            old_owner_id: sender_id,
+            old_owner_id: &owner_id,
             new_owner_id: receiver_id,
             token_ids: &[token_id],
-            authorized_id: None,
+            authorized_id,
             memo: memo.as_deref(),
         }
         .emit();
 
-        // Return the new token info, when internal transfer succeeded
+        // Return the previous token info, when internal transfer succeeded
         Token {
             token_id: token_id.clone(),
-            owner_id: receiver_id.clone(),
+            owner_id,
             metadata: None,
-            approved_account_ids: None,
+            approved_account_ids: Some(approved_account_ids),
         }
     }
 
+    /// Splits the sale price of a LEASE token into a payout, as per NEP-199.
+    /// It follows the owner-configured royalty policy if any, or the royalty split of the leased NFT.
+    /// The rest of the sale price goes to the seller, i.e. the current lender.
+    pub(crate) fn internal_lease_token_payout(
+        &self,
+        token_id: &TokenId,
+        balance: u128,
+        max_len_payout: Option<u32>,
+    ) -> Payout {
+        let (lease_id, _) = self.internal_lease_token_owner(token_id);
+        let lease_condition = self.lease_map.get(&lease_id).unwrap();
+
+        let shares = match &self.lease_token_royalty_bps {
+            Some(royalty_bps) => {
+                let mut shares: HashMap<AccountId, u128> = HashMap::new();
+                let mut remaining = balance;
+                for (receiver_id, bps) in royalty_bps.iter() {
+                    let share = utils::mul_div(balance, *bps as u128, BPS_DENOMINATOR);
+                    remaining -= share;
+                    *shares.entry(receiver_id.clone()).or_insert(0) += share;
+                }
+                *shares.entry(lease_condition.lender_id.clone()).or_insert(0) += remaining;
+                shares
+            }
+            None => self.internal_split_rent(&lease_condition, balance),
+        };
+
+        let payout: PayoutHashMap = shares
+            .into_iter()
+            .filter(|(_, share)| *share > 0)
+            .map(|(receiver_id, share)| (receiver_id, U128::from(share)))
+            .collect();
+        assert!(
+            payout.len() as u32 <= max_len_payout.unwrap_or(MAX_LEN_PAYOUT).min(MAX_LEN_PAYOUT),
+            "Cannot payout to that many receivers!"
+        );
+
+        Payout { payout }
+    }
+
     /// Update NFT related fields. It will be called once lease become active.
     /// In essence, this function updates indices that tracks active lease.
     /// This function is visible only within the current contract
@@ -2,6 +2,7 @@ use crate::*;
 use near_contract_standards::non_fungible_token::metadata::{
     NFTContractMetadata, NonFungibleTokenMetadataProvider, NFT_METADATA_SPEC,
 };
+use near_sdk::serde_json::json;
 
 // TODO(libo): Consider minifying it before launch to mainnet.
 const DATA_IMAGE_SVG: &str = include_str!("data_image_svg.txt");
@@ -21,3 +22,163 @@ impl NonFungibleTokenMetadataProvider for Contract {
         }
     }
 }
+
+/// The lease terms, as a machine-readable JSON for the `extra` field of the token metadata.
+pub(crate) fn lease_terms_json(lease_condition: &LeaseCondition) -> String {
+    json!({
+        "contract_addr": lease_condition.contract_addr,
+        "token_id": lease_condition.token_id,
+        "price": lease_condition.price,
+        "currency": lease_condition.currency,
+        "state": lease_condition.state,
+        "start_ts_nano": U64::from(lease_condition.start_ts_nano),
+        "end_ts_nano": U64::from(lease_condition.end_ts_nano),
+    })
+    .to_string()
+}
+
+/// A timestamp of the token metadata, i.e. Unix epoch in milliseconds as per NEP-177.
+pub(crate) fn ts_nano_to_metadata_ts(ts_nano: u64) -> String {
+    (ts_nano / 1_000_000).to_string()
+}
+
+/// Renders the token image on the fly, showing the leased token id and the remaining time of the lease.
+pub(crate) fn render_lease_svg(label: &str, lease_condition: &LeaseCondition) -> String {
+    let svg = format!(
+        concat!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="598" height="598">"##,
+            r##"<rect width="598" height="598" fill="#fff" stroke="#343434" stroke-width="16"/>"##,
+            r##"<text x="299" y="200" font-family="monospace" font-size="56" text-anchor="middle" fill="#343434">{label}</text>"##,
+            r##"<text x="299" y="300" font-family="monospace" font-size="28" text-anchor="middle" fill="#343434">{token_id}</text>"##,
+            r##"<text x="299" y="400" font-family="monospace" font-size="28" text-anchor="middle" fill="#343434">{remaining_time}</text>"##,
+            "</svg>"
+        ),
+        label = escape_xml(label),
+        token_id = escape_xml(&shorten(&lease_condition.token_id, 32)),
+        remaining_time = remaining_time(lease_condition, env::block_timestamp()),
+    );
+
+    format!("data:image/svg+xml,{}", percent_encode(&svg))
+}
+
+// The remaining time of a lease at the given time, e.g. "2d 3h 4m left"
+fn remaining_time(lease_condition: &LeaseCondition, now_ts_nano: u64) -> String {
+    if now_ts_nano < lease_condition.start_ts_nano {
+        format!(
+            "Starts in {}",
+            format_duration(lease_condition.start_ts_nano - now_ts_nano)
+        )
+    } else if now_ts_nano < lease_condition.end_ts_nano {
+        format!(
+            "{} left",
+            format_duration(lease_condition.end_ts_nano - now_ts_nano)
+        )
+    } else {
+        "Expired".to_string()
+    }
+}
+
+fn format_duration(duration_nano: u64) -> String {
+    let minutes = duration_nano / (60 * 1_000_000_000);
+    format!(
+        "{}d {}h {}m",
+        minutes / (24 * 60),
+        minutes / 60 % 24,
+        minutes % 60
+    )
+}
+
+fn shorten(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        text.to_string()
+    } else {
+        format!(
+            "{}...",
+            text.chars().take(max_chars - 3).collect::<String>()
+        )
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// Percent-encodes the SVG for the data URI, as done for the static image of the contract metadata
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    /*
+    Unit test cases and helper functions
+
+    Test naming format for better readability:
+    - test_{function_name} _{succeeds_or_fails} _{condition}
+    - When more than one test cases are needed for one function,
+    follow the code order of testing failing conditions first and success condition last
+    */
+
+    use super::*;
+    use crate::tests::*;
+
+    #[test]
+    fn test_lease_terms_json_succeeds() {
+        let lease_condition = create_lease_condition_default();
+
+        let lease_terms_expected = r#"{"contract_addr":"eugene","currency":{"ft":"fargo"},"end_ts_nano":"1000","price":"5","start_ts_nano":"1","state":"PendingOnRent","token_id":"test_token"}"#;
+        assert_eq!(lease_terms_json(&lease_condition), lease_terms_expected);
+    }
+
+    #[test]
+    fn test_ts_nano_to_metadata_ts_succeeds() {
+        assert_eq!(
+            ts_nano_to_metadata_ts(1672531200123456789),
+            "1672531200123".to_string()
+        );
+    }
+
+    #[test]
+    fn test_remaining_time_succeeds() {
+        let mut lease_condition = create_lease_condition_default();
+        let minute_nano = 60 * 1_000_000_000;
+        lease_condition.start_ts_nano = 10 * minute_nano;
+        lease_condition.end_ts_nano = (2 * 24 * 60 + 3 * 60 + 14) * minute_nano;
+
+        assert_eq!(
+            remaining_time(&lease_condition, 0),
+            "Starts in 0d 0h 10m".to_string()
+        );
+        assert_eq!(
+            remaining_time(&lease_condition, 10 * minute_nano),
+            "2d 3h 4m left".to_string()
+        );
+        assert_eq!(
+            remaining_time(&lease_condition, lease_condition.end_ts_nano),
+            "Expired".to_string()
+        );
+    }
+
+    #[test]
+    fn test_render_lease_svg_succeeds_escaping_token_id() {
+        let mut lease_condition = create_lease_condition_default();
+        lease_condition.token_id = "<a&b>".to_string();
+
+        let media = render_lease_svg("LEASE", &lease_condition);
+
+        assert!(media.starts_with("data:image/svg+xml,%3Csvg%20"));
+        // "&lt;a&amp;b&gt;", percent-encoded
+        assert!(media.contains("%26lt%3Ba%26amp%3Bb%26gt%3B"));
+    }
+}
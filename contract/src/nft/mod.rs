@@ -1,3 +1,5 @@
+pub mod approval;
+pub mod borrower;
 pub mod core;
 pub mod enumeration;
 pub mod internal;
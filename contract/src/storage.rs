@@ -0,0 +1,424 @@
+use crate::*;
+use near_contract_standards::storage_management::{
+    StorageBalance, StorageBalanceBounds, StorageManagement,
+};
+use near_sdk::assert_one_yocto;
+
+/// NEP-145 storage management.
+/// Lenders pay for the storage used by their leases, out of their storage balance in this contract.
+/// A marketplace can deposit on behalf of a lender by specifying the `account_id`.
+#[near_bindgen]
+impl StorageManagement for Contract {
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        self.internal_assert_migrated();
+        let amount = env::attached_deposit();
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let registration_only = registration_only.unwrap_or(false);
+        let min_balance = self.storage_balance_bounds().min.0;
+
+        let storage_balance = match self.storage_balances.get(&account_id) {
+            Some(storage_balance) => {
+                if registration_only {
+                    // Already registered. Refund the full deposit
+                    if amount > 0 {
+                        Promise::new(env::predecessor_account_id()).transfer(amount);
+                    }
+                    storage_balance
+                } else {
+                    StorageBalance {
+                        total: U128::from(storage_balance.total.0 + amount),
+                        available: U128::from(storage_balance.available.0 + amount),
+                    }
+                }
+            }
+            None => {
+                assert!(
+                    amount >= min_balance,
+                    "The attached deposit is less than the minimum storage balance"
+                );
+                if registration_only {
+                    let refund = amount - min_balance;
+                    if refund > 0 {
+                        Promise::new(env::predecessor_account_id()).transfer(refund);
+                    }
+                    StorageBalance {
+                        total: U128::from(min_balance),
+                        available: U128::from(0),
+                    }
+                } else {
+                    StorageBalance {
+                        total: U128::from(amount),
+                        available: U128::from(amount - min_balance),
+                    }
+                }
+            }
+        };
+
+        self.storage_balances.insert(&account_id, &storage_balance);
+        storage_balance
+    }
+
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        self.internal_assert_migrated();
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let storage_balance = self
+            .storage_balances
+            .get(&account_id)
+            .expect("The account is not registered");
+
+        let amount = amount.unwrap_or(storage_balance.available).0;
+        assert!(
+            amount <= storage_balance.available.0,
+            "The amount is greater than the available storage balance"
+        );
+
+        let storage_balance = StorageBalance {
+            total: U128::from(storage_balance.total.0 - amount),
+            available: U128::from(storage_balance.available.0 - amount),
+        };
+        self.storage_balances.insert(&account_id, &storage_balance);
+
+        if amount > 0 {
+            Promise::new(account_id).transfer(amount);
+        }
+        storage_balance
+    }
+
+    /// Force unregistration is not supported, as the storage of leases must be paid for.
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        self.internal_assert_migrated();
+        assert_one_yocto();
+        let _ = force;
+        let account_id = env::predecessor_account_id();
+        let storage_balance = match self.storage_balances.get(&account_id) {
+            Some(storage_balance) => storage_balance,
+            None => return false,
+        };
+
+        let min_balance = self.storage_balance_bounds().min.0;
+        assert_eq!(
+            storage_balance.total.0,
+            storage_balance.available.0 + min_balance,
+            "Can't unregister the account, while its leases use the storage balance"
+        );
+
+        self.storage_balances.remove(&account_id);
+        Promise::new(account_id).transfer(storage_balance.total.0);
+        true
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: U128::from(self.account_storage_usage as u128 * env::storage_byte_cost()),
+            max: None,
+        }
+    }
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_balances.get(&account_id)
+    }
+}
+
+impl Contract {
+    /// Measures the storage used by registering an account with the longest possible id
+    pub(crate) fn measure_account_storage_usage(&mut self) {
+        let initial_storage_usage = env::storage_usage();
+        let tmp_account_id = AccountId::new_unchecked("a".repeat(64));
+        self.storage_balances.insert(
+            &tmp_account_id,
+            &StorageBalance {
+                total: U128::from(0),
+                available: U128::from(0),
+            },
+        );
+        self.account_storage_usage = env::storage_usage() - initial_storage_usage;
+        self.storage_balances.remove(&tmp_account_id);
+    }
+
+    /// Charges the account for the given bytes of storage, out of its available storage balance
+    pub(crate) fn internal_charge_storage(&mut self, account_id: &AccountId, bytes: u64) {
+        let cost = bytes as u128 * env::storage_byte_cost();
+        let storage_balance = self
+            .storage_balances
+            .get(account_id)
+            .expect("The lender is not registered for storage!");
+        assert!(
+            cost <= storage_balance.available.0,
+            "Insufficient storage balance to pay for the lease!"
+        );
+
+        self.storage_balances.insert(
+            account_id,
+            &StorageBalance {
+                total: storage_balance.total,
+                available: U128::from(storage_balance.available.0 - cost),
+            },
+        );
+    }
+
+    /// Gives back the cost of the given bytes of storage to the account, if it's still registered
+    pub(crate) fn internal_refund_storage(&mut self, account_id: &AccountId, bytes: u64) {
+        let cost = bytes as u128 * env::storage_byte_cost();
+        if let Some(storage_balance) = self.storage_balances.get(account_id) {
+            self.storage_balances.insert(
+                account_id,
+                &StorageBalance {
+                    total: storage_balance.total,
+                    available: U128::from(storage_balance.available.0 + cost),
+                },
+            );
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    /*
+    Unit test cases and helper functions
+    Test naming format for better readability:
+    - test_{function_name} _{succeeds_or_fails} _{condition}
+    - When more than one test cases are needed for one function,
+    follow the code order of testing failing conditions first and success condition last
+    */
+
+    use crate::tests::*;
+    use crate::{Contract, LeaseTerms, NonFungibleTokenTransferReceiver, Payout, RentTerms};
+    use std::collections::HashMap;
+
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::json_types::U128;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{env, testing_env};
+
+    #[test]
+    #[should_panic(expected = "The attached deposit is less than the minimum storage balance")]
+    fn test_storage_deposit_fails_less_than_min_balance() {
+        let mut contract = Contract::new(accounts(1).into());
+        let min_balance = contract.storage_balance_bounds().min.0;
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(min_balance - 1)
+            .build());
+        contract.storage_deposit(None, None);
+    }
+
+    #[test]
+    fn test_storage_deposit_succeeds_on_behalf_of_lender() {
+        let mut contract = Contract::new(accounts(1).into());
+        let min_balance = contract.storage_balance_bounds().min.0;
+
+        // e.g. the marketplace deposits for the lender
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(min_balance + 100)
+            .build());
+        contract.storage_deposit(Some(accounts(2)), None);
+
+        let storage_balance = contract.storage_balance_of(accounts(2)).unwrap();
+        assert_eq!(storage_balance.total, U128::from(min_balance + 100));
+        assert_eq!(storage_balance.available, U128::from(100));
+        assert!(contract.storage_balance_of(accounts(3)).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "The amount is greater than the available storage balance")]
+    fn test_storage_withdraw_fails_more_than_available() {
+        let mut contract = Contract::new(accounts(1).into());
+        let min_balance = contract.storage_balance_bounds().min.0;
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(min_balance + 100)
+            .build());
+        contract.storage_deposit(None, None);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        contract.storage_withdraw(Some(U128::from(101)));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Can't unregister the account, while its leases use the storage balance"
+    )]
+    fn test_storage_unregister_fails_storage_in_use() {
+        let mut contract = Contract::new(accounts(1).into());
+        let min_balance = contract.storage_balance_bounds().min.0;
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(min_balance + env::storage_byte_cost())
+            .build());
+        contract.storage_deposit(None, None);
+        contract.internal_charge_storage(&accounts(2), 1);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        contract.storage_unregister(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient storage balance to pay for the lease!")]
+    fn test_internal_charge_storage_fails_insufficient_balance() {
+        let mut contract = Contract::new(accounts(1).into());
+        let min_balance = contract.storage_balance_bounds().min.0;
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(min_balance + env::storage_byte_cost())
+            .build());
+        contract.storage_deposit(None, None);
+
+        contract.internal_charge_storage(&accounts(2), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "The lender is not registered for storage!")]
+    fn test_nft_on_transfer_fails_lender_not_registered() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        allow_ft(&mut contract, &ft_contract_addr(&lease_condition));
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(lease_condition.contract_addr.clone())
+            .build());
+        contract.nft_on_transfer(
+            accounts(0),
+            lease_condition.lender_id.clone(),
+            lease_condition.token_id.clone(),
+            create_lease_json_msg(&lease_condition),
+        );
+    }
+
+    #[test]
+    fn test_nft_on_transfer_succeeds_lender_registered_by_marketplace() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        allow_ft(&mut contract, &ft_contract_addr(&lease_condition));
+
+        // The marketplace pays for the storage of the lease, as the listing is accepted. 0.03 NEAR
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(30_000_000_000_000_000_000_000)
+            .build());
+        contract.storage_deposit(Some(lease_condition.lender_id.clone()), None);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(lease_condition.contract_addr.clone())
+            .build());
+        contract.nft_on_transfer(
+            accounts(0),
+            lease_condition.lender_id.clone(),
+            lease_condition.token_id.clone(),
+            create_lease_json_msg(&lease_condition),
+        );
+
+        assert_eq!(contract.lease_map.len(), 1);
+        let storage_balance = contract
+            .storage_balance_of(lease_condition.lender_id)
+            .unwrap();
+        assert!(storage_balance.available.0 < storage_balance.total.0);
+    }
+
+    #[test]
+    fn test_create_lease_with_payout_succeeds_charges_and_refunds_lender_storage() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        register_storage(&mut contract, &lease_condition.lender_id);
+        let available_before = contract
+            .storage_balance_of(lease_condition.lender_id.clone())
+            .unwrap()
+            .available;
+
+        contract.create_lease_with_payout(
+            lease_condition.contract_addr.clone(),
+            lease_condition.token_id.clone(),
+            lease_condition.lender_id.clone(),
+            lease_condition.borrower_id.clone(),
+            RentTerms {
+                currency: lease_condition.currency.clone(),
+                price: lease_condition.price,
+                start_ts_nano: lease_condition.start_ts_nano,
+                end_ts_nano: lease_condition.end_ts_nano,
+                nft_payout: Payout {
+                    payout: HashMap::from([(
+                        lease_condition.lender_id.clone(),
+                        lease_condition.price,
+                    )]),
+                },
+            },
+            LeaseTerms::default(),
+        );
+
+        let lease_id = contract
+            .lease_ids_by_contract_addr_and_token_id
+            .get(&(lease_condition.contract_addr, lease_condition.token_id))
+            .unwrap()[0]
+            .clone();
+        let storage_bytes = contract.lease_map.get(&lease_id).unwrap().storage_bytes;
+        assert!(storage_bytes > 0);
+        assert_eq!(
+            contract
+                .storage_balance_of(lease_condition.lender_id.clone())
+                .unwrap()
+                .available
+                .0,
+            available_before.0 - storage_bytes as u128 * env::storage_byte_cost()
+        );
+
+        contract.internal_remove_lease(&lease_id);
+        assert_eq!(
+            contract
+                .storage_balance_of(lease_condition.lender_id)
+                .unwrap()
+                .available,
+            available_before
+        );
+    }
+
+    #[test]
+    fn test_internal_remove_lease_succeeds_refunds_storage_payer_after_lease_token_transfer() {
+        let mut contract = Contract::new(accounts(1).into());
+        let lease_condition = create_lease_condition_default();
+        register_storage(&mut contract, &lease_condition.lender_id);
+        let available_before = contract
+            .storage_balance_of(lease_condition.lender_id.clone())
+            .unwrap()
+            .available;
+
+        let lease_id = "test_lease_id".to_string();
+        contract.internal_insert_lease_paying_storage(&lease_id, lease_condition);
+        let lease_condition = contract.lease_map.get(&lease_id).unwrap();
+        contract.nft_mint(lease_id.clone(), lease_condition.lender_id.clone());
+
+        // The LEASE token is transferred to an account not registered for storage
+        contract.internal_update_active_lease_lender(
+            &lease_condition.lender_id,
+            &accounts(4),
+            &lease_id,
+        );
+        contract.internal_remove_lease(&lease_id);
+
+        assert_eq!(
+            contract
+                .storage_balance_of(lease_condition.storage_payer_id)
+                .unwrap()
+                .available,
+            available_before
+        );
+        assert!(contract.storage_balance_of(accounts(4)).is_none());
+    }
+}
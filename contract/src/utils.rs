@@ -1,7 +1,40 @@
 use near_sdk::{env, AccountId, CryptoHash};
 
+// The code generated by `construct_uint!` is out of our control, so silence its lints.
+#[allow(clippy::assign_op_pattern, clippy::manual_div_ceil)]
+mod uint_types {
+    uint::construct_uint! {
+        /// 256-bit unsigned integer, used to avoid overflow in intermediate calculations.
+        pub struct U256(4);
+    }
+}
+pub use uint_types::U256;
+
 pub fn hash_account_id(account_id: &AccountId) -> CryptoHash {
     let mut hash = CryptoHash::default();
     hash.copy_from_slice(&env::sha256(account_id.as_bytes()));
     hash
 }
+
+/// Calculates `a * b / c`, rounding down, without overflowing in the intermediate product.
+pub fn mul_div(a: u128, b: u128, c: u128) -> u128 {
+    (U256::from(a) * U256::from(b) / U256::from(c)).as_u128()
+}
+
+/// Formats the amount of a token with the given decimals, e.g. 1_500_000 with 6 decimals as "1.5".
+pub fn format_amount(amount: u128, decimals: u8) -> String {
+    let amount = amount.to_string();
+    let decimals = decimals as usize;
+    if decimals == 0 {
+        return amount;
+    }
+
+    let padded = format!("{:0>width$}", amount, width = decimals + 1);
+    let (integer, fraction) = padded.split_at(padded.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        integer.to_string()
+    } else {
+        format!("{}.{}", integer, fraction)
+    }
+}
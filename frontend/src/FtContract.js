@@ -1,5 +1,5 @@
 
-const { Contract } = window.nearApi;
+const { Contract, utils } = window.nearApi;
 
 export async function initFtContract(contractName) {
   return await new Contract(window.walletConnection.account(), contractName, {
@@ -32,3 +32,11 @@ export function ftSymbol(contractId) {
   const metadata = window.CURRENCY_OPTIONS.find((m) => m.address === contractId);
   return metadata.symbol;
 }
+
+// A listing's currency is either "near" or { ft: contractId }
+export function priceString(currency, amount) {
+  if (currency === "near") {
+    return `${utils.format.formatNearAmount(amount)} NEAR`;
+  }
+  return `${fromNormalisedAmount(currency.ft, amount)} ${ftSymbol(currency.ft)}`;
+}
@@ -47,7 +47,7 @@ async function render() {
               <Route path="lendings" element={<LendingsPage />} />
               <Route path="borrowings" element={<BorrowingsPage />} />
               <Route
-                path="listings/:contractId/:tokenId/accept"
+                path="listings/:contractId/:tokenId/:startTsNano/accept"
                 element={<ListingAcceptPage />}
               />
             </Route>
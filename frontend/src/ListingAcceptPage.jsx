@@ -1,31 +1,36 @@
 import React from "react";
-import { nearConfig, listingByContractIdAndTokenId } from "./near-api";
-import { fromNormalisedAmount, ftSymbol, initFtContract } from "./FtContract";
+import { nearConfig, listingByContractIdAndTokenId, acceptListingWithNear } from "./near-api";
+import { priceString, initFtContract } from "./FtContract";
 import { NftInfo } from "./NftInfo";
 import { useParams } from "react-router-dom";
 import { dateTimeString } from "./Utils";
 
 export default function ListingAcceptPage() {
-  let { contractId, tokenId } = useParams()
+  let { contractId, tokenId, startTsNano } = useParams()
   const [listing, setListing] = React.useState(null);
 
   React.useEffect(() => {
     async function fetchListing() {
-      let listing = await listingByContractIdAndTokenId(contractId, tokenId);
+      let listing = await listingByContractIdAndTokenId(contractId, tokenId, startTsNano);
       setListing((_) => listing);
     }
     fetchListing();
-  }, [contractId, tokenId]);
+  }, [contractId, tokenId, startTsNano]);
 
   let onSubmit = async () => {
     if (!listing) return;
-    const ftContract = await initFtContract(listing.ft_contract_id);
-    const amount = BigInt(listing.price).toString();
+    const listingId = [contractId, tokenId, startTsNano];
+    // The collateral is paid together with the rent
+    const amount = (BigInt(listing.price) + BigInt(listing.collateral)).toString();
+    if (listing.currency === "near") {
+      return await acceptListingWithNear(listingId, amount);
+    }
+    const ftContract = await initFtContract(listing.currency.ft);
     return await ftContract.ft_transfer_call({
       args: {
         receiver_id: nearConfig.contractName,
         amount: amount,
-        msg: JSON.stringify({ listing_id: [contractId, tokenId] })
+        msg: JSON.stringify({ listing_id: listingId })
       },
       gas: "300000000000000",
       amount: "1",
@@ -84,7 +89,7 @@ export default function ListingAcceptPage() {
                       Rent
                     </label>
                     <div className="mt-1 sm:w-2/3 sm:mt-0">
-                      {fromNormalisedAmount(listing.ft_contract_id, listing.price)} {ftSymbol(listing.ft_contract_id)}
+                      {priceString(listing.currency, listing.price)}
                     </div>
                   </div>
                 </div>
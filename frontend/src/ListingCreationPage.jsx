@@ -6,6 +6,7 @@ import { initContract, newListing, getPayout } from "./NftContract";
 import { fromNormalisedAmount, toNormalisedAmount } from "./FtContract";
 import { NftInfo } from "./NftInfo";
 import { MS_TO_NS_SCALE } from "./Utils";
+import { isRegisteredForStorage, registerForStorage } from "./near-api";
 
 
 export default function ListingCreationPage() {
@@ -59,6 +60,8 @@ export default function ListingCreationPage() {
 
   // If the transactionHashes appears in the URL paramters, redirect to the shop page.
   React.useEffect(() => {
+    // Back from registering for storage, the lender is still to list the NFT
+    if (searchParams.has("storageRegistered")) return;
     if (window.location.search.includes("transactionHashes")) {
       window.location.href = "/app/shops/" + contractId + "/";
     }
@@ -75,6 +78,11 @@ export default function ListingCreationPage() {
     if (Object.keys(errors).length > 0) {
       return;
     }
+    // The lender registers for the storage of their leases first, then lists the NFT
+    if (!(await isRegisteredForStorage())) {
+      await registerForStorage(window.location.href + "&storageRegistered=true");
+      return;
+    }
     const contract = await initContract(contractId);
     const startTsNano = new Date(startTimeStr).valueOf() * MS_TO_NS_SCALE;
     const endTsNano = new Date(endTimeStr).valueOf() * MS_TO_NS_SCALE;
@@ -51,7 +51,7 @@ export async function newListing(
   const priceNormalised = toNormalisedAmount(ftAddress, price);
   // TODO(libo): Revist the message
   const message = JSON.stringify({
-    ft_contract_id: ftAddress,
+    currency: { ft: ftAddress },
     price: priceNormalised,
     lease_start_ts_nano: startTsNano.toString(),
     lease_end_ts_nano: endTsNano.toString(),
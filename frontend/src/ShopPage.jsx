@@ -2,7 +2,7 @@ import React from "react";
 import { useParams } from "react-router-dom";
 import { useQuery, gql } from "@apollo/client";
 import { listingsByNftContractId } from "./near-api";
-import { fromNormalisedAmount, ftSymbol } from "./FtContract"
+import { priceString } from "./FtContract"
 import { contractIdToDescription, contractIdToName, dateTimeString, durationString, mintbaseStoreUrl } from "./Utils";
 
 const GET_TOKENS = gql`
@@ -73,17 +73,17 @@ export default function ShopPage() {
       {listings.length == 0 && <div className="text-center">No NFTs available for rent at the moment</div>}
       <div className="grid gap-4 grid-cols-1 sm:grid-cols-2 lg:grid-cols-3">
         {
-          listings.map(({ nft_token_id, owner_id, price, ft_contract_id, lease_start_ts_nano, lease_end_ts_nano, }) => {
+          listings.map(({ nft_token_id, owner_id, price, currency, lease_start_ts_nano, lease_end_ts_nano, }) => {
             let nft_info = nft_info_by_token_id[nft_token_id];
-            return <div key={contractId + "/" + nft_token_id} className="border p-4 border-black rounded-md space-y-4">
+            return <div key={contractId + "/" + nft_token_id + "/" + lease_start_ts_nano} className="border p-4 border-black rounded-md space-y-4">
               <p>{nft_info.title}</p>
               <span className="h-36 w-36 overflow-hidden  bg-gray-100">
                 <img className="w-full" src={nft_info.media} />
               </span>
-              <p className="text-center">{fromNormalisedAmount(ft_contract_id, price)} {ftSymbol(ft_contract_id)} / ~{durationString(lease_end_ts_nano - lease_start_ts_nano)}</p>
+              <p className="text-center">{priceString(currency, price)} / ~{durationString(lease_end_ts_nano - lease_start_ts_nano)}</p>
               <p className="text-center text-sm">Start from {dateTimeString(lease_start_ts_nano)} </p>
               <div className="flex flex-row justify-center space-x-2">
-                <a href={"/app/listings/" + contractId + "/" + nft_token_id + "/accept"}
+                <a href={"/app/listings/" + contractId + "/" + nft_token_id + "/" + lease_start_ts_nano + "/accept"}
                   className="primary-btn flex-1 w-32 text-center"> Rent </a>
                 <a href={"/app/nfts/" + contractId + "/?tokenId=" + nft_token_id}
                   className="btn flex-1 w-32 text-center"> Details </a>
@@ -48,7 +48,7 @@ export async function initContract() {
         "get_listing_by_id",
         "get_rental_contract_id"
       ],
-      changeMethods: [],
+      changeMethods: ["accept_listing_with_near"],
     }
   );
 
@@ -58,8 +58,13 @@ export async function initContract() {
     window.walletConnection.account(),
     window.rentalContractId,
     {
-      viewMethods: ["leases_by_borrower", "leases_by_owner", "lease_by_contract_and_token"],
-      changeMethods: ["claim_back"],
+      viewMethods: [
+        "leases_by_borrower",
+        "leases_by_owner",
+        "lease_by_contract_and_token",
+        "storage_balance_of",
+      ],
+      changeMethods: ["claim_back", "storage_deposit"],
     });
 }
 
@@ -89,6 +94,38 @@ export async function acceptLease(leaseId, rent) {
   return response;
 }
 
+// The rental contract charges lenders for the storage of their leases.
+// The marketplace only lists the NFTs of lenders registered for storage.
+export async function isRegisteredForStorage() {
+  const storageBalance = await window.rentalContract.storage_balance_of({
+    account_id: window.accountId,
+  });
+  return storageBalance != null;
+}
+
+export async function registerForStorage(callbackUrl) {
+  return await window.rentalContract.storage_deposit({
+    args: {
+      account_id: window.accountId,
+    },
+    gas: "30000000000000",
+    // 0.03 NEAR, i.e. the registration and the storage of a lease
+    amount: "30000000000000000000000",
+    callbackUrl: callbackUrl,
+  });
+}
+
+// Listings priced in NEAR are paid by attaching the rent and the collateral to the call.
+export async function acceptListingWithNear(listingId, amount) {
+  return await window.contract.accept_listing_with_near({
+    args: {
+      listing_id: listingId,
+    },
+    gas: "300000000000000",
+    amount: amount,
+  });
+}
+
 export async function listingsByNftContractId(nftContractId) {
   const listings = await window.contract.list_listings_by_nft_contract_id({
     nft_contract_id: nftContractId,
@@ -100,23 +137,34 @@ export async function listAllowedNftContractIds(nftContractId) {
   return await window.contract.list_allowed_nft_contract_ids({})
 }
 
-export async function listingByContractIdAndTokenId(nftContractId, tokenId) {
+export async function listingByContractIdAndTokenId(nftContractId, tokenId, startTsNano) {
   const listing = await window.contract.get_listing_by_id({
-    listing_id: [nftContractId, tokenId],
+    listing_id: [nftContractId, tokenId, startTsNano],
   });
   return listing;
 }
 
+// The lease views return a page of leases at a time. Read all the pages of the account.
+async function allLeasesOfAccount(viewMethod) {
+  const limit = 50;
+  let leases = [];
+  while (true) {
+    const page = await window.rentalContract[viewMethod]({
+      account_id: window.accountId,
+      from_index: leases.length.toString(),
+      limit: limit,
+    });
+    leases = leases.concat(page);
+    if (page.length < limit) return leases;
+  }
+}
+
 export async function myLendings() {
-  return await window.rentalContract.leases_by_owner({
-    account_id: window.accountId,
-  });
+  return await allLeasesOfAccount("leases_by_owner");
 }
 
 export async function myBorrowings() {
-  return await window.rentalContract.leases_by_borrower({
-    account_id: window.accountId,
-  });
+  return await allLeasesOfAccount("leases_by_borrower");
 }
 
 export async function leaseByContractIdAndTokenId(nftContractId, tokenId) {
@@ -0,0 +1,28 @@
+use near_contract_standards::non_fungible_token::core::NonFungibleTokenReceiver;
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::{env, near_bindgen, AccountId, PromiseOrValue};
+
+#[near_bindgen]
+#[derive(Default, BorshDeserialize, BorshSerialize)]
+pub struct Contract {}
+
+/// A minilism NFT receiver (NEP-171) for testing purpose.
+/// Depending on the msg, it keeps the token, asks for the token to be returned or panics.
+#[near_bindgen]
+impl NonFungibleTokenReceiver for Contract {
+    fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: TokenId,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        let _ = (sender_id, previous_owner_id, token_id);
+        match msg.as_str() {
+            "return" => PromiseOrValue::Value(true),
+            "panic" => env::panic_str("Rejecting the token!"),
+            _ => PromiseOrValue::Value(false),
+        }
+    }
+}
@@ -6,7 +6,7 @@ use near_contract_standards::non_fungible_token::{
 use near_sdk::json_types::U128;
 use near_sdk::{log, AccountId};
 use near_units::parse_near;
-use nft_rental::{LeaseCondition, LeaseState};
+use nft_rental::{Currency, LeaseCondition, LeaseState};
 use niftyrent_marketplace::Listing;
 use serde_json::json;
 use workspaces::{network::Sandbox, Account, Contract, Worker};
@@ -38,6 +38,8 @@ const NFT_PAYOUT_CODE: &[u8] =
 const NFT_NO_PAYOUT_CODE: &[u8] =
     include_bytes!("../target/wasm32-unknown-unknown/release/test_nft_without_payout.wasm");
 const FT_CODE: &[u8] = include_bytes!("../target/wasm32-unknown-unknown/release/test_ft.wasm");
+const NFT_RECEIVER_CODE: &[u8] =
+    include_bytes!("../target/wasm32-unknown-unknown/release/test_nft_receiver.wasm");
 
 // TODO(syu): init is used by all tests, making run time too long. Consider simplify init for some tests.
 async fn init(nft_code: &[u8]) -> anyhow::Result<Context> {
@@ -120,6 +122,16 @@ async fn init(nft_code: &[u8]) -> anyhow::Result<Context> {
         .await?
         .into_result()?;
 
+    // rental contract config - allow the FT for the rent payment, after fetching its metadata
+    let ft_allowed: bool = account
+        .call(rental_contract.id(), "add_allowed_ft_contract_addr")
+        .args_json(json!({ "ft_contract_addr": ft_contract.id() }))
+        .max_gas()
+        .transact()
+        .await?
+        .json()?;
+    assert!(ft_allowed);
+
     // mint nfts for renting
     account
         .call(nft_contract.id(), "nft_mint")
@@ -188,6 +200,15 @@ async fn init(nft_code: &[u8]) -> anyhow::Result<Context> {
         .await?
         .into_result()?;
 
+    // register the lender for storage on the rental contract, to pay for the lease records
+    alice
+        .call(rental_contract.id(), "storage_deposit")
+        .args_json(json!({}))
+        .deposit(parse_near!("0.1 N"))
+        .transact()
+        .await?
+        .into_result()?;
+
     // marketplace config - add allowed nft contracts
     log!("Adding allowed NFT contracts for marketplace...");
     let allowed_nft_contracts_ids_expected = vec![nft_contract.id().as_str()];
@@ -289,7 +310,7 @@ async fn test_accept_leases_already_lent() -> anyhow::Result<()> {
             "msg": json!({"contract_addr": nft_contract.id(),
                           "token_id": token_id,
                           "borrower_id": borrower.id(),
-                          "ft_contract_addr": ft_contract.id(),
+                          "currency": {"ft": ft_contract.id()},
                           "start_ts_nano": start_ts_nano,
                           "end_ts_nano": expiration_ts_nano,
                           "price": "1000"
@@ -411,7 +432,7 @@ async fn test_accept_lease_fails_already_transferred() -> anyhow::Result<()> {
             "msg": json!({"contract_addr": nft_contract.id(),
                           "token_id": token_id,
                           "borrower_id": borrower.id(),
-                          "ft_contract_addr": ft_contract.id(),
+                          "currency": {"ft": ft_contract.id()},
                           "start_ts_nano": start_ts_nano,
                           "end_ts_nano": expiration_ts_nano,
                           "price": "1000"
@@ -521,7 +542,7 @@ async fn test_lender_receives_a_lease_nft_after_lease_activation() -> anyhow::Re
             "msg": json!({"contract_addr": nft_contract.id(),
                           "token_id": token_id,
                           "borrower_id": borrower.id(),
-                          "ft_contract_addr": ft_contract.id(),
+                          "currency": {"ft": ft_contract.id()},
                           "start_ts_nano": start_ts_nano,
                           "end_ts_nano": expiration_ts_nano,
                           "price": price.to_string(),
@@ -694,7 +715,7 @@ async fn test_lease_nft_can_be_transferred_to_other_account() -> anyhow::Result<
             "msg": json!({"contract_addr": nft_contract.id(),
                           "token_id": token_id,
                           "borrower_id": borrower.id(),
-                          "ft_contract_addr": ft_contract.id(),
+                          "currency": {"ft": ft_contract.id()},
                           "start_ts_nano": start_ts_nano,
                           "end_ts_nano": expiration_ts_nano,
                           "price": price.to_string(),
@@ -854,7 +875,7 @@ async fn test_claim_back_without_payout_using_lease_nft() -> anyhow::Result<()>
             "msg": json!({"contract_addr": nft_contract.id(),
                           "token_id": token_id,
                           "borrower_id": borrower.id(),
-                          "ft_contract_addr": ft_contract.id(),
+                          "currency": {"ft": ft_contract.id()},
                           "start_ts_nano": start_ts_nano,
                           "end_ts_nano": expiration_ts_nano,
                           "price": price.to_string(),
@@ -911,10 +932,10 @@ async fn test_claim_back_without_payout_using_lease_nft() -> anyhow::Result<()>
     worker.fast_forward(12).await?;
 
     println!("Claiming back the NFT...");
-    let balance_before_claim_back_original_lender: U128 = ft_contract
+    let balance_before_claim_back_lease_nft_receiver: U128 = ft_contract
         .view("ft_balance_of")
         .args_json(json!({
-            "account_id": lender.id(),
+            "account_id": lease_nft_receiver.id(),
         }))
         .await?
         .json()?;
@@ -929,17 +950,18 @@ async fn test_claim_back_without_payout_using_lease_nft() -> anyhow::Result<()>
         .await?
         .into_result()?;
 
-    let balance_after_claim_back_original_lender: U128 = ft_contract
+    let balance_after_claim_back_lease_nft_receiver: U128 = ft_contract
         .view("ft_balance_of")
         .args_json(json!({
-            "account_id": lender.id(),
+            "account_id": lease_nft_receiver.id(),
         }))
         .await?
         .json()?;
 
-    // All fund goes to the original lender.
+    // All fund goes to the LEASE NFT holder.
     assert_aprox_eq(
-        balance_after_claim_back_original_lender.0 - balance_before_claim_back_original_lender.0,
+        balance_after_claim_back_lease_nft_receiver.0
+            - balance_before_claim_back_lease_nft_receiver.0,
         price,
     );
     println!("      ✅ Rent payout is correct");
@@ -987,7 +1009,7 @@ async fn test_claim_back_with_payout_using_lease_nft() -> anyhow::Result<()> {
             "msg": json!({"contract_addr": nft_contract.id(),
                           "token_id": token_id,
                           "borrower_id": borrower.id(),
-                          "ft_contract_addr": ft_contract.id(),
+                          "currency": {"ft": ft_contract.id()},
                           "start_ts_nano": start_ts_nano,
                           "end_ts_nano": expiration_ts_nano,
                           "price": price.to_string(),
@@ -1044,10 +1066,10 @@ async fn test_claim_back_with_payout_using_lease_nft() -> anyhow::Result<()> {
     worker.fast_forward(12).await?;
 
     println!("Claiming back the NFT...");
-    let balance_before_claim_back_original_lender: U128 = ft_contract
+    let balance_before_claim_back_lease_nft_receiver: U128 = ft_contract
         .view("ft_balance_of")
         .args_json(json!({
-            "account_id": lender.id(),
+            "account_id": lease_nft_receiver.id(),
         }))
         .await?
         .json()?;
@@ -1070,10 +1092,10 @@ async fn test_claim_back_with_payout_using_lease_nft() -> anyhow::Result<()> {
         .await?
         .into_result()?;
 
-    let balance_after_claim_back_original_lender: U128 = ft_contract
+    let balance_after_claim_back_lease_nft_receiver: U128 = ft_contract
         .view("ft_balance_of")
         .args_json(json!({
-            "account_id": lender.id(),
+            "account_id": lease_nft_receiver.id(),
         }))
         .await?
         .json()?;
@@ -1088,9 +1110,10 @@ async fn test_claim_back_with_payout_using_lease_nft() -> anyhow::Result<()> {
 
     // Based on the demo NFT royalty logic:
     // - the NFT contract keeps 5% of the rent.
-    // - the lender receives the rest 95% of the rent.
+    // - the LEASE NFT holder receives the rest 95% of the rent.
     assert_aprox_eq(
-        balance_after_claim_back_original_lender.0 - balance_before_claim_back_original_lender.0,
+        balance_after_claim_back_lease_nft_receiver.0
+            - balance_before_claim_back_lease_nft_receiver.0,
         price / 20 * 19,
     );
     assert_aprox_eq(
@@ -1138,7 +1161,7 @@ async fn test_create_a_lease_to_start_in_the_future() -> anyhow::Result<()> {
             "msg": json!({"contract_addr": nft_contract.id(),
                           "token_id": token_id,
                           "borrower_id": borrower.id(),
-                          "ft_contract_addr": ft_contract.id(),
+                          "currency": {"ft": ft_contract.id()},
                           "start_ts_nano": start_ts_nano,
                           "end_ts_nano": expiration_ts_nano,
                           "price": price.to_string(),
@@ -1263,7 +1286,7 @@ async fn test_lender_creates_a_listing_in_marketplace_succeeds() -> anyhow::Resu
             "token_id": nft_token_id,
             "account_id": marketplace_contract.id(),
             "msg": json!({
-                "ft_contract_id": ft_contract.id(),
+                "currency": {"ft": ft_contract.id()},
                 "price": price.to_string(),
                 "lease_start_ts_nano": lease_start_ts_nano.to_string(),
                 "lease_end_ts_nano": lease_expiration_ts_nano.to_string(),
@@ -1292,12 +1315,12 @@ async fn test_lender_creates_a_listing_in_marketplace_succeeds() -> anyhow::Resu
     );
     assert_eq!(new_listing.nft_token_id, nft_token_id);
     assert_eq!(
-        new_listing.ft_contract_id.as_str(),
-        ft_contract.id().as_str()
+        new_listing.currency,
+        niftyrent_marketplace::Currency::Ft(ft_contract.id().as_str().parse().unwrap())
     );
     assert_eq!(new_listing.price.0, price);
-    assert_eq!(new_listing.lease_start_ts_nano, lease_start_ts_nano);
-    assert_eq!(new_listing.lease_end_ts_nano, lease_expiration_ts_nano);
+    assert_eq!(new_listing.lease_start_ts_nano.0, lease_start_ts_nano);
+    assert_eq!(new_listing.lease_end_ts_nano.0, lease_expiration_ts_nano);
     log!("      ✅ Confirmed the created listing");
 
     Ok(())
@@ -1329,7 +1352,7 @@ async fn test_borrower_accepts_a_lease_succeeds() -> anyhow::Result<()> {
             "token_id": nft_token_id,
             "account_id": marketplace_contract.id(),
             "msg": json!({
-                "ft_contract_id": ft_contract.id(),
+                "currency": {"ft": ft_contract.id()},
                 "price": price.to_string(),
                 "lease_start_ts_nano": lease_start_ts_nano.to_string(),
                 "lease_end_ts_nano": lease_expiration_ts_nano.to_string(),
@@ -1421,9 +1444,10 @@ async fn test_borrower_accepts_a_lease_succeeds() -> anyhow::Result<()> {
     log!("*** END ***");
 
     log!("Borrower accepting the created listing...");
-    let listing_id: (String, String) = (
+    let listing_id: (String, String, String) = (
         nft_contract.id().clone().to_string(),
         nft_token_id.clone().to_string(),
+        lease_start_ts_nano.to_string(),
     );
 
     let result = borrower
@@ -1578,7 +1602,7 @@ async fn test_owner_claims_back_with_payout_succeeds() -> anyhow::Result<()> {
             "token_id": nft_token_id,
             "account_id": marketplace_contract.id(),
             "msg": json!({
-                "ft_contract_id": ft_contract.id(),
+                "currency": {"ft": ft_contract.id()},
                 "price": price.to_string(),
                 "lease_start_ts_nano": lease_start_ts_nano.to_string(),
                 "lease_end_ts_nano": lease_expiration_ts_nano.to_string(),
@@ -1600,9 +1624,10 @@ async fn test_owner_claims_back_with_payout_succeeds() -> anyhow::Result<()> {
     log!("      ✅ Confirmed the created listing");
 
     log!("Borrower accepting the created listing...");
-    let listing_id: (String, String) = (
+    let listing_id: (String, String, String) = (
         nft_contract.id().clone().to_string(),
         nft_token_id.clone().to_string(),
+        lease_start_ts_nano.to_string(),
     );
 
     let result = borrower
@@ -1732,7 +1757,7 @@ async fn test_owner_claims_back_without_payout_succeeds() -> anyhow::Result<()>
             "token_id": nft_token_id,
             "account_id": marketplace_contract.id(),
             "msg": json!({
-                "ft_contract_id": ft_contract.id(),
+                "currency": {"ft": ft_contract.id()},
                 "price": price.to_string(),
                 "lease_start_ts_nano": lease_start_ts_nano.to_string(),
                 "lease_end_ts_nano": lease_expiration_ts_nano.to_string(),
@@ -1754,9 +1779,10 @@ async fn test_owner_claims_back_without_payout_succeeds() -> anyhow::Result<()>
     log!("      ✅ Confirmed the created listing");
 
     log!("Borrower accepting the created listing...");
-    let listing_id: (String, String) = (
+    let listing_id: (String, String, String) = (
         nft_contract.id().clone().to_string(),
         nft_token_id.clone().to_string(),
+        lease_start_ts_nano.to_string(),
     );
 
     let result = borrower
@@ -1842,3 +1868,625 @@ async fn test_owner_claims_back_without_payout_succeeds() -> anyhow::Result<()>
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_lease_nft_holder_claims_back_with_payout_succeeds() -> anyhow::Result<()> {
+    let context = init(NFT_PAYOUT_CODE).await?;
+    let worker = context.worker;
+    let rental_contract = context.rental_contract;
+    let marketplace_contract = context.marketplace_contract;
+    let nft_contract = context.nft_contract;
+    let ft_contract = context.ft_contract;
+    let lender = context.lender;
+    let borrower = context.borrower;
+    let lease_nft_receiver = context.lease_nft_receiver;
+
+    let nft_token_id = "test";
+    let price: u128 = 10000;
+    let latest_block = worker.view_block().await?;
+    let lease_start_ts_nano = latest_block.timestamp() + ONE_BLOCK_IN_NANO * 10;
+    let lease_expiration_ts_nano = latest_block.timestamp() + ONE_BLOCK_IN_NANO * 15;
+
+    log!("Creating a listing on marketplace...");
+    lender
+        .call(nft_contract.id(), "nft_approve")
+        .args_json(json!({
+            "token_id": nft_token_id,
+            "account_id": marketplace_contract.id(),
+            "msg": json!({
+                "currency": {"ft": ft_contract.id()},
+                "price": price.to_string(),
+                "lease_start_ts_nano": lease_start_ts_nano.to_string(),
+                "lease_end_ts_nano": lease_expiration_ts_nano.to_string(),
+            }).to_string()
+        }))
+        .deposit(parse_near!("0.1 N"))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let listings: Vec<Listing> = marketplace_contract
+        .call("list_listings_by_owner_id")
+        .args_json(json!({"owner_id": lender.id()}))
+        .transact()
+        .await?
+        .json()?;
+    assert_eq!(listings.len(), 1);
+    log!("      ✅ Confirmed the created listing");
+
+    log!("Borrower accepting the created listing...");
+    let listing_id: (String, String, String) = (
+        nft_contract.id().clone().to_string(),
+        nft_token_id.clone().to_string(),
+        lease_start_ts_nano.to_string(),
+    );
+
+    let result = borrower
+        .call(ft_contract.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": marketplace_contract.id(),
+            "amount": price.to_string(),
+            "memo": "",
+            "msg": json!({
+                "listing_id": listing_id,
+            }).to_string()
+        }))
+        .deposit(1)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(result.is_success());
+
+    let leases: Vec<(String, LeaseCondition)> = rental_contract
+        .call("leases_by_borrower")
+        .args_json(json!({
+            "account_id": borrower.id().clone(),
+        }))
+        .transact()
+        .await?
+        .json()?;
+    let lease_id = &leases[0].0;
+    let lease = &leases[0].1;
+
+    assert_eq!(leases.len(), 1);
+    assert_eq!(lease.state, LeaseState::Active);
+    log!("      ✅ Confirmed Lease activation on Rental contract");
+
+    log!("Lender transferring the LEASE NFT to a new user...");
+    let lease_token_id = format!("{}{}", lease_id, "_lender");
+    lender
+        .call(rental_contract.id(), "nft_transfer")
+        .args_json(json!({
+            "receiver_id": lease_nft_receiver.id(),
+            "token_id": lease_token_id,
+        }))
+        .deposit(1)
+        .transact()
+        .await?
+        .into_result()?;
+    log!("      ✅ LEASE NFT transferred");
+
+    log!("Fast forword to post Lease expiration.");
+    worker.fast_forward(20).await?;
+
+    println!("Claiming back the NFT...");
+    let balance_before_claim_back_lender: U128 = ft_contract
+        .view("ft_balance_of")
+        .args_json(json!({
+            "account_id": lender.id(),
+        }))
+        .await?
+        .json()?;
+
+    let balance_before_claim_back_lease_nft_receiver: U128 = ft_contract
+        .view("ft_balance_of")
+        .args_json(json!({
+            "account_id": lease_nft_receiver.id(),
+        }))
+        .await?
+        .json()?;
+
+    let balance_before_claim_back_nft_contract: U128 = ft_contract
+        .view("ft_balance_of")
+        .args_json(json!({
+            "account_id": nft_contract.id(),
+        }))
+        .await?
+        .json()?;
+
+    let result = lease_nft_receiver
+        .call(rental_contract.id(), "claim_back")
+        .args_json(json!({
+            "lease_id": lease_id,
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(result.is_success());
+
+    let balance_after_claim_back_lender: U128 = ft_contract
+        .view("ft_balance_of")
+        .args_json(json!({
+            "account_id": lender.id(),
+        }))
+        .await?
+        .json()?;
+
+    let balance_after_claim_back_lease_nft_receiver: U128 = ft_contract
+        .view("ft_balance_of")
+        .args_json(json!({
+            "account_id": lease_nft_receiver.id(),
+        }))
+        .await?
+        .json()?;
+
+    let balance_after_claim_back_nft_contract: U128 = ft_contract
+        .view("ft_balance_of")
+        .args_json(json!({
+            "account_id": nft_contract.id(),
+        }))
+        .await?
+        .json()?;
+
+    // The NFT contract keeps its 5% royalty. The rest 95% of the rent goes to the LEASE NFT holder,
+    // instead of the original lender.
+    assert_eq!(
+        balance_after_claim_back_lender.0,
+        balance_before_claim_back_lender.0
+    );
+    assert_aprox_eq(
+        balance_after_claim_back_lease_nft_receiver.0
+            - balance_before_claim_back_lease_nft_receiver.0,
+        price / 20 * 19,
+    );
+    assert_aprox_eq(
+        balance_after_claim_back_nft_contract.0 - balance_before_claim_back_nft_contract.0,
+        price / 20,
+    );
+    log!("      ✅ Rent payouts follow the LEASE NFT holder");
+
+    let owned_tokens: Vec<Token> = nft_contract
+        .call("nft_tokens_for_owner")
+        .args_json(json!({"account_id": lease_nft_receiver.id().to_string()}))
+        .transact()
+        .await?
+        .json()?;
+
+    let nft_token = &owned_tokens[0];
+    assert_eq!(nft_token.token_id, nft_token_id);
+    log!("      ✅ NFT claimed back by the LEASE NFT holder");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lease_nft_holder_claims_back_without_payout_succeeds() -> anyhow::Result<()> {
+    let context = init(NFT_NO_PAYOUT_CODE).await?;
+    let worker = context.worker;
+    let rental_contract = context.rental_contract;
+    let marketplace_contract = context.marketplace_contract;
+    let nft_contract = context.nft_contract;
+    let ft_contract = context.ft_contract;
+    let lender = context.lender;
+    let borrower = context.borrower;
+    let lease_nft_receiver = context.lease_nft_receiver;
+
+    let nft_token_id = "test";
+    let price: u128 = 10000;
+    let latest_block = worker.view_block().await?;
+    let lease_start_ts_nano = latest_block.timestamp() + ONE_BLOCK_IN_NANO * 10;
+    let lease_expiration_ts_nano = latest_block.timestamp() + ONE_BLOCK_IN_NANO * 15;
+
+    log!("Creating a listing on marketplace...");
+    lender
+        .call(nft_contract.id(), "nft_approve")
+        .args_json(json!({
+            "token_id": nft_token_id,
+            "account_id": marketplace_contract.id(),
+            "msg": json!({
+                "currency": {"ft": ft_contract.id()},
+                "price": price.to_string(),
+                "lease_start_ts_nano": lease_start_ts_nano.to_string(),
+                "lease_end_ts_nano": lease_expiration_ts_nano.to_string(),
+            }).to_string()
+        }))
+        .deposit(parse_near!("0.1 N"))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let listings: Vec<Listing> = marketplace_contract
+        .call("list_listings_by_owner_id")
+        .args_json(json!({"owner_id": lender.id()}))
+        .transact()
+        .await?
+        .json()?;
+    assert_eq!(listings.len(), 1);
+    log!("      ✅ Confirmed the created listing");
+
+    log!("Borrower accepting the created listing...");
+    let listing_id: (String, String, String) = (
+        nft_contract.id().clone().to_string(),
+        nft_token_id.clone().to_string(),
+        lease_start_ts_nano.to_string(),
+    );
+
+    let result = borrower
+        .call(ft_contract.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": marketplace_contract.id(),
+            "amount": price.to_string(),
+            "memo": "",
+            "msg": json!({
+                "listing_id": listing_id,
+            }).to_string()
+        }))
+        .deposit(1)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(result.is_success());
+
+    let leases: Vec<(String, LeaseCondition)> = rental_contract
+        .call("leases_by_borrower")
+        .args_json(json!({
+            "account_id": borrower.id().clone(),
+        }))
+        .transact()
+        .await?
+        .json()?;
+    let lease_id = &leases[0].0;
+    let lease = &leases[0].1;
+
+    assert_eq!(leases.len(), 1);
+    assert_eq!(lease.state, LeaseState::Active);
+    log!("      ✅ Confirmed Lease activation on Rental contract");
+
+    log!("Lender transferring the LEASE NFT to a new user...");
+    let lease_token_id = format!("{}{}", lease_id, "_lender");
+    lender
+        .call(rental_contract.id(), "nft_transfer")
+        .args_json(json!({
+            "receiver_id": lease_nft_receiver.id(),
+            "token_id": lease_token_id,
+        }))
+        .deposit(1)
+        .transact()
+        .await?
+        .into_result()?;
+    log!("      ✅ LEASE NFT transferred");
+
+    log!("Fast forword to post Lease expiration.");
+    worker.fast_forward(20).await?;
+
+    println!("Claiming back the NFT...");
+    let balance_before_claim_back_lender: U128 = ft_contract
+        .view("ft_balance_of")
+        .args_json(json!({
+            "account_id": lender.id(),
+        }))
+        .await?
+        .json()?;
+
+    let balance_before_claim_back_lease_nft_receiver: U128 = ft_contract
+        .view("ft_balance_of")
+        .args_json(json!({
+            "account_id": lease_nft_receiver.id(),
+        }))
+        .await?
+        .json()?;
+
+    let result = lease_nft_receiver
+        .call(rental_contract.id(), "claim_back")
+        .args_json(json!({
+            "lease_id": lease_id,
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(result.is_success());
+
+    let balance_after_claim_back_lender: U128 = ft_contract
+        .view("ft_balance_of")
+        .args_json(json!({
+            "account_id": lender.id(),
+        }))
+        .await?
+        .json()?;
+
+    let balance_after_claim_back_lease_nft_receiver: U128 = ft_contract
+        .view("ft_balance_of")
+        .args_json(json!({
+            "account_id": lease_nft_receiver.id(),
+        }))
+        .await?
+        .json()?;
+
+    // All rent goes to the LEASE NFT holder, instead of the original lender.
+    assert_eq!(
+        balance_after_claim_back_lender.0,
+        balance_before_claim_back_lender.0
+    );
+    assert_aprox_eq(
+        balance_after_claim_back_lease_nft_receiver.0
+            - balance_before_claim_back_lease_nft_receiver.0,
+        price,
+    );
+    log!("      ✅ Rent payouts follow the LEASE NFT holder");
+
+    let owned_tokens: Vec<Token> = nft_contract
+        .call("nft_tokens_for_owner")
+        .args_json(json!({"account_id": lease_nft_receiver.id().to_string()}))
+        .transact()
+        .await?
+        .json()?;
+
+    let nft_token = &owned_tokens[0];
+    assert_eq!(nft_token.token_id, nft_token_id);
+    log!("      ✅ NFT claimed back by the LEASE NFT holder");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lease_nft_transfer_call_to_rejecting_receiver_succeeds() -> anyhow::Result<()> {
+    let context = init(NFT_NO_PAYOUT_CODE).await?;
+    let worker = context.worker;
+    let rental_contract = context.rental_contract;
+    let marketplace_contract = context.marketplace_contract;
+    let nft_contract = context.nft_contract;
+    let ft_contract = context.ft_contract;
+    let lender = context.lender;
+    let borrower = context.borrower;
+    let receiver_contract = worker.dev_deploy(NFT_RECEIVER_CODE).await?;
+
+    let nft_token_id = "test";
+    let price: u128 = 10000;
+    let latest_block = worker.view_block().await?;
+    let lease_start_ts_nano = latest_block.timestamp() + ONE_BLOCK_IN_NANO * 10;
+    let lease_expiration_ts_nano = latest_block.timestamp() + ONE_BLOCK_IN_NANO * 15;
+
+    log!("Creating a listing on marketplace...");
+    lender
+        .call(nft_contract.id(), "nft_approve")
+        .args_json(json!({
+            "token_id": nft_token_id,
+            "account_id": marketplace_contract.id(),
+            "msg": json!({
+                "currency": {"ft": ft_contract.id()},
+                "price": price.to_string(),
+                "lease_start_ts_nano": lease_start_ts_nano.to_string(),
+                "lease_end_ts_nano": lease_expiration_ts_nano.to_string(),
+            }).to_string()
+        }))
+        .deposit(parse_near!("0.1 N"))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    log!("Borrower accepting the created listing...");
+    let listing_id: (String, String, String) = (
+        nft_contract.id().clone().to_string(),
+        nft_token_id.clone().to_string(),
+        lease_start_ts_nano.to_string(),
+    );
+
+    let result = borrower
+        .call(ft_contract.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": marketplace_contract.id(),
+            "amount": price.to_string(),
+            "memo": "",
+            "msg": json!({
+                "listing_id": listing_id,
+            }).to_string()
+        }))
+        .deposit(1)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(result.is_success());
+
+    let leases: Vec<(String, LeaseCondition)> = rental_contract
+        .call("leases_by_borrower")
+        .args_json(json!({
+            "account_id": borrower.id().clone(),
+        }))
+        .transact()
+        .await?
+        .json()?;
+    let lease_id = &leases[0].0;
+    let lease_token_id = format!("{}{}", lease_id, "_lender");
+    log!("      ✅ Confirmed Lease activation on Rental contract");
+
+    // The receiver asks for the token to be returned, and then panics.
+    // In both cases, the LEASE NFT should go back to the lender.
+    for msg in ["return", "panic"] {
+        log!(
+            "Lender transferring the LEASE NFT to a receiver which rejects it by {}...",
+            msg
+        );
+        lender
+            .call(rental_contract.id(), "nft_transfer_call")
+            .args_json(json!({
+                "receiver_id": receiver_contract.id(),
+                "token_id": lease_token_id,
+                "msg": msg,
+            }))
+            .deposit(1)
+            .max_gas()
+            .transact()
+            .await?
+            .into_result()?;
+
+        let lease_nft_token: Option<Token> = rental_contract
+            .view("nft_token")
+            .args_json(json!({"token_id": lease_token_id}))
+            .await?
+            .json()?;
+        assert_eq!(
+            lease_nft_token.unwrap().owner_id.to_string(),
+            lender.id().to_string()
+        );
+
+        let lease: Option<LeaseCondition> = rental_contract
+            .view("lease_by_id")
+            .args_json(json!({"lease_id": lease_id}))
+            .await?
+            .json()?;
+        assert_eq!(
+            lease.unwrap().lender_id.to_string(),
+            lender.id().to_string()
+        );
+        log!("      ✅ LEASE NFT returned to the lender");
+    }
+
+    log!("Lender transferring the LEASE NFT to a receiver which keeps it...");
+    lender
+        .call(rental_contract.id(), "nft_transfer_call")
+        .args_json(json!({
+            "receiver_id": receiver_contract.id(),
+            "token_id": lease_token_id,
+            "msg": "keep",
+        }))
+        .deposit(1)
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let lease_nft_token: Option<Token> = rental_contract
+        .view("nft_token")
+        .args_json(json!({"token_id": lease_token_id}))
+        .await?
+        .json()?;
+    assert_eq!(
+        lease_nft_token.unwrap().owner_id.to_string(),
+        receiver_contract.id().to_string()
+    );
+    log!("      ✅ LEASE NFT kept by the receiver");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_borrower_accepts_a_listing_with_near_and_owner_claims_back_succeeds(
+) -> anyhow::Result<()> {
+    let context = init(NFT_NO_PAYOUT_CODE).await?;
+    let worker = context.worker;
+    let rental_contract = context.rental_contract;
+    let marketplace_contract = context.marketplace_contract;
+    let marketplace_owner = context.markeplace_owner;
+    let nft_contract = context.nft_contract;
+    let lender = context.lender;
+    let borrower = context.borrower;
+
+    log!("Allowing NEAR as a rent currency on rental contract and marketplace...");
+    context
+        .rental_contract_owner
+        .call(rental_contract.id(), "set_near_allowed")
+        .args_json(json!({ "near_allowed": true }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    marketplace_owner
+        .call(marketplace_contract.id(), "set_near_allowed")
+        .args_json(json!({ "near_allowed": true }))
+        .deposit(1)
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let nft_token_id = "test";
+    let price: u128 = parse_near!("1 N");
+    let latest_block = worker.view_block().await?;
+    let lease_start_ts_nano = latest_block.timestamp() + ONE_BLOCK_IN_NANO * 10;
+    let lease_expiration_ts_nano = latest_block.timestamp() + ONE_BLOCK_IN_NANO * 15;
+
+    log!("Creating a listing priced in NEAR on marketplace...");
+    lender
+        .call(nft_contract.id(), "nft_approve")
+        .args_json(json!({
+            "token_id": nft_token_id,
+            "account_id": marketplace_contract.id(),
+            "msg": json!({
+                "currency": "near",
+                "price": price.to_string(),
+                "lease_start_ts_nano": lease_start_ts_nano.to_string(),
+                "lease_end_ts_nano": lease_expiration_ts_nano.to_string(),
+            }).to_string()
+        }))
+        .deposit(parse_near!("0.1 N"))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    log!("Borrower accepting the created listing with NEAR...");
+    let listing_id: (String, String, String) = (
+        nft_contract.id().to_string(),
+        nft_token_id.to_string(),
+        lease_start_ts_nano.to_string(),
+    );
+    let result = borrower
+        .call(marketplace_contract.id(), "accept_listing_with_near")
+        .args_json(json!({
+            "listing_id": listing_id,
+        }))
+        .deposit(price)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(result.is_success());
+
+    let leases: Vec<(String, LeaseCondition)> = rental_contract
+        .call("leases_by_borrower")
+        .args_json(json!({
+            "account_id": borrower.id().clone(),
+        }))
+        .transact()
+        .await?
+        .json()?;
+    let lease_id = &leases[0].0;
+    let lease = &leases[0].1;
+
+    assert_eq!(leases.len(), 1);
+    assert_eq!(lease.state, LeaseState::Active);
+    assert_eq!(lease.currency, Currency::Near);
+    log!("      ✅ Confirmed Lease activation with the rent paid in NEAR");
+
+    log!("Fast forword to post Lease expiration.");
+    worker.fast_forward(20).await?;
+
+    let balance_before_claim_back = lender.view_account().await?.balance;
+    let result = lender
+        .call(rental_contract.id(), "claim_back")
+        .args_json(json!({
+            "lease_id": lease_id,
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(result.is_success());
+    let balance_after_claim_back = lender.view_account().await?.balance;
+
+    // All rent goes to the lender in NEAR, less the gas for claiming back
+    assert!(balance_after_claim_back - balance_before_claim_back > price - parse_near!("0.05 N"));
+    log!("      ✅ Rent paid out to the lender in NEAR");
+
+    let owned_tokens: Vec<Token> = nft_contract
+        .call("nft_tokens_for_owner")
+        .args_json(json!({"account_id": lender.id().to_string()}))
+        .transact()
+        .await?
+        .json()?;
+    assert_eq!(owned_tokens[0].token_id, nft_token_id);
+    log!("      ✅ NFT claimed back by the lender");
+
+    Ok(())
+}
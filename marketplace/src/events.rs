@@ -0,0 +1,128 @@
+//! Standard events of the listings, as per NEP-297. e.g.
+//! `EVENT_JSON:{"standard":"niftyrent","version":"1.0.0","event":"listing_created","data":[{...}]}`
+//! The rental contract emits the events of the leases in the same event family.
+use crate::Currency;
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{env, serde_json, AccountId};
+
+pub const EVENT_STANDARD: &str = "niftyrent";
+pub const EVENT_VERSION: &str = "1.0.0";
+
+/// A listing has been created for the rental period of the NFT.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ListingCreated<'a> {
+    pub owner_id: &'a AccountId,
+    pub nft_contract_id: &'a AccountId,
+    pub nft_token_id: &'a TokenId,
+    pub currency: &'a Currency,
+    pub price: U128,
+    pub lease_start_ts_nano: U64,
+    pub lease_end_ts_nano: U64,
+}
+
+impl ListingCreated<'_> {
+    pub fn emit(self) {
+        new_event(EventKind::ListingCreated(&[self])).emit()
+    }
+}
+
+/// A listing has been removed, e.g. once accepted by a borrower.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ListingRemoved<'a> {
+    pub owner_id: &'a AccountId,
+    pub nft_contract_id: &'a AccountId,
+    pub nft_token_id: &'a TokenId,
+    pub lease_start_ts_nano: U64,
+}
+
+impl ListingRemoved<'_> {
+    pub fn emit(self) {
+        new_event(EventKind::ListingRemoved(&[self])).emit()
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+enum EventKind<'a> {
+    ListingCreated(&'a [ListingCreated<'a>]),
+    ListingRemoved(&'a [ListingRemoved<'a>]),
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+struct Event<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event_kind: EventKind<'a>,
+}
+
+impl Event<'_> {
+    fn emit(self) {
+        env::log_str(&format!(
+            "EVENT_JSON:{}",
+            serde_json::to_string(&self).unwrap()
+        ));
+    }
+}
+
+fn new_event(event_kind: EventKind) -> Event {
+    Event {
+        standard: EVENT_STANDARD,
+        version: EVENT_VERSION,
+        event_kind,
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils;
+
+    fn alice() -> AccountId {
+        AccountId::new_unchecked("alice".to_string())
+    }
+
+    fn nft() -> AccountId {
+        AccountId::new_unchecked("nft".to_string())
+    }
+
+    #[test]
+    fn test_listing_created_log_succeeds() {
+        ListingCreated {
+            owner_id: &alice(),
+            nft_contract_id: &nft(),
+            nft_token_id: &"test_token".to_string(),
+            currency: &Currency::Ft(AccountId::new_unchecked("ft".to_string())),
+            price: U128::from(100),
+            lease_start_ts_nano: U64::from(1),
+            lease_end_ts_nano: U64::from(1000),
+        }
+        .emit();
+
+        let log_expected = r#"EVENT_JSON:{"standard":"niftyrent","version":"1.0.0","event":"listing_created","data":[{"owner_id":"alice","nft_contract_id":"nft","nft_token_id":"test_token","currency":{"ft":"ft"},"price":"100","lease_start_ts_nano":"1","lease_end_ts_nano":"1000"}]}"#;
+        assert_eq!(test_utils::get_logs()[0], log_expected);
+    }
+
+    #[test]
+    fn test_listing_removed_log_succeeds() {
+        ListingRemoved {
+            owner_id: &alice(),
+            nft_contract_id: &nft(),
+            nft_token_id: &"test_token".to_string(),
+            lease_start_ts_nano: U64::from(1),
+        }
+        .emit();
+
+        let log_expected = r#"EVENT_JSON:{"standard":"niftyrent","version":"1.0.0","event":"listing_removed","data":[{"owner_id":"alice","nft_contract_id":"nft","nft_token_id":"test_token","lease_start_ts_nano":"1"}]}"#;
+        assert_eq!(test_utils::get_logs()[0], log_expected);
+    }
+}
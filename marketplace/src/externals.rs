@@ -33,21 +33,35 @@ pub trait FungibleToken {
 trait ExtSelf {
     fn transfer_rent_after_nft_transfer(
         &mut self,
-        ft_contract_id: AccountId,
+        currency: Currency,
         amount: U128,
         memo: Option<String>,
         listing_id: ListingId,
     ) -> PromiseOrValue<U128>;
 
+    fn transfer_after_lease_booking(
+        &mut self,
+        amount: U128,
+        listing_id: ListingId,
+        msg_lease_json: String,
+    ) -> PromiseOrValue<U128>;
+
+    fn resolve_accept_listing_with_near(&mut self, borrower_id: AccountId, amount: U128);
+
+    fn resolve_near_rent_transfer(&mut self, amount: U128, listing: Listing) -> U128;
+
+    fn resolve_ft_rent_transfer(&mut self, amount: U128, listing: Listing) -> U128;
+
     fn create_listing_with_payout(
         &mut self,
         owner_id: AccountId,
         approval_id: u64,
         nft_contract_id: AccountId,
         nft_token_id: TokenId,
-        ft_contract_id: AccountId,
+        currency: Currency,
         price: U128,
         lease_start_ts_nano: u64,
         lease_end_ts_nano: u64,
+        lease_terms: LeaseTerms,
     ) -> Promise;
 }
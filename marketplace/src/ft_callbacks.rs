@@ -1,4 +1,4 @@
-use near_sdk::PromiseOrValue;
+use near_sdk::{Promise, PromiseOrValue};
 
 use crate::externals::*;
 use crate::*;
@@ -25,8 +25,10 @@ pub trait FungibleTokenReceiver {
  * 1. Borrower(Sender) calls `ft_transfer_call` on FT contract.
  * 2. FT contract transfers `amount` tokens from Borrower to Marketplace(reciever).
  * 3. FT contract calls `ft_on_transfer` on Marketplace contract.
- * 4.1 Marketplace contract makes XCC (nft_transfer_call) to transfer the leasing NFT to Core contract.
- * 4.2 Marketplace contract makes XCC (ft_transfer) to transfer rent to Core contract.
+ * 4.0 Marketplace contract makes XCC (storage_deposit) to pay for the storage of the lease, on behalf of the lender.
+ * 4.1 Marketplace contract makes XCC (book_lease_in_custody) to book the lease, if Core contract already holds the NFT.
+ * 4.2 Otherwise, Marketplace contract makes XCC (nft_transfer_call) to transfer the leasing NFT to Core contract.
+ * 4.3 Marketplace contract makes XCC (ft_transfer) to transfer rent to Core contract.
  * 5. Marketplace contract resolves the promise returned from Core and returns Promise accordingly.
 */
 #[near_bindgen]
@@ -57,77 +59,210 @@ impl FungibleTokenReceiver for Contract {
             .get(&listing_acceptance_json.listing_id)
             .unwrap();
 
+        // NEAR is paid through `accept_listing_with_near`
         assert_eq!(
-            ft_contract_id.clone(),
-            listing.ft_contract_id,
+            Currency::Ft(ft_contract_id),
+            listing.currency,
             "Wrong FT contract id!"
         );
+        // The collateral is paid together with the rent
         assert_eq!(
-            amount.0, listing.price.0,
+            amount.0,
+            listing.price.0 + listing.collateral.0,
             "Transferred amount doesn't match the asked rent!"
         );
 
-        // Transfer both the to be rented NFT and the rent payment (FT) to the rental contract.
+        self.internal_accept_listing(sender_id, amount, listing_acceptance_json.listing_id)
+            .as_return()
+            .into()
+    }
+}
+
+/**
+ * The borrower accepts a listing priced in native NEAR, by attaching the rent and the collateral.
+ * It follows the same flow as `ft_on_transfer`, with the rent paid to Core contract in NEAR.
+ * If the acceptance fails, e.g. the lease can't be booked or Core contract rejects the rent,
+ * the attached deposit is refunded to the borrower.
+ */
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn accept_listing_with_near(&mut self, listing_id: ListingId) -> Promise {
+        let borrower_id = env::predecessor_account_id();
+        let amount = U128::from(env::attached_deposit());
+
+        let listing: Listing = self
+            .listing_by_id
+            .get(&listing_id)
+            .expect("Listing not found");
+
+        assert_eq!(
+            listing.currency,
+            Currency::Near,
+            "Listing is not priced in NEAR!"
+        );
+        // The collateral is paid together with the rent
+        assert_eq!(
+            amount.0,
+            listing.price.0 + listing.collateral.0,
+            "Attached deposit doesn't match the asked rent!"
+        );
+
+        self.internal_accept_listing(borrower_id.clone(), amount, listing_id)
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(BASE_GAS)
+                    .resolve_accept_listing_with_near(borrower_id, amount),
+            )
+    }
+
+    /// Refunds the borrower, if the listing failed to be accepted.
+    /// Like `ft_on_transfer`, the acceptance returns the unused amount, i.e. the rent rejected by Core contract.
+    #[private]
+    pub fn resolve_accept_listing_with_near(&mut self, borrower_id: AccountId, amount: U128) {
+        let refund_amount = match env::promise_result(0) {
+            PromiseResult::NotReady => env::abort(),
+            PromiseResult::Successful(value) => near_sdk::serde_json::from_slice::<U128>(&value)
+                .map(|unused_amount| unused_amount.0)
+                .unwrap_or(0),
+            PromiseResult::Failed => amount.0,
+        };
+        if refund_amount == 0 {
+            return;
+        }
+
+        env::log_str(
+            &json!({
+                "type": "[WARN] NiFTyRent Marketplace: listing acceptance failed. Refund the borrower",
+                "params": {
+                    "borrower_id": borrower_id,
+                    "amount": U128::from(refund_amount),
+                }
+            })
+            .to_string(),
+        );
+        Promise::new(borrower_id).transfer(refund_amount);
+    }
+
+    /// Restores the listing, if Core contract rejected its rent in NEAR, e.g. the pending lease has timed out.
+    /// Returns the amount to be refunded to the borrower by `resolve_accept_listing_with_near`.
+    #[private]
+    pub fn resolve_near_rent_transfer(&mut self, amount: U128, listing: Listing) -> U128 {
+        if is_promise_success() {
+            return U128::from(0);
+        }
+
+        env::log_str(
+            &json!({
+                "type": "[WARN] NiFTyRent Marketplace: rent transfer failed. Restore the listing",
+                "params": {
+                    "nft_contract_id": listing.nft_contract_id,
+                    "nft_token_id": listing.nft_token_id,
+                    "amount": amount,
+                }
+            })
+            .to_string(),
+        );
+        self.internal_index_listing(&listing);
+        amount
+    }
+
+    /// Restores the listing, if Core contract rejected its rent in FT, e.g. the pending lease has timed out.
+    /// Returns the unused amount to the FT contract, which refunds it to the borrower.
+    #[private]
+    pub fn resolve_ft_rent_transfer(&mut self, amount: U128, listing: Listing) -> U128 {
+        // `ft_transfer_call` returns the amount used by Core contract.
+        // If it failed, the rent is still held by this contract, i.e. none was used.
+        let used_amount = match env::promise_result(0) {
+            PromiseResult::Successful(value) => near_sdk::serde_json::from_slice::<U128>(&value)
+                .map(|used_amount| used_amount.0)
+                .unwrap_or(0),
+            _ => 0,
+        };
+        if used_amount > 0 {
+            return U128::from(amount.0.saturating_sub(used_amount));
+        }
+
+        env::log_str(
+            &json!({
+                "type": "[WARN] NiFTyRent Marketplace: rent transfer failed. Restore the listing",
+                "params": {
+                    "nft_contract_id": listing.nft_contract_id,
+                    "nft_token_id": listing.nft_token_id,
+                    "amount": amount,
+                }
+            })
+            .to_string(),
+        );
+        self.internal_index_listing(&listing);
+        amount
+    }
+
+    // Books the lease of the listing, or transfers the NFT to Core contract, then the rent.
+    fn internal_accept_listing(
+        &mut self,
+        borrower_id: AccountId,
+        amount: U128,
+        listing_id: ListingId,
+    ) -> Promise {
+        let listing: Listing = self.listing_by_id.get(&listing_id).unwrap();
+
+        // Book the lease for the NFT, or transfer the NFT to Core contract, then the rent.
         // The Core rental contract will activate the lease.
         // When Core returns successfully, remove the listing in marketplace
-        // 1. Marketplace transfers the NFT to Core contract
-        //    1.1 Core contract will create the lease
-        // 2. Marketplace transfers rent to Core contract
-        // 3. Marketplace reolves the result from the above two steps and returns accordingly
+        // 1. Marketplace books the lease, if the NFT is already in the custody of Core contract.
+        //    e.g. for the week after its current lease
+        // 2. Otherwise, Marketplace transfers the NFT to Core contract
+        //    2.1 Core contract will create the lease
+        // 3. Marketplace transfers rent to Core contract
+        // 4. Marketplace reolves the result from the above steps and returns accordingly
 
         // msg to be passed in nft_transfer_call for a lease creation
         let msg_lease_json = json!({
             "nft_contract_id": listing.nft_contract_id.clone(),
             "nft_token_id": listing.nft_token_id.clone(),
             "lender_id": listing.owner_id.clone(),
-            "borrower_id": sender_id.clone(),
-            "ft_contract_addr": listing.ft_contract_id.clone(),
+            "borrower_id": borrower_id.clone(),
+            "currency": listing.currency.clone(),
             "price": listing.price.clone(),
-            "start_ts_nano": listing.lease_start_ts_nano.clone(),
-            "end_ts_nano": listing.lease_end_ts_nano.clone(),
+            "start_ts_nano": listing.lease_start_ts_nano.0,
+            "end_ts_nano": listing.lease_end_ts_nano.0,
             "nft_payout":listing.payout.clone(),
+            "refund_on_early_return": listing.refund_on_early_return,
+            "renewal_terms": listing.renewal_terms,
+            "collateral": listing.collateral,
+            "allowed_methods": listing.allowed_methods,
+            "sublease_allowed": listing.sublease_allowed,
         })
         .to_string();
 
-        // log nft transfer
-        env::log_str(
-            &json!({
-                "type": "[INFO] NiFTyRent Marketplace: transfer leasing nft.",
-                "params": {
-                    "nft_contract_id": listing.nft_contract_id.clone(),
-                    "nft_token_id": listing.nft_token_id.clone(),
-                    "lender": listing.owner_id.clone(),
-                    "borrower": sender_id.clone(),
-                    "nft_payout": listing.payout.clone(),
-                }
-            })
-            .to_string(),
-        );
-
-        // Transfer the leasing nft to Core contract
-        ext_nft::ext(listing.nft_contract_id.clone())
-            .with_static_gas(Gas(10 * TGAS))
-            .with_attached_deposit(1)
-            .nft_transfer_call(
-                self.rental_contract_id.clone(),   // receiver_id
-                listing.nft_token_id.clone(),      // nft_token_id
-                msg_lease_json,                    // msg
-                Some(listing.approval_id.clone()), // approval_id
-                None,                              // memo
+        // Core contract charges the lender for the storage of the lease.
+        // Pay for it on behalf of the lender, who may not be registered for storage.
+        Promise::new(self.rental_contract_id.clone())
+            .function_call(
+                "storage_deposit".to_string(),
+                json!({ "account_id": listing.owner_id }).to_string().into_bytes(),
+                STORAGE_DEPOSIT_FOR_LEASE,
+                BASE_GAS,
             )
             .then(
-                // Trasnfer the rent to Core contract, after resolving the returned promise
-                // listing will also be removed when both transfers succeeded
+                // Try booking the lease for the NFT in custody first
+                Promise::new(self.rental_contract_id.clone()).function_call(
+                    "book_lease_in_custody".to_string(),
+                    json!({ "msg": msg_lease_json }).to_string().into_bytes(),
+                    0,
+                    Gas(20 * TGAS),
+                ),
+            )
+            .then(
+                // Transfer the rent, or the leasing nft first, after resolving the returned promise
                 ext_self::ext(env::current_account_id())
-                    .with_static_gas(Gas(10 * TGAS))
-                    .transfer_rent_after_nft_transfer(
-                        listing.ft_contract_id.clone(), // ft_contract_id
-                        listing.price.clone(),          // amount
-                        None,                           // memo
-                        listing_acceptance_json.listing_id,
+                    .with_static_gas(Gas(50 * TGAS))
+                    .transfer_after_lease_booking(
+                        amount, // amount, i.e. rent and collateral
+                        listing_id,
+                        msg_lease_json,
                     ),
             )
-            .as_return()
-            .into()
     }
 }
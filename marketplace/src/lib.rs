@@ -1,4 +1,5 @@
 use near_contract_standards::non_fungible_token::TokenId;
+use near_contract_standards::storage_management::StorageBalance;
 use near_sdk::{
     assert_one_yocto,
     borsh::{self, BorshDeserialize, BorshSerialize},
@@ -10,14 +11,20 @@ use near_sdk::{
     serde::{Deserialize, Serialize},
     serde_json,
     serde_json::json,
-    AccountId, BorshStorageKey, CryptoHash, Gas, PanicOnDefault, PromiseResult,
+    AccountId, BorshStorageKey, CryptoHash, Gas, PanicOnDefault, Promise, PromiseOrValue,
+    PromiseResult,
 };
 use std::collections::HashMap;
 
+mod events;
 mod externals;
 mod ft_callbacks;
+mod migration;
 mod nft_callbacks;
+use crate::events::*;
 use crate::externals::*;
+use crate::migration::*;
+use crate::nft_callbacks::MAX_LEN_PAYOUT;
 
 pub const TGAS: u64 = 1_000_000_000_000;
 pub const BASE_GAS: Gas = Gas(5 * TGAS);
@@ -25,10 +32,17 @@ pub const GAS_FOR_ROYALTIES: Gas = BASE_GAS;
 // the tolerance of lease price minus the sum of payout
 // Set it to 1 to avoid linter error
 pub const PAYOUT_DIFF_TORLANCE_YACTO: u128 = 1;
-
-// In the current design, one nft token can only have one active lease, even at different rental periods.
-// (NFT Contract, NFT Token ID).
-type ListingId = (AccountId, TokenId);
+// The storage deposit forwarded to Core contract for the lender, when a listing is accepted.
+// It covers the registration and the records of a lease with the longest ids and payout. 0.03 NEAR
+pub const STORAGE_DEPOSIT_FOR_LEASE: u128 = 30_000_000_000_000_000_000_000;
+// The version of the contract state layout. V1 is migrated by `migrate`
+pub const STATE_VERSION: u8 = 2;
+// The max number of listings moved to the latest format by one `migrate` call
+pub const DEFAULT_MIGRATION_PAGE_SIZE: u64 = 50;
+
+// One nft token can be listed for several non-overlapping rental periods, i.e. its lease calendar.
+// (NFT Contract, NFT Token ID, Lease start timestamp).
+type ListingId = (AccountId, TokenId, U64);
 
 // type used for storing nft token's payout
 pub type PayoutHashMap = HashMap<AccountId, U128>;
@@ -44,6 +58,70 @@ pub struct Payout {
     pub payout: PayoutHashMap,
 }
 
+/// Terms set by the lender, under which the borrower can extend an active lease.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RenewalTerms {
+    /// Rent for extending the lease by one unit of time
+    pub price_per_unit: U128,
+    /// The length of one unit of time, in nano seconds
+    pub unit_duration_nano: U64,
+    /// The lease can not be extended beyond this timestamp
+    pub max_end_ts_nano: U64,
+}
+
+/// A method of the leased NFT contract, which the borrower can call through the rental contract.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AllowedMethod {
+    pub method_name: String,
+    /// Max deposit the borrower can attach. No limit, if not set
+    pub max_deposit: Option<U128>,
+    /// Max gas the borrower can attach. No limit besides the rental contract's own, if not set
+    pub max_gas: Option<U64>,
+}
+
+/// The optional terms of a listing, on top of its price. They are passed on to the lease.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LeaseTerms {
+    /// Whether the borrower gets the unused rent back when returning the NFT early
+    pub refund_on_early_return: bool,
+    /// Terms for the borrower to extend the lease. None if the lease can not be extended.
+    pub renewal_terms: Option<RenewalTerms>,
+    /// Refundable deposit to be paid by the borrower on top of the price, in the same currency
+    pub collateral: U128,
+    /// Methods of the NFT contract the borrower can call during the lease
+    pub allowed_methods: Vec<AllowedMethod>,
+    /// Whether the borrower can sub-lease the NFT during the lease
+    pub sublease_allowed: bool,
+}
+
+impl Default for LeaseTerms {
+    // The terms of a listing created without any option
+    fn default() -> Self {
+        Self {
+            refund_on_early_return: true,
+            renewal_terms: None,
+            collateral: U128(0),
+            allowed_methods: Vec::new(),
+            sublease_allowed: true,
+        }
+    }
+}
+
+/// The currency the rent and the collateral of a listing are paid in.
+/// It's passed on to the rental contract as the currency of the lease.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum Currency {
+    /// Native NEAR, paid through `accept_listing_with_near`
+    Near,
+    /// The contract of a NEP-141 token, paid through `ft_on_transfer`
+    Ft(AccountId),
+}
+
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct Listing {
@@ -53,12 +131,22 @@ pub struct Listing {
     pub approval_id: u64,
     pub nft_contract_id: AccountId,
     pub nft_token_id: TokenId,
-    pub ft_contract_id: AccountId,
+    pub currency: Currency,
     pub price: U128,
-    pub lease_start_ts_nano: u64,
-    pub lease_end_ts_nano: u64,
+    pub lease_start_ts_nano: U64,
+    pub lease_end_ts_nano: U64,
     /// Lease token's payout info
     pub payout: Payout,
+    /// Whether the borrower gets the unused rent back when returning the NFT early
+    pub refund_on_early_return: bool,
+    /// Terms for the borrower to extend the lease. None if the lease can not be extended.
+    pub renewal_terms: Option<RenewalTerms>,
+    /// Refundable deposit to be paid by the borrower on top of the price, in the same currency
+    pub collateral: U128,
+    /// Methods of the NFT contract the borrower can call during the lease
+    pub allowed_methods: Vec<AllowedMethod>,
+    /// Whether the borrower can sub-lease the NFT during the lease
+    pub sublease_allowed: bool,
 }
 
 #[near_bindgen]
@@ -73,23 +161,49 @@ pub struct Contract {
     pub listing_by_id: UnorderedMap<ListingId, Listing>,
     /// Whitelist of FT contracts for rent payment.
     pub allowed_ft_contract_ids: UnorderedSet<AccountId>,
+    /// Whether listings can be priced in native NEAR, besides the allowed FTs.
+    pub near_allowed: bool,
     // TODO(libo): Shops?
     pub allowed_nft_contract_ids: UnorderedSet<AccountId>,
 
     /// Indices of listing for quick lookup.
     pub listing_ids_by_owner_id: LookupMap<AccountId, UnorderedSet<ListingId>>,
     pub listing_ids_by_nft_contract_id: LookupMap<AccountId, UnorderedSet<ListingId>>,
+
+    /// The listings of the first version, not moved to the latest format yet. Empty once migrated.
+    pub legacy_listing_by_id: UnorderedMap<ListingIdV1, ListingV1>,
+    /// The version of the contract state layout, to pick the migration on upgrades.
+    /// It stays at the previous version, until all the listings are migrated.
+    pub state_version: u8,
 }
 
 #[derive(BorshStorageKey, BorshSerialize)]
 enum StorageKey {
+    // The listings of the first version and their indices, keyed by the NFT only
     Listings,
     FTTokenIds,
     NFTContractIds,
     ListingsByOwnerId,
-    ListingsByOwnerIdInner { account_id_hash: CryptoHash },
+    // The inner sets of V1 are only read and emptied by the migration, never created anymore
+    #[allow(dead_code)]
+    ListingsByOwnerIdInner {
+        account_id_hash: CryptoHash,
+    },
     ListingsByNftContractId,
-    ListingsByNftContractIdInner { account_id_hash: CryptoHash },
+    #[allow(dead_code)]
+    ListingsByNftContractIdInner {
+        account_id_hash: CryptoHash,
+    },
+    // The listings and their indices, keyed by the lease start too
+    ListingsV2,
+    ListingsByOwnerIdV2,
+    ListingsByOwnerIdInnerV2 {
+        account_id_hash: CryptoHash,
+    },
+    ListingsByNftContractIdV2,
+    ListingsByNftContractIdInnerV2 {
+        account_id_hash: CryptoHash,
+    },
 }
 
 #[near_bindgen]
@@ -101,12 +215,32 @@ impl Contract {
             owner_id: owner_id.into(),
             treasury_id: treasury_id.into(),
             rental_contract_id,
-            listing_by_id: UnorderedMap::new(StorageKey::Listings),
+            listing_by_id: UnorderedMap::new(StorageKey::ListingsV2),
             allowed_ft_contract_ids: UnorderedSet::new(StorageKey::FTTokenIds),
+            near_allowed: false,
             allowed_nft_contract_ids: UnorderedSet::new(StorageKey::NFTContractIds),
-            listing_ids_by_owner_id: LookupMap::new(StorageKey::ListingsByOwnerId),
-            listing_ids_by_nft_contract_id: LookupMap::new(StorageKey::ListingsByNftContractId),
+            listing_ids_by_owner_id: LookupMap::new(StorageKey::ListingsByOwnerIdV2),
+            listing_ids_by_nft_contract_id: LookupMap::new(StorageKey::ListingsByNftContractIdV2),
+            legacy_listing_by_id: UnorderedMap::new(StorageKey::Listings),
+            state_version: STATE_VERSION,
+        }
+    }
+
+    /// Migrates the contract state of a previous version to the latest one.
+    /// The listings are moved `limit` at a time. Call it again until `get_state_version` returns `STATE_VERSION`.
+    #[init(ignore_state)]
+    pub fn migrate(limit: Option<u64>) -> Self {
+        let mut this = Self::internal_read_state_for_migration();
+        this.assert_owner();
+
+        match this.state_version {
+            STATE_VERSION => panic!("The contract state has already been migrated!"),
+            STATE_VERSION_V1 => {
+                this.internal_migrate_listings_from_v1(limit.unwrap_or(DEFAULT_MIGRATION_PAGE_SIZE))
+            }
+            _ => panic!("Unknown contract state version!"),
         }
+        this
     }
 
     // ------------------ Admin Functions -----------------
@@ -136,6 +270,13 @@ impl Contract {
         insert_accounts(ft_contract_ids, &mut self.allowed_ft_contract_ids);
     }
 
+    /// Allow or disallow new listings to be priced in native NEAR
+    #[payable]
+    pub fn set_near_allowed(&mut self, near_allowed: bool) {
+        self.assert_owner();
+        self.near_allowed = near_allowed;
+    }
+
     // ------------------ View Functions -----------------
     /// List all NFT contract that are allowed to be listed in the market.
     pub fn list_allowed_nft_contract_ids(&self) -> Vec<AccountId> {
@@ -145,13 +286,17 @@ impl Contract {
     pub fn list_allowed_ft_contract_ids(&self) -> Vec<AccountId> {
         return self.allowed_ft_contract_ids.to_vec();
     }
+    /// Whether listings can be priced in native NEAR.
+    pub fn is_near_allowed(&self) -> bool {
+        return self.near_allowed;
+    }
 
     // TODO(syu): check if the reuturn should be a vector of <(listing_id, listing)>, instead of just listing
     pub fn list_listings_by_owner_id(&self, owner_id: AccountId) -> Vec<Listing> {
         return self
             .listing_ids_by_owner_id
             .get(&owner_id)
-            .unwrap_or(UnorderedSet::new(StorageKey::Listings))
+            .unwrap_or(UnorderedSet::new(StorageKey::ListingsV2))
             .iter()
             .map(|list_id| self.listing_by_id.get(&list_id).unwrap())
             .collect::<Vec<_>>();
@@ -161,7 +306,7 @@ impl Contract {
         return self
             .listing_ids_by_nft_contract_id
             .get(&nft_contract_id)
-            .unwrap_or(UnorderedSet::new(StorageKey::Listings))
+            .unwrap_or(UnorderedSet::new(StorageKey::ListingsV2))
             .iter()
             .map(|list_id| self.listing_by_id.get(&list_id).unwrap())
             .collect::<Vec<_>>();
@@ -178,6 +323,10 @@ impl Contract {
         return self.rental_contract_id.clone();
     }
 
+    pub fn get_state_version(&self) -> u8 {
+        return self.state_version;
+    }
+
     // ------------------ XCC RPCs -----------------
     /**
      * This method will handle the transfer of rent to Core rental contract,
@@ -189,11 +338,11 @@ impl Contract {
     #[private]
     pub fn transfer_rent_after_nft_transfer(
         &mut self,
-        ft_contract_id: AccountId,
+        currency: Currency,
         amount: U128,
         memo: Option<String>,
         listing_id: ListingId,
-    ) -> U128 {
+    ) -> PromiseOrValue<U128> {
         // previoux XCC should be successful
         require!(
             is_promise_success(),
@@ -211,49 +360,78 @@ impl Contract {
             }
         }
 
-        // Trasnfer the rent to Core contract.
-        // msg to be passed in ft_transfer_call. Used for specifying the targeting lease.
+        self.internal_transfer_rent(currency, amount, memo, listing_id)
+    }
+
+    /**
+     * This method will handle the result of booking the lease of a listing,
+     * for an NFT already in the custody of Core rental contract, e.g. rented for the previous week.
+     * If booked, the rent is transferred right away.
+     * If the NFT is not in custody, it's transferred to Core contract first, to create the lease.
+     * This XCC can only be called by this contract itself. Thus made private.
+     */
+    #[private]
+    pub fn transfer_after_lease_booking(
+        &mut self,
+        amount: U128,
+        listing_id: ListingId,
+        msg_lease_json: String,
+    ) -> PromiseOrValue<U128> {
+        // previous XCC, book_lease_in_custody, should not fail. e.g. the lease overlaps another lease
+        require!(
+            is_promise_success(),
+            "Lease booking failed. Abort rent transfer!"
+        );
+
+        let lease_booked = promise_result_as_success()
+            .and_then(|value| near_sdk::serde_json::from_slice::<bool>(&value).ok())
+            .unwrap_or(false);
         let listing = self
             .listing_by_id
             .get(&listing_id)
             .expect("Listing Id for rent transfer does not exist!");
-        let msg_rent_transfer_json = json!({
-            "nft_contract_id":listing.nft_contract_id.clone(),
-            "nft_token_id": listing.nft_token_id.clone(),
-        })
-        .to_string();
+        if lease_booked {
+            return self.internal_transfer_rent(listing.currency, amount, None, listing_id);
+        }
 
-        // log rent transfer
+        // log nft transfer
         env::log_str(
             &json!({
-                "type": "[INFO] NiFTyRent Marketplace: transfer rent",
+                "type": "[INFO] NiFTyRent Marketplace: transfer leasing nft.",
                 "params": {
                     "nft_contract_id": listing.nft_contract_id.clone(),
                     "nft_token_id": listing.nft_token_id.clone(),
-                    "ft_contract": listing.ft_contract_id.clone(),
-                    "price": listing.price.clone(),
+                    "lender": listing.owner_id.clone(),
+                    "nft_payout": listing.payout.clone(),
                 }
             })
             .to_string(),
         );
 
-        // The result of this XCC should be properly handled.
-        ext_ft::ext(ft_contract_id.clone())
+        // Transfer the leasing nft to Core contract
+        ext_nft::ext(listing.nft_contract_id.clone())
+            .with_static_gas(Gas(10 * TGAS))
             .with_attached_deposit(1)
-            .with_static_gas(Gas(3 * TGAS))
-            .ft_transfer_call(
-                self.rental_contract_id.clone(), // receiver_id
-                amount,                          // amount
-                memo,                            // memo
-                msg_rent_transfer_json,
-            );
-
-        // remove the listing when both nft transfer and rent transfer succeeded
-        self.internal_remove_listing(listing_id.clone());
-
-        // refund set to 0
-        let refund_ammount: U128 = U128::from(0);
-        return refund_ammount;
+            .nft_transfer_call(
+                self.rental_contract_id.clone(),   // receiver_id
+                listing.nft_token_id.clone(),      // nft_token_id
+                msg_lease_json,                    // msg
+                Some(listing.approval_id.clone()), // approval_id
+                None,                              // memo
+            )
+            .then(
+                // Trasnfer the rent to Core contract, after resolving the returned promise
+                // listing will also be removed when both transfers succeeded
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(Gas(25 * TGAS))
+                    .transfer_rent_after_nft_transfer(
+                        listing.currency.clone(), // currency
+                        amount,                   // amount, i.e. rent and collateral
+                        None,                     // memo
+                        listing_id,
+                    ),
+            )
+            .into()
     }
 
     #[private]
@@ -263,10 +441,11 @@ impl Contract {
         approval_id: u64,
         nft_contract_id: AccountId,
         nft_token_id: TokenId,
-        ft_contract_id: AccountId,
+        currency: Currency,
         price: U128,
         lease_start_ts_nano: u64,
         lease_end_ts_nano: u64,
+        lease_terms: LeaseTerms,
     ) {
         // log the request to create a listing
         env::log_str(
@@ -281,29 +460,44 @@ impl Contract {
             .to_string(),
         );
 
+        // The lender pays Core contract for the storage of the leases, so it should be registered first.
+        // The result of `storage_balance_of` is the second promise result, after `nft_payout`.
+        let storage_balance = match env::promise_result(1) {
+            PromiseResult::Successful(value) => {
+                serde_json::from_slice::<Option<StorageBalance>>(&value).unwrap_or(None)
+            }
+            _ => None,
+        };
+        require!(
+            storage_balance.is_some(),
+            "The lender is not registered for storage in the rental contract!"
+        );
+
         let optional_payout;
-        if is_promise_success() {
+        if let PromiseResult::Successful(value) = env::promise_result(0) {
             // If NFT has implemented the `nft_payout` interface
             // then process the result and verify if sum of payout is close enough to the original price
-            optional_payout = promise_result_as_success().map(|value| {
-                let payout = serde_json::from_slice::<Payout>(&value).unwrap();
-                let payout_diff: u128 = price
-                    .0
-                    .checked_sub(
-                        payout
-                            .payout
-                            .values()
-                            .map(|v| v.0)
-                            .into_iter()
-                            .sum::<u128>(),
-                    )
-                    .unwrap();
-                assert!(
-                    payout_diff <= PAYOUT_DIFF_TORLANCE_YACTO,
-                    "The difference between the listing price and the sum of payout is too large."
-                );
-                payout
-            });
+            let payout = serde_json::from_slice::<Payout>(&value).unwrap();
+            assert!(
+                payout.payout.len() as u32 <= MAX_LEN_PAYOUT,
+                "Too many payout receivers!"
+            );
+            let payout_diff: u128 = price
+                .0
+                .checked_sub(
+                    payout
+                        .payout
+                        .values()
+                        .map(|v| v.0)
+                        .into_iter()
+                        .sum::<u128>(),
+                )
+                .unwrap();
+            assert!(
+                payout_diff <= PAYOUT_DIFF_TORLANCE_YACTO,
+                "The difference between the listing price and the sum of payout is too large."
+            );
+            optional_payout = Some(payout);
         } else {
             // If leased nft didn't provide payouts, we add a proxy payout record making original lender own all the rent.
             // This will make claiming back using LEASE NFT easier.
@@ -318,11 +512,16 @@ impl Contract {
             approval_id: approval_id,
             nft_contract_id: nft_contract_id.clone(),
             nft_token_id: nft_token_id.clone(),
-            ft_contract_id: ft_contract_id,
+            currency: currency,
             price: price,
-            lease_start_ts_nano: lease_start_ts_nano,
-            lease_end_ts_nano: lease_end_ts_nano,
+            lease_start_ts_nano: U64::from(lease_start_ts_nano),
+            lease_end_ts_nano: U64::from(lease_end_ts_nano),
             payout: optional_payout.unwrap(),
+            refund_on_early_return: lease_terms.refund_on_early_return,
+            renewal_terms: lease_terms.renewal_terms,
+            collateral: lease_terms.collateral,
+            allowed_methods: lease_terms.allowed_methods,
+            sublease_allowed: lease_terms.sublease_allowed,
         };
 
         self.internal_insert_listing(&new_listing);
@@ -330,10 +529,20 @@ impl Contract {
     // ------------------ Internal Helpers -----------------
 
     fn internal_insert_listing(&mut self, listing_info: &Listing) {
+        // Approving the marketplace again invalidates the previous approval id of the token.
+        // Update the other listings of the token to the latest approval id.
+        self.internal_update_approval_id(listing_info);
+
+        self.internal_index_listing(listing_info);
+    }
+
+    // Store the listing and add it to the indices
+    fn internal_index_listing(&mut self, listing_info: &Listing) {
         // create listing_id based on listing info
         let listing_id = (
             listing_info.nft_contract_id.clone(),
             listing_info.nft_token_id.clone(),
+            listing_info.lease_start_ts_nano,
         );
 
         self.listing_by_id.insert(&listing_id, &listing_info);
@@ -343,7 +552,7 @@ impl Contract {
             .listing_ids_by_owner_id
             .get(&listing_info.owner_id)
             .unwrap_or_else(|| {
-                UnorderedSet::new(StorageKey::ListingsByOwnerIdInner {
+                UnorderedSet::new(StorageKey::ListingsByOwnerIdInnerV2 {
                     account_id_hash: hash_account_id(&listing_info.owner_id),
                 })
             });
@@ -356,7 +565,7 @@ impl Contract {
             .listing_ids_by_nft_contract_id
             .get(&listing_info.nft_contract_id)
             .unwrap_or_else(|| {
-                UnorderedSet::new(StorageKey::ListingsByNftContractIdInner {
+                UnorderedSet::new(StorageKey::ListingsByNftContractIdInnerV2 {
                     account_id_hash: hash_account_id(&listing_info.nft_contract_id),
                 })
             });
@@ -364,23 +573,111 @@ impl Contract {
         self.listing_ids_by_nft_contract_id
             .insert(&listing_info.nft_contract_id, &listing_ids_set);
 
-        // TODO(steven): remove this logging or find out why it breaks when running on testnet.
-        // env::log_str(
-        //     &json!({
-        //         "type": "insert_listing",
-        //         "params": {
-        //             "owner_id": owner_id,
-        //             "approval_id": approval_id,
-        //             "nft_contract_id": nft_contract_id,
-        //             "nft_token_id": nft_token_id,
-        //             "ft_contract_id": ft_contract_id,
-        //             "price": price,
-        //             "lease_start_ts_nano": lease_start_ts_nano,
-        //             "lease_end_ts_nano": lease_end_ts_nano,
-        //         }
-        //     })
-        //     .to_string(),
-        // );
+        // log the listing creation
+        ListingCreated {
+            owner_id: &listing_info.owner_id,
+            nft_contract_id: &listing_info.nft_contract_id,
+            nft_token_id: &listing_info.nft_token_id,
+            currency: &listing_info.currency,
+            price: listing_info.price,
+            lease_start_ts_nano: listing_info.lease_start_ts_nano,
+            lease_end_ts_nano: listing_info.lease_end_ts_nano,
+        }
+        .emit();
+    }
+
+    // Trasnfer the rent to Core contract, then remove the listing.
+    // Rent in NEAR is resolved by `resolve_near_rent_transfer`, which returns the amount to refund to the borrower.
+    fn internal_transfer_rent(
+        &mut self,
+        currency: Currency,
+        amount: U128,
+        memo: Option<String>,
+        listing_id: ListingId,
+    ) -> PromiseOrValue<U128> {
+        // msg to be passed in ft_transfer_call. Used for specifying the targeting lease.
+        let listing = self
+            .listing_by_id
+            .get(&listing_id)
+            .expect("Listing Id for rent transfer does not exist!");
+        let msg_rent_transfer_json = json!({
+            "nft_contract_id":listing.nft_contract_id.clone(),
+            "nft_token_id": listing.nft_token_id.clone(),
+            "start_ts_nano": listing.lease_start_ts_nano.0,
+        })
+        .to_string();
+
+        // log rent transfer
+        env::log_str(
+            &json!({
+                "type": "[INFO] NiFTyRent Marketplace: transfer rent",
+                "params": {
+                    "nft_contract_id": listing.nft_contract_id.clone(),
+                    "nft_token_id": listing.nft_token_id.clone(),
+                    "currency": listing.currency.clone(),
+                    "price": listing.price.clone(),
+                }
+            })
+            .to_string(),
+        );
+
+        // remove the listing when both nft transfer and rent transfer succeeded
+        self.internal_remove_listing(listing_id.clone());
+
+        // If Core contract rejects the rent, it's refunded to this contract.
+        // Pass it on to the borrower, and restore the listing.
+        match currency {
+            Currency::Near => Promise::new(self.rental_contract_id.clone())
+                .function_call(
+                    "near_on_transfer".to_string(),
+                    json!({ "msg": msg_rent_transfer_json })
+                        .to_string()
+                        .into_bytes(),
+                    amount.0,
+                    Gas(10 * TGAS),
+                )
+                .then(
+                    ext_self::ext(env::current_account_id())
+                        .with_static_gas(BASE_GAS)
+                        .resolve_near_rent_transfer(amount, listing),
+                )
+                .into(),
+            Currency::Ft(ft_contract_id) => ext_ft::ext(ft_contract_id)
+                .with_attached_deposit(1)
+                .with_static_gas(Gas(3 * TGAS))
+                .ft_transfer_call(
+                    self.rental_contract_id.clone(), // receiver_id
+                    amount,                          // amount
+                    memo,                            // memo
+                    msg_rent_transfer_json,
+                )
+                .then(
+                    // The unused amount is returned to the FT contract, which refunds the borrower
+                    ext_self::ext(env::current_account_id())
+                        .with_static_gas(BASE_GAS)
+                        .resolve_ft_rent_transfer(amount, listing),
+                )
+                .into(),
+        }
+    }
+
+    fn internal_update_approval_id(&mut self, listing_info: &Listing) {
+        let listing_ids: Vec<ListingId> = self
+            .listing_ids_by_nft_contract_id
+            .get(&listing_info.nft_contract_id)
+            .map(|listing_ids| listing_ids.to_vec())
+            .unwrap_or_default();
+
+        for listing_id in listing_ids {
+            if listing_id.1 != listing_info.nft_token_id {
+                continue;
+            }
+            let mut listing = self.listing_by_id.get(&listing_id).unwrap();
+            if listing.approval_id != listing_info.approval_id {
+                listing.approval_id = listing_info.approval_id;
+                self.listing_by_id.insert(&listing_id, &listing);
+            }
+        }
     }
 
     fn internal_remove_listing(&mut self, listing_id: ListingId) {
@@ -420,18 +717,13 @@ impl Contract {
         }
 
         // log the listing removal
-        env::log_str(
-            &json!({
-                "type": "[INFO] NiFTyRent Marketplace: remove listing",
-                "params": {
-                    "listing_id": &listing_id,
-                    "owner_id": listing.owner_id,
-                    "nft_contract_id": listing.nft_contract_id,
-                    "nft_token_id": listing.nft_token_id,
-                }
-            })
-            .to_string(),
-        );
+        ListingRemoved {
+            owner_id: &listing.owner_id,
+            nft_contract_id: &listing.nft_contract_id,
+            nft_token_id: &listing.nft_token_id,
+            lease_start_ts_nano: listing.lease_start_ts_nano,
+        }
+        .emit();
     }
 
     fn assert_owner(&self) {
@@ -474,6 +766,7 @@ mod tests {
     follow the code order of testing failing conditions first and success condition last
     */
     use super::*;
+    use crate::ft_callbacks::FungibleTokenReceiver;
     use near_sdk::test_utils::{accounts, VMContextBuilder};
     use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
 
@@ -535,9 +828,9 @@ mod tests {
         let ft_contract_id: AccountId = accounts(3).into();
         let price: U128 = U128(100);
         // Monday, March 27, 2023 2:32:10 AM
-        let lease_start_ts_nano: u64 = 1679884330000000000;
+        let lease_start_ts_nano = U64(1679884330000000000);
         // Tuesday, March 28, 2023 2:32:10 AM
-        let lease_end_ts_nano: u64 = 1679970730000000000;
+        let lease_end_ts_nano = U64(1679970730000000000);
 
         // build the listing
         let new_listing: Listing = Listing {
@@ -545,13 +838,18 @@ mod tests {
             approval_id: approval_id.clone(),
             nft_contract_id: nft_contract_id.clone(),
             nft_token_id: nft_token_id.clone(),
-            ft_contract_id: ft_contract_id.clone(),
+            currency: Currency::Ft(ft_contract_id.clone()),
             price: price.clone(),
-            lease_start_ts_nano: lease_start_ts_nano.clone(),
-            lease_end_ts_nano: lease_end_ts_nano.clone(),
+            lease_start_ts_nano: lease_start_ts_nano,
+            lease_end_ts_nano: lease_end_ts_nano,
             payout: Payout {
                 payout: HashMap::new(),
             },
+            refund_on_early_return: true,
+            renewal_terms: None,
+            collateral: U128(0),
+            allowed_methods: Vec::new(),
+            sublease_allowed: true,
         };
 
         contract.internal_insert_listing(&new_listing);
@@ -576,9 +874,9 @@ mod tests {
         let ft_contract_id: AccountId = accounts(3).into();
         let price: U128 = U128(100);
         // Monday, March 27, 2023 2:32:10 AM
-        let lease_start_ts_nano: u64 = 1679884330000000000;
+        let lease_start_ts_nano = U64(1679884330000000000);
         // Tuesday, March 28, 2023 2:32:10 AM
-        let lease_end_ts_nano: u64 = 1679970730000000000;
+        let lease_end_ts_nano = U64(1679970730000000000);
 
         // build the listing
         let new_listing: Listing = Listing {
@@ -586,13 +884,18 @@ mod tests {
             approval_id: approval_id.clone(),
             nft_contract_id: nft_contract_id.clone(),
             nft_token_id: nft_token_id.clone(),
-            ft_contract_id: ft_contract_id.clone(),
+            currency: Currency::Ft(ft_contract_id.clone()),
             price: price.clone(),
-            lease_start_ts_nano: lease_start_ts_nano.clone(),
-            lease_end_ts_nano: lease_end_ts_nano.clone(),
+            lease_start_ts_nano: lease_start_ts_nano,
+            lease_end_ts_nano: lease_end_ts_nano,
             payout: Payout {
                 payout: HashMap::new(),
             },  // dummy payout field for testing
+            refund_on_early_return: true,
+            renewal_terms: None,
+            collateral: U128(0),
+            allowed_methods: Vec::new(),
+            sublease_allowed: true,
         };
 
         contract.internal_insert_listing(&new_listing);
@@ -616,9 +919,9 @@ mod tests {
         let ft_contract_id: AccountId = accounts(3).into();
         let price: U128 = U128(100);
         // Monday, March 27, 2023 2:32:10 AM
-        let lease_start_ts_nano: u64 = 1679884330000000000;
+        let lease_start_ts_nano = U64(1679884330000000000);
         // Tuesday, March 28, 2023 2:32:10 AM
-        let lease_end_ts_nano: u64 = 1679970730000000000;
+        let lease_end_ts_nano = U64(1679970730000000000);
 
         // build the listing
         let new_listing: Listing = Listing {
@@ -626,13 +929,18 @@ mod tests {
             approval_id: approval_id.clone(),
             nft_contract_id: nft_contract_id.clone(),
             nft_token_id: nft_token_id.clone(),
-            ft_contract_id: ft_contract_id.clone(),
+            currency: Currency::Ft(ft_contract_id.clone()),
             price: price.clone(),
-            lease_start_ts_nano: lease_start_ts_nano.clone(),
-            lease_end_ts_nano: lease_end_ts_nano.clone(),
+            lease_start_ts_nano: lease_start_ts_nano,
+            lease_end_ts_nano: lease_end_ts_nano,
             payout: Payout {
                 payout: HashMap::new(),
             },   // dummy payout field for testing
+            refund_on_early_return: true,
+            renewal_terms: None,
+            collateral: U128(0),
+            allowed_methods: Vec::new(),
+            sublease_allowed: true,
         };
 
         contract.internal_insert_listing(&new_listing);
@@ -657,9 +965,9 @@ mod tests {
         let ft_contract_id: AccountId = accounts(3).into();
         let price: U128 = U128(100);
         // Monday, March 27, 2023 2:32:10 AM
-        let lease_start_ts_nano: u64 = 1679884330000000000;
+        let lease_start_ts_nano = U64(1679884330000000000);
         // Tuesday, March 28, 2023 2:32:10 AM
-        let lease_end_ts_nano: u64 = 1679970730000000000;
+        let lease_end_ts_nano = U64(1679970730000000000);
 
         // build the listing
         let new_listing: Listing = Listing {
@@ -667,13 +975,18 @@ mod tests {
             approval_id: approval_id.clone(),
             nft_contract_id: nft_contract_id.clone(),
             nft_token_id: nft_token_id.clone(),
-            ft_contract_id: ft_contract_id.clone(),
+            currency: Currency::Ft(ft_contract_id.clone()),
             price: price.clone(),
-            lease_start_ts_nano: lease_start_ts_nano.clone(),
-            lease_end_ts_nano: lease_end_ts_nano.clone(),
+            lease_start_ts_nano: lease_start_ts_nano,
+            lease_end_ts_nano: lease_end_ts_nano,
             payout: Payout {
                 payout: HashMap::new(),
             },   // dummy payout field for testing
+            refund_on_early_return: true,
+            renewal_terms: None,
+            collateral: U128(0),
+            allowed_methods: Vec::new(),
+            sublease_allowed: true,
         };
 
         contract.internal_insert_listing(&new_listing);
@@ -715,9 +1028,10 @@ mod tests {
             VMConfig::test(),
             RuntimeFeesConfig::test(),
             HashMap::default(),
-            vec![PromiseResult::Successful(
-                serde_json::to_vec(&payout_expected).unwrap()
-            )],
+            vec![
+                PromiseResult::Successful(serde_json::to_vec(&payout_expected).unwrap()),
+                storage_balance_registered(),
+            ],
         );
 
         contract.create_listing_with_payout(
@@ -725,10 +1039,11 @@ mod tests {
             1, // dummy approval id
             nft_contract_id.clone(),
             nft_token_id.clone(),
-            ft_contract_id.clone(),
+            Currency::Ft(ft_contract_id.clone()),
             price,
             0,
             1000,
+            LeaseTerms::default(),
         );
 
         assert!(!contract.listing_by_id.is_empty());
@@ -742,7 +1057,7 @@ mod tests {
         assert_eq!(nft_token_owner_id, listing_info.owner_id);
         assert_eq!(payout_expected, listing_info.payout);
         assert_eq!(5, listing_info.price.0);
-        assert_eq!(1000, listing_info.lease_end_ts_nano);
+        assert_eq!(1000, listing_info.lease_end_ts_nano.0);
     }
 
     #[test]
@@ -774,7 +1089,7 @@ mod tests {
             VMConfig::test(),
             RuntimeFeesConfig::test(),
             HashMap::default(),
-            vec![PromiseResult::Failed],
+            vec![PromiseResult::Failed, storage_balance_registered()],
         );
 
         contract.create_listing_with_payout(
@@ -782,10 +1097,11 @@ mod tests {
             1, // dummy approval id
             nft_contract_id.clone(),
             nft_token_id.clone(),
-            ft_contract_id.clone(),
+            Currency::Ft(ft_contract_id.clone()),
             price,
             0,
             1000,
+            LeaseTerms::default(),
         );
 
         assert!(!contract.listing_by_id.is_empty());
@@ -839,9 +1155,10 @@ mod tests {
             VMConfig::test(),
             RuntimeFeesConfig::test(),
             HashMap::default(),
-            vec![PromiseResult::Successful(
-                serde_json::to_vec(&payout_returned).unwrap()
-            )],
+            vec![
+                PromiseResult::Successful(serde_json::to_vec(&payout_returned).unwrap()),
+                storage_balance_registered(),
+            ],
         );
 
         contract.create_listing_with_payout(
@@ -849,13 +1166,604 @@ mod tests {
             1, // dummy approval id
             nft_contract_id.clone(),
             nft_token_id.clone(),
-            ft_contract_id.clone(),
+            Currency::Ft(ft_contract_id.clone()),
             price,
             0,
             1000,
+            LeaseTerms::default(),
         );
     }
 
+    #[test]
+    #[should_panic(expected = "Too many payout receivers!")]
+    fn test_create_listing_with_payout_failed_too_many_payout_receivers() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+
+        let nft_token_owner_id: AccountId = create_a_dummy_account_id("nft_token_owner");
+        let payout_returned = Payout {
+            payout: (0..=MAX_LEN_PAYOUT)
+                .map(|i| (create_a_dummy_account_id(&format!("receiver_{}", i)), U128::from(1)))
+                .collect(),
+        };
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(nft_token_owner_id.clone())
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![
+                PromiseResult::Successful(serde_json::to_vec(&payout_returned).unwrap()),
+                storage_balance_registered(),
+            ],
+        );
+
+        contract.create_listing_with_payout(
+            nft_token_owner_id.clone(),
+            1, // dummy approval id
+            create_a_dummy_account_id("nft_contract"),
+            "test_token".to_string(),
+            Currency::Ft(create_a_dummy_account_id("ft_contract_id")),
+            U128::from(MAX_LEN_PAYOUT as u128 + 1),
+            0,
+            1000,
+            LeaseTerms::default(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The lender is not registered for storage in the rental contract!")]
+    fn test_create_listing_with_payout_failed_lender_not_registered() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+
+        let nft_token_owner_id: AccountId = create_a_dummy_account_id("nft_token_owner");
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(nft_token_owner_id.clone())
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![
+                PromiseResult::Failed,
+                PromiseResult::Successful(b"null".to_vec()),
+            ],
+        );
+
+        contract.create_listing_with_payout(
+            nft_token_owner_id,
+            1, // dummy approval id
+            create_a_dummy_account_id("nft_contract"),
+            "test_token".to_string(),
+            Currency::Ft(create_a_dummy_account_id("ft_contract_id")),
+            U128::from(5),
+            0,
+            1000,
+            LeaseTerms::default(),
+        );
+    }
+
+    #[test]
+    fn test_internal_insert_listing_succeeds_updates_approval_id_of_same_token() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+        // Listing the token for the next week approves the marketplace again
+        contract.internal_insert_listing(&create_listing(1000, 1));
+        contract.internal_insert_listing(&create_listing(2000, 2));
+
+        let listings = contract.list_listings_by_nft_contract_id(accounts(2).into());
+        assert_eq!(2, listings.len());
+        assert!(listings.iter().all(|listing| listing.approval_id == 2));
+    }
+
+    #[test]
+    fn test_get_listing_by_id_succeeds_start_beyond_js_safe_integer() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+        // Monday, March 27, 2023 2:32:10 AM, plus 1ns. Not representable as a JS number
+        let lease_start_ts_nano: u64 = 1679884330000000001;
+        contract.internal_insert_listing(&create_listing(lease_start_ts_nano, 1));
+
+        // The listing id is passed in as json by the frontend, with the start as a string
+        let listing_id: ListingId = serde_json::from_value(json!([
+            accounts(2),
+            "test_token",
+            lease_start_ts_nano.to_string()
+        ]))
+        .unwrap();
+        let listing = contract.get_listing_by_id(listing_id);
+        assert_eq!(lease_start_ts_nano, listing.lease_start_ts_nano.0);
+        assert_eq!(
+            json!(lease_start_ts_nano.to_string()),
+            serde_json::to_value(&listing).unwrap()["lease_start_ts_nano"]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Lease booking failed. Abort rent transfer!")]
+    fn test_transfer_after_lease_booking_fails_booking_failed() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+        let listing = create_listing(1000, 1);
+        contract.internal_insert_listing(&listing);
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Failed],
+        );
+
+        contract.transfer_after_lease_booking(
+            listing.price,
+            (listing.nft_contract_id, listing.nft_token_id, U64(1000)),
+            "".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_transfer_after_lease_booking_succeeds_nft_not_in_custody() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+        let listing = create_listing(1000, 1);
+        contract.internal_insert_listing(&listing);
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(
+                serde_json::to_vec(&false).unwrap()
+            )],
+        );
+
+        contract.transfer_after_lease_booking(
+            listing.price,
+            (listing.nft_contract_id, listing.nft_token_id, U64(1000)),
+            "".to_string(),
+        );
+
+        // The listing is kept until the NFT and the rent are transferred
+        assert_eq!(1, contract.listing_by_id.len());
+    }
+
+    #[test]
+    fn test_transfer_after_lease_booking_succeeds_lease_booked() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+        let listing = create_listing(1000, 1);
+        contract.internal_insert_listing(&listing);
+        contract.internal_insert_listing(&create_listing(2000, 1));
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(
+                serde_json::to_vec(&true).unwrap()
+            )],
+        );
+
+        contract.transfer_after_lease_booking(
+            listing.price,
+            (
+                listing.nft_contract_id.clone(),
+                listing.nft_token_id.clone(),
+                U64(1000),
+            ),
+            "".to_string(),
+        );
+
+        // Only the booked listing is removed, once the rent is transferred
+        let listings = contract.list_listings_by_nft_contract_id(listing.nft_contract_id);
+        assert_eq!(1, listings.len());
+        assert_eq!(2000, listings[0].lease_start_ts_nano.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Wrong FT contract id!")]
+    fn test_ft_on_transfer_fails_listing_priced_in_near() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+        let mut listing = create_listing(1000, 1);
+        listing.currency = Currency::Near;
+        contract.internal_insert_listing(&listing);
+
+        // An FT contract can't pay for a listing priced in NEAR, whatever its account id
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id("near".parse().unwrap())
+            .build());
+
+        contract.ft_on_transfer(
+            accounts(4),
+            listing.price,
+            json!({
+                "listing_id": (listing.nft_contract_id, listing.nft_token_id, U64(1000)),
+            })
+            .to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Listing is not priced in NEAR!")]
+    fn test_accept_listing_with_near_fails_listing_priced_in_ft() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+        let listing = create_listing(1000, 1);
+        contract.internal_insert_listing(&listing);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(4))
+            .attached_deposit(listing.price.0)
+            .build());
+
+        contract.accept_listing_with_near((
+            listing.nft_contract_id,
+            listing.nft_token_id,
+            U64(1000),
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Attached deposit doesn't match the asked rent!")]
+    fn test_accept_listing_with_near_fails_wrong_deposit() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+        let mut listing = create_listing(1000, 1);
+        listing.currency = Currency::Near;
+        contract.internal_insert_listing(&listing);
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(4))
+            .attached_deposit(listing.price.0 - 1)
+            .build());
+
+        contract.accept_listing_with_near((
+            listing.nft_contract_id,
+            listing.nft_token_id,
+            U64(1000),
+        ));
+    }
+
+    #[test]
+    fn test_accept_listing_with_near_succeeds() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+        let mut listing = create_listing(1000, 1);
+        listing.currency = Currency::Near;
+        contract.internal_insert_listing(&listing);
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(4))
+            .attached_deposit(listing.price.0)
+            .build());
+
+        contract.accept_listing_with_near((
+            listing.nft_contract_id,
+            listing.nft_token_id,
+            U64(1000),
+        ));
+
+        // The storage of the lease is paid for on the rental contract first
+        let receipts = near_sdk::test_utils::get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, contract.rental_contract_id);
+    }
+
+    #[test]
+    fn test_ft_on_transfer_succeeds_pays_storage_for_lender() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+        let listing = create_listing(1000, 1);
+        contract.internal_insert_listing(&listing);
+
+        // The lender of the listing may not be registered for storage in the rental contract
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .predecessor_account_id(accounts(3))
+            .build());
+
+        contract.ft_on_transfer(
+            accounts(4),
+            listing.price,
+            json!({
+                "listing_id": (
+                    listing.nft_contract_id.clone(),
+                    listing.nft_token_id.clone(),
+                    U64(1000),
+                ),
+            })
+            .to_string(),
+        );
+
+        // The storage deposit for the lender is made before booking the lease
+        let receipts = near_sdk::test_utils::get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, contract.rental_contract_id);
+        match &receipts[0].actions[0] {
+            near_sdk::mock::VmAction::FunctionCall {
+                function_name,
+                args,
+                deposit,
+                ..
+            } => {
+                assert_eq!(function_name, "storage_deposit");
+                assert_eq!(
+                    serde_json::from_slice::<serde_json::Value>(args).unwrap(),
+                    json!({ "account_id": listing.owner_id })
+                );
+                assert_eq!(*deposit, STORAGE_DEPOSIT_FOR_LEASE);
+            }
+            _ => panic!("Expected a function call!"),
+        }
+        assert_eq!(receipts[1].receiver_id, contract.rental_contract_id);
+        match &receipts[1].actions[0] {
+            near_sdk::mock::VmAction::FunctionCall { function_name, .. } => {
+                assert_eq!(function_name, "book_lease_in_custody");
+            }
+            _ => panic!("Expected a function call!"),
+        }
+    }
+
+    #[test]
+    fn test_resolve_accept_listing_with_near_succeeds_refund_on_failure() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Failed],
+        );
+
+        contract.resolve_accept_listing_with_near(accounts(4), U128(100));
+
+        let receipt = &near_sdk::test_utils::get_created_receipts()[0];
+        assert_eq!(receipt.receiver_id, accounts(4));
+        assert_eq!(
+            receipt.actions,
+            vec![near_sdk::mock::VmAction::Transfer { deposit: 100 }]
+        );
+    }
+
+    #[test]
+    fn test_resolve_accept_listing_with_near_succeeds_refund_rejected_rent() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+
+        // The acceptance succeeded, but returned the rent rejected by the rental contract
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(
+                serde_json::to_vec(&U128(100)).unwrap()
+            )],
+        );
+
+        contract.resolve_accept_listing_with_near(accounts(4), U128(100));
+
+        let receipt = &near_sdk::test_utils::get_created_receipts()[0];
+        assert_eq!(receipt.receiver_id, accounts(4));
+        assert_eq!(
+            receipt.actions,
+            vec![near_sdk::mock::VmAction::Transfer { deposit: 100 }]
+        );
+    }
+
+    #[test]
+    fn test_resolve_accept_listing_with_near_succeeds_no_refund_on_success() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(
+                serde_json::to_vec(&U128(0)).unwrap()
+            )],
+        );
+
+        contract.resolve_accept_listing_with_near(accounts(4), U128(100));
+
+        assert!(near_sdk::test_utils::get_created_receipts().is_empty());
+    }
+
+    #[test]
+    fn test_resolve_near_rent_transfer_succeeds_restores_listing_on_failure() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+        let mut listing = create_listing(1000, 1);
+        listing.currency = Currency::Near;
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Failed],
+        );
+
+        let refund_amount = contract.resolve_near_rent_transfer(listing.price, listing);
+
+        assert_eq!(refund_amount, U128(100));
+        let listing =
+            contract.get_listing_by_id((accounts(2), "test_token".to_string(), U64(1000)));
+        assert_eq!(listing.owner_id, accounts(5));
+    }
+
+    #[test]
+    fn test_resolve_ft_rent_transfer_succeeds_restores_listing_on_failure() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+        let listing = create_listing(1000, 1);
+
+        // Core contract rejected the rent, so none of it was used
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(
+                serde_json::to_vec(&U128(0)).unwrap()
+            )],
+        );
+
+        let unused_amount = contract.resolve_ft_rent_transfer(listing.price, listing);
+
+        assert_eq!(unused_amount, U128(100));
+        let listing =
+            contract.get_listing_by_id((accounts(2), "test_token".to_string(), U64(1000)));
+        assert_eq!(listing.owner_id, accounts(5));
+    }
+
+    #[test]
+    fn test_resolve_ft_rent_transfer_succeeds_rent_used() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+        let listing = create_listing(1000, 1);
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(
+                serde_json::to_vec(&listing.price).unwrap()
+            )],
+        );
+
+        let unused_amount = contract.resolve_ft_rent_transfer(listing.price, listing);
+
+        assert_eq!(unused_amount, U128(0));
+        assert!(contract.listing_by_id.is_empty());
+    }
+
+    #[test]
+    fn test_transfer_after_lease_booking_succeeds_rent_paid_in_near() {
+        let mut contract =
+            Contract::new(accounts(1).into(), accounts(2).into(), accounts(3).into());
+        let mut listing = create_listing(1000, 1);
+        listing.currency = Currency::Near;
+        contract.internal_insert_listing(&listing);
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(0))
+                .build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(
+                serde_json::to_vec(&true).unwrap()
+            )],
+        );
+
+        contract.transfer_after_lease_booking(
+            listing.price,
+            (listing.nft_contract_id, listing.nft_token_id, U64(1000)),
+            "".to_string(),
+        );
+
+        // The rent is attached to the call of the rental contract, instead of an FT transfer
+        let receipt = &near_sdk::test_utils::get_created_receipts()[0];
+        assert_eq!(receipt.receiver_id, contract.rental_contract_id);
+        match &receipt.actions[0] {
+            near_sdk::mock::VmAction::FunctionCall {
+                function_name,
+                deposit,
+                ..
+            } => {
+                assert_eq!(function_name, "near_on_transfer");
+                assert_eq!(*deposit, listing.price.0);
+            }
+            _ => panic!("Expected a function call!"),
+        }
+        // The result of the rent transfer is resolved, to refund the borrower if rejected
+        let receipt = &near_sdk::test_utils::get_created_receipts()[1];
+        assert_eq!(receipt.receiver_id, accounts(0));
+        match &receipt.actions[0] {
+            near_sdk::mock::VmAction::FunctionCall { function_name, .. } => {
+                assert_eq!(function_name, "resolve_near_rent_transfer");
+            }
+            _ => panic!("Expected a function call!"),
+        }
+    }
+
+    // Helper function to return the result of `storage_balance_of`, for a lender registered in the rental contract
+    fn storage_balance_registered() -> PromiseResult {
+        PromiseResult::Successful(
+            serde_json::to_vec(&Some(StorageBalance {
+                total: U128(1),
+                available: U128(0),
+            }))
+            .unwrap(),
+        )
+    }
+
+    // Helper function to build a listing of the same token, for the rental period starting at the given time
+    fn create_listing(lease_start_ts_nano: u64, approval_id: u64) -> Listing {
+        Listing {
+            owner_id: accounts(5).into(),
+            approval_id,
+            nft_contract_id: accounts(2).into(),
+            nft_token_id: "test_token".to_string(),
+            currency: Currency::Ft(accounts(3)),
+            price: U128(100),
+            lease_start_ts_nano: U64(lease_start_ts_nano),
+            lease_end_ts_nano: U64(lease_start_ts_nano + 999),
+            payout: Payout {
+                payout: HashMap::new(),
+            }, // dummy payout field for testing
+            refund_on_early_return: true,
+            renewal_terms: None,
+            collateral: U128(0),
+            allowed_methods: Vec::new(),
+            sublease_allowed: true,
+        }
+    }
+
     // Helper function to generate a dummy AccountId using input name
     pub(crate) fn create_a_dummy_account_id(account_name: &str) -> AccountId {
         AccountId::new_unchecked(account_name.to_string())
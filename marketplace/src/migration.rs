@@ -0,0 +1,298 @@
+use crate::*;
+
+// The key of the contract state in the storage, as used by near_bindgen
+const STATE_KEY: &[u8] = b"STATE";
+// The state version while some listings are still in the format of V1
+pub const STATE_VERSION_V1: u8 = 1;
+
+// The listing id of the first version. One nft token could only be listed once.
+// (NFT Contract, NFT Token ID)
+pub type ListingIdV1 = (AccountId, TokenId);
+
+/// The listing of the first version, before the lease options were added
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ListingV1 {
+    pub owner_id: AccountId,
+    pub approval_id: u64,
+    pub nft_contract_id: AccountId,
+    pub nft_token_id: TokenId,
+    pub ft_contract_id: AccountId,
+    pub price: U128,
+    pub lease_start_ts_nano: u64,
+    pub lease_end_ts_nano: u64,
+    pub payout: Payout,
+}
+
+/// The first version of the contract state
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV1 {
+    pub owner_id: AccountId,
+    pub treasury_id: AccountId,
+    pub rental_contract_id: AccountId,
+    pub listing_by_id: UnorderedMap<ListingIdV1, ListingV1>,
+    pub allowed_ft_contract_ids: UnorderedSet<AccountId>,
+    pub allowed_nft_contract_ids: UnorderedSet<AccountId>,
+    pub listing_ids_by_owner_id: LookupMap<AccountId, UnorderedSet<ListingIdV1>>,
+    pub listing_ids_by_nft_contract_id: LookupMap<AccountId, UnorderedSet<ListingIdV1>>,
+}
+
+impl From<ListingV1> for Listing {
+    // The lease options take the values matching the behaviour of the first version
+    fn from(prev: ListingV1) -> Self {
+        Self {
+            owner_id: prev.owner_id,
+            approval_id: prev.approval_id,
+            nft_contract_id: prev.nft_contract_id,
+            nft_token_id: prev.nft_token_id,
+            currency: Currency::Ft(prev.ft_contract_id),
+            price: prev.price,
+            lease_start_ts_nano: U64::from(prev.lease_start_ts_nano),
+            lease_end_ts_nano: U64::from(prev.lease_end_ts_nano),
+            payout: prev.payout,
+            refund_on_early_return: true,
+            renewal_terms: None,
+            collateral: U128::from(0),
+            allowed_methods: Vec::new(),
+            // The lender has never agreed to sub-leasing
+            sublease_allowed: false,
+        }
+    }
+}
+
+impl From<ContractV1> for Contract {
+    // The listings are moved to the latest format by `internal_migrate_listings_from_v1`
+    fn from(prev: ContractV1) -> Self {
+        Self {
+            owner_id: prev.owner_id,
+            treasury_id: prev.treasury_id,
+            rental_contract_id: prev.rental_contract_id,
+            listing_by_id: UnorderedMap::new(StorageKey::ListingsV2),
+            allowed_ft_contract_ids: prev.allowed_ft_contract_ids,
+            near_allowed: false,
+            allowed_nft_contract_ids: prev.allowed_nft_contract_ids,
+            listing_ids_by_owner_id: LookupMap::new(StorageKey::ListingsByOwnerIdV2),
+            listing_ids_by_nft_contract_id: LookupMap::new(StorageKey::ListingsByNftContractIdV2),
+            legacy_listing_by_id: prev.listing_by_id,
+            state_version: STATE_VERSION_V1,
+        }
+    }
+}
+
+impl Contract {
+    /// Reads the contract state to be migrated.
+    /// The first version has no `state_version`, so its layout is told by trying the latest version first.
+    /// Borsh requires all bytes to be consumed, so only the matching version can be read.
+    pub(crate) fn internal_read_state_for_migration() -> Self {
+        let state = env::storage_read(STATE_KEY).expect("ERR_NOT_INITIALIZED");
+
+        if let Ok(this) = Contract::try_from_slice(&state) {
+            return this;
+        }
+        ContractV1::try_from_slice(&state)
+            .map(Contract::from)
+            .expect("Unknown contract state version!")
+    }
+
+    /// Moves up to `limit` more listings of the first version to the latest format, keyed by their lease start.
+    /// A `ListingCreated` event is emitted for each of them, as the first version didn't emit events.
+    /// Once all the listings are moved, the state is at the latest version.
+    pub(crate) fn internal_migrate_listings_from_v1(&mut self, limit: u64) {
+        let mut prev_listing_ids_by_owner_id: LookupMap<AccountId, UnorderedSet<ListingIdV1>> =
+            LookupMap::new(StorageKey::ListingsByOwnerId);
+        let mut prev_listing_ids_by_nft_contract_id: LookupMap<
+            AccountId,
+            UnorderedSet<ListingIdV1>,
+        > = LookupMap::new(StorageKey::ListingsByNftContractId);
+
+        for _ in 0..limit {
+            if self.legacy_listing_by_id.is_empty() {
+                break;
+            }
+            // Taking the last listing doesn't move any other listing in the map
+            let prev_listing_id = self
+                .legacy_listing_by_id
+                .keys_as_vector()
+                .get(self.legacy_listing_by_id.len() - 1)
+                .unwrap();
+            let prev_listing = self.legacy_listing_by_id.remove(&prev_listing_id).unwrap();
+
+            remove_legacy_listing_id(
+                &mut prev_listing_ids_by_owner_id,
+                &prev_listing.owner_id,
+                &prev_listing_id,
+            );
+            remove_legacy_listing_id(
+                &mut prev_listing_ids_by_nft_contract_id,
+                &prev_listing.nft_contract_id,
+                &prev_listing_id,
+            );
+
+            self.internal_index_listing(&Listing::from(prev_listing));
+        }
+
+        if self.legacy_listing_by_id.is_empty() {
+            self.state_version = STATE_VERSION;
+        }
+    }
+}
+
+/// Helper function to remove a listing id of the first version from one of its indices.
+fn remove_legacy_listing_id(
+    index: &mut LookupMap<AccountId, UnorderedSet<ListingIdV1>>,
+    account_id: &AccountId,
+    listing_id: &ListingIdV1,
+) {
+    if let Some(mut listing_ids) = index.get(account_id) {
+        listing_ids.remove(listing_id);
+        if listing_ids.is_empty() {
+            index.remove(account_id);
+        } else {
+            index.insert(account_id, &listing_ids);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    /*
+    Unit test cases and helper functions
+
+    Test naming format for better readability:
+    - test_{function_name} _{succeeds_or_fails} _{condition}
+    - When more than one test cases are needed for one function,
+    follow the code order of testing failing conditions first and success condition last
+    */
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    #[test]
+    #[should_panic(expected = "This function can only be called by the owner!")]
+    fn test_migrate_fails_called_by_non_owner() {
+        env::state_write(&create_contract_v1_with_listings());
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(2))
+            .build());
+        Contract::migrate(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The contract state has already been migrated!")]
+    fn test_migrate_fails_already_migrated() {
+        env::state_write(&Contract::new(
+            accounts(1).into(),
+            accounts(2).into(),
+            accounts(3).into(),
+        ));
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(1))
+            .build());
+        Contract::migrate(None);
+    }
+
+    #[test]
+    fn test_migrate_succeeds_resumes_by_page() {
+        env::state_write(&create_contract_v1_with_listings());
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(1))
+            .build());
+        let contract = Contract::migrate(Some(1));
+
+        // Only one listing is moved, so the listings are still pending migration
+        assert_eq!(contract.get_state_version(), STATE_VERSION_V1);
+        assert_eq!(contract.legacy_listing_by_id.len(), 1);
+        assert_eq!(contract.listing_by_id.len(), 1);
+
+        env::state_write(&contract);
+        let contract = Contract::migrate(Some(1));
+
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        assert!(contract.legacy_listing_by_id.is_empty());
+        assert_eq!(contract.listing_by_id.len(), 2);
+    }
+
+    #[test]
+    fn test_migrate_succeeds_keeps_listings_and_indices() {
+        env::state_write(&create_contract_v1_with_listings());
+
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(1))
+            .build());
+        let contract = Contract::migrate(None);
+
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        assert_eq!(contract.owner_id, accounts(1));
+        assert_eq!(contract.list_allowed_ft_contract_ids(), vec![accounts(3)]);
+
+        let listing =
+            contract.get_listing_by_id((accounts(2), "test_token".to_string(), U64(1000)));
+        assert_eq!(listing.owner_id, accounts(5));
+        assert_eq!(listing.price, U128(100));
+        assert_eq!(listing.lease_end_ts_nano, U64(1999));
+        assert!(listing.refund_on_early_return);
+        assert!(!listing.sublease_allowed);
+
+        assert_eq!(contract.list_listings_by_owner_id(accounts(5)).len(), 2);
+        assert_eq!(
+            contract.list_listings_by_nft_contract_id(accounts(2)).len(),
+            2
+        );
+        // The indices of the first version are cleared
+        let prev_listing_ids_by_owner_id: LookupMap<AccountId, UnorderedSet<ListingIdV1>> =
+            LookupMap::new(StorageKey::ListingsByOwnerId);
+        assert!(prev_listing_ids_by_owner_id.get(&accounts(5)).is_none());
+    }
+
+    // Helper function to build the V1 state, with two listings of the same lender
+    fn create_contract_v1_with_listings() -> ContractV1 {
+        let mut prev = ContractV1 {
+            owner_id: accounts(1),
+            treasury_id: accounts(2),
+            rental_contract_id: accounts(3),
+            listing_by_id: UnorderedMap::new(StorageKey::Listings),
+            allowed_ft_contract_ids: UnorderedSet::new(StorageKey::FTTokenIds),
+            allowed_nft_contract_ids: UnorderedSet::new(StorageKey::NFTContractIds),
+            listing_ids_by_owner_id: LookupMap::new(StorageKey::ListingsByOwnerId),
+            listing_ids_by_nft_contract_id: LookupMap::new(StorageKey::ListingsByNftContractId),
+        };
+        prev.allowed_ft_contract_ids.insert(&accounts(3));
+
+        let mut listing_ids_by_owner_id = UnorderedSet::new(StorageKey::ListingsByOwnerIdInner {
+            account_id_hash: hash_account_id(&accounts(5)),
+        });
+        let mut listing_ids_by_nft_contract_id =
+            UnorderedSet::new(StorageKey::ListingsByNftContractIdInner {
+                account_id_hash: hash_account_id(&accounts(2)),
+            });
+        for (token_id, lease_start_ts_nano) in [("test_token", 1000), ("test_token_2", 2000)] {
+            let listing_id = (accounts(2), token_id.to_string());
+            prev.listing_by_id.insert(
+                &listing_id,
+                &ListingV1 {
+                    owner_id: accounts(5),
+                    approval_id: 1,
+                    nft_contract_id: accounts(2),
+                    nft_token_id: token_id.to_string(),
+                    ft_contract_id: accounts(3),
+                    price: U128(100),
+                    lease_start_ts_nano,
+                    lease_end_ts_nano: lease_start_ts_nano + 999,
+                    payout: Payout {
+                        payout: HashMap::new(),
+                    },
+                },
+            );
+            listing_ids_by_owner_id.insert(&listing_id);
+            listing_ids_by_nft_contract_id.insert(&listing_id);
+        }
+        prev.listing_ids_by_owner_id
+            .insert(&accounts(5), &listing_ids_by_owner_id);
+        prev.listing_ids_by_nft_contract_id
+            .insert(&accounts(2), &listing_ids_by_nft_contract_id);
+
+        prev
+    }
+}
@@ -1,14 +1,26 @@
 use crate::*;
 /// approval callbacks from NFT Contracts
 
-pub const MAX_LEN_PAYOUT: u32 = 50;
+// The rental contract settles a lease with a transfer per payout receiver,
+// so longer payouts don't fit in the gas of a claim back.
+pub const MAX_LEN_PAYOUT: u32 = 10;
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct ListingJson {
-    ft_contract_id: AccountId,
+    currency: Currency,
     price: U128,
     lease_start_ts_nano: U64,
     lease_end_ts_nano: U64,
+    /// Whether the borrower gets the unused rent back when returning the NFT early. Default: true
+    refund_on_early_return: Option<bool>,
+    /// Terms for the borrower to extend the lease. Default: the lease can not be extended
+    renewal_terms: Option<RenewalTerms>,
+    /// Refundable deposit to be paid by the borrower on top of the price. Default: no collateral
+    collateral: Option<U128>,
+    /// Methods of the NFT contract the borrower can call during the lease. Default: none
+    allowed_methods: Option<Vec<AllowedMethod>>,
+    /// Whether the borrower can sub-lease the NFT during the lease. Default: true
+    sublease_allowed: Option<bool>,
 }
 
 /**
@@ -55,31 +67,57 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
             "nft_contract_id is not allowed!"
         );
 
-        // enfore the token is not listed more than once
-        require!(
-            self.listing_by_id
-                .get(&(nft_contract_id.clone(), token_id.clone()))
-                .is_none(),
-            "One nft token cannot be listed more than once!!"
-        );
-
         // extract listing details
         let listing_json: ListingJson =
             near_sdk::serde_json::from_str(&msg).expect("Invalid Listing Json!");
 
-        // enforce ft contract is allowed
+        // enfore the token is not listed more than once for the same rental period
         require!(
-            self.allowed_ft_contract_ids
-                .contains(&listing_json.ft_contract_id),
-            "ft_contract_id is not allowed!"
+            self.listing_by_id
+                .get(&(
+                    nft_contract_id.clone(),
+                    token_id.clone(),
+                    listing_json.lease_start_ts_nano
+                ))
+                .is_none(),
+            "One nft token cannot be listed more than once for the same lease start!!"
         );
 
-        // query the payouts field of the leasing token & create a listing accordingly
+        // enforce the currency is allowed
+        match &listing_json.currency {
+            Currency::Near => require!(self.near_allowed, "NEAR is not allowed!"),
+            Currency::Ft(ft_contract_id) => require!(
+                self.allowed_ft_contract_ids.contains(ft_contract_id),
+                "ft_contract_id is not allowed!"
+            ),
+        }
+
+        // enforce renewal terms are valid
+        if let Some(renewal_terms) = &listing_json.renewal_terms {
+            require!(
+                renewal_terms.price_per_unit.0 > 0 && renewal_terms.unit_duration_nano.0 > 0,
+                "Renewal price and time unit should be positive!"
+            );
+            require!(
+                renewal_terms.max_end_ts_nano.0 > listing_json.lease_end_ts_nano.0,
+                "Renewal max end time should be after the lease end time!"
+            );
+        }
+
+        // query the payouts field of the leasing token, and the storage registration of the lender in Core contract.
+        // create a listing accordingly
         ext_nft::ext(nft_contract_id.clone())
         .nft_payout(
             token_id.clone(), 
             U128::from(listing_json.price.0),  // price
             Some(MAX_LEN_PAYOUT),            // max_len_payout
+        ).and(
+            Promise::new(self.rental_contract_id.clone()).function_call(
+                "storage_balance_of".to_string(),
+                json!({ "account_id": owner_id }).to_string().into_bytes(),
+                0,
+                BASE_GAS,
+            ),
         ).then(
             ext_self::ext(env::current_account_id())
             .with_static_gas(GAS_FOR_ROYALTIES)
@@ -88,10 +126,17 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
                 approval_id, 
                 nft_contract_id, 
                 token_id, 
-                listing_json.ft_contract_id, 
+                listing_json.currency, 
                 listing_json.price, 
                 listing_json.lease_start_ts_nano.0,
                 listing_json.lease_end_ts_nano.0,
+                LeaseTerms {
+                    refund_on_early_return: listing_json.refund_on_early_return.unwrap_or(true),
+                    renewal_terms: listing_json.renewal_terms,
+                    collateral: listing_json.collateral.unwrap_or(U128(0)),
+                    allowed_methods: listing_json.allowed_methods.unwrap_or_default(),
+                    sublease_allowed: listing_json.sublease_allowed.unwrap_or(true),
+                },
             ),
         ).as_return();
         